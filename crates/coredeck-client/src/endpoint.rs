@@ -0,0 +1,87 @@
+//! Where the daemon listens, and opening a byte stream to it
+
+use anyhow::{anyhow, Context, Result};
+use coredeck_protocol::{default_socket_path, TokenFile, APP_TOKEN_NAME, DEFAULT_DAEMON_ADDR, TOKENS_FILE_NAME};
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::debug;
+
+/// How to reach the daemon. The Unix socket is tried first; its permissions
+/// stand in for the token, so the token is only sent over TCP.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    /// Unix socket path (ignored when it doesn't exist, and on Windows)
+    pub socket: Option<PathBuf>,
+    /// TCP `host:port`
+    pub addr: Option<String>,
+    /// API token for TCP connections
+    pub token: Option<String>,
+}
+
+impl Endpoint {
+    /// The local daemon at its default socket and address, with the app's token
+    pub fn local() -> Self {
+        Self::local_at(DEFAULT_DAEMON_ADDR)
+    }
+
+    /// The local daemon's default socket, falling back to TCP at `addr`
+    pub fn local_at(addr: &str) -> Self {
+        Self { socket: default_socket_path(), addr: Some(addr.to_string()), token: app_token() }
+    }
+
+    /// TCP only
+    pub fn tcp(addr: &str, token: Option<String>) -> Self {
+        Self { socket: None, addr: Some(addr.to_string()), token }
+    }
+
+    pub(crate) async fn open(&self) -> Result<Connection> {
+        #[cfg(unix)]
+        if let Some(path) = self.socket.as_deref().filter(|p| p.exists()) {
+            match tokio::net::UnixStream::connect(path).await {
+                Ok(stream) => {
+                    debug!("Connected via {}", path.display());
+                    return Ok(Connection { stream: Box::new(stream), host: "localhost".to_string(), token: None });
+                }
+                Err(e) => debug!("Daemon socket {} unavailable: {}", path.display(), e),
+            }
+        }
+
+        let addr = self.addr.as_deref().ok_or_else(|| anyhow!("Daemon socket not found and no TCP address given"))?;
+        let stream = tokio::net::TcpStream::connect(addr)
+            .await
+            .with_context(|| format!("Daemon not reachable at {} (is it running?)", addr))?;
+        stream.set_nodelay(true)?;
+        Ok(Connection { stream: Box::new(stream), host: addr.to_string(), token: self.token.clone() })
+    }
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Self::local()
+    }
+}
+
+/// The app's API token from the daemon's token file (written by the daemon on
+/// first start). Read again for every `Endpoint::local`, so a freshly started
+/// daemon's token is picked up.
+pub fn app_token() -> Option<String> {
+    let path = directories::ProjectDirs::from("com", "coredeck", "CoreDeck")?
+        .config_dir()
+        .join(TOKENS_FILE_NAME);
+    let content = std::fs::read_to_string(path).ok()?;
+    let file: TokenFile = toml::from_str(&content).ok()?;
+    file.find(APP_TOKEN_NAME).map(|t| t.token.clone())
+}
+
+/// Byte stream to the daemon (Unix socket or TCP)
+pub(crate) trait DaemonStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> DaemonStream for T {}
+
+/// An open stream and how to address the daemon on it
+pub(crate) struct Connection {
+    pub stream: Box<dyn DaemonStream>,
+    /// `Host` for requests: the TCP address, or "localhost" on the socket
+    pub host: String,
+    /// Token to present (`None` on the socket)
+    pub token: Option<String>,
+}
@@ -0,0 +1,30 @@
+//! Async client for the CoreDeck daemon
+//!
+//! `WsClient` holds a WebSocket connection: commands are answered through
+//! the seq-numbered request/response layer, and device events arrive on the
+//! `Events` channel returned by `connect`. `RestClient` covers the HTTP API
+//! for one-shot tools that don't need events. Both reach the daemon through
+//! an `Endpoint`, preferring its Unix socket.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use coredeck_client::{Endpoint, WsClient};
+//! use coredeck_client::protocol::{DeviceMode, WsClientRole};
+//!
+//! let (client, mut events) = WsClient::connect(&Endpoint::local(), WsClientRole::Controller, "my-tool").await?;
+//! client.set_mode(DeviceMode::Plan).await?;
+//! while let Some(event) = events.recv().await {
+//!     println!("{:?}", event);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod endpoint;
+mod rest;
+mod ws;
+
+pub use coredeck_protocol as protocol;
+pub use endpoint::{app_token, Endpoint};
+pub use rest::{RestClient, RestError};
+pub use ws::{Events, WsClient};
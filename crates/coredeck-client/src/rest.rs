@@ -0,0 +1,236 @@
+//! REST client — one HTTP/1.0 exchange per call
+//!
+//! Mutating endpoints answer 409 while a WebSocket controller (normally the
+//! app) is connected; use `WsClient` to share the deck with it. The display
+//! endpoints are the exception: `update_display` adds a layer composited with
+//! the controller's content instead.
+
+use anyhow::{anyhow, Context, Result};
+use coredeck_protocol::{
+    AlertRecord, AlertRequest, ApiError, BrightnessRequest, ClearAlertRequest, ClearDisplayRequest, DaemonStatus,
+    DisplayUpdateRequest, FieldAdjustments, FieldIssue, SetModeRequest, SetSoftKeyRequest, SoftKeyAssignment,
+    ValidationError,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::Endpoint;
+
+/// How long to wait for the daemon to answer one request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The daemon answered with an error status
+#[derive(Debug)]
+pub struct RestError {
+    pub status: u16,
+    /// `ApiError`/`ValidationError` message, or the raw body
+    pub message: String,
+}
+
+impl std::fmt::Display for RestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl std::error::Error for RestError {}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+/// Client for the daemon's HTTP API. `device` arguments select a deck by
+/// serial; `None` means the first connected deck.
+#[derive(Debug, Clone, Default)]
+pub struct RestClient {
+    endpoint: Endpoint,
+}
+
+impl RestClient {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self { endpoint }
+    }
+
+    /// GET /api/status
+    pub async fn status(&self) -> Result<DaemonStatus> {
+        self.get("/api/status").await
+    }
+
+    /// GET /api/alerts — alert history, newest first
+    pub async fn alerts(&self) -> Result<Vec<AlertRecord>> {
+        self.get("/api/alerts").await
+    }
+
+    /// GET /api/version — firmware version
+    pub async fn version(&self, device: Option<&str>) -> Result<String> {
+        let response: VersionResponse = self.get(&format!("/api/version{}", device_query(device))).await?;
+        Ok(response.version)
+    }
+
+    /// POST /api/display; returns the fields the daemon shortened
+    pub async fn update_display(&self, req: &DisplayUpdateRequest) -> Result<Vec<FieldIssue>> {
+        self.send("POST", "/api/display", req).await.map(adjustments)
+    }
+
+    /// POST /api/display/clear
+    pub async fn clear_display(&self, req: &ClearDisplayRequest) -> Result<()> {
+        self.send("POST", "/api/display/clear", req).await.map(drop)
+    }
+
+    /// POST /api/alert; returns the fields the daemon shortened
+    pub async fn alert(&self, req: &AlertRequest) -> Result<Vec<FieldIssue>> {
+        self.send("POST", "/api/alert", req).await.map(adjustments)
+    }
+
+    /// POST /api/alert/clear
+    pub async fn clear_alert(&self, req: &ClearAlertRequest) -> Result<()> {
+        self.send("POST", "/api/alert/clear", req).await.map(drop)
+    }
+
+    /// POST /api/brightness
+    pub async fn set_brightness(&self, req: &BrightnessRequest) -> Result<()> {
+        self.send("POST", "/api/brightness", req).await.map(drop)
+    }
+
+    /// POST /api/mode
+    pub async fn set_mode(&self, req: &SetModeRequest) -> Result<()> {
+        self.send("POST", "/api/mode", req).await.map(drop)
+    }
+
+    /// GET /api/softkeys
+    pub async fn soft_keys(&self, device: Option<&str>) -> Result<Vec<SoftKeyAssignment>> {
+        self.get(&format!("/api/softkeys{}", device_query(device))).await
+    }
+
+    /// PUT /api/softkeys/{index}; returns the fields the daemon shortened
+    pub async fn set_soft_key(&self, index: u8, req: &SetSoftKeyRequest) -> Result<Vec<FieldIssue>> {
+        self.send("PUT", &format!("/api/softkeys/{}", index), req).await.map(adjustments)
+    }
+
+    /// POST /api/softkeys/reset; returns the new assignments
+    pub async fn reset_soft_keys(&self, device: Option<&str>) -> Result<Vec<SoftKeyAssignment>> {
+        let body = self.request("POST", &format!("/api/softkeys/reset{}", device_query(device)), None).await?;
+        serde_json::from_str(&body).context("Unexpected response from POST /api/softkeys/reset")
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let body = self.request("GET", path, None).await?;
+        serde_json::from_str(&body).with_context(|| format!("Unexpected response from GET {}", path))
+    }
+
+    async fn send(&self, method: &str, path: &str, body: &impl Serialize) -> Result<String> {
+        self.request(method, path, Some(serde_json::to_string(body)?)).await
+    }
+
+    /// One HTTP/1.0 exchange; the daemon closes the connection after answering
+    async fn request(&self, method: &str, path: &str, body: Option<String>) -> Result<String> {
+        let exchange = async {
+            let mut connection = self.endpoint.open().await?;
+            let mut request = format!("{} {} HTTP/1.0\r\nHost: {}\r\n", method, path, connection.host);
+            if let Some(token) = &connection.token {
+                request.push_str(&format!("Authorization: Bearer {}\r\n", token));
+            }
+            let body = body.unwrap_or_default();
+            if !body.is_empty() {
+                request.push_str(&format!("Content-Type: application/json\r\nContent-Length: {}\r\n", body.len()));
+            }
+            request.push_str("\r\n");
+            request.push_str(&body);
+            connection.stream.write_all(request.as_bytes()).await?;
+
+            let mut response = Vec::new();
+            connection.stream.read_to_end(&mut response).await?;
+            Ok::<_, anyhow::Error>(response)
+        };
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, exchange)
+            .await
+            .map_err(|_| anyhow!("{} {} timed out", method, path))??;
+
+        let (status, body) = parse_response(&response)?;
+        if (200..300).contains(&status) {
+            return Ok(body);
+        }
+        let message = serde_json::from_str::<ValidationError>(&body)
+            .map(|e| e.to_string())
+            .or_else(|_| serde_json::from_str::<ApiError>(&body).map(|e| e.error))
+            .unwrap_or(body);
+        Err(anyhow::Error::new(RestError { status, message }).context(format!("{} {} failed", method, path)))
+    }
+}
+
+/// Fields listed in a `FieldAdjustments` body (none for an empty 200)
+fn adjustments(body: String) -> Vec<FieldIssue> {
+    serde_json::from_str::<FieldAdjustments>(&body).map(|a| a.adjusted).unwrap_or_default()
+}
+
+/// Status code and body of a raw HTTP response
+fn parse_response(response: &[u8]) -> Result<(u16, String)> {
+    let response = String::from_utf8_lossy(response);
+    let (head, body) = response.split_once("\r\n\r\n").ok_or_else(|| anyhow!("Malformed response from daemon"))?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow!("Malformed status line from daemon"))?;
+    Ok((status, body.to_string()))
+}
+
+/// `?device=<serial>` for read endpoints. Serials may be OS device paths, so
+/// they are percent-encoded.
+fn device_query(device: Option<&str>) -> String {
+    let Some(serial) = device else {
+        return String::new();
+    };
+    let encoded: String = serial
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("?device={}", encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let raw = b"HTTP/1.1 409 Conflict\r\ncontent-type: application/json\r\n\r\n{\"error\":\"locked\"}";
+        assert_eq!(parse_response(raw).unwrap(), (409, "{\"error\":\"locked\"}".to_string()));
+        assert!(parse_response(b"garbage").is_err());
+    }
+
+    #[test]
+    fn test_device_query() {
+        assert_eq!(device_query(None), "");
+        assert_eq!(device_query(Some("CD-0001")), "?device=CD-0001");
+        assert_eq!(device_query(Some("/dev/hidraw3")), "?device=%2Fdev%2Fhidraw3");
+    }
+
+    #[tokio::test]
+    async fn test_error_status() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = RestClient::new(Endpoint::tcp(&listener.local_addr().unwrap().to_string(), Some("t0k".into())));
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 1024];
+            let n = stream.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..n]).into_owned();
+            assert!(request.starts_with("POST /api/mode HTTP/1.0\r\n"), "{}", request);
+            assert!(request.contains("Authorization: Bearer t0k\r\n"), "{}", request);
+            let body = r#"{"error":"device locked by WebSocket client"}"#;
+            let response = format!("HTTP/1.0 409 Conflict\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let req = SetModeRequest { mode: coredeck_protocol::DeviceMode::Plan, device: None };
+        let err = client.set_mode(&req).await.unwrap_err();
+        assert_eq!(format!("{:#}", err), "POST /api/mode failed: device locked by WebSocket client (409)");
+        assert_eq!(err.downcast_ref::<RestError>().map(|e| e.status), Some(409));
+    }
+}
@@ -0,0 +1,426 @@
+//! WebSocket client — seq-numbered commands and the device event stream
+//!
+//! Every command carries a non-zero sequence number that the daemon echoes in
+//! its response; `Pending` routes responses back to the caller awaiting them.
+//! Frames with seq 0 are events and go to the `Events` channel.
+
+use anyhow::{anyhow, bail, Result};
+use coredeck_protocol::{
+    decode_ws_frame, AlertRequest, BrightnessRequest, ClearAlertRequest, ClientHello, DaemonHello,
+    DeviceCapabilities, DeviceMode, DisplayUpdate, SetModeRequest, SoftKeyAction, SoftKeyConfig, WsClientRole,
+    WsCommand, WsCommandResult, WsCommandTag, WsEvent, WsEventTag, WsResponseTag, PROTOCOL_VERSION,
+    TOKEN_QUERY_PARAM,
+};
+use futures_util::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+
+use crate::Endpoint;
+
+/// How long to wait for the daemon to answer a command
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Device events in the order the daemon sent them. `recv` returns `None`
+/// once the connection is gone.
+pub type Events = mpsc::UnboundedReceiver<WsEvent>;
+
+/// A response frame: (tag, payload)
+type Response = (u8, Vec<u8>);
+
+/// Commands waiting for their response, keyed by sequence number
+#[derive(Default)]
+struct Pending {
+    /// Sequence counter (wraps, skips 0)
+    seq: AtomicU16,
+    waiting: Mutex<HashMap<u16, oneshot::Sender<Response>>>,
+}
+
+impl Pending {
+    /// Allocate the next non-zero sequence number.
+    fn next_seq(&self) -> u16 {
+        loop {
+            let s = self.seq.fetch_add(1, Ordering::Relaxed);
+            if s != 0 {
+                return s;
+            }
+        }
+    }
+
+    /// Allocate a sequence number whose response is delivered to the receiver
+    fn register(&self) -> (u16, oneshot::Receiver<Response>) {
+        let seq = self.next_seq();
+        let (tx, rx) = oneshot::channel();
+        self.waiting.lock().insert(seq, tx);
+        (seq, rx)
+    }
+
+    /// Hand a response to the command waiting for it. False if none is.
+    fn resolve(&self, seq: u16, response: Response) -> bool {
+        match self.waiting.lock().remove(&seq) {
+            Some(tx) => tx.send(response).is_ok(),
+            None => false,
+        }
+    }
+
+    fn cancel(&self, seq: u16) {
+        self.waiting.lock().remove(&seq);
+    }
+
+    /// Fail every waiting command (the connection is gone)
+    fn close(&self) {
+        self.waiting.lock().clear();
+    }
+}
+
+/// State shared between the client handles and the connection's reader task
+struct Shared {
+    pending: Pending,
+    connected: AtomicBool,
+    role: Mutex<WsClientRole>,
+    /// Limits of the connected deck (defaults until it reports them)
+    capabilities: Mutex<DeviceCapabilities>,
+}
+
+/// Connection to the daemon's WebSocket API.
+///
+/// Clones share the connection, which closes when the last one is dropped.
+/// There is no reconnect: once `is_connected` turns false (and `Events`
+/// ends), connect again.
+#[derive(Clone)]
+pub struct WsClient {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    shared: Arc<Shared>,
+    hello: Arc<DaemonHello>,
+}
+
+impl WsClient {
+    /// Connect and exchange `Hello`, announcing the client as `name`.
+    ///
+    /// The daemon refuses a `Controller` connection while another client has
+    /// control; connect as an `Observer` and `request_control` to wait for it.
+    pub async fn connect(endpoint: &Endpoint, role: WsClientRole, name: &str) -> Result<(Self, Events)> {
+        let connection = endpoint.open().await?;
+        let role_param = match role {
+            WsClientRole::Controller => "controller",
+            WsClientRole::Observer => "observer",
+        };
+        let mut url = format!("ws://{}/ws?role={}", connection.host, role_param);
+        if let Some(token) = &connection.token {
+            url.push_str(&format!("&{}={}", TOKEN_QUERY_PARAM, token));
+        }
+        let (ws_stream, _) = tokio_tungstenite::client_async(url, connection.stream).await?;
+        let (mut ws_sink, mut ws_rx) = ws_stream.split();
+
+        let shared = Arc::new(Shared {
+            pending: Pending::default(),
+            connected: AtomicBool::new(true),
+            role: Mutex::new(role),
+            capabilities: Mutex::new(DeviceCapabilities::default()),
+        });
+
+        // Announce our protocol version before anything else is sent
+        let (hello_seq, hello_rx) = shared.pending.register();
+        let hello = ClientHello { protocol_version: PROTOCOL_VERSION, client: name.to_string() };
+        ws_sink.send(Message::Binary(WsCommand::Hello(hello).encode(hello_seq).into())).await?;
+
+        // Forward outgoing frames until every client handle is dropped
+        let (tx, mut outgoing) = mpsc::unbounded_channel::<Vec<u8>>();
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = outgoing.recv().await {
+                if ws_sink.send(Message::Binary(frame.into())).await.is_err() {
+                    return;
+                }
+            }
+            let _ = ws_sink.send(Message::Close(None)).await;
+        });
+
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let reader_shared = Arc::clone(&shared);
+        tokio::spawn(async move {
+            loop {
+                match ws_rx.next().await {
+                    Some(Ok(Message::Binary(data))) => handle_frame(&reader_shared, &data, &events_tx),
+                    Some(Ok(Message::Close(_))) => {
+                        info!("Daemon closed WS connection");
+                        break;
+                    }
+                    Some(Ok(_)) => {} // Ignore text/ping/pong
+                    Some(Err(e)) => {
+                        warn!("WS read error: {}", e);
+                        break;
+                    }
+                    None => break,
+                }
+            }
+            reader_shared.connected.store(false, Ordering::Relaxed);
+            reader_shared.pending.close();
+            writer.abort();
+        });
+
+        let hello = match tokio::time::timeout(REQUEST_TIMEOUT, hello_rx).await {
+            Ok(Ok((tag, payload))) => match WsCommandResult::decode(WsCommandTag::Hello, tag, &payload) {
+                Ok(WsCommandResult::Hello(hello)) => hello,
+                // Daemons that predate the handshake answer "unknown command"
+                Err(e) if tag == WsResponseTag::CommandError as u8 => {
+                    debug!("Daemon predates Hello: {}", e);
+                    DaemonHello::legacy()
+                }
+                other => bail!("Invalid answer to Hello: {:?}", other),
+            },
+            Ok(Err(_)) => return Err(closed()),
+            Err(_) => bail!("Daemon did not answer Hello"),
+        };
+        if let Some(capabilities) = hello.devices.iter().find_map(|d| d.capabilities.clone()) {
+            *shared.capabilities.lock() = capabilities;
+        }
+
+        Ok((Self { tx, shared, hello: Arc::new(hello) }, events))
+    }
+
+    /// The daemon's answer to `Hello` (`DaemonHello::legacy` for old daemons)
+    pub fn hello(&self) -> &DaemonHello {
+        &self.hello
+    }
+
+    /// Whether the daemon accepts `tag`
+    pub fn supports(&self, tag: WsCommandTag) -> bool {
+        self.hello.supports(tag)
+    }
+
+    /// Whether the connection is still open
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::Relaxed)
+    }
+
+    /// Current role, as last reported by the daemon
+    pub fn role(&self) -> WsClientRole {
+        *self.shared.role.lock()
+    }
+
+    /// Limits of the connected deck
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        self.shared.capabilities.lock().clone()
+    }
+
+    /// Send a command and wait for the daemon's answer
+    pub async fn call(&self, command: WsCommand) -> Result<WsCommandResult> {
+        let tag = command.tag();
+        let (seq, rx) = self.shared.pending.register();
+        if let Err(e) = self.transmit(command, seq) {
+            self.shared.pending.cancel(seq);
+            return Err(e);
+        }
+        let (reply, payload) = match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => return Err(closed()),
+            Err(_) => {
+                self.shared.pending.cancel(seq);
+                bail!("No answer to {:?} within {}s", tag, REQUEST_TIMEOUT.as_secs());
+            }
+        };
+        WsCommandResult::decode(tag, reply, &payload).map_err(|e| anyhow!("{:?} failed: {}", tag, e))
+    }
+
+    /// Send a command without waiting for the answer. Errors the daemon
+    /// reports for it are only logged.
+    pub fn send(&self, command: WsCommand) -> Result<()> {
+        self.transmit(command, self.shared.pending.next_seq())
+    }
+
+    fn transmit(&self, mut command: WsCommand, seq: u16) -> Result<()> {
+        let tag = command.tag();
+        if !self.supports(tag) {
+            bail!("{:?} is not supported by this daemon version", tag);
+        }
+        {
+            let capabilities = self.shared.capabilities.lock();
+            match &mut command {
+                WsCommand::UpdateDisplay(update) => capabilities.clamp_display(update),
+                WsCommand::Alert(alert) => capabilities.clamp_alert(alert),
+                _ => {}
+            }
+        }
+        self.tx.send(command.encode(seq)).map_err(|_| closed())
+    }
+
+    async fn command(&self, command: WsCommand) -> Result<()> {
+        self.call(command).await.map(|_| ())
+    }
+
+    // ── Typed commands ───────────────────────────────────────────────
+
+    /// Show `update` on every deck (clamped to the deck's limits)
+    pub async fn update_display(&self, update: DisplayUpdate) -> Result<()> {
+        self.command(WsCommand::UpdateDisplay(update)).await
+    }
+
+    /// Show an alert overlay (clamped to the deck's limits)
+    pub async fn alert(&self, alert: AlertRequest) -> Result<()> {
+        self.command(WsCommand::Alert(alert)).await
+    }
+
+    /// Clear the alert overlay for a tab
+    pub async fn clear_alert(&self, tab: usize) -> Result<()> {
+        self.command(WsCommand::ClearAlert(ClearAlertRequest { tab, device: None })).await
+    }
+
+    pub async fn set_mode(&self, mode: DeviceMode) -> Result<()> {
+        self.command(WsCommand::SetMode(SetModeRequest { mode, device: None })).await
+    }
+
+    /// Set the display brightness, persisting it when `save` is set
+    pub async fn set_brightness(&self, level: u8, save: bool) -> Result<()> {
+        self.command(WsCommand::SetBrightness(BrightnessRequest { level, save, device: None })).await
+    }
+
+    pub async fn soft_key(&self, index: u8) -> Result<SoftKeyConfig> {
+        match self.call(WsCommand::GetSoftKey { index, device: None }).await? {
+            WsCommandResult::SoftKey(config) => Ok(config),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Assign a soft key, persisting it when `save` is set
+    pub async fn set_soft_key(&self, index: u8, action: &SoftKeyAction, save: bool) -> Result<()> {
+        let (key_type, data) = action.to_wire();
+        self.command(WsCommand::SetSoftKey { index, key_type, data, save, device: None }).await
+    }
+
+    /// Reset every soft key to the firmware default; returns the new assignments
+    pub async fn reset_soft_keys(&self) -> Result<Vec<SoftKeyConfig>> {
+        match self.call(WsCommand::ResetSoftKeys(None)).await? {
+            WsCommandResult::SoftKeys(configs) => Ok(configs),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Firmware version string
+    pub async fn version(&self) -> Result<String> {
+        match self.call(WsCommand::GetVersion).await? {
+            WsCommandResult::Version(version) => Ok(version),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Ask for control. Granted at once if free, otherwise when the controller
+    /// yields or disconnects (announced by a `RoleChanged` event).
+    pub async fn request_control(&self) -> Result<()> {
+        self.command(WsCommand::RequestControl).await
+    }
+
+    /// Hand control to the longest-waiting observer
+    pub async fn yield_control(&self) -> Result<()> {
+        self.command(WsCommand::YieldControl).await
+    }
+}
+
+fn closed() -> anyhow::Error {
+    anyhow!("Daemon connection closed")
+}
+
+fn unexpected(result: WsCommandResult) -> anyhow::Error {
+    anyhow!("Unexpected answer from daemon: {:?}", result)
+}
+
+/// Process a single binary frame from the daemon.
+fn handle_frame(shared: &Shared, data: &[u8], events: &mpsc::UnboundedSender<WsEvent>) {
+    let Some((tag, seq, payload)) = decode_ws_frame(data) else {
+        return;
+    };
+
+    // Responses (seq > 0): route to the waiting command
+    if seq != 0 {
+        if !shared.pending.resolve(seq, (tag, payload.to_vec())) && tag == WsResponseTag::CommandError as u8 {
+            warn!("Daemon refused command: {}", String::from_utf8_lossy(payload));
+        }
+        return;
+    }
+
+    let Some(event) = WsEventTag::from_byte(tag).and_then(|tag| WsEvent::decode(tag, payload)) else {
+        debug!("Ignoring unknown or malformed event 0x{:02X}", tag);
+        return;
+    };
+    match &event {
+        // Older firmware and daemons don't report limits; assume the original ones
+        WsEvent::DeviceConnected(info) => {
+            *shared.capabilities.lock() = info.capabilities.clone().unwrap_or_default();
+        }
+        WsEvent::RoleChanged { role } => *shared.role.lock() = *role,
+        _ => {}
+    }
+    let _ = events.send(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coredeck_protocol::{encode_ws_frame, WsCommandTag as Tag};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_pending_routes_responses() {
+        let pending = Pending::default();
+        let (first, mut first_rx) = pending.register();
+        let (second, _second_rx) = pending.register();
+        assert_eq!((first, second), (1, 2));
+
+        assert!(pending.resolve(first, (0x87, vec![])));
+        assert_eq!(first_rx.try_recv().unwrap(), (0x87, vec![]));
+        assert!(!pending.resolve(first, (0x87, vec![])), "resolved twice");
+
+        pending.seq.store(u16::MAX, Ordering::Relaxed);
+        assert_eq!(pending.next_seq(), u16::MAX);
+        assert_eq!(pending.next_seq(), 1, "seq 0 is reserved for events");
+    }
+
+    /// A daemon that knows `Hello`, `GetVersion` and `Ping`, and refuses the latter
+    async fn fake_daemon(listener: TcpListener) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        while let Some(Ok(Message::Binary(frame))) = ws.next().await {
+            let (tag, seq, _) = decode_ws_frame(&frame).unwrap();
+            let reply = match Tag::from_byte(tag).unwrap() {
+                Tag::Hello => WsCommandResult::Hello(DaemonHello {
+                    protocol_version: PROTOCOL_VERSION,
+                    daemon_version: "0.9.0".to_string(),
+                    commands: vec![Tag::Hello as u8, Tag::GetVersion as u8, Tag::Ping as u8],
+                    devices: Vec::new(),
+                })
+                .encode(seq),
+                Tag::GetVersion => {
+                    let event = WsEvent::KeyEvent { keycode: 0x29, serial: None };
+                    ws.send(Message::Binary(event.encode().into())).await.unwrap();
+                    WsCommandResult::Version("1.2.0".to_string()).encode(seq)
+                }
+                _ => encode_ws_frame(WsResponseTag::CommandError as u8, seq, b"unknown command"),
+            };
+            ws.send(Message::Binary(reply.into())).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commands_and_events() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = Endpoint::tcp(&listener.local_addr().unwrap().to_string(), None);
+        tokio::spawn(fake_daemon(listener));
+
+        let (client, mut events) = WsClient::connect(&endpoint, WsClientRole::Observer, "test").await.unwrap();
+        assert_eq!(client.hello().daemon_version, "0.9.0");
+        assert_eq!(client.version().await.unwrap(), "1.2.0");
+        assert!(matches!(events.recv().await, Some(WsEvent::KeyEvent { keycode: 0x29, serial: None })));
+
+        let err = client.call(WsCommand::Ping).await.unwrap_err();
+        assert_eq!(err.to_string(), "Ping failed: unknown command");
+        let err = client.set_mode(DeviceMode::Plan).await.unwrap_err();
+        assert_eq!(err.to_string(), "SetMode is not supported by this daemon version");
+
+        drop(client);
+        assert!(events.recv().await.is_none());
+    }
+}
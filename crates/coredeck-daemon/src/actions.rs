@@ -0,0 +1,240 @@
+//! Headless key actions — the deck as a plain macropad when no app is connected
+//!
+//! While no WebSocket controller is connected, key presses and mode/YOLO
+//! changes are matched against `[actions]` in `daemon.toml`, and each match
+//! runs a shell command or POSTs the event to a webhook. As long as any action
+//! is configured the daemon keeps the decks open without an app (otherwise
+//! their keys would go to the system keyboard); with none, decks are only
+//! open while a controller is connected.
+
+use anyhow::{bail, Context, Result};
+use coredeck_protocol::DeviceMode;
+use std::process::Stdio;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::DaemonState;
+use crate::config::{ActionTarget, KeyAction, StateAction};
+use crate::state::DaemonEvent;
+use crate::webhook::{self, WebhookUrl};
+use crate::ws;
+
+/// Headless actions allowed to run at once; presses beyond that are skipped
+pub const MAX_RUNNING_ACTIONS: usize = 4;
+
+/// Whether decks stay open while no controller is connected
+pub fn keep_decks_open(state: &DaemonState) -> bool {
+    state.config.read().actions.is_enabled()
+}
+
+/// Open or release the decks to match the configured actions. Does nothing
+/// while a controller is connected, since it decides then.
+pub async fn update_headless(state: &Arc<DaemonState>) {
+    if state.ws_clients.lock().await.has_controller() {
+        return;
+    }
+    if keep_decks_open(state) {
+        let opened = {
+            let hid = state.hid.lock().await;
+            hid.is_device_available().then(|| hid.open_device(None))
+        };
+        if let Some(opened) = opened {
+            info!("Headless actions configured — opening decks");
+            if let Err(e) = opened.await {
+                warn!("Failed to open HID device: {}", e);
+            }
+        }
+    } else if state.hid.lock().await.is_connected() {
+        info!("No headless actions — releasing decks");
+        ws::release_decks(state).await;
+    }
+}
+
+/// Run the actions matching a deck event, unless a controller is connected.
+/// `before` is the deck's mode and YOLO state prior to a `DeviceStateChanged`.
+pub async fn dispatch(state: &Arc<DaemonState>, event: &DaemonEvent, before: Option<(DeviceMode, bool)>) {
+    if state.ws_clients.lock().await.has_controller() {
+        return;
+    }
+    let targets: Vec<ActionTarget> = {
+        let config = state.config.read();
+        match (event, before) {
+            (DaemonEvent::HidKeyEvent { serial, keycode }, _) => config
+                .actions
+                .key
+                .iter()
+                .filter(|action| key_matches(action, serial, *keycode))
+                .map(|action| action.target.clone())
+                .collect(),
+            (DaemonEvent::DeviceStateChanged { serial, mode, yolo }, Some(before)) => config
+                .actions
+                .state
+                .iter()
+                .filter(|action| state_entered(action, serial, before, (*mode, *yolo)))
+                .map(|action| action.target.clone())
+                .collect(),
+            _ => return,
+        }
+    };
+
+    for target in targets {
+        // Skipped rather than queued, so mashing a key can't pile up processes
+        let Ok(slot) = Arc::clone(&state.action_slots).try_acquire_owned() else {
+            warn!("{} headless actions still running — skipping this one", MAX_RUNNING_ACTIONS);
+            continue;
+        };
+        let event = event.clone();
+        tokio::spawn(async move {
+            if let Err(e) = run(&target, &event).await {
+                warn!("Headless action failed: {:#}", e);
+            }
+            drop(slot);
+        });
+    }
+}
+
+fn key_matches(action: &KeyAction, serial: &str, keycode: u16) -> bool {
+    action.key.to_keycode() == keycode && action.device.as_deref().map_or(true, |device| device == serial)
+}
+
+/// Whether a state change enters the state `action` waits for. An action
+/// with neither `mode` nor `yolo` runs on every change.
+fn state_entered(action: &StateAction, serial: &str, before: (DeviceMode, bool), after: (DeviceMode, bool)) -> bool {
+    let matches = |(mode, yolo): (DeviceMode, bool)| {
+        action.mode.map_or(true, |m| m == mode) && action.yolo.map_or(true, |y| y == yolo)
+    };
+    let unconstrained = action.mode.is_none() && action.yolo.is_none();
+    action.device.as_deref().map_or(true, |device| device == serial)
+        && matches(after)
+        && (!matches(before) || unconstrained && before != after)
+}
+
+async fn run(target: &ActionTarget, event: &DaemonEvent) -> Result<()> {
+    match (&target.command, &target.webhook) {
+        (Some(command), _) => {
+            info!("Running action: {}", command);
+            run_command(command, event).await
+        }
+        (None, Some(url)) => {
+            info!("Posting action to {}", url);
+            let body = serde_json::to_string(event)?;
+            let status = webhook::post_json(&WebhookUrl::parse(url)?, &body, &[]).await?;
+            if !(200..300).contains(&status) {
+                bail!("{} answered {}", url, status);
+            }
+            Ok(())
+        }
+        (None, None) => Ok(()),
+    }
+}
+
+/// Run `command` through the shell; its output goes to the daemon's log
+async fn run_command(command: &str, event: &DaemonEvent) -> Result<()> {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let status = tokio::process::Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .envs(event_env(event))
+        .stdin(Stdio::null())
+        .status()
+        .await
+        .with_context(|| format!("Failed to run `{}`", command))?;
+    if !status.success() {
+        bail!("`{}` exited with {}", command, status);
+    }
+    Ok(())
+}
+
+/// `COREDECK_*` variables describing the event to a command
+fn event_env(event: &DaemonEvent) -> Vec<(&'static str, String)> {
+    match event {
+        DaemonEvent::HidKeyEvent { serial, keycode } => vec![
+            ("COREDECK_EVENT", "key".to_string()),
+            ("COREDECK_SERIAL", serial.clone()),
+            ("COREDECK_KEYCODE", format!("0x{:04x}", keycode)),
+        ],
+        DaemonEvent::DeviceStateChanged { serial, mode, yolo } => vec![
+            ("COREDECK_EVENT", "state".to_string()),
+            ("COREDECK_SERIAL", serial.clone()),
+            ("COREDECK_MODE", mode.to_string()),
+            ("COREDECK_YOLO", yolo.to_string()),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coredeck_protocol::KeyStroke;
+
+    fn command(command: &str) -> ActionTarget {
+        ActionTarget { command: Some(command.to_string()), webhook: None }
+    }
+
+    #[test]
+    fn test_key_matches() {
+        let ctrl_c = KeyStroke { usage: 0x06, ctrl: true, ..Default::default() };
+        let mut action = KeyAction { key: ctrl_c, device: None, target: command("true") };
+        assert!(key_matches(&action, "A1", 0x0106));
+        assert!(!key_matches(&action, "A1", 0x0006));
+
+        action.device = Some("B2".to_string());
+        assert!(!key_matches(&action, "A1", 0x0106));
+        assert!(key_matches(&action, "B2", 0x0106));
+    }
+
+    #[test]
+    fn test_state_entered() {
+        let plan = StateAction { mode: Some(DeviceMode::Plan), yolo: None, device: None, target: command("true") };
+        assert!(state_entered(&plan, "A1", (DeviceMode::Default, false), (DeviceMode::Plan, false)));
+        // Already in plan mode: toggling YOLO doesn't re-run it
+        assert!(!state_entered(&plan, "A1", (DeviceMode::Plan, false), (DeviceMode::Plan, true)));
+        assert!(!state_entered(&plan, "A1", (DeviceMode::Plan, false), (DeviceMode::Accept, false)));
+
+        let yolo_on = StateAction { mode: None, yolo: Some(true), ..plan.clone() };
+        assert!(state_entered(&yolo_on, "A1", (DeviceMode::Plan, false), (DeviceMode::Plan, true)));
+        assert!(!state_entered(&yolo_on, "A1", (DeviceMode::Plan, true), (DeviceMode::Accept, true)));
+
+        let any = StateAction { mode: None, yolo: None, ..plan };
+        assert!(state_entered(&any, "A1", (DeviceMode::Plan, true), (DeviceMode::Accept, true)));
+        assert!(!state_entered(&any, "A1", (DeviceMode::Plan, true), (DeviceMode::Plan, true)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_sees_event() {
+        let event = DaemonEvent::HidKeyEvent { serial: "A1".to_string(), keycode: 0x0068 };
+        run_command(r#"test "$COREDECK_EVENT $COREDECK_SERIAL $COREDECK_KEYCODE" = "key A1 0x0068""#, &event)
+            .await
+            .unwrap();
+        let err = run_command("exit 3", &event).await.unwrap_err();
+        assert!(err.to_string().contains("exited with"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_running_actions_capped() {
+        let state = crate::tests::test_state();
+        let log = std::env::temp_dir().join(format!("coredeck-actions-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let run = command(&format!("echo run >> '{}'; sleep 1", log.display()));
+        let key = KeyStroke { usage: 0x68, ..Default::default() };
+        state.config.write().actions.key = vec![KeyAction { key, device: None, target: run }];
+
+        let press = DaemonEvent::HidKeyEvent { serial: "A1".to_string(), keycode: 0x0068 };
+        for _ in 0..MAX_RUNNING_ACTIONS + 2 {
+            dispatch(&state, &press, None).await;
+        }
+        assert_eq!(state.action_slots.available_permits(), 0);
+
+        // Every slot is back once the commands exit; the extra presses never ran
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while state.action_slots.available_permits() < MAX_RUNNING_ACTIONS {
+            assert!(std::time::Instant::now() < deadline, "actions still running");
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), MAX_RUNNING_ACTIONS);
+        let _ = std::fs::remove_file(&log);
+    }
+}
@@ -0,0 +1,325 @@
+//! Alert history — a ring buffer of every alert raised and how it was cleared
+//!
+//! Alerts are fire-and-forget on the device, so the daemon keeps its own
+//! record. The firmware does not report dismissals; pressing the deck's
+//! Claude button (F20, the key the app answers alerts with) while the alert's
+//! tab is the active one counts as acknowledgement on the hardware. Other keys
+//! and soft-key strings don't. Alerts sent to one deck are tracked apart from
+//! alerts on the same tab of another deck.
+
+use coredeck_protocol::{AlertRecord, AlertRequest, AlertSource, WsEventTag, encode_ws_frame};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::DaemonState;
+use crate::webhook::{self, AlertEvent};
+
+/// Alerts kept in memory (and in the persisted log)
+pub const ALERT_HISTORY_CAPACITY: usize = 200;
+
+/// Keycode that acknowledges an alert: F20, the deck's Claude button
+pub const ACK_KEYCODE: u16 = 0x006F;
+
+/// Ring buffer of alert records, oldest first
+pub struct AlertLog {
+    records: VecDeque<AlertRecord>,
+    next_id: u64,
+    /// JSON file the history is mirrored to (`--alert-log`)
+    path: Option<PathBuf>,
+    /// Bumped on every change; snapshots carry it so stale ones aren't written
+    generation: u64,
+    /// Generation last written to `path`
+    written: Arc<parking_lot::Mutex<u64>>,
+}
+
+/// A snapshot of the history waiting to be written to `--alert-log`
+pub struct PendingWrite {
+    path: PathBuf,
+    data: Vec<u8>,
+    generation: u64,
+    written: Arc<parking_lot::Mutex<u64>>,
+}
+
+impl PendingWrite {
+    /// Write the snapshot (blocking), unless a newer one already reached the disk
+    pub fn write(self) {
+        let mut written = self.written.lock();
+        if *written >= self.generation {
+            return;
+        }
+        match std::fs::write(&self.path, &self.data) {
+            Ok(()) => *written = self.generation,
+            Err(e) => warn!("Failed to write alert log {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+impl AlertLog {
+    /// Create an empty log, or load the history from `path` if it exists.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let records: VecDeque<AlertRecord> = path
+            .as_ref()
+            .and_then(|p| match std::fs::read(p) {
+                Ok(data) => match serde_json::from_slice(&data) {
+                    Ok(records) => Some(records),
+                    Err(e) => {
+                        warn!("Ignoring unreadable alert log {}: {}", p.display(), e);
+                        None
+                    }
+                },
+                Err(_) => None, // Not created yet
+            })
+            .unwrap_or_default();
+
+        let next_id = records.iter().map(|r| r.id + 1).max().unwrap_or(1);
+        if let Some(p) = &path {
+            info!("Alert log: {} ({} entries)", p.display(), records.len());
+        }
+        Self { records, next_id, path, generation: 0, written: Arc::default() }
+    }
+
+    /// All records, newest first
+    pub fn records(&self) -> impl Iterator<Item = &AlertRecord> {
+        self.records.iter().rev()
+    }
+
+    /// Record a newly shown alert. An alert still open on the same tab of
+    /// the same deck is closed as replaced, since a deck only shows one per tab.
+    /// Returns the new record and the replaced ones.
+    pub fn raise(&mut self, req: &AlertRequest, by: AlertSource, now: u64) -> (AlertRecord, Vec<AlertRecord>) {
+        let replaced = self.close_open(req.tab, req.device.as_deref(), AlertSource::Replaced, now);
+
+        if self.records.len() == ALERT_HISTORY_CAPACITY {
+            self.records.pop_front();
+        }
+        self.records.push_back(AlertRecord {
+            id: self.next_id,
+            tab: req.tab,
+            session: req.session.clone(),
+            text: req.text.clone(),
+            details: req.details.clone(),
+            raised_at: now,
+            raised_by: by,
+            cleared_at: None,
+            cleared_by: None,
+            device: req.device.clone(),
+        });
+        self.next_id += 1;
+        self.generation += 1;
+        (self.records.back().cloned().expect("record just pushed"), replaced)
+    }
+
+    /// Record that the alert on `tab` of `device` (`None` = every deck) was
+    /// cleared. Returns the closed records; empty if no alert was open there.
+    pub fn clear(&mut self, tab: usize, device: Option<&str>, by: AlertSource, now: u64) -> Vec<AlertRecord> {
+        let records = self.close_open(tab, device, by, now);
+        if !records.is_empty() {
+            self.generation += 1;
+        }
+        records
+    }
+
+    /// Close open alerts on `tab` shown on `device`. An alert for every deck
+    /// (`None`) is shown on each of them.
+    fn close_open(&mut self, tab: usize, device: Option<&str>, by: AlertSource, now: u64) -> Vec<AlertRecord> {
+        self.records
+            .iter_mut()
+            .filter(|r| {
+                r.tab == tab
+                    && r.cleared_at.is_none()
+                    && (device.is_none() || r.device.is_none() || r.device.as_deref() == device)
+            })
+            .map(|record| {
+                record.cleared_at = Some(now);
+                record.cleared_by = Some(by);
+                record.clone()
+            })
+            .collect()
+    }
+
+    /// Snapshot of the history for the disk, if it changed since the last one
+    pub fn pending_write(&self) -> Option<PendingWrite> {
+        let path = self.path.clone()?;
+        if *self.written.lock() >= self.generation {
+            return None;
+        }
+        let data = match serde_json::to_vec_pretty(&self.records) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Failed to serialize alert log: {}", e);
+                return None;
+            }
+        };
+        Some(PendingWrite { path, data, generation: self.generation, written: Arc::clone(&self.written) })
+    }
+}
+
+/// Current Unix time in milliseconds
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Mirror the history to disk off the async workers (best effort)
+fn persist(pending: Option<PendingWrite>) {
+    if let Some(pending) = pending {
+        tokio::task::spawn_blocking(move || pending.write());
+    }
+}
+
+/// Record an alert the deck now shows, and tell webhooks about it and the
+/// alert it replaced
+pub async fn raised(state: &DaemonState, req: &AlertRequest, by: AlertSource) {
+    let (record, replaced, pending) = {
+        let mut log = state.alerts.lock().await;
+        let (record, replaced) = log.raise(req, by, now_millis());
+        (record, replaced, log.pending_write())
+    };
+    persist(pending);
+    for alert in replaced {
+        webhook::notify(state, &AlertEvent::AlertCleared { alert });
+    }
+    webhook::notify(state, &AlertEvent::AlertRaised { alert: record });
+}
+
+/// Record that the alert on `tab` of `device` was cleared, and tell webhooks.
+/// Returns the closed records.
+pub async fn cleared(state: &DaemonState, tab: usize, device: Option<&str>, by: AlertSource) -> Vec<AlertRecord> {
+    let (records, pending) = {
+        let mut log = state.alerts.lock().await;
+        let records = log.clear(tab, device, by, now_millis());
+        (records, log.pending_write())
+    };
+    persist(pending);
+    for alert in &records {
+        webhook::notify(state, &AlertEvent::AlertCleared { alert: alert.clone() });
+    }
+    records
+}
+
+/// A key was pressed on deck `serial`: if it is the acknowledge key, clear
+/// the alert on the deck's active tab (if any) and tell every WS client.
+pub async fn acknowledge_on_key(state: &Arc<DaemonState>, serial: &str, keycode: u16) {
+    if keycode != ACK_KEYCODE {
+        return;
+    }
+    let active_tab = match state.display.lock().await.current(serial) {
+        Some(update) => update.active,
+        None => return,
+    };
+
+    let records = cleared(state, active_tab, Some(serial), AlertSource::Key).await;
+
+    for record in records {
+        info!("Alert on tab {} acknowledged on device {}", record.tab, serial);
+        let payload = serde_json::to_vec(&record).unwrap_or_default();
+        let frame = encode_ws_frame(WsEventTag::AlertAcknowledged as u8, 0, &payload);
+        state.ws_clients.lock().await.broadcast(&frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(tab: usize, text: &str) -> AlertRequest {
+        AlertRequest {
+            tab,
+            session: "proj".to_string(),
+            text: text.to_string(),
+            details: None,
+            device: None,
+        }
+    }
+
+    #[test]
+    fn test_raise_and_clear() {
+        let mut log = AlertLog::new(None);
+        log.raise(&alert(1, "Allow edit?"), AlertSource::App, 100);
+
+        let cleared = log.clear(1, None, AlertSource::Key, 200).pop().unwrap();
+        assert_eq!(cleared.text, "Allow edit?");
+        assert_eq!(cleared.cleared_at, Some(200));
+        assert_eq!(cleared.cleared_by, Some(AlertSource::Key));
+
+        // Already cleared — the app's follow-up ClearAlert doesn't overwrite it
+        assert!(log.clear(1, None, AlertSource::App, 300).is_empty());
+        assert_eq!(log.records().next().unwrap().cleared_by, Some(AlertSource::Key));
+    }
+
+    #[test]
+    fn test_new_alert_replaces_open_one() {
+        let mut log = AlertLog::new(None);
+        log.raise(&alert(0, "first"), AlertSource::Rest, 100);
+        log.raise(&alert(0, "second"), AlertSource::Rest, 200);
+
+        let records: Vec<_> = log.records().collect();
+        assert_eq!(records[0].text, "second");
+        assert!(records[0].cleared_at.is_none());
+        assert_eq!(records[1].cleared_by, Some(AlertSource::Replaced));
+        assert!(records[1].id < records[0].id);
+    }
+
+    #[test]
+    fn test_alerts_per_device() {
+        let mut log = AlertLog::new(None);
+        let on = |device: &str, text: &str| AlertRequest {
+            device: Some(device.to_string()),
+            ..alert(0, text)
+        };
+        log.raise(&on("A", "a"), AlertSource::Rest, 100);
+        log.raise(&on("B", "b"), AlertSource::Rest, 100);
+        assert!(log.records().all(|r| r.cleared_at.is_none()));
+
+        // A key on deck B only acknowledges B's alert
+        let cleared = log.clear(0, Some("B"), AlertSource::Key, 200);
+        assert_eq!(cleared.len(), 1);
+        assert_eq!(cleared[0].device.as_deref(), Some("B"));
+
+        // A mirrored alert replaces A's, and is acknowledged from any deck
+        log.raise(&alert(0, "all"), AlertSource::Rest, 300);
+        assert_eq!(log.records().nth(2).unwrap().cleared_by, Some(AlertSource::Replaced));
+        assert_eq!(log.clear(0, Some("A"), AlertSource::Key, 400)[0].text, "all");
+    }
+
+    #[test]
+    fn test_ring_buffer_capacity() {
+        let mut log = AlertLog::new(None);
+        for i in 0..ALERT_HISTORY_CAPACITY + 5 {
+            log.raise(&alert(i % 16, "a"), AlertSource::App, i as u64);
+        }
+        assert_eq!(log.records().count(), ALERT_HISTORY_CAPACITY);
+        assert_eq!(log.records().last().unwrap().raised_at, 5);
+    }
+
+    #[test]
+    fn test_persisted_log_reloads() {
+        let path = std::env::temp_dir().join(format!("coredeck-alerts-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut log = AlertLog::new(Some(path.clone()));
+        log.raise(&alert(2, "Approve?"), AlertSource::App, 100);
+        let first = log.pending_write().unwrap();
+        log.clear(2, None, AlertSource::Rest, 150);
+        log.pending_write().unwrap().write();
+        assert!(log.pending_write().is_none());
+        // A snapshot that lost the race to a newer one is dropped
+        first.write();
+
+        let mut reloaded = AlertLog::new(Some(path.clone()));
+        let record = reloaded.records().next().unwrap().clone();
+        assert_eq!(record.text, "Approve?");
+        assert_eq!(record.cleared_by, Some(AlertSource::Rest));
+
+        // IDs keep increasing across restarts
+        reloaded.raise(&alert(3, "Next"), AlertSource::App, 200);
+        assert!(reloaded.records().next().unwrap().id > record.id);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
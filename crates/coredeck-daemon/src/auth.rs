@@ -0,0 +1,327 @@
+//! API authentication — scoped tokens and the browser origin allow-list
+//!
+//! Every HTTP and WS request passes through [`authorize`]. Browsers always
+//! send `Origin`, so pages not on `[server] allowed_origins` are refused
+//! outright (CORS alone does not stop a page from opening `/ws`). Native
+//! clients send no origin and are judged by their token alone, except on the
+//! Unix socket, where the socket's file permissions decide who gets in.
+
+use anyhow::{Context, Result};
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use coredeck_protocol::{APP_TOKEN_NAME, ApiError, ApiToken, AuthScope, TOKEN_QUERY_PARAM, TokenFile};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::DaemonState;
+
+/// Tokens accepted by the daemon (`tokens.toml`)
+#[derive(Default)]
+pub struct TokenStore {
+    tokens: Vec<ApiToken>,
+}
+
+impl TokenStore {
+    /// Load tokens from `path`. A missing file yields no tokens.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read token file: {:?}", path))?;
+        let file: TokenFile = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse token file: {:?}", path))?;
+        Ok(Self { tokens: file.tokens })
+    }
+
+    /// Find the entry matching a presented token
+    pub fn lookup(&self, presented: &str) -> Option<&ApiToken> {
+        self.tokens
+            .iter()
+            .find(|t| !t.token.is_empty() && constant_time_eq(t.token.as_bytes(), presented.as_bytes()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+}
+
+/// Compare without short-circuiting on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Marks requests that arrived on the Unix socket (request extension)
+#[derive(Debug, Clone, Copy)]
+pub struct LocalPeer;
+
+/// What the current request may do (inserted as a request extension)
+#[derive(Debug, Clone)]
+pub struct Grant {
+    scopes: Vec<AuthScope>,
+    unrestricted: bool,
+}
+
+impl Grant {
+    /// Everything allowed (`require_token = false`)
+    pub fn unrestricted() -> Self {
+        Self { scopes: Vec::new(), unrestricted: true }
+    }
+
+    /// Scopes of a token. Every valid token may read.
+    pub fn for_token(token: &ApiToken) -> Self {
+        Self { scopes: token.scopes.clone(), unrestricted: false }
+    }
+
+    pub fn allows(&self, scope: AuthScope) -> bool {
+        self.unrestricted || scope == AuthScope::Read || self.scopes.contains(&scope)
+    }
+}
+
+/// Create the token file with a full-access token for the app if it doesn't exist yet.
+pub fn ensure_token_file(path: &Path) -> Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create config directory: {:?}", parent))?;
+    }
+
+    let file = TokenFile {
+        tokens: vec![ApiToken {
+            name: APP_TOKEN_NAME.to_string(),
+            token: generate_token()?,
+            scopes: vec![AuthScope::Read, AuthScope::Display, AuthScope::Softkey],
+        }],
+    };
+    let content = format!(
+        "# CoreDeck daemon API tokens — keep this file private.\n\
+         # Scopes: read, display, softkey. Every token may read.\n\n{}",
+        toml::to_string_pretty(&file).context("Failed to serialize token file")?
+    );
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut out = options
+        .open(path)
+        .with_context(|| format!("Failed to create token file: {:?}", path))?;
+    std::io::Write::write_all(&mut out, content.as_bytes())
+        .with_context(|| format!("Failed to write token file: {:?}", path))?;
+
+    info!("Generated API token for the app in {}", path.display());
+    Ok(())
+}
+
+/// 32 random bytes, hex-encoded
+fn generate_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow::anyhow!("No OS randomness: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Re-read the token file after a change. Keeps the old tokens on error.
+pub fn reload_tokens(state: &DaemonState) {
+    let (require_token, path) = {
+        let config = state.config.read();
+        (config.auth.require_token, config.auth.tokens_path())
+    };
+    let Some(path) = path else { return };
+    // Auth may have just been switched on
+    if require_token {
+        if let Err(e) = ensure_token_file(&path) {
+            warn!("{:#}", e);
+        }
+    }
+    match TokenStore::load(&path) {
+        Ok(store) => {
+            info!("Loaded {} API token(s) from {}", store.len(), path.display());
+            *state.tokens.write() = store;
+        }
+        Err(e) => warn!("Keeping previous tokens: {:#}", e),
+    }
+}
+
+/// Scope an HTTP route needs. WS commands are checked per command in `ws.rs`.
+fn route_scope(method: &Method, path: &str) -> AuthScope {
+    if method == Method::GET || path == "/ws" {
+        AuthScope::Read
+    } else if path.starts_with("/api/softkeys/") {
+        AuthScope::Softkey
+    } else {
+        AuthScope::Display
+    }
+}
+
+/// Token from `Authorization: Bearer <token>` or the `token` query parameter
+fn presented_token(req: &Request) -> Option<String> {
+    if let Some(value) = req.headers().get(header::AUTHORIZATION) {
+        return value
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|t| t.trim().to_string());
+    }
+    req.uri().query()?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == TOKEN_QUERY_PARAM).then(|| value.to_string())
+    })
+}
+
+fn reject(status: StatusCode, error: &str) -> Response {
+    (status, Json(ApiError { error: error.to_string() })).into_response()
+}
+
+/// Middleware: check origin and token, then attach the request's [`Grant`].
+pub async fn authorize(State(state): State<Arc<DaemonState>>, mut req: Request, next: Next) -> Response {
+    if let Some(origin) = req.headers().get(header::ORIGIN) {
+        let allowed = origin
+            .to_str()
+            .is_ok_and(|origin| state.config.read().allows_origin(origin));
+        if !allowed {
+            return reject(StatusCode::FORBIDDEN, "origin not allowed");
+        }
+    }
+
+    let local = req.extensions().get::<LocalPeer>().is_some();
+    let grant = if !local && state.config.read().auth.require_token {
+        let token = presented_token(&req);
+        let tokens = state.tokens.read();
+        match token.as_deref().and_then(|t| tokens.lookup(t)) {
+            Some(entry) => Grant::for_token(entry),
+            None => return reject(StatusCode::UNAUTHORIZED, "missing or invalid API token"),
+        }
+    } else {
+        Grant::unrestricted()
+    };
+
+    let scope = route_scope(req.method(), req.uri().path());
+    if !grant.allows(scope) {
+        return reject(
+            StatusCode::FORBIDDEN,
+            &format!("token lacks the {} scope", scope.as_str()),
+        );
+    }
+
+    req.extensions_mut().insert(grant);
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(value: &str, scopes: Vec<AuthScope>) -> ApiToken {
+        ApiToken {
+            name: "test".to_string(),
+            token: value.to_string(),
+            scopes,
+        }
+    }
+
+    #[test]
+    fn test_grant_scopes() {
+        let read_only = Grant::for_token(&token("a", vec![]));
+        assert!(read_only.allows(AuthScope::Read));
+        assert!(!read_only.allows(AuthScope::Display));
+
+        let display = Grant::for_token(&token("b", vec![AuthScope::Display]));
+        assert!(display.allows(AuthScope::Display));
+        assert!(!display.allows(AuthScope::Softkey));
+
+        assert!(Grant::unrestricted().allows(AuthScope::Softkey));
+    }
+
+    #[test]
+    fn test_lookup() {
+        let store = TokenStore {
+            tokens: vec![token("secret", vec![AuthScope::Display]), token("", vec![])],
+        };
+        assert!(store.lookup("secret").is_some());
+        assert!(store.lookup("secre").is_none());
+        assert!(store.lookup("").is_none());
+    }
+
+    #[test]
+    fn test_route_scope() {
+        assert_eq!(route_scope(&Method::GET, "/api/status"), AuthScope::Read);
+        assert_eq!(route_scope(&Method::GET, "/ws"), AuthScope::Read);
+        assert_eq!(route_scope(&Method::POST, "/api/display"), AuthScope::Display);
+        assert_eq!(route_scope(&Method::PUT, "/api/softkeys/1"), AuthScope::Softkey);
+        assert_eq!(route_scope(&Method::POST, "/api/softkeys/reset"), AuthScope::Softkey);
+        assert_eq!(route_scope(&Method::GET, "/api/softkeys"), AuthScope::Read);
+    }
+
+    #[test]
+    fn test_presented_token() {
+        let req = Request::builder()
+            .uri("/ws?role=observer&token=abc")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        assert_eq!(presented_token(&req).as_deref(), Some("abc"));
+
+        let req = Request::builder()
+            .uri("/api/status")
+            .header(header::AUTHORIZATION, "Bearer xyz")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        assert_eq!(presented_token(&req).as_deref(), Some("xyz"));
+    }
+
+    #[test]
+    fn test_generated_token_file() {
+        let path = std::env::temp_dir().join(format!("coredeck-tokens-{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        ensure_token_file(&path).unwrap();
+        let store = TokenStore::load(&path).unwrap();
+        assert_eq!(store.len(), 1);
+        let app = &store.tokens[0];
+        assert_eq!(app.name, APP_TOKEN_NAME);
+        assert_eq!(app.token.len(), 64);
+        assert!(Grant::for_token(app).allows(AuthScope::Softkey));
+
+        // Existing file is left alone
+        ensure_token_file(&path).unwrap();
+        assert_eq!(TokenStore::load(&path).unwrap().tokens[0].token, app.token);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// Every HTTP `curl` example for the API in `text`, continuation lines joined
+    fn api_curl_examples(text: &str) -> Vec<String> {
+        let joined = text.replace("\\\n", " ");
+        joined
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("curl ") && line.contains("http://"))
+            .filter(|line| line.contains("/api/"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_doc_examples_send_token() {
+        for file in ["README.md", "docs/API.md"] {
+            let Some(text) = crate::tests::workspace_file(file) else { continue };
+            let examples = api_curl_examples(&text);
+            assert!(!examples.is_empty(), "no curl examples in {}", file);
+            for example in examples {
+                assert!(
+                    example.contains("Authorization: Bearer $TOKEN"),
+                    "{} example without a token: {}",
+                    file,
+                    example
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,566 @@
+//! Daemon configuration file
+//!
+//! Loaded from `daemon.toml` in the CoreDeck config directory (or `--config`)
+//! and reloaded on SIGHUP or when the file changes. Reloading never touches
+//! the WebSocket clients, and only opens or closes decks when headless
+//! actions are added or all removed.
+
+use anyhow::{bail, Context, Result};
+use coredeck_protocol::{DEFAULT_DAEMON_ADDR, DeviceMode, KeyStroke, TOKENS_FILE_NAME, default_socket_path};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+
+use crate::DaemonState;
+use crate::actions;
+use crate::auth;
+use crate::webhook::{WebhookUrl, WEBHOOK_EVENTS};
+
+/// How often the config file's modification time is checked
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// HID device configuration (same keys as the app's `[hid]` section)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HidConfig {
+    /// USB Vendor ID
+    #[serde(default = "default_vendor_id")]
+    pub vendor_id: u16,
+    /// USB Product ID
+    #[serde(default = "default_product_id")]
+    pub product_id: u16,
+    /// HID Usage Page
+    #[serde(default = "default_usage_page")]
+    pub usage_page: u16,
+    /// HID Usage ID
+    #[serde(default = "default_usage_id")]
+    pub usage_id: u16,
+    /// Keep-alive ping interval in milliseconds
+    #[serde(default = "default_ping_interval")]
+    pub ping_interval_ms: u64,
+    /// Longest wait between presence checks while the device is absent (polling fallback)
+    #[serde(default = "default_reconnect_interval")]
+    pub reconnect_interval_ms: u64,
+}
+
+fn default_vendor_id() -> u16 {
+    0xFEED
+}
+fn default_product_id() -> u16 {
+    0x0803
+}
+fn default_usage_page() -> u16 {
+    0xFF60
+}
+fn default_usage_id() -> u16 {
+    0x61
+}
+fn default_ping_interval() -> u64 {
+    5000
+}
+fn default_reconnect_interval() -> u64 {
+    5000
+}
+
+impl Default for HidConfig {
+    fn default() -> Self {
+        Self {
+            vendor_id: default_vendor_id(),
+            product_id: default_product_id(),
+            usage_page: default_usage_page(),
+            usage_id: default_usage_id(),
+            ping_interval_ms: default_ping_interval(),
+            reconnect_interval_ms: default_reconnect_interval(),
+        }
+    }
+}
+
+/// HTTP/WS server configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// Listen address (overridden by `--listen`; changes need a restart)
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// Listen on TCP at `listen`. Turn off to serve the Unix socket only.
+    #[serde(default = "default_true")]
+    pub tcp: bool,
+    /// Listen on a Unix socket too (access controlled by file permissions, no token)
+    #[serde(default = "default_true")]
+    pub unix_socket: bool,
+    /// Unix socket path (default: `$XDG_RUNTIME_DIR/coredeck/daemon.sock`)
+    #[serde(default)]
+    pub unix_socket_path: Option<PathBuf>,
+    /// Browser origins allowed to call the API (CORS and `Origin` check).
+    /// `"*"` allows any origin; requests without an `Origin` are not affected.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+}
+
+fn default_listen() -> String {
+    DEFAULT_DAEMON_ADDR.to_string()
+}
+fn default_true() -> bool {
+    true
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: default_listen(),
+            tcp: default_true(),
+            unix_socket: default_true(),
+            unix_socket_path: None,
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Unix socket to serve, if enabled and a location is known
+    pub fn socket_path(&self) -> Option<PathBuf> {
+        if !self.unix_socket {
+            return None;
+        }
+        self.unix_socket_path.clone().or_else(default_socket_path)
+    }
+
+    /// Whether two configs bind the same listeners
+    fn same_listeners(&self, other: &Self) -> bool {
+        (&self.listen, self.tcp, self.socket_path()) == (&other.listen, other.tcp, other.socket_path())
+    }
+}
+
+/// Display configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisplayConfig {
+    /// Brightness (0-255) applied whenever the device is opened.
+    /// Unset keeps the brightness saved on the device.
+    #[serde(default)]
+    pub default_brightness: Option<u8>,
+}
+
+/// API authentication
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthConfig {
+    /// Require a token from `tokens.toml` on every HTTP/WS request
+    #[serde(default = "default_true")]
+    pub require_token: bool,
+    /// Token file (default: `tokens.toml` in the CoreDeck config directory)
+    #[serde(default)]
+    pub tokens_file: Option<PathBuf>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            require_token: default_true(),
+            tokens_file: None,
+        }
+    }
+}
+
+impl AuthConfig {
+    /// Where tokens are read from (the app looks in the default location)
+    pub fn tokens_path(&self) -> Option<PathBuf> {
+        self.tokens_file.clone().or_else(|| {
+            ProjectDirs::from("com", "coredeck", "CoreDeck")
+                .map(|dirs| dirs.config_dir().join(TOKENS_FILE_NAME))
+        })
+    }
+}
+
+/// What a headless action does. Exactly one of the two is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionTarget {
+    /// Shell command (`sh -c`), with the event in `COREDECK_*` variables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// `http://` or `https://` URL the event is POSTed to as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+}
+
+impl ActionTarget {
+    fn validate(&self) -> Result<()> {
+        match (&self.command, &self.webhook) {
+            (Some(_), None) => Ok(()),
+            (None, Some(url)) => WebhookUrl::parse(url).map(drop),
+            _ => bail!("set exactly one of `command` or `webhook`"),
+        }
+    }
+}
+
+/// Action for a key press (`[[actions.key]]`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyAction {
+    /// Key as reported by the deck, e.g. `{ usage = 0x68 }` for F13
+    pub key: KeyStroke,
+    /// Only for this deck (serial); any deck when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(flatten)]
+    pub target: ActionTarget,
+}
+
+/// Action for the mode button or YOLO switch (`[[actions.state]]`).
+/// Runs when the deck enters the given state; unset fields match anything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateAction {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<DeviceMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yolo: Option<bool>,
+    /// Only for this deck (serial); any deck when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(flatten)]
+    pub target: ActionTarget,
+}
+
+/// Deck input handled by the daemon itself while no app is connected
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionsConfig {
+    #[serde(default)]
+    pub key: Vec<KeyAction>,
+    #[serde(default)]
+    pub state: Vec<StateAction>,
+}
+
+impl ActionsConfig {
+    /// Whether any action is configured (decks then stay open without an app)
+    pub fn is_enabled(&self) -> bool {
+        !self.key.is_empty() || !self.state.is_empty()
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (i, action) in self.key.iter().enumerate() {
+            action.target.validate().with_context(|| format!("actions.key[{}]", i))?;
+        }
+        for (i, action) in self.state.iter().enumerate() {
+            action.target.validate().with_context(|| format!("actions.state[{}]", i))?;
+        }
+        Ok(())
+    }
+}
+
+/// Outbound webhook (`[[webhooks]]`): selected events are POSTed to `url`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// `http://` or `https://` URL the events are POSTed to as JSON
+    pub url: String,
+    /// Event types to send, e.g. `"hid_key_event"` or `"alert_raised"`
+    pub events: Vec<String>,
+    /// Key for the `X-CoreDeck-Signature` header (HMAC-SHA256 of the body)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Further attempts after a failed delivery
+    #[serde(default = "default_webhook_retries")]
+    pub retries: u32,
+}
+
+fn default_webhook_retries() -> u32 {
+    3
+}
+
+impl WebhookConfig {
+    /// Whether events of type `event` go to this webhook
+    pub fn wants(&self, event: &str) -> bool {
+        self.events.iter().any(|e| e == event)
+    }
+
+    fn validate(&self) -> Result<()> {
+        WebhookUrl::parse(&self.url)?;
+        if self.events.is_empty() {
+            bail!("no `events` selected");
+        }
+        if let Some(unknown) = self.events.iter().find(|e| !WEBHOOK_EVENTS.contains(&e.as_str())) {
+            bail!("unknown event '{}' (expected one of: {})", unknown, WEBHOOK_EVENTS.join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// Main daemon configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonConfig {
+    /// HID device configuration
+    #[serde(default)]
+    pub hid: HidConfig,
+    /// HTTP/WS server configuration
+    #[serde(default)]
+    pub server: ServerConfig,
+    /// Display configuration
+    #[serde(default)]
+    pub display: DisplayConfig,
+    /// API authentication
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// Headless key and state actions
+    #[serde(default)]
+    pub actions: ActionsConfig,
+    /// Outbound webhooks
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+impl DaemonConfig {
+    /// Load configuration from `path`. A missing file yields the defaults.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {:?}", path))?;
+        let config: Self =
+            toml::from_str(&content).with_context(|| format!("Failed to parse config file: {:?}", path))?;
+        config.validate().with_context(|| format!("Invalid config file: {:?}", path))?;
+        Ok(config)
+    }
+
+    /// Check what the TOML types alone can't
+    fn validate(&self) -> Result<()> {
+        self.actions.validate()?;
+        for (i, webhook) in self.webhooks.iter().enumerate() {
+            webhook.validate().with_context(|| format!("webhooks[{}]", i))?;
+        }
+        Ok(())
+    }
+
+    /// Default config file location (next to the app's `config.toml`)
+    pub fn default_path() -> Option<PathBuf> {
+        ProjectDirs::from("com", "coredeck", "CoreDeck")
+            .map(|dirs| dirs.config_dir().join("daemon.toml"))
+    }
+
+    /// Whether browser requests from `origin` are allowed
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.server.allowed_origins.iter().any(|o| o == "*" || o == origin)
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Modification times of the config and token files
+fn watched_times(state: &DaemonState) -> (Option<SystemTime>, Option<SystemTime>) {
+    let tokens_path = state.config.read().auth.tokens_path();
+    (
+        state.config_path.as_deref().and_then(modified_time),
+        tokens_path.as_deref().and_then(modified_time),
+    )
+}
+
+/// Reload the config and token files on SIGHUP or when either one changes.
+pub async fn watch(state: Arc<DaemonState>) {
+    #[cfg(unix)]
+    let mut sighup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(s) => Some(s),
+        Err(e) => {
+            warn!("Failed to install SIGHUP handler: {}", e);
+            None
+        }
+    };
+
+    let mut last_modified = watched_times(&state);
+    let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+
+    loop {
+        #[cfg(unix)]
+        let hangup = async {
+            match sighup.as_mut() {
+                Some(s) => {
+                    s.recv().await;
+                }
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = hangup => {
+                info!("SIGHUP received, reloading config");
+            }
+            _ = interval.tick() => {
+                if watched_times(&state) == last_modified {
+                    continue;
+                }
+                debug!("Config file changed");
+            }
+        }
+
+        if let Some(path) = &state.config_path {
+            reload(&state, path).await;
+        }
+        auth::reload_tokens(&state);
+        last_modified = watched_times(&state);
+    }
+}
+
+/// Re-read the config file and apply what changed.
+async fn reload(state: &Arc<DaemonState>, path: &Path) {
+    let new = match DaemonConfig::load(path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Keeping previous config: {:#}", e);
+            return;
+        }
+    };
+
+    let old = std::mem::replace(&mut *state.config.write(), new.clone());
+    if old == new {
+        return;
+    }
+    info!("Config reloaded from {}", path.display());
+
+    if !old.server.same_listeners(&new.server) {
+        warn!("Listener settings changed — restart the daemon to apply");
+    }
+
+    if old.actions.is_enabled() != new.actions.is_enabled() {
+        actions::update_headless(state).await;
+    }
+
+    let hid = state.hid.lock().await;
+    if old.hid != new.hid {
+        hid.set_config(new.hid.clone());
+    }
+    if old.display.default_brightness != new.display.default_brightness && hid.is_connected() {
+        if let Some(level) = new.display.default_brightness {
+            let done = hid.set_brightness(None, level, false);
+            drop(hid);
+            if let Err(e) = done.await {
+                warn!("Failed to apply default brightness: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config() {
+        let config = DaemonConfig::default();
+        assert_eq!(config.hid, HidConfig::default());
+        assert_eq!(config.server.listen, DEFAULT_DAEMON_ADDR);
+        assert!(config.server.tcp && config.server.unix_socket);
+        assert!(!config.allows_origin("https://example.com"));
+        assert_eq!(config.display.default_brightness, None);
+        assert!(config.auth.require_token);
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let config: DaemonConfig = toml::from_str(
+            r#"
+            [hid]
+            product_id = 0x0804
+            ping_interval_ms = 2000
+
+            [server]
+            allowed_origins = ["http://localhost:3000"]
+            tcp = false
+            unix_socket_path = "/run/user/1000/deck.sock"
+
+            [display]
+            default_brightness = 120
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.hid.vendor_id, 0xFEED);
+        assert_eq!(config.hid.product_id, 0x0804);
+        assert_eq!(config.hid.ping_interval_ms, 2000);
+        assert!(!config.server.tcp);
+        assert_eq!(config.server.socket_path(), Some(PathBuf::from("/run/user/1000/deck.sock")));
+        assert!(config.allows_origin("http://localhost:3000"));
+        assert!(!config.allows_origin("https://evil.example"));
+        assert_eq!(config.display.default_brightness, Some(120));
+        assert!(config.auth.require_token);
+    }
+
+    #[test]
+    fn test_actions_config() {
+        let config: DaemonConfig = toml::from_str(
+            r#"
+            [[actions.key]]
+            key = { usage = 0x68 }
+            command = "playerctl play-pause"
+
+            [[actions.state]]
+            mode = "Plan"
+            device = "CD-0001"
+            webhook = "http://127.0.0.1:8123/api/webhook/deck"
+            "#,
+        )
+        .unwrap();
+        assert!(config.actions.is_enabled());
+        assert_eq!(config.actions.key[0].key.to_keycode(), 0x68);
+        assert_eq!(config.actions.key[0].target.command.as_deref(), Some("playerctl play-pause"));
+        assert_eq!(config.actions.state[0].mode, Some(DeviceMode::Plan));
+        assert_eq!(config.actions.state[0].yolo, None);
+        assert!(config.actions.validate().is_ok());
+        assert!(!DaemonConfig::default().actions.is_enabled());
+
+        let both: ActionsConfig = toml::from_str(
+            r#"
+            [[key]]
+            key = { usage = 4 }
+            command = "true"
+            webhook = "http://localhost/"
+            "#,
+        )
+        .unwrap();
+        let err = both.validate().unwrap_err();
+        assert_eq!(format!("{:#}", err), "actions.key[0]: set exactly one of `command` or `webhook`");
+
+        let https: ActionsConfig =
+            toml::from_str("[[state]]\nyolo = true\nwebhook = \"https://example.com\"").unwrap();
+        assert!(https.validate().is_ok());
+        let ftp: ActionsConfig = toml::from_str("[[state]]\nyolo = true\nwebhook = \"ftp://example.com\"").unwrap();
+        assert!(ftp.validate().is_err());
+    }
+
+    #[test]
+    fn test_webhooks_config() {
+        let config: DaemonConfig = toml::from_str(
+            r#"
+            [[webhooks]]
+            url = "http://127.0.0.1:9000/deck"
+            events = ["device_state_changed", "alert_raised"]
+            secret = "s3cret"
+            "#,
+        )
+        .unwrap();
+        let webhook = &config.webhooks[0];
+        assert_eq!(webhook.retries, 3);
+        assert!(webhook.wants("alert_raised"));
+        assert!(!webhook.wants("hid_key_event"));
+        assert!(config.validate().is_ok());
+
+        let mut bad = config.clone();
+        bad.webhooks[0].events.push("key_pressed".to_string());
+        let err = format!("{:#}", bad.validate().unwrap_err());
+        assert!(err.starts_with("webhooks[0]: unknown event 'key_pressed'"), "{}", err);
+
+        bad.webhooks[0].events.clear();
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_shipped_example_parses() {
+        let Some(text) = crate::tests::workspace_file("config/daemon.toml") else { return };
+        let config: DaemonConfig = toml::from_str(&text).unwrap();
+        assert_eq!(config, DaemonConfig::default());
+    }
+
+    #[test]
+    fn test_missing_file_is_default() {
+        let path = std::env::temp_dir().join("coredeck-daemon-no-such-config.toml");
+        assert_eq!(DaemonConfig::load(&path).unwrap(), DaemonConfig::default());
+    }
+}
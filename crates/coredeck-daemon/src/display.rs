@@ -0,0 +1,264 @@
+//! Display compositor — per-source display layers with priorities and leases
+//!
+//! Every writer (the app over WS, scripts over REST) owns a named layer.
+//! The screen shows the highest-priority layer, preferring the most recently
+//! updated one on ties. Leased layers drop out when they expire and the
+//! display reverts to whatever is underneath. A layer either targets one
+//! deck (by serial) or mirrors to all of them.
+
+use anyhow::Result;
+use coredeck_protocol::DisplayUpdate;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::DaemonState;
+use crate::hid::HidManager;
+
+/// Layer written by the WS controller (UpdateDisplay, and layer commands without a source)
+pub const APP_SOURCE: &str = "app";
+
+/// Layer written by REST callers that don't name one
+pub const REST_SOURCE: &str = "rest";
+
+/// How often expired leases are swept
+pub const LEASE_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+struct DisplayLayer {
+    update: DisplayUpdate,
+    priority: u8,
+    expires_at: Option<Instant>,
+    /// Update order, for tie-breaking equal priorities
+    seq: u64,
+}
+
+/// Display content per source, and which one wins each deck's screen
+#[derive(Default)]
+pub struct DisplayCompositor {
+    /// Layers by source and target deck (`None` = every deck)
+    layers: HashMap<(String, Option<String>), DisplayLayer>,
+    next_seq: u64,
+}
+
+impl DisplayCompositor {
+    /// Set or replace a source's layer for `device` (`None` = every deck),
+    /// optionally leased for `ttl`
+    pub fn set(
+        &mut self,
+        source: &str,
+        device: Option<&str>,
+        update: DisplayUpdate,
+        priority: u8,
+        ttl: Option<Duration>,
+        now: Instant,
+    ) {
+        self.next_seq += 1;
+        self.layers.insert(
+            (source.to_string(), device.map(str::to_string)),
+            DisplayLayer {
+                update,
+                priority,
+                expires_at: ttl.map(|ttl| now + ttl),
+                seq: self.next_seq,
+            },
+        );
+    }
+
+    /// Remove a source's layer for `device`, or all of the source's layers
+    /// when `None`. Returns whether any existed.
+    pub fn clear(&mut self, source: &str, device: Option<&str>) -> bool {
+        let before = self.layers.len();
+        self.layers.retain(|(s, d), _| {
+            s != source || (device.is_some() && d.as_deref() != device)
+        });
+        self.layers.len() != before
+    }
+
+    /// Drop layers whose lease has run out. Returns whether any were removed.
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let before = self.layers.len();
+        self.layers
+            .retain(|_, layer| layer.expires_at.map_or(true, |at| at > now));
+        self.layers.len() != before
+    }
+
+    /// Content that should be on the screen of deck `serial`
+    pub fn current(&self, serial: &str) -> Option<&DisplayUpdate> {
+        self.layers
+            .iter()
+            .filter(|((_, device), _)| device.as_deref().map_or(true, |d| d == serial))
+            .map(|(_, layer)| layer)
+            .max_by_key(|layer| (layer.priority, layer.seq))
+            .map(|layer| &layer.update)
+    }
+}
+
+/// Queue each open deck's winning layer. Decks with no layer left get a blank
+/// screen, so cleared or expired content doesn't linger.
+/// The returned future needs no locks and resolves to the first error.
+pub fn push_current(hid: &HidManager, display: &DisplayCompositor) -> impl Future<Output = Result<()>> {
+    let blank = DisplayUpdate::default();
+    let mut pending = Vec::new();
+    for serial in hid.connected_serials() {
+        let update = display.current(&serial).unwrap_or(&blank);
+        pending.push(hid.send_display_update(
+            Some(&serial),
+            &update.session,
+            Some(update.task.as_str()).filter(|s| !s.is_empty()),
+            Some(update.task2.as_str()).filter(|s| !s.is_empty()),
+            &update.tabs,
+            update.active,
+        ));
+    }
+    async move {
+        let mut result = Ok(());
+        for done in pending {
+            let sent = done.await;
+            if result.is_ok() {
+                result = sent;
+            }
+        }
+        result
+    }
+}
+
+/// Sweep expired leases and revert the screen if the winner changed.
+///
+/// Only acts while the device is open — without a controller the display is
+/// written transiently and idles afterwards, so there is nothing to revert.
+pub async fn expire_leases(state: &Arc<DaemonState>) {
+    {
+        let mut display = state.display.lock().await;
+        if !display.expire(Instant::now()) {
+            return;
+        }
+    }
+    debug!("Display lease expired");
+
+    let pushed = {
+        let hid = state.hid.lock().await;
+        if !hid.is_connected() {
+            return;
+        }
+        push_current(&hid, &*state.display.lock().await)
+    };
+    if let Err(e) = pushed.await {
+        warn!("Failed to revert display after lease expiry: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HidConfig;
+    use crate::hid::protocol::ProtocolMode;
+    use crate::hid::{VirtualBackend, VirtualDeck};
+    use crate::state::DaemonEventSender;
+
+    fn update(session: &str) -> DisplayUpdate {
+        DisplayUpdate {
+            session: session.to_string(),
+            task: String::new(),
+            task2: String::new(),
+            tabs: vec![],
+            active: 0,
+        }
+    }
+
+    fn shown<'a>(display: &'a DisplayCompositor, serial: &str) -> Option<&'a str> {
+        display.current(serial).map(|u| u.session.as_str())
+    }
+
+    #[test]
+    fn test_higher_priority_wins() {
+        let mut display = DisplayCompositor::default();
+        let now = Instant::now();
+        display.set("ci", None, update("ci"), 5, None, now);
+        display.set(APP_SOURCE, None, update("app"), 0, None, now);
+        assert_eq!(shown(&display, "A"), Some("ci"));
+
+        assert!(display.clear("ci", None));
+        assert_eq!(shown(&display, "A"), Some("app"));
+        assert!(!display.clear("ci", None));
+    }
+
+    #[test]
+    fn test_equal_priority_most_recent_wins() {
+        let mut display = DisplayCompositor::default();
+        let now = Instant::now();
+        display.set("a", None, update("a1"), 0, None, now);
+        display.set("b", None, update("b1"), 0, None, now);
+        assert_eq!(shown(&display, "A"), Some("b1"));
+
+        // Updating a layer moves it back on top
+        display.set("a", None, update("a2"), 0, None, now);
+        assert_eq!(shown(&display, "A"), Some("a2"));
+    }
+
+    #[test]
+    fn test_lease_expiry_reverts() {
+        let mut display = DisplayCompositor::default();
+        let now = Instant::now();
+        display.set(APP_SOURCE, None, update("app"), 0, None, now);
+        display.set("build", None, update("build"), 10, Some(Duration::from_secs(30)), now);
+        assert_eq!(shown(&display, "A"), Some("build"));
+
+        assert!(!display.expire(now + Duration::from_secs(29)));
+        assert_eq!(shown(&display, "A"), Some("build"));
+
+        assert!(display.expire(now + Duration::from_secs(30)));
+        assert_eq!(shown(&display, "A"), Some("app"));
+    }
+
+    #[test]
+    fn test_device_layers() {
+        let mut display = DisplayCompositor::default();
+        let now = Instant::now();
+        display.set(APP_SOURCE, None, update("app"), 0, None, now);
+        display.set("ci", Some("B"), update("ci-b"), 5, None, now);
+        assert_eq!(shown(&display, "A"), Some("app"));
+        assert_eq!(shown(&display, "B"), Some("ci-b"));
+
+        display.set("ci", Some("A"), update("ci-a"), 5, None, now);
+        assert!(display.clear("ci", Some("B")));
+        assert_eq!(shown(&display, "A"), Some("ci-a"));
+        assert_eq!(shown(&display, "B"), Some("app"));
+
+        // Clearing without a device drops the source everywhere
+        assert!(display.clear("ci", None));
+        assert_eq!(shown(&display, "A"), Some("app"));
+    }
+
+    #[test]
+    fn test_empty_compositor() {
+        let mut display = DisplayCompositor::default();
+        assert!(display.current("A").is_none());
+        assert!(!display.expire(Instant::now()));
+    }
+
+    #[tokio::test]
+    async fn test_last_lease_expiry_blanks_screen() {
+        let deck = VirtualDeck::new(ProtocolMode::Standalone);
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let hid = HidManager::with_backend(
+            HidConfig::default(),
+            DaemonEventSender::new(tx),
+            Box::new(VirtualBackend::new(deck.clone())),
+        );
+        hid.open_device(None).await.unwrap();
+        let shown = || deck.snapshot().display.unwrap()["session"].clone();
+
+        let mut display = DisplayCompositor::default();
+        let now = Instant::now();
+        display.set("build", None, update("build"), 10, Some(Duration::from_secs(30)), now);
+        push_current(&hid, &display).await.unwrap();
+        assert_eq!(shown(), "build");
+
+        // Nothing is left underneath, so the screen is blanked rather than kept
+        assert!(display.expire(now + Duration::from_secs(30)));
+        push_current(&hid, &display).await.unwrap();
+        assert_eq!(shown(), "");
+    }
+}
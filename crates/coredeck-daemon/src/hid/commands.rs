@@ -9,17 +9,22 @@ use super::protocol::{build_chunked_packets, DeviceMode, HidCommand, HidPacket,
 
 /// Build a display update with session name, current task, tab states, and active tab index
 pub fn build_display_update(session: &str, task: Option<&str>, task2: Option<&str>, tabs: &[u8], active: usize, mode: ProtocolMode) -> Vec<HidPacket> {
-    let json = serde_json::json!({
+    let json = display_payload(session, task, task2, tabs, active);
+
+    tracing::info!("HID display payload: {}", json);
+
+    build_chunked_packets(HidCommand::UpdateDisplay, json.to_string().as_bytes(), mode)
+}
+
+/// JSON message of a display update, as the firmware reassembles it
+pub fn display_payload(session: &str, task: Option<&str>, task2: Option<&str>, tabs: &[u8], active: usize) -> serde_json::Value {
+    serde_json::json!({
         "session": session,
         "task": task.unwrap_or(""),
         "task2": task2.unwrap_or(""),
         "tabs": tabs,
         "active": active,
-    });
-
-    tracing::info!("HID display payload: {}", json);
-
-    build_chunked_packets(HidCommand::UpdateDisplay, json.to_string().as_bytes(), mode)
+    })
 }
 
 /// Build a ping packet (single packet)
@@ -62,6 +67,13 @@ pub fn build_set_mode(device_mode: DeviceMode, mode: ProtocolMode) -> Vec<HidPac
 
 /// Build an alert command to show an overlay on the device
 pub fn build_alert(tab: usize, session: &str, text: &str, details: Option<&str>, mode: ProtocolMode) -> Vec<HidPacket> {
+    let json = alert_payload(tab, session, text, details);
+    tracing::info!("HID alert payload: {}", json);
+    build_chunked_packets(HidCommand::Alert, json.to_string().as_bytes(), mode)
+}
+
+/// JSON message of an alert, as the firmware reassembles it
+pub fn alert_payload(tab: usize, session: &str, text: &str, details: Option<&str>) -> serde_json::Value {
     let mut json = serde_json::json!({
         "tab": tab,
         "session": session,
@@ -70,8 +82,7 @@ pub fn build_alert(tab: usize, session: &str, text: &str, details: Option<&str>,
     if let Some(d) = details {
         json["details"] = serde_json::Value::String(d.to_string());
     }
-    tracing::info!("HID alert payload: {}", json);
-    build_chunked_packets(HidCommand::Alert, json.to_string().as_bytes(), mode)
+    json
 }
 
 /// Build a get version query (single packet, no payload)
@@ -79,6 +90,11 @@ pub fn build_get_version(mode: ProtocolMode) -> Vec<HidPacket> {
     build_chunked_packets(HidCommand::GetVersion, &[], mode)
 }
 
+/// Build a capabilities query (single packet, no payload)
+pub fn build_get_capabilities(mode: ProtocolMode) -> Vec<HidPacket> {
+    build_chunked_packets(HidCommand::GetCapabilities, &[], mode)
+}
+
 /// Build a clear alert command (no text field = clear)
 pub fn build_clear_alert(tab: usize, mode: ProtocolMode) -> Vec<HidPacket> {
     let json = serde_json::json!({
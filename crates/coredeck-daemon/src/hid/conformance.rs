@@ -0,0 +1,447 @@
+//! Protocol conformance suite
+//!
+//! Drives `HidManager` against the virtual deck through a fault-injecting
+//! transport and checks that the host side of the chunked protocol copes with
+//! everything the firmware can put on the wire: every command, messages at the
+//! 512-byte reassembly limit, unsolicited packets interleaved mid-response,
+//! `Error (0xFF)` replies and lost END flags. Every scenario runs in both
+//! Standalone and VIAL modes.
+
+use super::protocol::{
+    build_chunked_packets, DeviceMode, DeviceState, HidCommand, HidPacket, ProtoError,
+    ProtocolMode, SoftKeyType, FLAG_END, FLAG_START, MAX_MESSAGE_SIZE, PACKET_SIZE,
+};
+use super::transport::{DeviceListing, HidBackend, HidTransport};
+use super::virtual_deck::{VirtualBackend, VirtualDeck, VIRTUAL_FIRMWARE_VERSION};
+use super::HidManager;
+use crate::state::{DaemonEvent, DaemonEventSender};
+use crate::HidConfig;
+use anyhow::Result;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+const MODES: [ProtocolMode; 2] = [ProtocolMode::Standalone, ProtocolMode::Vial];
+
+/// How long to wait for an asynchronous event before failing
+const EVENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Wire-level fault applied to the next response to one command.
+///
+/// Faults are applied inside the read that picks up the response, so the
+/// manager's reader thread can never observe them out of order.
+#[derive(Debug, Clone)]
+enum Fault {
+    /// Slip a StateReport and a KeyEvent in between the response chunks
+    Interleave,
+    /// Replace the response with an `Error (0xFF)` reply
+    ErrorReply(ProtoError),
+    /// Clear the END flag on the last response chunk
+    DropEnd,
+    /// Replace the response with one that runs past `MAX_MESSAGE_SIZE` without END
+    Runaway,
+    /// Deliver these packets (stale replies, orphaned chunks) ahead of the response
+    Prepend(Vec<HidPacket>),
+}
+
+#[derive(Default)]
+struct Tap {
+    /// Command bytes of every message the host started
+    sent: Vec<u8>,
+    /// Armed fault and the command whose response it applies to
+    fault: Option<(HidCommand, Fault)>,
+    /// Rewritten reports, delivered before anything else from the deck
+    pending: VecDeque<[u8; PACKET_SIZE]>,
+}
+
+/// Transport wrapper that records host writes and rewrites deck responses
+struct FaultyTransport {
+    inner: Box<dyn HidTransport>,
+    mode: ProtocolMode,
+    tap: Arc<Mutex<Tap>>,
+}
+
+impl FaultyTransport {
+    /// Read the rest of a response whose first chunk is `first`
+    fn collect_response(&self, first: HidPacket) -> Vec<HidPacket> {
+        let mut packets = vec![first];
+        let mut buf = [0u8; PACKET_SIZE];
+        while !packets.last().unwrap().is_end() {
+            match self.inner.read_timeout(&mut buf, 50) {
+                Ok(n) if n > 0 => {
+                    if let Some(pkt) = HidPacket::from_wire(&buf, self.mode) {
+                        packets.push(pkt);
+                    }
+                }
+                _ => break,
+            }
+        }
+        packets
+    }
+}
+
+impl HidTransport for FaultyTransport {
+    fn write(&self, report: &[u8]) -> Result<usize> {
+        if let Some(pkt) = HidPacket::from_wire(report, self.mode) {
+            if pkt.is_start() {
+                self.tap.lock().sent.push(pkt.command_byte());
+            }
+        }
+        self.inner.write(report)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        if let Some(report) = self.tap.lock().pending.pop_front() {
+            buf[..PACKET_SIZE].copy_from_slice(&report);
+            return Ok(PACKET_SIZE);
+        }
+
+        let n = self.inner.read_timeout(buf, timeout_ms)?;
+        let Some(pkt) = (n > 0).then(|| HidPacket::from_wire(buf, self.mode)).flatten() else {
+            return Ok(n);
+        };
+
+        let mut tap = self.tap.lock();
+        let fault = match tap.fault.take() {
+            Some((command, fault)) if pkt.command() == Some(command) && pkt.is_start() => fault,
+            other => {
+                tap.fault = other;
+                return Ok(n);
+            }
+        };
+        drop(tap);
+
+        let command = pkt.command_byte();
+        let mut packets = self.collect_response(pkt);
+        match fault {
+            Fault::Interleave => {
+                let state = build_chunked_packets(HidCommand::StateReport, &[0x06], self.mode);
+                let key = build_chunked_packets(HidCommand::KeyEvent, &[0x00, 0x29], self.mode);
+                let at = if packets.len() > 1 { 1 } else { 0 };
+                packets.splice(at..at, state.into_iter().chain(key));
+            }
+            Fault::ErrorReply(error) => {
+                packets = build_chunked_packets(HidCommand::Error, &[error as u8, command], self.mode);
+            }
+            Fault::DropEnd => {
+                let last = packets.last_mut().unwrap();
+                *last = without_end(last);
+            }
+            Fault::Runaway => {
+                packets = build_chunked_packets(HidCommand::GetSoftKey, &key_data(MAX_MESSAGE_SIZE * 2), self.mode);
+                let last = packets.pop().unwrap();
+                packets.push(without_end(&last));
+            }
+            Fault::Prepend(stale) => {
+                packets.splice(0..0, stale);
+            }
+        }
+
+        let mut tap = self.tap.lock();
+        tap.pending.extend(packets.iter().map(|p| p.to_wire(self.mode)));
+        let report = tap.pending.pop_front().unwrap();
+        buf[..PACKET_SIZE].copy_from_slice(&report);
+        Ok(PACKET_SIZE)
+    }
+}
+
+struct FaultyBackend {
+    inner: VirtualBackend,
+    mode: ProtocolMode,
+    tap: Arc<Mutex<Tap>>,
+}
+
+impl HidBackend for FaultyBackend {
+    fn refresh(&mut self) -> Result<()> {
+        self.inner.refresh()
+    }
+
+    fn list(&self, config: &HidConfig) -> Vec<DeviceListing> {
+        self.inner.list(config)
+    }
+
+    fn open(&self, config: &HidConfig, serial: &str) -> Result<Box<dyn HidTransport>> {
+        let transport = FaultyTransport {
+            inner: self.inner.open(config, serial)?,
+            mode: self.mode,
+            tap: Arc::clone(&self.tap),
+        };
+        Ok(Box::new(transport))
+    }
+
+    fn supports_hotplug(&self) -> bool {
+        false
+    }
+}
+
+/// A connected manager + deck pair with the fault tap in between
+struct Harness {
+    mode: ProtocolMode,
+    deck: VirtualDeck,
+    hid: HidManager,
+    tap: Arc<Mutex<Tap>>,
+    events: mpsc::UnboundedReceiver<DaemonEvent>,
+}
+
+impl Harness {
+    fn connect(mode: ProtocolMode) -> Self {
+        let deck = VirtualDeck::new(mode);
+        let tap = Arc::new(Mutex::new(Tap::default()));
+        let backend = FaultyBackend {
+            inner: VirtualBackend::new(deck.clone()),
+            mode,
+            tap: Arc::clone(&tap),
+        };
+        let (tx, events) = mpsc::unbounded_channel();
+        let hid = HidManager::with_backend(HidConfig::default(), DaemonEventSender::new(tx), Box::new(backend));
+        hid.open_device(None).wait().unwrap();
+
+        let mut harness = Self { mode, deck, hid, tap, events };
+        assert!(
+            harness.wait_for(|e| matches!(e, DaemonEvent::HidConnected { firmware_version, .. }
+                if firmware_version == VIRTUAL_FIRMWARE_VERSION)),
+            "{:?}: protocol not detected",
+            mode
+        );
+        harness
+    }
+
+    fn arm(&self, command: HidCommand, fault: Fault) {
+        self.tap.lock().fault = Some((command, fault));
+    }
+
+    /// Queue raw packets from the deck, bypassing its own protocol handling.
+    /// The manager's reader thread may pick these up before a request does.
+    fn inject(&self, packets: &[HidPacket]) {
+        for pkt in packets {
+            self.deck.inject_raw_report(pkt.to_wire(self.mode));
+        }
+    }
+
+    fn sent(&self) -> Vec<u8> {
+        self.tap.lock().sent.clone()
+    }
+
+    /// Wait until an event matching `pred` arrives, discarding others
+    fn wait_for(&mut self, pred: impl Fn(&DaemonEvent) -> bool) -> bool {
+        let deadline = Instant::now() + EVENT_TIMEOUT;
+        while Instant::now() < deadline {
+            match self.events.try_recv() {
+                Ok(event) if pred(&event) => return true,
+                Ok(_) => {}
+                Err(_) => std::thread::sleep(Duration::from_millis(5)),
+            }
+        }
+        false
+    }
+}
+
+fn without_end(pkt: &HidPacket) -> HidPacket {
+    let mut bytes = *pkt.as_bytes();
+    bytes[0] &= !FLAG_END;
+    HidPacket::from_bytes(&bytes)
+}
+
+/// Soft key string data that never ends in (or contains) a zero byte
+fn key_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 250) as u8 + 1).collect()
+}
+
+/// Bytes of soft key data that make a `GetSoftKey` response exactly `len` bytes
+/// (`[status, index, type, data...]`)
+fn key_data_for_response(len: usize) -> Vec<u8> {
+    key_data(len - 3)
+}
+
+#[test]
+fn test_every_command_round_trips() {
+    for mode in MODES {
+        let mut h = Harness::connect(mode);
+
+        h.hid.send_display_update(None, "proj", Some("Building"), Some("Tests"), &[0, 1], 1).wait().unwrap();
+        assert_eq!(h.deck.snapshot().display.unwrap()["task2"], "Tests", "{:?}", mode);
+
+        h.hid.set_brightness(None, 42, false).wait().unwrap();
+        assert_eq!(h.deck.snapshot().brightness, 42, "{:?}", mode);
+
+        h.hid.set_soft_key(None, 0, SoftKeyType::Keycode, &[0x00, 0x04], false).wait().unwrap();
+        let key = h.hid.get_soft_key(None, 0).wait().unwrap();
+        assert_eq!((key.key_type, key.data), (SoftKeyType::Keycode, vec![0x00, 0x04]), "{:?}", mode);
+
+        let reset = h.hid.reset_soft_keys(None).wait().unwrap();
+        assert!(reset.iter().all(|k| k.key_type == SoftKeyType::Default), "{:?}", mode);
+        assert_eq!(h.hid.get_soft_key(None, 0).wait().unwrap().key_type, SoftKeyType::Default, "{:?}", mode);
+
+        h.hid.set_mode(None, DeviceMode::Plan).wait().unwrap();
+        assert_eq!(h.deck.snapshot().mode, DeviceMode::Plan, "{:?}", mode);
+
+        h.hid.send_alert(None, 1, "proj", "Allow edit?", Some("src/main.rs")).wait().unwrap();
+        assert_eq!(h.deck.snapshot().alerts[&1]["details"], "src/main.rs", "{:?}", mode);
+        h.hid.clear_alert(None, 1).wait().unwrap();
+        assert!(h.deck.snapshot().alerts.is_empty(), "{:?}", mode);
+
+        assert_eq!(h.hid.query_version(None).wait().unwrap(), VIRTUAL_FIRMWARE_VERSION, "{:?}", mode);
+
+        // Device-initiated messages
+        h.deck.inject_key_event(0x7E01);
+        assert!(h.wait_for(|e| matches!(e, DaemonEvent::HidKeyEvent { keycode: 0x7E01, .. })), "{:?}", mode);
+
+        let long_text = "git commit -m 'a message long enough to need several chunks'";
+        h.deck.inject_type_string(long_text, true);
+        assert!(
+            h.wait_for(|e| matches!(e, DaemonEvent::HidTypeString { text, send_enter: true, .. } if text == long_text)),
+            "{:?}",
+            mode
+        );
+
+        h.deck.inject_state(DeviceState { mode: DeviceMode::Accept, yolo: true });
+        assert!(
+            h.wait_for(|e| matches!(e, DaemonEvent::DeviceStateChanged { mode: DeviceMode::Accept, yolo: true, .. })),
+            "{:?}",
+            mode
+        );
+
+        h.hid.close_device(None).wait().unwrap();
+        assert!(h.deck.snapshot().idle, "{:?}", mode);
+
+        // The reader thread pings right after connecting
+        let sent = h.sent();
+        for command in [
+            HidCommand::UpdateDisplay,
+            HidCommand::Ping,
+            HidCommand::SetBrightness,
+            HidCommand::SetSoftKey,
+            HidCommand::GetSoftKey,
+            HidCommand::ResetSoftKeys,
+            HidCommand::SetMode,
+            HidCommand::Alert,
+            HidCommand::GetVersion,
+            HidCommand::GetCapabilities,
+            HidCommand::Disconnect,
+        ] {
+            assert!(sent.contains(&command.as_byte()), "{:?}: {:?} never sent", mode, command);
+        }
+    }
+}
+
+#[test]
+fn test_max_size_messages() {
+    for mode in MODES {
+        let h = Harness::connect(mode);
+
+        // Largest response the host accepts, spread over many chunks
+        let data = key_data_for_response(MAX_MESSAGE_SIZE);
+        h.hid.set_soft_key(None, 2, SoftKeyType::String, &data, false).wait().unwrap();
+        let key = h.hid.get_soft_key(None, 2).wait().unwrap();
+        assert_eq!(key.data, data, "{:?}: 512-byte response corrupted", mode);
+
+        // One byte over: the deck rejects the write, the old assignment stays
+        let too_long = key_data_for_response(MAX_MESSAGE_SIZE + 1);
+        h.hid.set_soft_key(None, 2, SoftKeyType::String, &too_long, false).wait().unwrap();
+        assert_eq!(h.hid.get_soft_key(None, 2).wait().unwrap().data, data, "{:?}", mode);
+
+        // A response that runs past the limit without END is abandoned...
+        h.arm(HidCommand::GetSoftKey, Fault::Runaway);
+        let err = h.hid.get_soft_key(None, 2).wait().unwrap_err();
+        assert!(err.to_string().contains("exceeded"), "{:?}: {}", mode, err);
+
+        // ...and the next request is answered cleanly
+        assert_eq!(h.hid.get_soft_key(None, 2).wait().unwrap().data, data, "{:?}", mode);
+    }
+}
+
+#[test]
+fn test_interleaved_unsolicited_packets() {
+    for mode in MODES {
+        let mut h = Harness::connect(mode);
+
+        let data = key_data(100);
+        h.hid.set_soft_key(None, 1, SoftKeyType::String, &data, false).wait().unwrap();
+
+        h.arm(HidCommand::GetSoftKey, Fault::Interleave);
+        assert_eq!(h.hid.get_soft_key(None, 1).wait().unwrap().data, data, "{:?}", mode);
+        assert!(
+            h.wait_for(|e| matches!(e, DaemonEvent::DeviceStateChanged { mode: DeviceMode::Plan, yolo: true, .. })),
+            "{:?}: state report lost",
+            mode
+        );
+        assert!(h.wait_for(|e| matches!(e, DaemonEvent::HidKeyEvent { keycode: 0x0029, .. })), "{:?}: key event lost", mode);
+
+        // Single-chunk response with the unsolicited packets in front
+        h.arm(HidCommand::GetVersion, Fault::Interleave);
+        assert_eq!(h.hid.query_version(None).wait().unwrap(), VIRTUAL_FIRMWARE_VERSION, "{:?}", mode);
+        assert!(h.wait_for(|e| matches!(e, DaemonEvent::HidKeyEvent { keycode: 0x0029, .. })), "{:?}", mode);
+    }
+}
+
+#[test]
+fn test_error_replies() {
+    for mode in MODES {
+        let h = Harness::connect(mode);
+        h.hid.set_soft_key(None, 0, SoftKeyType::Keycode, &[0x00, 0x05], false).wait().unwrap();
+
+        h.arm(HidCommand::GetSoftKey, Fault::ErrorReply(ProtoError::BadSequence));
+        let err = h.hid.get_soft_key(None, 0).wait().unwrap_err();
+        assert!(err.to_string().contains("bad packet sequence"), "{:?}: {}", mode, err);
+        assert_eq!(h.hid.get_soft_key(None, 0).wait().unwrap().data, vec![0x00, 0x05], "{:?}", mode);
+
+        h.arm(HidCommand::GetVersion, Fault::ErrorReply(ProtoError::UnknownCommand));
+        assert_eq!(h.hid.query_version(None).wait().unwrap(), "unknown", "{:?}", mode);
+        assert_eq!(h.hid.query_version(None).wait().unwrap(), VIRTUAL_FIRMWARE_VERSION, "{:?}", mode);
+
+        // Firmware without GetCapabilities still opens, reporting none
+        assert!(h.hid.capabilities("virtual-1").is_some(), "{:?}", mode);
+        h.hid.close_device(None).wait().unwrap();
+        h.arm(HidCommand::GetCapabilities, Fault::ErrorReply(ProtoError::UnknownCommand));
+        h.hid.open_device(None).wait().unwrap();
+        assert_eq!(h.hid.capabilities("virtual-1"), None, "{:?}", mode);
+        assert_eq!(h.hid.query_version(None).wait().unwrap(), VIRTUAL_FIRMWARE_VERSION, "{:?}", mode);
+
+        // Errors for fire-and-forget commands don't break the next request
+        h.arm(HidCommand::SetBrightness, Fault::ErrorReply(ProtoError::Overflow));
+        h.hid.set_brightness(None, 10, false).wait().unwrap();
+        assert_eq!(h.hid.get_soft_key(None, 0).wait().unwrap().data, vec![0x00, 0x05], "{:?}", mode);
+
+        // A stale error for some other command is skipped
+        let stale = build_chunked_packets(
+            HidCommand::Error,
+            &[ProtoError::Overflow as u8, HidCommand::UpdateDisplay.as_byte()],
+            mode,
+        );
+        h.arm(HidCommand::GetSoftKey, Fault::Prepend(stale));
+        assert_eq!(h.hid.get_soft_key(None, 0).wait().unwrap().data, vec![0x00, 0x05], "{:?}", mode);
+    }
+}
+
+#[test]
+fn test_lost_end_flag() {
+    for mode in MODES {
+        let mut h = Harness::connect(mode);
+        let data = key_data(80);
+        h.hid.set_soft_key(None, 1, SoftKeyType::String, &data, false).wait().unwrap();
+
+        // Response never finishes: the request times out, the next one works
+        h.arm(HidCommand::GetSoftKey, Fault::DropEnd);
+        assert!(h.hid.get_soft_key(None, 1).wait().is_err(), "{:?}", mode);
+        assert_eq!(h.hid.get_soft_key(None, 1).wait().unwrap().data, data, "{:?}", mode);
+
+        // An orphaned START is superseded by the real response
+        let orphan = HidPacket::with_command(FLAG_START, HidCommand::GetSoftKey);
+        h.arm(HidCommand::GetSoftKey, Fault::Prepend(vec![orphan]));
+        assert_eq!(h.hid.get_soft_key(None, 1).wait().unwrap().data, data, "{:?}", mode);
+
+        // A type string that lost its END is dropped, the next one is delivered intact
+        let mut broken = build_chunked_packets(HidCommand::TypeString, b"\x00echo 'this string never finishes, so it is never typed'", mode);
+        let last = broken.len() - 1;
+        broken.truncate(last);
+        h.inject(&broken);
+        h.deck.inject_type_string("ls", false);
+        assert!(
+            h.wait_for(|e| matches!(e, DaemonEvent::HidTypeString { text, send_enter: false, .. } if text == "ls")),
+            "{:?}",
+            mode
+        );
+    }
+}
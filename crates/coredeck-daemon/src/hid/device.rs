@@ -1,144 +1,233 @@
 //! HID device discovery and connection management
+//!
+//! Several decks can be plugged in at once. Each is addressed by the serial
+//! from enumeration; commands given no serial go to every open deck.
 
 use super::commands;
+use super::metrics::{HidMetrics, MeteredTransport};
 use super::protocol::{
-    DeviceMode, DeviceState, HidCommand, HidPacket, ProtocolMode, ResponsePacket, SoftKeyConfig,
-    SoftKeyType, PACKET_SIZE, VIAL_PREFIX,
+    DeviceMode, DeviceState, HidCommand, HidPacket, ProtoError, ProtocolMode, ResponsePacket,
+    SoftKeyConfig, SoftKeyType, MAX_MESSAGE_SIZE, PACKET_SIZE,
 };
+use super::transport::{DeviceListing, HidApiBackend, HidBackend, HidTransport};
 use crate::HidConfig;
 use crate::state::{DaemonEvent, DaemonEventSender};
-use anyhow::{anyhow, Context, Result};
-use hidapi::{HidApi, HidDevice};
+use anyhow::{anyhow, Result};
+use super::writer::{self, HidCompletion, HidQueue};
+use coredeck_protocol::{DeviceCapabilities, DisplayUpdate};
 use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
 
-#[cfg(target_os = "macos")]
-use super::hotplug_macos::{HotplugEvent, HotplugWatcher};
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+use super::hotplug::HotplugWatcher;
 
 /// Number of consecutive ping failures before declaring disconnection
 const DISCONNECT_THRESHOLD: u32 = 3;
 
-/// Polling interval when hotplug is not available (non-macOS platforms)
-#[cfg(not(target_os = "macos"))]
+/// Upper bound on reports read while waiting for one response.
+/// A 512-byte response is 18 VIAL chunks; the rest is headroom for
+/// unsolicited packets interleaved mid-response.
+const MAX_RESPONSE_READS: usize = 64;
+
+/// Polling interval when hotplug is not available (other platforms, virtual deck).
+/// Backs off up to `reconnect_interval_ms` while no device is present.
 const RECONNECT_INITIAL_MS: u64 = 500;
 
-#[cfg(not(target_os = "macos"))]
-const RECONNECT_MAX_MS: u64 = 5000;
+/// Delay between a hotplug notification and re-enumerating.
+/// Linux sees the kernel uevent before udev has applied the hidraw permissions.
+#[cfg(target_os = "macos")]
+const HOTPLUG_SETTLE_MS: u64 = 100;
+#[cfg(target_os = "linux")]
+const HOTPLUG_SETTLE_MS: u64 = 500;
+
+/// One plugged-in deck and, while open, its HID interface
+struct Deck {
+    /// Display name from enumeration
+    name: String,
+    /// Open HID interface (`None` while closed)
+    transport: Mutex<Option<Box<dyn HidTransport>>>,
+    /// Whether the HID interface is open (readable without waiting on `transport`)
+    connected: AtomicBool,
+    /// Active protocol mode (Standalone=0, Vial=1)
+    protocol_mode: AtomicU8,
+    /// Limits reported by the firmware while open (`None` if it predates the query)
+    capabilities: Mutex<Option<DeviceCapabilities>>,
+    /// Last display payload sent (for deduplication). Cleared on close.
+    last_display_payload: Mutex<String>,
+}
+
+impl Deck {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            transport: Mutex::new(None),
+            connected: AtomicBool::new(false),
+            protocol_mode: AtomicU8::new(ProtocolMode::Standalone as u8),
+            capabilities: Mutex::new(None),
+            last_display_payload: Mutex::new(String::new()),
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Current protocol mode (Standalone or Vial)
+    fn mode(&self) -> ProtocolMode {
+        ProtocolMode::from_byte(self.protocol_mode.load(Ordering::Relaxed))
+    }
+
+    /// Drop the HID interface and forget per-connection state.
+    /// With `goodbye`, sends Disconnect first so the firmware goes idle.
+    /// Returns whether the deck was open.
+    fn close(&self, goodbye: bool) -> bool {
+        let mut guard = self.transport.lock();
+        if goodbye {
+            if let Some(dev) = guard.as_deref() {
+                let mode = self.mode();
+                let packets = commands::build_disconnect(mode);
+                if let Err(e) = send_packets_to_device(dev, &packets, mode) {
+                    debug!("Failed to send disconnect: {}", e);
+                }
+            }
+        }
+        let was_open = guard.take().is_some();
+        self.connected.store(false, Ordering::Relaxed);
+        self.protocol_mode.store(ProtocolMode::Standalone as u8, Ordering::Relaxed);
+        *self.capabilities.lock() = None;
+        // Clear dedup cache so the next connect sends a fresh display update
+        *self.last_display_payload.lock() = String::new();
+        was_open
+    }
+}
 
-/// Manager for HID device communication with Core Deck
+/// Plugged-in decks by serial, shared with the monitor threads
+type Decks = Arc<Mutex<BTreeMap<String, Arc<Deck>>>>;
+
+/// Per-deck state of the reader thread
+#[derive(Default)]
+struct ReaderState {
+    consecutive_failures: u32,
+    /// `None` triggers an immediate first ping
+    last_ping: Option<Instant>,
+    type_string_buf: Vec<u8>,
+}
+
+/// Manager for HID device communication with Core Decks
 pub struct HidManager {
-    /// HID API instance
-    api: Arc<Mutex<HidApi>>,
-    /// Connected device (if any)
-    device: Arc<Mutex<Option<HidDevice>>>,
-    /// Configuration
-    config: HidConfig,
+    /// Device discovery backend (hidapi or virtual)
+    backend: Arc<Mutex<Box<dyn HidBackend>>>,
+    /// Plugged-in decks (tracked by enumeration/hotplug)
+    decks: Decks,
+    /// Configuration (replaced on config reload, read by the monitor threads)
+    config: Arc<Mutex<HidConfig>>,
     /// Event sender for status updates (wakes event loop)
     event_tx: DaemonEventSender,
-    /// Whether currently connected (HID interface open)
-    connected: Arc<AtomicBool>,
-    /// Whether the USB device is physically present (detected by enumeration/hotplug)
-    device_available: Arc<AtomicBool>,
-    /// Cached device name from enumeration (available without opening device)
-    cached_device_name: Arc<Mutex<Option<String>>>,
     /// Whether the monitor thread should stop
     stop_monitor: Arc<AtomicBool>,
-    /// Active protocol mode (Standalone=0, Vial=1), shared with all threads
-    protocol_mode: Arc<AtomicU8>,
-    /// Last display payload sent (for deduplication).
-    /// Shared with monitor threads so disconnect clears it.
-    last_display_payload: Arc<Mutex<String>>,
-    /// macOS hotplug watcher
-    #[cfg(target_os = "macos")]
+    /// Link health counters (shared with the reader thread and `/api/metrics`)
+    metrics: Arc<HidMetrics>,
+    /// Commands waiting for the writer thread
+    queue: Arc<HidQueue>,
+    /// Native hotplug watcher (IOKit on macOS, uevents on Linux)
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
     hotplug_watcher: Option<HotplugWatcher>,
 }
 
 impl HidManager {
-    /// Create a new HID manager.
+    /// Create a new HID manager for physical devices (hidapi backend).
     ///
-    /// Does NOT open the HID device — only enumerates to check availability.
-    /// Call `open_device()` to actually open the device (when the app connects).
+    /// Does NOT open any HID device — only enumerates to check availability.
+    /// Call `open_device()` to actually open decks (when the app connects).
     pub fn new(config: HidConfig, event_tx: DaemonEventSender) -> Result<Self> {
-        let api = HidApi::new().context("Failed to initialize HID API")?;
-
-        // Don't seize the device exclusively on macOS — we only need the
-        // vendor-specific raw-HID interface (0xFF60) and must not prevent
-        // the system keyboard driver from receiving events on the standard
-        // keyboard interface of the same composite USB device.
-        #[cfg(target_os = "macos")]
-        {
-            api.set_open_exclusive(false);
-        }
+        let backend = HidApiBackend::new()?;
+        Ok(Self::with_backend(config, event_tx, Box::new(backend)))
+    }
 
-        // Check if device is physically present (enumerate only, no open)
-        let (available, cached_name) = check_device_presence(&api, &config);
-        if available {
-            info!("Device available: {}", cached_name.as_deref().unwrap_or("?"));
-        } else {
+    /// Create a new HID manager on top of an arbitrary backend (e.g. the virtual deck).
+    pub fn with_backend(
+        config: HidConfig,
+        event_tx: DaemonEventSender,
+        backend: Box<dyn HidBackend>,
+    ) -> Self {
+        // Check which decks are physically present (enumerate only, no open)
+        let listings = backend.list(&config);
+        if listings.is_empty() {
             info!("Device not found during initial enumeration");
         }
+        let decks = listings
+            .into_iter()
+            .map(|listing| {
+                info!("Device available: {} ({})", listing.name, listing.serial);
+                (listing.serial, Arc::new(Deck::new(listing.name)))
+            })
+            .collect();
+
+        let use_hotplug = backend.supports_hotplug();
+        let metrics = Arc::new(HidMetrics::default());
 
         let mut manager = Self {
-            api: Arc::new(Mutex::new(api)),
-            device: Arc::new(Mutex::new(None)),
-            config: config.clone(),
-            event_tx: event_tx.clone(),
-            connected: Arc::new(AtomicBool::new(false)),
-            device_available: Arc::new(AtomicBool::new(available)),
-            cached_device_name: Arc::new(Mutex::new(cached_name)),
+            backend: Arc::new(Mutex::new(backend)),
+            decks: Arc::new(Mutex::new(decks)),
+            config: Arc::new(Mutex::new(config.clone())),
+            event_tx,
             stop_monitor: Arc::new(AtomicBool::new(false)),
-            protocol_mode: Arc::new(AtomicU8::new(ProtocolMode::Standalone as u8)),
-            last_display_payload: Arc::new(Mutex::new(String::new())),
-            #[cfg(target_os = "macos")]
+            metrics: Arc::clone(&metrics),
+            queue: Arc::new(HidQueue::new(metrics)),
+            #[cfg(any(target_os = "macos", target_os = "linux"))]
             hotplug_watcher: None,
         };
 
         // Start the appropriate monitor mechanism (hotplug only tracks availability)
-        #[cfg(target_os = "macos")]
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
         {
-            manager.start_macos_hotplug(config, event_tx);
+            if use_hotplug {
+                manager.start_hotplug(config);
+            } else {
+                manager.start_polling_monitor_internal();
+            }
         }
 
-        #[cfg(not(target_os = "macos"))]
+        #[cfg(not(any(target_os = "macos", target_os = "linux")))]
         {
+            let _ = (use_hotplug, config);
             manager.start_polling_monitor();
         }
 
         // Start ping thread for connection health monitoring
         manager.start_ping_thread();
+        manager.start_writer_thread();
 
-        Ok(manager)
+        manager
     }
 
-    /// Start macOS IOKit hotplug watcher.
+    /// Start the native hotplug watcher.
     ///
-    /// Only tracks device availability (plug/unplug). Does NOT open the device.
-    /// If the device was open when removed, closes it and emits HidDisconnected.
-    #[cfg(target_os = "macos")]
-    fn start_macos_hotplug(&mut self, config: HidConfig, _event_tx: DaemonEventSender) {
+    /// Every notification triggers a re-enumeration, which tracks availability
+    /// (plug/unplug) per deck. Does NOT open decks. A deck removed while open
+    /// is closed and HidDisconnected is emitted.
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    fn start_hotplug(&mut self, config: HidConfig) {
         // Create channel for hotplug events
         let (hotplug_tx, mut hotplug_rx) = tokio::sync::mpsc::unbounded_channel();
 
-        // Start the IOKit watcher
+        // Start the native watcher
         match HotplugWatcher::new(config.vendor_id, config.product_id, hotplug_tx) {
             Ok(watcher) => {
                 self.hotplug_watcher = Some(watcher);
-                info!("Started native IOKit hotplug watcher");
+                info!("Started native hotplug watcher");
 
-                let api = Arc::clone(&self.api);
-                let device = Arc::clone(&self.device);
-                let connected = Arc::clone(&self.connected);
-                let device_available = Arc::clone(&self.device_available);
-                let cached_device_name = Arc::clone(&self.cached_device_name);
+                let backend = Arc::clone(&self.backend);
+                let decks = Arc::clone(&self.decks);
                 let stop_monitor = Arc::clone(&self.stop_monitor);
-                let protocol_mode = Arc::clone(&self.protocol_mode);
-                let last_display_payload = Arc::clone(&self.last_display_payload);
                 let event_tx = self.event_tx.clone();
-                let config = self.config.clone();
+                let config = Arc::clone(&self.config);
+                let metrics = Arc::clone(&self.metrics);
 
                 thread::spawn(move || {
                     let rt = tokio::runtime::Builder::new_current_thread()
@@ -150,52 +239,14 @@ impl HidManager {
                         while !stop_monitor.load(Ordering::Relaxed) {
                             tokio::select! {
                                 Some(event) = hotplug_rx.recv() => {
-                                    match event {
-                                        HotplugEvent::DeviceArrived => {
-                                            // Small delay to let the device initialize
-                                            tokio::time::sleep(Duration::from_millis(100)).await;
-
-                                            // Refresh device list to see the new device
-                                            {
-                                                let mut api_guard = api.lock();
-                                                let _ = api_guard.refresh_devices();
-                                            }
-
-                                            // Check presence (enumerate only, no open)
-                                            let name = {
-                                                let api_guard = api.lock();
-                                                let (avail, name) = check_device_presence(&api_guard, &config);
-                                                if avail {
-                                                    device_available.store(true, Ordering::Relaxed);
-                                                    *cached_device_name.lock() = name.clone();
-                                                }
-                                                name
-                                            };
-
-                                            let device_name = name.unwrap_or_else(|| "Core Deck".to_string());
-                                            info!("Device available via hotplug: {}", device_name);
-                                            let _ = event_tx.send(DaemonEvent::DeviceAvailable {
-                                                device_name,
-                                            });
-                                        }
-                                        HotplugEvent::DeviceRemoved => {
-                                            device_available.store(false, Ordering::Relaxed);
-                                            *cached_device_name.lock() = None;
-
-                                            // If device was open, close it
-                                            if connected.load(Ordering::Relaxed) {
-                                                *device.lock() = None;
-                                                connected.store(false, Ordering::Relaxed);
-                                                protocol_mode.store(ProtocolMode::Standalone as u8, Ordering::Relaxed);
-                                                *last_display_payload.lock() = String::new();
-                                                let _ = event_tx.send(DaemonEvent::HidDisconnected);
-                                                info!("Device disconnected via hotplug (was open)");
-                                            }
-
-                                            let _ = event_tx.send(DaemonEvent::DeviceUnavailable);
-                                            info!("Device unavailable via hotplug");
-                                        }
-                                    }
+                                    debug!("Hotplug event: {:?}", event);
+                                    // Let the device initialize, then fold any burst
+                                    // of notifications into one re-enumeration
+                                    tokio::time::sleep(Duration::from_millis(HOTPLUG_SETTLE_MS)).await;
+                                    while hotplug_rx.try_recv().is_ok() {}
+
+                                    let config = config.lock().clone();
+                                    rescan(&backend, &config, &decks, &event_tx, &metrics, "hotplug");
                                 }
                                 _ = tokio::time::sleep(Duration::from_millis(100)) => {
                                     if stop_monitor.load(Ordering::Relaxed) {
@@ -208,93 +259,43 @@ impl HidManager {
                 });
             }
             Err(e) => {
-                warn!("Failed to start IOKit hotplug watcher: {}, falling back to polling", e);
+                warn!("Failed to start hotplug watcher: {}, falling back to polling", e);
                 self.start_polling_monitor_internal();
             }
         }
     }
 
-    /// Start polling-based monitor (for non-macOS or fallback)
-    #[cfg(not(target_os = "macos"))]
+    /// Start polling-based monitor (for platforms without a hotplug watcher)
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
     fn start_polling_monitor(&self) {
         self.start_polling_monitor_internal();
     }
 
     /// Internal polling monitor implementation.
     ///
-    /// Only tracks device availability (enumerate, no open). Does NOT open the device.
+    /// Only tracks device availability (enumerate, no open). Does NOT open decks.
     fn start_polling_monitor_internal(&self) {
-        let api = Arc::clone(&self.api);
-        let device = Arc::clone(&self.device);
-        let connected = Arc::clone(&self.connected);
-        let device_available = Arc::clone(&self.device_available);
-        let cached_device_name = Arc::clone(&self.cached_device_name);
+        let backend = Arc::clone(&self.backend);
+        let decks = Arc::clone(&self.decks);
         let stop_monitor = Arc::clone(&self.stop_monitor);
-        let protocol_mode = Arc::clone(&self.protocol_mode);
-        let last_display_payload = Arc::clone(&self.last_display_payload);
         let event_tx = self.event_tx.clone();
-        let config = self.config.clone();
+        let config = Arc::clone(&self.config);
+        let metrics = Arc::clone(&self.metrics);
 
         thread::spawn(move || {
             info!("HID polling monitor thread started");
 
-            #[cfg(not(target_os = "macos"))]
             let mut poll_interval_ms = RECONNECT_INITIAL_MS;
-            #[cfg(target_os = "macos")]
-            let mut poll_interval_ms = 500u64;
-
-            #[cfg(not(target_os = "macos"))]
-            let max_interval = RECONNECT_MAX_MS;
-            #[cfg(target_os = "macos")]
-            let max_interval = 5000u64;
-
-            let mut was_available = device_available.load(Ordering::Relaxed);
 
             while !stop_monitor.load(Ordering::Relaxed) {
-                // Refresh device list
-                {
-                    let mut api_guard = api.lock();
-                    if let Err(e) = api_guard.refresh_devices() {
-                        debug!("Failed to refresh device list: {}", e);
-                    }
+                let config = config.lock().clone();
+                if rescan(&backend, &config, &decks, &event_tx, &metrics, "polling") {
+                    poll_interval_ms = RECONNECT_INITIAL_MS;
+                } else if decks.lock().is_empty() {
+                    poll_interval_ms = (poll_interval_ms * 3 / 2)
+                        .min(config.reconnect_interval_ms.max(RECONNECT_INITIAL_MS));
                 }
 
-                // Check presence (enumerate only, no open)
-                let (is_available, name) = {
-                    let api_guard = api.lock();
-                    check_device_presence(&api_guard, &config)
-                };
-
-                if is_available && !was_available {
-                    // Device just appeared
-                    device_available.store(true, Ordering::Relaxed);
-                    *cached_device_name.lock() = name.clone();
-                    let device_name = name.unwrap_or_else(|| "Core Deck".to_string());
-                    info!("Device available via polling: {}", device_name);
-                    let _ = event_tx.send(DaemonEvent::DeviceAvailable { device_name });
-                    poll_interval_ms = 500;
-                } else if !is_available && was_available {
-                    // Device just disappeared
-                    device_available.store(false, Ordering::Relaxed);
-                    *cached_device_name.lock() = None;
-
-                    // If device was open, close it
-                    if connected.load(Ordering::Relaxed) {
-                        *device.lock() = None;
-                        connected.store(false, Ordering::Relaxed);
-                        protocol_mode.store(ProtocolMode::Standalone as u8, Ordering::Relaxed);
-                        *last_display_payload.lock() = String::new();
-                        let _ = event_tx.send(DaemonEvent::HidDisconnected);
-                        info!("Device disconnected via polling (was open)");
-                    }
-
-                    let _ = event_tx.send(DaemonEvent::DeviceUnavailable);
-                    info!("Device unavailable via polling");
-                } else if !is_available {
-                    poll_interval_ms = (poll_interval_ms * 3 / 2).min(max_interval);
-                }
-
-                was_available = is_available;
                 thread::sleep(Duration::from_millis(poll_interval_ms));
             }
             info!("HID polling monitor thread stopped");
@@ -303,532 +304,871 @@ impl HidManager {
 
     /// Start reader thread for connection health monitoring and incoming key events.
     ///
-    /// This thread performs two duties:
+    /// For every open deck, this thread:
     /// 1. Sends ping keepalives on a timer to detect disconnection
     /// 2. Polls for incoming device-initiated packets (key events, type strings, state reports)
     fn start_ping_thread(&self) {
-        let device = Arc::clone(&self.device);
-        let connected = Arc::clone(&self.connected);
+        let decks = Arc::clone(&self.decks);
         let stop_monitor = Arc::clone(&self.stop_monitor);
-        let protocol_mode = Arc::clone(&self.protocol_mode);
-        let last_display_payload = Arc::clone(&self.last_display_payload);
         let event_tx = self.event_tx.clone();
-        let ping_interval = Duration::from_millis(self.config.ping_interval_ms);
+        let config = Arc::clone(&self.config);
+        let metrics = Arc::clone(&self.metrics);
 
         thread::spawn(move || {
             info!("HID reader thread started");
-            let mut consecutive_failures: u32 = 0;
-            let mut last_ping = Instant::now() - ping_interval; // trigger immediate first ping
-            let mut type_string_buf: Vec<u8> = Vec::new();
+            let mut readers: HashMap<String, ReaderState> = HashMap::new();
 
             while !stop_monitor.load(Ordering::Relaxed) {
-                if !connected.load(Ordering::Relaxed) {
+                let ping_interval = Duration::from_millis(config.lock().ping_interval_ms);
+                let open: Vec<(String, Arc<Deck>)> = decks
+                    .lock()
+                    .iter()
+                    .filter(|(_, deck)| deck.is_connected())
+                    .map(|(serial, deck)| (serial.clone(), Arc::clone(deck)))
+                    .collect();
+
+                // Forget decks that were closed since the last pass
+                readers.retain(|serial, _| open.iter().any(|(s, _)| s == serial));
+                if open.is_empty() {
                     thread::sleep(Duration::from_millis(100));
                     continue;
                 }
 
-                // --- Ping on timer ---
-                if last_ping.elapsed() >= ping_interval {
-                    let mode = ProtocolMode::from_byte(protocol_mode.load(Ordering::Relaxed));
-                    let ping_ok = {
-                        let device_guard = device.lock();
-                        if let Some(ref dev) = *device_guard {
-                            let packets = commands::build_ping(mode);
-                            match send_packets_to_device(dev, &packets, mode) {
-                                Ok(()) => {
-                                    debug!("Ping sent");
-                                    // Read pong response
-                                    match read_raw_packet(dev, 100, mode) {
-                                        Ok(Some(pkt)) => {
-                                            dispatch_incoming_packet(&pkt, &event_tx, &mut type_string_buf);
-                                            true
-                                        }
-                                        Ok(None) => {
-                                            debug!("No pong response");
-                                            true // Write succeeded, device might be busy
-                                        }
-                                        Err(e) => {
-                                            warn!("Error reading pong: {}", e);
-                                            false
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    warn!("Failed to send ping: {}", e);
-                                    false
-                                }
-                            }
-                        } else {
-                            false
-                        }
-                    };
-
-                    last_ping = Instant::now();
-
-                    if ping_ok {
-                        consecutive_failures = 0;
-                    } else {
-                        consecutive_failures += 1;
-                        warn!(
-                            "Ping failure {} of {}",
-                            consecutive_failures, DISCONNECT_THRESHOLD
-                        );
-
-                        if consecutive_failures >= DISCONNECT_THRESHOLD {
-                            info!("Device disconnected (consecutive ping failures)");
-                            *device.lock() = None;
-                            connected.store(false, Ordering::Relaxed);
-                            protocol_mode.store(ProtocolMode::Standalone as u8, Ordering::Relaxed);
-                            *last_display_payload.lock() = String::new();
-                            let _ = event_tx.send(DaemonEvent::HidDisconnected);
-                            consecutive_failures = 0;
-                            type_string_buf.clear();
-                            continue;
-                        }
-                    }
+                for (serial, deck) in &open {
+                    let reader = readers.entry(serial.clone()).or_default();
+                    service_deck(serial, deck, reader, ping_interval, &event_tx, &metrics);
                 }
-
-                // --- Poll for incoming device-initiated packets ---
-                // Use try_lock to avoid blocking command sends (send_display_update, etc.)
-                if let Some(device_guard) = device.try_lock() {
-                    if let Some(ref dev) = *device_guard {
-                        let poll_mode = ProtocolMode::from_byte(protocol_mode.load(Ordering::Relaxed));
-                        match read_raw_packet(dev, 20, poll_mode) {
-                            Ok(Some(pkt)) => {
-                                dispatch_incoming_packet(&pkt, &event_tx, &mut type_string_buf);
-                            }
-                            Ok(None) => {} // Timeout, no data
-                            Err(e) => {
-                                debug!("Poll read error: {}", e);
-                            }
-                        }
-                    }
-                }
-                // Brief yield if nothing happened to avoid busy-wait
-                // (the 20ms read timeout above provides the main throttle)
+                // No extra sleep: the 20ms poll read per deck provides the throttle
             }
             info!("HID reader thread stopped");
         });
     }
 
-    /// Try to connect to the Core Deck device
-    pub fn try_connect(&self) -> Result<()> {
-        let api = self.api.lock();
-
-        // Find device by VID/PID and usage page
-        let device_info = api
-            .device_list()
-            .find(|d| {
-                d.vendor_id() == self.config.vendor_id
-                    && d.product_id() == self.config.product_id
-                    && d.usage_page() == self.config.usage_page
-                    && d.usage() == self.config.usage_id
-            })
-            .ok_or_else(|| {
-                anyhow!(
-                    "Core Deck not found (VID: 0x{:04X}, PID: 0x{:04X}, Usage: 0x{:04X}/0x{:02X})",
-                    self.config.vendor_id,
-                    self.config.product_id,
-                    self.config.usage_page,
-                    self.config.usage_id
-                )
-            })?;
-
-        let device_name = format!(
-            "{} {}",
-            device_info.manufacturer_string().unwrap_or("").trim(),
-            device_info.product_string().unwrap_or("Core Deck").trim()
-        ).trim().to_string();
-
-        info!("Found Core Deck: {}", device_name);
-
-        // Open the device
-        let device = device_info
-            .open_device(&api)
-            .context("Failed to open HID device")?;
-
-        // Set non-blocking mode
-        device
-            .set_blocking_mode(false)
-            .context("Failed to set non-blocking mode")?;
-
-        // Detect protocol mode and firmware version before storing
-        let (detected_mode, firmware_version) = detect_protocol_mode(&device, &self.event_tx);
-        self.protocol_mode.store(detected_mode as u8, Ordering::Relaxed);
-
-        // Store device
-        *self.device.lock() = Some(device);
-        self.connected.store(true, Ordering::Relaxed);
+    /// Start the writer thread, which carries out queued commands in order
+    fn start_writer_thread(&self) {
+        let queue = Arc::clone(&self.queue);
+        let io = DeckIo {
+            backend: Arc::clone(&self.backend),
+            decks: Arc::clone(&self.decks),
+            config: Arc::clone(&self.config),
+            event_tx: self.event_tx.clone(),
+            metrics: Arc::clone(&self.metrics),
+        };
 
-        // Notify connection
-        let _ = self.event_tx.send(DaemonEvent::HidConnected {
-            device_name,
-            firmware_version,
+        thread::spawn(move || {
+            info!("HID writer thread started");
+            while let Some(job) = queue.pop() {
+                job.run(&io);
+            }
+            info!("HID writer thread stopped");
         });
+    }
 
-        info!("Connected to Core Deck");
-        Ok(())
+    /// Replace the HID configuration (config reload).
+    ///
+    /// Intervals take effect on the next monitor/ping iteration and new IDs on
+    /// the next enumeration. Already-open decks stay open.
+    pub fn set_config(&self, config: HidConfig) {
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
+        {
+            let old = self.config.lock();
+            if self.hotplug_watcher.is_some()
+                && (old.vendor_id != config.vendor_id || old.product_id != config.product_id)
+            {
+                warn!("Hotplug watcher keeps the old VID/PID until the daemon restarts");
+            }
+        }
+        *self.config.lock() = config;
     }
 
-    /// Check if device is connected (HID interface open)
+    /// Link health counters
+    pub fn metrics(&self) -> Arc<HidMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Check if any deck is connected (HID interface open)
     pub fn is_connected(&self) -> bool {
-        self.connected.load(Ordering::Relaxed)
+        self.decks.lock().values().any(|deck| deck.is_connected())
     }
 
-    /// Check if device is physically present (USB enumerated, not necessarily open)
+    /// Check if any deck is physically present (USB enumerated, not necessarily open)
     pub fn is_device_available(&self) -> bool {
-        self.device_available.load(Ordering::Relaxed)
+        !self.decks.lock().is_empty()
+    }
+
+    /// Plugged-in decks (available without opening), ordered by serial
+    pub fn devices(&self) -> Vec<DeviceListing> {
+        self.decks
+            .lock()
+            .iter()
+            .map(|(serial, deck)| DeviceListing {
+                serial: serial.clone(),
+                name: deck.name.clone(),
+            })
+            .collect()
+    }
+
+    /// Limits reported by an open deck's firmware
+    pub fn capabilities(&self, serial: &str) -> Option<DeviceCapabilities> {
+        self.decks.lock().get(serial).and_then(|deck| deck.capabilities.lock().clone())
+    }
+
+    /// Limits a payload for the addressed decks (`None` = every open deck) must
+    /// fit: the tightest reported, or the defaults for firmware that reports none.
+    pub fn limits(&self, serial: Option<&str>) -> DeviceCapabilities {
+        let decks = self.decks.lock();
+        let mut limits: Option<DeviceCapabilities> = None;
+        for (_, deck) in decks.iter().filter(|(s, deck)| serial.map_or(deck.is_connected(), |serial| *s == serial)) {
+            let caps = deck.capabilities.lock().clone().unwrap_or_default();
+            limits = Some(match limits {
+                None => caps,
+                Some(mut l) => {
+                    l.max_tabs = l.max_tabs.min(caps.max_tabs);
+                    l.max_text_bytes = l.max_text_bytes.min(caps.max_text_bytes);
+                    l.max_payload_bytes = l.max_payload_bytes.min(caps.max_payload_bytes);
+                    l.soft_key_count = l.soft_key_count.min(caps.soft_key_count);
+                    l
+                }
+            });
+        }
+        limits.unwrap_or_default()
     }
 
-    /// Get cached device name from enumeration (available without opening)
-    pub fn cached_device_name(&self) -> Option<String> {
-        self.cached_device_name.lock().clone()
+    /// Serials of the decks whose HID interface is open
+    pub fn connected_serials(&self) -> Vec<String> {
+        self.decks
+            .lock()
+            .iter()
+            .filter(|(_, deck)| deck.is_connected())
+            .map(|(serial, _)| serial.clone())
+            .collect()
     }
 
-    /// Open the HID device for communication.
+    /// Open decks for communication (`None` = every plugged-in deck).
     ///
     /// Called by WS handler when the app connects. On macOS this causes
     /// IOHIDDeviceOpen which captures key-up events from the keyboard
     /// interface — this is intentional while the app is connected.
-    pub fn open_device(&self) -> Result<()> {
-        if self.connected.load(Ordering::Relaxed) {
-            return Ok(()); // Already open
-        }
-        self.try_connect()
+    /// The writer thread opens them; succeeds if at least one addressed deck
+    /// is open afterwards.
+    pub fn open_device(&self, serial: Option<&str>) -> HidCompletion<()> {
+        let serial = serial.map(str::to_string);
+        self.queue.push(move |io| io.open_device(serial.as_deref()))
     }
 
-    /// Close the HID device, releasing the IOKit HID handle.
+    /// Close decks (`None` = every open deck), releasing the IOKit HID handle.
     ///
     /// Sends a Disconnect command first so the firmware immediately goes
     /// idle (restores key routing to system keyboard, shows logo, dims).
-    /// Called by WS handler when the app disconnects and by HTTP handlers
-    /// after transient operations.
-    pub fn close_device(&self) {
-        let mut device_guard = self.device.lock();
-        if let Some(ref dev) = *device_guard {
-            // Tell firmware we're going away — it will immediately go idle
-            let mode = self.mode();
-            let packets = commands::build_disconnect(mode);
-            if let Err(e) = send_packets_to_device(dev, &packets, mode) {
-                debug!("Failed to send disconnect: {}", e);
+    /// Called by WS handler when the app disconnects. Queued behind the
+    /// commands already waiting, which still reach the decks.
+    pub fn close_device(&self, serial: Option<&str>) -> HidCompletion<()> {
+        let serial = serial.map(str::to_string);
+        self.queue.push(move |io| {
+            io.close_device(serial.as_deref());
+            Ok(())
+        })
+    }
+
+    /// Queue opening the addressed decks (`None` = every plugged-in deck) that
+    /// aren't open yet, for an HTTP request. Resolves to the serials it opened.
+    pub fn open_transient(&self, serial: Option<&str>) -> HidCompletion<Vec<String>> {
+        let serial = serial.map(str::to_string);
+        self.queue.push(move |io| {
+            let already_open = io.connected_serials();
+            io.open_device(serial.as_deref())?;
+            Ok(io.connected_serials().into_iter().filter(|s| !already_open.contains(s)).collect())
+        })
+    }
+
+    /// Queue closing the decks `open_transient` opened, after the commands queued before
+    pub fn close_transient(&self, serials: Vec<String>) -> HidCompletion<()> {
+        self.queue.push(move |io| {
+            for serial in &serials {
+                io.close_device(Some(serial));
             }
-        }
-        if device_guard.take().is_some() {
-            self.connected.store(false, Ordering::Relaxed);
-            self.protocol_mode.store(ProtocolMode::Standalone as u8, Ordering::Relaxed);
-            *self.last_display_payload.lock() = String::new();
-            // Don't emit HidDisconnected — the WS handler manages the lifecycle
-            info!("HID device closed (released to system)");
-        }
+            Ok(())
+        })
     }
 
-    /// Current protocol mode (Standalone or Vial)
-    fn mode(&self) -> ProtocolMode {
-        ProtocolMode::from_byte(self.protocol_mode.load(Ordering::Relaxed))
+    /// Queue a display update with session name, current task, tab states, and active tab index.
+    /// A queued update for the same deck that the writer hasn't reached yet is replaced.
+    pub fn send_display_update(
+        &self,
+        serial: Option<&str>,
+        session: &str,
+        task: Option<&str>,
+        task2: Option<&str>,
+        tabs: &[u8],
+        active: usize,
+    ) -> HidCompletion<()> {
+        let update = DisplayUpdate {
+            session: session.to_string(),
+            task: task.unwrap_or("").to_string(),
+            task2: task2.unwrap_or("").to_string(),
+            tabs: tabs.to_vec(),
+            active,
+        };
+        self.queue.push_display(serial.map(str::to_string), update)
     }
 
-    /// Send a display update with session name, current task, tab states, and active tab index.
-    /// Skips sending if the payload is identical to the last one sent.
-    pub fn send_display_update(&self, session: &str, task: Option<&str>, task2: Option<&str>, tabs: &[u8], active: usize) -> Result<()> {
-        // Build a dedup key from the payload fields
-        let payload_key = format!("{}|{}|{}|{:?}|{}", session, task.unwrap_or(""), task2.unwrap_or(""), tabs, active);
-        {
-            let mut last = self.last_display_payload.lock();
-            if *last == payload_key {
-                return Ok(());
+    /// Query firmware version from a deck (`None` = the first open one).
+    /// Resolves to a fallback if the device doesn't support the command.
+    pub fn query_version(&self, serial: Option<&str>) -> HidCompletion<String> {
+        let serial = serial.map(str::to_string);
+        self.queue.push(move |io| Ok(io.query_version(serial.as_deref())))
+    }
+
+    /// Set display brightness
+    pub fn set_brightness(&self, serial: Option<&str>, level: u8, save: bool) -> HidCompletion<()> {
+        let serial = serial.map(str::to_string);
+        self.queue.push(move |io| io.set_brightness(serial.as_deref(), level, save))
+    }
+
+    /// Set a soft key assignment
+    pub fn set_soft_key(
+        &self,
+        serial: Option<&str>,
+        index: u8,
+        key_type: SoftKeyType,
+        data: &[u8],
+        save: bool,
+    ) -> HidCompletion<()> {
+        let serial = serial.map(str::to_string);
+        let data = data.to_vec();
+        self.queue.push(move |io| io.set_soft_key(serial.as_deref(), index, key_type, &data, save))
+    }
+
+    /// Get a soft key configuration (`None` = from the first open deck)
+    pub fn get_soft_key(&self, serial: Option<&str>, index: u8) -> HidCompletion<SoftKeyConfig> {
+        let serial = serial.map(str::to_string);
+        self.queue.push(move |io| io.get_soft_key(serial.as_deref(), index))
+    }
+
+    /// Reset all soft keys to defaults, resolving to the effective assignments
+    pub fn reset_soft_keys(&self, serial: Option<&str>) -> HidCompletion<[SoftKeyConfig; 3]> {
+        let serial = serial.map(str::to_string);
+        self.queue.push(move |io| io.reset_soft_keys(serial.as_deref()))
+    }
+
+    /// Send an alert overlay
+    pub fn send_alert(
+        &self,
+        serial: Option<&str>,
+        tab: usize,
+        session: &str,
+        text: &str,
+        details: Option<&str>,
+    ) -> HidCompletion<()> {
+        let serial = serial.map(str::to_string);
+        let (session, text, details) = (session.to_string(), text.to_string(), details.map(str::to_string));
+        self.queue
+            .push(move |io| io.send_alert(serial.as_deref(), tab, &session, &text, details.as_deref()))
+    }
+
+    /// Clear an alert overlay
+    pub fn clear_alert(&self, serial: Option<&str>, tab: usize) -> HidCompletion<()> {
+        let serial = serial.map(str::to_string);
+        self.queue.push(move |io| io.clear_alert(serial.as_deref(), tab))
+    }
+
+    /// Set device LED mode
+    pub fn set_mode(&self, serial: Option<&str>, mode: DeviceMode) -> HidCompletion<()> {
+        let serial = serial.map(str::to_string);
+        self.queue.push(move |io| io.set_mode(serial.as_deref(), mode))
+    }
+
+    /// Disconnect from every deck
+    pub fn disconnect(&self) {
+        let decks: Vec<(String, Arc<Deck>)> = self
+            .decks
+            .lock()
+            .iter()
+            .map(|(serial, deck)| (serial.clone(), Arc::clone(deck)))
+            .collect();
+        for (serial, deck) in decks {
+            if deck.close(false) {
+                let _ = self.event_tx.send(DaemonEvent::HidDisconnected { serial: serial.clone() });
+                info!("Disconnected from Core Deck {}", serial);
             }
-            *last = payload_key;
         }
+    }
+}
 
-        let device_guard = self.device.lock();
-        let device = device_guard
-            .as_ref()
-            .ok_or_else(|| anyhow!("Device not connected"))?;
+/// What the writer thread needs to reach the decks
+pub struct DeckIo {
+    backend: Arc<Mutex<Box<dyn HidBackend>>>,
+    decks: Decks,
+    config: Arc<Mutex<HidConfig>>,
+    event_tx: DaemonEventSender,
+    metrics: Arc<HidMetrics>,
+}
+
+impl DeckIo {
+    /// Send a command's packets, retrying report writes that fail
+    fn send(&self, device: &dyn HidTransport, packets: &[HidPacket], mode: ProtocolMode) -> Result<()> {
+        for packet in packets {
+            let written = writer::write_report(device, &packet.to_wire(mode), &self.metrics)?;
+            debug!("Wrote {} bytes to HID device (mode={:?})", written, mode);
+        }
+        Ok(())
+    }
 
-        let mode = self.mode();
-        let packets = commands::build_display_update(session, task, task2, tabs, active, mode);
-        send_packets_to_device(device, &packets, mode)?;
+    /// Open one deck, detect its protocol and announce it
+    fn open_deck(&self, serial: &str, deck: &Deck) -> Result<()> {
+        let config = self.config.lock().clone();
+        let device = self.backend.lock().open(&config, serial)?;
+        let device: Box<dyn HidTransport> = Box::new(MeteredTransport::new(device, Arc::clone(&self.metrics)));
 
-        self.drain_response(device);
+        info!("Found Core Deck: {} ({})", deck.name, serial);
+        self.metrics.opened(serial);
 
+        // Detect protocol mode and firmware version before storing
+        let (detected_mode, firmware_version) =
+            detect_protocol_mode(device.as_ref(), serial, &self.event_tx, &self.metrics);
+        deck.protocol_mode.store(detected_mode as u8, Ordering::Relaxed);
+        let capabilities =
+            query_capabilities(device.as_ref(), serial, &self.event_tx, &self.metrics, detected_mode);
+        *deck.capabilities.lock() = capabilities.clone();
+
+        // Store device
+        *deck.transport.lock() = Some(device);
+        deck.connected.store(true, Ordering::Relaxed);
+
+        // Notify connection
+        let _ = self.event_tx.send(DaemonEvent::HidConnected {
+            serial: serial.to_string(),
+            device_name: deck.name.clone(),
+            firmware_version,
+            capabilities,
+        });
+
+        info!("Connected to Core Deck {}", serial);
         Ok(())
     }
 
-    /// Query firmware version from the device.
-    /// Returns the version string, or a fallback if the device doesn't support the command.
-    pub fn query_version(&self) -> String {
-        let device_guard = self.device.lock();
-        match device_guard.as_ref() {
-            Some(device) => {
-                let mode = self.mode();
-                let packets = commands::build_get_version(mode);
-                if let Err(e) = send_packets_to_device(device, &packets, mode) {
-                    debug!("Failed to send GetVersion: {}", e);
-                    return "unknown".to_string();
-                }
-                match read_response(device, HidCommand::GetVersion, &self.event_tx, mode) {
-                    Ok(response) if response.status == 0 => {
-                        let version = String::from_utf8_lossy(&response.data).trim().to_string();
-                        if version.is_empty() { "unknown".to_string() } else { version }
-                    }
-                    _ => "unknown".to_string(),
-                }
+    /// Open decks (`None` = every plugged-in deck).
+    /// Succeeds if at least one addressed deck is open afterwards.
+    pub fn open_device(&self, serial: Option<&str>) -> Result<()> {
+        let decks = select(&self.decks, serial)?;
+        let mut errors = Vec::new();
+        for (serial, deck) in &decks {
+            if deck.is_connected() {
+                continue; // Already open
+            }
+            if let Err(e) = self.open_deck(serial, deck) {
+                errors.push((serial, e));
+            }
+        }
+
+        if decks.iter().any(|(_, deck)| deck.is_connected()) {
+            for (serial, e) in errors {
+                warn!("Failed to open device {}: {}", serial, e);
             }
-            None => "unknown".to_string(),
+            return Ok(());
+        }
+        match errors.into_iter().next() {
+            Some((_, e)) => Err(e),
+            None => Err(anyhow!("Device not available")),
         }
     }
 
-    /// Set display brightness (chunked protocol)
-    pub fn set_brightness(&self, level: u8, save: bool) -> Result<()> {
-        let device_guard = self.device.lock();
-        let device = device_guard
-            .as_ref()
-            .ok_or_else(|| anyhow!("Device not connected"))?;
+    /// Close decks (`None` = every open deck), sending Disconnect first
+    pub fn close_device(&self, serial: Option<&str>) {
+        let Ok(decks) = select(&self.decks, serial) else { return };
+        for (serial, deck) in decks {
+            // Don't emit HidDisconnected — the WS handler manages the lifecycle
+            if deck.close(true) {
+                info!("HID device {} closed (released to system)", serial);
+            }
+        }
+    }
 
-        let mode = self.mode();
-        let packets = commands::build_set_brightness(level, save, mode);
-        send_packets_to_device(device, &packets, mode)?;
+    /// Serials of the decks whose HID interface is open
+    fn connected_serials(&self) -> Vec<String> {
+        self.decks
+            .lock()
+            .iter()
+            .filter(|(_, deck)| deck.is_connected())
+            .map(|(serial, _)| serial.clone())
+            .collect()
+    }
 
-        // Read response
-        self.drain_response(device);
+    /// Run a command on every addressed open deck (`None` = all open decks).
+    /// Returns the first error, or "not connected" if no addressed deck is open.
+    fn for_each_open(
+        &self,
+        serial: Option<&str>,
+        mut command: impl FnMut(&str, &Deck, &dyn HidTransport) -> Result<()>,
+    ) -> Result<()> {
+        let mut ran = false;
+        let mut result = Ok(());
+        for (serial, deck) in select(&self.decks, serial)? {
+            let guard = deck.transport.lock();
+            let Some(device) = guard.as_deref() else { continue };
+            ran = true;
+            if let Err(e) = command(&serial, &deck, device) {
+                if result.is_ok() {
+                    result = Err(e);
+                } else {
+                    warn!("Device {}: {}", serial, e);
+                }
+            }
+        }
+        if !ran {
+            return Err(anyhow!("Device not connected"));
+        }
+        result
+    }
 
-        info!("Brightness set to {}", level);
-        Ok(())
+    /// Run a query on the first addressed open deck (`None` = any open deck)
+    fn with_first_open<T>(
+        &self,
+        serial: Option<&str>,
+        query: impl FnOnce(&str, &Deck, &dyn HidTransport) -> Result<T>,
+    ) -> Result<T> {
+        for (serial, deck) in select(&self.decks, serial)? {
+            let guard = deck.transport.lock();
+            if let Some(device) = guard.as_deref() {
+                return query(&serial, &deck, device);
+            }
+        }
+        Err(anyhow!("Device not connected"))
     }
 
-    /// Set a soft key assignment
-    pub fn set_soft_key(&self, index: u8, key_type: SoftKeyType, data: &[u8], save: bool) -> Result<()> {
-        let device_guard = self.device.lock();
-        let device = device_guard
-            .as_ref()
-            .ok_or_else(|| anyhow!("Device not connected"))?;
+    /// Send a display update with session name, current task, tab states, and active tab index.
+    /// Skips a deck if the payload is identical to the last one sent to it.
+    pub fn send_display_update(&self, serial: Option<&str>, update: &DisplayUpdate) -> Result<()> {
+        let DisplayUpdate { session, task, task2, tabs, active } = update;
+        let task = Some(task.as_str()).filter(|s| !s.is_empty());
+        let task2 = Some(task2.as_str()).filter(|s| !s.is_empty());
+        // Build a dedup key from the payload fields
+        let payload_key = format!("{}|{}|{}|{:?}|{}", session, task.unwrap_or(""), task2.unwrap_or(""), tabs, active);
 
-        let mode = self.mode();
-        let packets = commands::build_set_soft_key(index, key_type, data, save, mode);
-        send_packets_to_device(device, &packets, mode)?;
+        self.for_each_open(serial, |serial, deck, device| {
+            {
+                let mut last = deck.last_display_payload.lock();
+                if *last == payload_key {
+                    self.metrics.display_deduplicated.inc();
+                    return Ok(());
+                }
+                *last = payload_key.clone();
+            }
 
-        self.drain_response(device);
+            let mode = deck.mode();
+            let packets = commands::build_display_update(session, task, task2, tabs, *active, mode);
+            self.send(device, &packets, mode)?;
 
-        info!("Soft key {} set", index);
-        Ok(())
+            drain_response(device, serial, &self.event_tx, mode);
+            Ok(())
+        })
     }
 
-    /// Get a soft key configuration
-    pub fn get_soft_key(&self, index: u8) -> Result<SoftKeyConfig> {
-        let device_guard = self.device.lock();
-        let device = device_guard
-            .as_ref()
-            .ok_or_else(|| anyhow!("Device not connected"))?;
+    /// Query firmware version from a deck (`None` = the first open one).
+    /// Returns the version string, or a fallback if the device doesn't support the command.
+    pub fn query_version(&self, serial: Option<&str>) -> String {
+        self.with_first_open(serial, |serial, deck, device| {
+            let mode = deck.mode();
+            let packets = commands::build_get_version(mode);
+            self.send(device, &packets, mode)?;
+            let response = read_response(device, HidCommand::GetVersion, serial, &self.event_tx, &self.metrics, mode)?;
+            let version = String::from_utf8_lossy(&response.data).trim().to_string();
+            if response.status != 0 || version.is_empty() {
+                return Err(anyhow!("No version reported"));
+            }
+            Ok(version)
+        })
+        .unwrap_or_else(|e| {
+            debug!("GetVersion failed: {}", e);
+            "unknown".to_string()
+        })
+    }
 
-        let mode = self.mode();
-        let packets = commands::build_get_soft_key(index, mode);
-        send_packets_to_device(device, &packets, mode)?;
+    /// Set display brightness (chunked protocol)
+    pub fn set_brightness(&self, serial: Option<&str>, level: u8, save: bool) -> Result<()> {
+        self.for_each_open(serial, |serial, deck, device| {
+            let mode = deck.mode();
+            let packets = commands::build_set_brightness(level, save, mode);
+            self.send(device, &packets, mode)?;
 
-        // Read response — expect chunked response with key config data
-        let response = read_response(device, HidCommand::GetSoftKey, &self.event_tx, mode)?;
+            // Read response
+            drain_response(device, serial, &self.event_tx, mode);
 
-        // Parse response: [key_index, key_type, ...entry_data]
-        // The firmware sends: send_response(cmd, status=0x00, [key_index, type, data...])
-        // read_response() strips the status byte, so response.data = [key_index, type, entry_data...]
-        if response.data.len() < 2 {
-            return Ok(SoftKeyConfig {
-                index,
-                key_type: SoftKeyType::Default,
-                data: vec![],
-            });
-        }
+            info!("Brightness of {} set to {}", serial, level);
+            Ok(())
+        })
+    }
 
-        let _key_index = response.data[0];
-        let key_type = SoftKeyType::from_byte(response.data[1]).unwrap_or(SoftKeyType::Default);
-        let data = if response.data.len() > 2 {
-            response.data[2..].to_vec()
-        } else {
-            vec![]
-        };
+    /// Set a soft key assignment
+    pub fn set_soft_key(
+        &self,
+        serial: Option<&str>,
+        index: u8,
+        key_type: SoftKeyType,
+        data: &[u8],
+        save: bool,
+    ) -> Result<()> {
+        self.for_each_open(serial, |serial, deck, device| {
+            let mode = deck.mode();
+            let packets = commands::build_set_soft_key(index, key_type, data, save, mode);
+            self.send(device, &packets, mode)?;
+
+            drain_response(device, serial, &self.event_tx, mode);
+
+            info!("Soft key {} set on {}", index, serial);
+            Ok(())
+        })
+    }
 
-        Ok(SoftKeyConfig {
-            index,
-            key_type,
-            data,
+    /// Get a soft key configuration (`None` = from the first open deck)
+    pub fn get_soft_key(&self, serial: Option<&str>, index: u8) -> Result<SoftKeyConfig> {
+        self.with_first_open(serial, |serial, deck, device| {
+            let mode = deck.mode();
+            let packets = commands::build_get_soft_key(index, mode);
+            self.send(device, &packets, mode)?;
+
+            // Read response — expect chunked response with key config data
+            let response = read_response(device, HidCommand::GetSoftKey, serial, &self.event_tx, &self.metrics, mode)?;
+
+            // Parse response: [key_index, key_type, ...entry_data]
+            // The firmware sends: send_response(cmd, status=0x00, [key_index, type, data...])
+            // read_response() strips the status byte, so response.data = [key_index, type, entry_data...]
+            if response.data.len() < 2 {
+                return Ok(SoftKeyConfig {
+                    index,
+                    key_type: SoftKeyType::Default,
+                    data: vec![],
+                });
+            }
+
+            let _key_index = response.data[0];
+            let key_type = SoftKeyType::from_byte(response.data[1]).unwrap_or(SoftKeyType::Default);
+            let data = if response.data.len() > 2 {
+                response.data[2..].to_vec()
+            } else {
+                vec![]
+            };
+
+            Ok(SoftKeyConfig {
+                index,
+                key_type,
+                data,
+            })
         })
     }
 
     /// Reset all soft keys to defaults
     ///
-    /// Returns the effective assignment for each key post-reset.
+    /// Returns the effective assignment for each key post-reset (from the
+    /// first deck when several are reset).
     /// Format from firmware: [type, kc_hi, kc_lo] x 3
-    pub fn reset_soft_keys(&self) -> Result<[SoftKeyConfig; 3]> {
-        let device_guard = self.device.lock();
-        let device = device_guard
-            .as_ref()
-            .ok_or_else(|| anyhow!("Device not connected"))?;
-
-        let mode = self.mode();
-        let packets = commands::build_reset_soft_keys(mode);
-        send_packets_to_device(device, &packets, mode)?;
-
-        // Read the response — firmware now returns effective assignments
-        let response = read_response(device, HidCommand::ResetSoftKeys, &self.event_tx, mode)?;
-
-        // Parse response data: [type, kc_hi, kc_lo] x 3
-        let mut configs = [
-            SoftKeyConfig { index: 0, key_type: SoftKeyType::Default, data: vec![] },
-            SoftKeyConfig { index: 1, key_type: SoftKeyType::Default, data: vec![] },
-            SoftKeyConfig { index: 2, key_type: SoftKeyType::Default, data: vec![] },
-        ];
-
-        for i in 0..3usize {
-            let offset = i * 3;
-            if offset + 2 < response.data.len() {
-                let key_type = SoftKeyType::from_byte(response.data[offset])
-                    .unwrap_or(SoftKeyType::Default);
-                let kc_hi = response.data[offset + 1];
-                let kc_lo = response.data[offset + 2];
-                configs[i] = SoftKeyConfig {
-                    index: i as u8,
-                    key_type,
-                    data: match key_type {
-                        SoftKeyType::Keycode | SoftKeyType::Default => vec![kc_hi, kc_lo],
-                        // String/Sequence only have kc=0 in the 0x06 response
-                        _ => vec![],
-                    },
-                };
+    pub fn reset_soft_keys(&self, serial: Option<&str>) -> Result<[SoftKeyConfig; 3]> {
+        let mut first = None;
+        self.for_each_open(serial, |serial, deck, device| {
+            let mode = deck.mode();
+            let packets = commands::build_reset_soft_keys(mode);
+            self.send(device, &packets, mode)?;
+
+            // Read the response — firmware now returns effective assignments
+            let response = read_response(device, HidCommand::ResetSoftKeys, serial, &self.event_tx, &self.metrics, mode)?;
+            if first.is_none() {
+                first = Some(parse_soft_key_reset(&response.data));
             }
-        }
 
-        info!("Soft keys reset to defaults");
-        Ok(configs)
+            info!("Soft keys of {} reset to defaults", serial);
+            Ok(())
+        })?;
+        first.ok_or_else(|| anyhow!("Device not connected"))
     }
 
+    /// Send an alert overlay
+    pub fn send_alert(
+        &self,
+        serial: Option<&str>,
+        tab: usize,
+        session: &str,
+        text: &str,
+        details: Option<&str>,
+    ) -> Result<()> {
+        self.for_each_open(serial, |serial, deck, device| {
+            let mode = deck.mode();
+            let packets = commands::build_alert(tab, session, text, details, mode);
+            self.send(device, &packets, mode)?;
+
+            drain_response(device, serial, &self.event_tx, mode);
+
+            info!("Alert sent to {}: tab={}, text={}", serial, tab, text);
+            Ok(())
+        })
+    }
 
-    /// Send an alert overlay to the device
-    pub fn send_alert(&self, tab: usize, session: &str, text: &str, details: Option<&str>) -> Result<()> {
-        let device_guard = self.device.lock();
-        let device = device_guard
-            .as_ref()
-            .ok_or_else(|| anyhow!("Device not connected"))?;
-
-        let mode = self.mode();
-        let packets = commands::build_alert(tab, session, text, details, mode);
-        send_packets_to_device(device, &packets, mode)?;
+    /// Clear an alert overlay
+    pub fn clear_alert(&self, serial: Option<&str>, tab: usize) -> Result<()> {
+        self.for_each_open(serial, |serial, deck, device| {
+            let mode = deck.mode();
+            let packets = commands::build_clear_alert(tab, mode);
+            self.send(device, &packets, mode)?;
 
-        self.drain_response(device);
+            drain_response(device, serial, &self.event_tx, mode);
 
-        info!("Alert sent: tab={}, text={}", tab, text);
-        Ok(())
+            debug!("Alert cleared on {}: tab={}", serial, tab);
+            Ok(())
+        })
     }
 
-    /// Clear an alert overlay on the device
-    pub fn clear_alert(&self, tab: usize) -> Result<()> {
-        let device_guard = self.device.lock();
-        let device = device_guard
-            .as_ref()
-            .ok_or_else(|| anyhow!("Device not connected"))?;
+    /// Set device LED mode
+    pub fn set_mode(&self, serial: Option<&str>, mode: DeviceMode) -> Result<()> {
+        self.for_each_open(serial, |serial, deck, device| {
+            let proto_mode = deck.mode();
+            let packets = commands::build_set_mode(mode, proto_mode);
+            self.send(device, &packets, proto_mode)?;
 
-        let mode = self.mode();
-        let packets = commands::build_clear_alert(tab, mode);
-        send_packets_to_device(device, &packets, mode)?;
+            drain_response(device, serial, &self.event_tx, proto_mode);
 
-        self.drain_response(device);
+            debug!("Device {} mode set to {}", serial, mode);
+            Ok(())
+        })
+    }
+}
 
-        debug!("Alert cleared: tab={}", tab);
-        Ok(())
+impl Drop for HidManager {
+    fn drop(&mut self) {
+        self.stop_monitor.store(true, Ordering::Relaxed);
+        self.queue.close();
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
+        {
+            if let Some(ref mut watcher) = self.hotplug_watcher {
+                watcher.stop();
+            }
+        }
+        self.disconnect();
     }
+}
 
-    /// Set device LED mode
-    pub fn set_mode(&self, mode: DeviceMode) -> Result<()> {
-        let device_guard = self.device.lock();
-        let device = device_guard
-            .as_ref()
-            .ok_or_else(|| anyhow!("Device not connected"))?;
+/// Decks addressed by `serial` (`None` = every plugged-in deck)
+fn select(decks: &Mutex<BTreeMap<String, Arc<Deck>>>, serial: Option<&str>) -> Result<Vec<(String, Arc<Deck>)>> {
+    let decks = decks.lock();
+    match serial {
+        Some(serial) => decks
+            .get(serial)
+            .map(|deck| vec![(serial.to_string(), Arc::clone(deck))])
+            .ok_or_else(|| anyhow!("Unknown device {}", serial)),
+        None => Ok(decks
+            .iter()
+            .map(|(serial, deck)| (serial.clone(), Arc::clone(deck)))
+            .collect()),
+    }
+}
 
-        let proto_mode = self.mode();
-        let packets = commands::build_set_mode(mode, proto_mode);
-        send_packets_to_device(device, &packets, proto_mode)?;
+/// Re-enumerate and reconcile the deck list: announce decks that appeared,
+/// and drop decks that went away (closing them if open).
+/// Returns whether anything changed.
+fn rescan(
+    backend: &Mutex<Box<dyn HidBackend>>,
+    config: &HidConfig,
+    decks: &Mutex<BTreeMap<String, Arc<Deck>>>,
+    event_tx: &DaemonEventSender,
+    metrics: &HidMetrics,
+    via: &str,
+) -> bool {
+    let listings = {
+        let mut backend = backend.lock();
+        if let Err(e) = backend.refresh() {
+            debug!("Failed to refresh device list: {}", e);
+        }
+        backend.list(config)
+    };
 
-        self.drain_response(device);
+    let (added, removed) = {
+        let mut decks = decks.lock();
+        let gone: Vec<String> = decks
+            .keys()
+            .filter(|serial| !listings.iter().any(|l| &l.serial == *serial))
+            .cloned()
+            .collect();
+        let removed: Vec<(String, Arc<Deck>)> = gone
+            .into_iter()
+            .filter_map(|serial| decks.remove(&serial).map(|deck| (serial, deck)))
+            .collect();
+        let added: Vec<DeviceListing> = listings
+            .into_iter()
+            .filter(|l| !decks.contains_key(&l.serial))
+            .collect();
+        for listing in &added {
+            decks.insert(listing.serial.clone(), Arc::new(Deck::new(listing.name.clone())));
+        }
+        (added, removed)
+    };
 
-        debug!("Device mode set to {}", mode);
-        Ok(())
+    for (serial, deck) in &removed {
+        if deck.close(false) {
+            metrics.link_lost(serial);
+            let _ = event_tx.send(DaemonEvent::HidDisconnected { serial: serial.clone() });
+            info!("Device {} disconnected via {} (was open)", serial, via);
+        }
+        let _ = event_tx.send(DaemonEvent::DeviceUnavailable { serial: serial.clone() });
+        info!("Device {} unavailable via {}", serial, via);
+    }
+    for listing in &added {
+        info!("Device available via {}: {} ({})", via, listing.name, listing.serial);
+        let _ = event_tx.send(DaemonEvent::DeviceAvailable {
+            serial: listing.serial.clone(),
+            device_name: listing.name.clone(),
+        });
     }
 
-    /// Read and discard response packets, forwarding key/string events but NOT state reports.
-    /// State reports from command confirmations are consumed silently — the reader thread
-    /// handles device-initiated state reports (button presses, YOLO switch).
-    fn drain_response(&self, device: &HidDevice) {
-        let mode = self.mode();
-        let mut type_string_buf = Vec::new();
-        for _ in 0..3 {
-            match read_raw_packet(device, 50, mode) {
-                Ok(Some(pkt)) => {
-                    let is_device_initiated = matches!(
-                        pkt.command(),
-                        Some(HidCommand::StateReport)
-                            | Some(HidCommand::KeyEvent)
-                            | Some(HidCommand::TypeString)
-                            | Some(HidCommand::Ping)
-                    );
-                    // Forward key/string/ping events but skip StateReport —
-                    // it's a confirmation echo, not a user action
-                    if is_device_initiated && pkt.command() != Some(HidCommand::StateReport) {
-                        dispatch_incoming_packet(&pkt, &self.event_tx, &mut type_string_buf);
-                    }
-                    // If this is END packet of a response, we're done
-                    if pkt.is_end() && !is_device_initiated {
-                        break;
+    !added.is_empty() || !removed.is_empty()
+}
+
+/// One reader-thread pass over an open deck: ping when due, then poll for
+/// device-initiated packets.
+fn service_deck(
+    serial: &str,
+    deck: &Deck,
+    reader: &mut ReaderState,
+    ping_interval: Duration,
+    event_tx: &DaemonEventSender,
+    metrics: &HidMetrics,
+) {
+    // --- Ping on timer ---
+    if reader.last_ping.map_or(true, |at| at.elapsed() >= ping_interval) {
+        let mode = deck.mode();
+        let ping_ok = {
+            let device_guard = deck.transport.lock();
+            let Some(dev) = device_guard.as_deref() else {
+                return; // Closed since the deck list was taken
+            };
+            let packets = commands::build_ping(mode);
+            let sent_at = Instant::now();
+            match send_packets_to_device(dev, &packets, mode) {
+                Ok(()) => {
+                    debug!("Ping sent to {}", serial);
+                    // Read pong response
+                    match read_raw_packet(dev, 100, mode) {
+                        Ok(Some(pkt)) => {
+                            if pkt.command() == Some(HidCommand::Ping) {
+                                metrics.ping_rtt.observe(sent_at.elapsed());
+                            }
+                            dispatch_incoming_packet(&pkt, serial, event_tx, &mut reader.type_string_buf, mode);
+                            true
+                        }
+                        Ok(None) => {
+                            debug!("No pong response");
+                            true // Write succeeded, device might be busy
+                        }
+                        Err(e) => {
+                            warn!("Error reading pong from {}: {}", serial, e);
+                            false
+                        }
                     }
                 }
-                Ok(None) => break, // Timeout, no more data
-                Err(_) => break,
+                Err(e) => {
+                    warn!("Failed to send ping to {}: {}", serial, e);
+                    false
+                }
+            }
+        };
+
+        reader.last_ping = Some(Instant::now());
+
+        if ping_ok {
+            reader.consecutive_failures = 0;
+        } else {
+            metrics.ping_failures.inc();
+            reader.consecutive_failures += 1;
+            warn!(
+                "Ping failure {} of {} ({})",
+                reader.consecutive_failures, DISCONNECT_THRESHOLD, serial
+            );
+
+            if reader.consecutive_failures >= DISCONNECT_THRESHOLD {
+                info!("Device {} disconnected (consecutive ping failures)", serial);
+                deck.close(false);
+                metrics.link_lost(serial);
+                let _ = event_tx.send(DaemonEvent::HidDisconnected { serial: serial.to_string() });
+                *reader = ReaderState::default();
+                return;
             }
         }
     }
 
-    /// Disconnect from the device
-    pub fn disconnect(&self) {
-        let mut device_guard = self.device.lock();
-        if device_guard.take().is_some() {
-            self.connected.store(false, Ordering::Relaxed);
-            self.protocol_mode.store(ProtocolMode::Standalone as u8, Ordering::Relaxed);
-            // Clear dedup cache so the next connect sends a fresh display update
-            *self.last_display_payload.lock() = String::new();
-            let _ = self.event_tx.send(DaemonEvent::HidDisconnected);
-            info!("Disconnected from Core Deck");
+    // --- Poll for incoming device-initiated packets ---
+    // Use try_lock to avoid blocking command sends (send_display_update, etc.)
+    if let Some(device_guard) = deck.transport.try_lock() {
+        if let Some(dev) = device_guard.as_deref() {
+            let poll_mode = deck.mode();
+            match read_raw_packet(dev, 20, poll_mode) {
+                Ok(Some(pkt)) => {
+                    dispatch_incoming_packet(&pkt, serial, event_tx, &mut reader.type_string_buf, poll_mode);
+                }
+                Ok(None) => {} // Timeout, no data
+                Err(e) => {
+                    debug!("Poll read error on {}: {}", serial, e);
+                }
+            }
         }
     }
 }
 
-impl Drop for HidManager {
-    fn drop(&mut self) {
-        self.stop_monitor.store(true, Ordering::Relaxed);
-        #[cfg(target_os = "macos")]
-        {
-            if let Some(ref mut watcher) = self.hotplug_watcher {
-                watcher.stop();
-            }
+/// Parse the `ResetSoftKeys` response: `[type, kc_hi, kc_lo] x 3`
+fn parse_soft_key_reset(data: &[u8]) -> [SoftKeyConfig; 3] {
+    let mut configs = [
+        SoftKeyConfig { index: 0, key_type: SoftKeyType::Default, data: vec![] },
+        SoftKeyConfig { index: 1, key_type: SoftKeyType::Default, data: vec![] },
+        SoftKeyConfig { index: 2, key_type: SoftKeyType::Default, data: vec![] },
+    ];
+
+    for (i, config) in configs.iter_mut().enumerate() {
+        let offset = i * 3;
+        if offset + 2 < data.len() {
+            let key_type = SoftKeyType::from_byte(data[offset]).unwrap_or(SoftKeyType::Default);
+            let kc_hi = data[offset + 1];
+            let kc_lo = data[offset + 2];
+            *config = SoftKeyConfig {
+                index: i as u8,
+                key_type,
+                data: match key_type {
+                    SoftKeyType::Keycode | SoftKeyType::Default => vec![kc_hi, kc_lo],
+                    // String/Sequence only have kc=0 in the 0x06 response
+                    _ => vec![],
+                },
+            };
         }
-        self.disconnect();
     }
+    configs
 }
 
-/// Check if the device is physically present by enumerating (no device open).
-/// Returns `(available, device_name)`.
-fn check_device_presence(api: &HidApi, config: &HidConfig) -> (bool, Option<String>) {
-    match api.device_list().find(|d| {
-        d.vendor_id() == config.vendor_id
-            && d.product_id() == config.product_id
-            && d.usage_page() == config.usage_page
-            && d.usage() == config.usage_id
-    }) {
-        Some(info) => {
-            let name = format!(
-                "{} {}",
-                info.manufacturer_string().unwrap_or("").trim(),
-                info.product_string().unwrap_or("Core Deck").trim()
-            ).trim().to_string();
-            (true, Some(name))
+/// Read and discard response packets, forwarding key/string events but NOT state reports.
+/// State reports from command confirmations are consumed silently — the reader thread
+/// handles device-initiated state reports (button presses, YOLO switch).
+fn drain_response(device: &dyn HidTransport, serial: &str, event_tx: &DaemonEventSender, mode: ProtocolMode) {
+    let mut type_string_buf = Vec::new();
+    for _ in 0..3 {
+        match read_raw_packet(device, 50, mode) {
+            Ok(Some(pkt)) => {
+                let is_device_initiated = matches!(
+                    pkt.command(),
+                    Some(HidCommand::StateReport)
+                        | Some(HidCommand::KeyEvent)
+                        | Some(HidCommand::TypeString)
+                        | Some(HidCommand::Ping)
+                );
+                // Forward key/string/ping events but skip StateReport —
+                // it's a confirmation echo, not a user action
+                if is_device_initiated && pkt.command() != Some(HidCommand::StateReport) {
+                    dispatch_incoming_packet(&pkt, serial, event_tx, &mut type_string_buf, mode);
+                }
+                if pkt.command() == Some(HidCommand::Error) {
+                    warn!("Device {} rejected command: {}", serial, describe_error(&pkt));
+                }
+                // If this is END packet of a response, we're done
+                if pkt.is_end() && !is_device_initiated {
+                    break;
+                }
+            }
+            Ok(None) => break, // Timeout, no more data
+            Err(_) => break,
         }
-        None => (false, None),
     }
 }
 
@@ -837,11 +1177,16 @@ fn check_device_presence(api: &HidApi, config: &HidConfig) -> (bool, Option<Stri
 /// Tries VIAL-prefixed GetVersion first. If the response starts with `0x80` and
 /// parses as a valid version, the device uses VIAL mode. Otherwise falls back to
 /// standalone GetVersion.
-fn detect_protocol_mode(device: &HidDevice, event_tx: &DaemonEventSender) -> (ProtocolMode, String) {
+fn detect_protocol_mode(
+    device: &dyn HidTransport,
+    serial: &str,
+    event_tx: &DaemonEventSender,
+    metrics: &HidMetrics,
+) -> (ProtocolMode, String) {
     // --- Phase 1: try VIAL mode ---
     let vial_packets = commands::build_get_version(ProtocolMode::Vial);
     if send_packets_to_device(device, &vial_packets, ProtocolMode::Vial).is_ok() {
-        match read_response(device, HidCommand::GetVersion, event_tx, ProtocolMode::Vial) {
+        match read_response(device, HidCommand::GetVersion, serial, event_tx, metrics, ProtocolMode::Vial) {
             Ok(response) if response.status == 0 => {
                 let version = String::from_utf8_lossy(&response.data).trim().to_string();
                 if !version.is_empty() {
@@ -871,7 +1216,7 @@ fn detect_protocol_mode(device: &HidDevice, event_tx: &DaemonEventSender) -> (Pr
         return (ProtocolMode::Standalone, "unknown".to_string());
     }
 
-    match read_response(device, HidCommand::GetVersion, event_tx, ProtocolMode::Standalone) {
+    match read_response(device, HidCommand::GetVersion, serial, event_tx, metrics, ProtocolMode::Standalone) {
         Ok(response) if response.status == 0 => {
             let version = String::from_utf8_lossy(&response.data).trim().to_string();
             let version = if version.is_empty() { "unknown".to_string() } else { version };
@@ -889,8 +1234,42 @@ fn detect_protocol_mode(device: &HidDevice, event_tx: &DaemonEventSender) -> (Pr
     }
 }
 
+/// Ask the firmware for its limits. `None` when it predates `GetCapabilities`
+/// or the answer does not parse; callers then assume the defaults.
+fn query_capabilities(
+    device: &dyn HidTransport,
+    serial: &str,
+    event_tx: &DaemonEventSender,
+    metrics: &HidMetrics,
+    mode: ProtocolMode,
+) -> Option<DeviceCapabilities> {
+    let packets = commands::build_get_capabilities(mode);
+    if let Err(e) = send_packets_to_device(device, &packets, mode) {
+        debug!("Failed to send GetCapabilities: {}", e);
+        return None;
+    }
+    let response = match read_response(device, HidCommand::GetCapabilities, serial, event_tx, metrics, mode) {
+        Ok(response) if response.status == 0 => response,
+        Ok(response) => {
+            debug!("GetCapabilities returned status 0x{:02X}", response.status);
+            return None;
+        }
+        Err(e) => {
+            debug!("GetCapabilities not supported or failed: {}", e);
+            return None;
+        }
+    };
+    match serde_json::from_slice(&response.data) {
+        Ok(capabilities) => Some(capabilities),
+        Err(e) => {
+            warn!("Ignoring malformed capabilities from {}: {}", serial, e);
+            None
+        }
+    }
+}
+
 /// Send multiple packets (chunks) to the HID device sequentially
-fn send_packets_to_device(device: &HidDevice, packets: &[HidPacket], mode: ProtocolMode) -> Result<()> {
+fn send_packets_to_device(device: &dyn HidTransport, packets: &[HidPacket], mode: ProtocolMode) -> Result<()> {
     for packet in packets {
         send_single_packet(device, packet, mode)?;
     }
@@ -901,33 +1280,9 @@ fn send_packets_to_device(device: &HidDevice, packets: &[HidPacket], mode: Proto
 ///
 /// In VIAL mode the wire bytes become `[0x80, flags, cmd, payload×29]` — the VIAL prefix
 /// is prepended and the last payload byte is dropped to stay within 32 bytes.
-fn send_single_packet(device: &HidDevice, packet: &HidPacket, mode: ProtocolMode) -> Result<()> {
-    let bytes = packet.as_bytes();
-
-    let wire: [u8; PACKET_SIZE] = match mode {
-        ProtocolMode::Vial => {
-            let mut buf = [0u8; PACKET_SIZE];
-            buf[0] = VIAL_PREFIX;
-            buf[1..PACKET_SIZE].copy_from_slice(&bytes[..PACKET_SIZE - 1]);
-            buf
-        }
-        ProtocolMode::Standalone => *bytes,
-    };
-
-    #[cfg(any(target_os = "macos", target_os = "windows"))]
-    let data = {
-        let mut data = Vec::with_capacity(PACKET_SIZE + 1);
-        data.push(0x00); // Report ID
-        data.extend_from_slice(&wire);
-        data
-    };
-
-    #[cfg(target_os = "linux")]
-    let data = wire.to_vec();
-
-    let written = device
-        .write(&data)
-        .context("Failed to write to HID device")?;
+fn send_single_packet(device: &dyn HidTransport, packet: &HidPacket, mode: ProtocolMode) -> Result<()> {
+    let wire = packet.to_wire(mode);
+    let written = device.write(&wire)?;
 
     debug!("Wrote {} bytes to HID device (mode={:?})", written, mode);
 
@@ -938,35 +1293,41 @@ fn send_single_packet(device: &HidDevice, packet: &HidPacket, mode: ProtocolMode
 ///
 /// In VIAL mode: if `buffer[0] != 0x80` the packet is a VIA echo and is discarded
 /// (returns `Ok(None)`). Otherwise the prefix is stripped by shifting bytes left by 1.
-fn read_raw_packet(device: &HidDevice, timeout_ms: i32, mode: ProtocolMode) -> Result<Option<HidPacket>> {
+fn read_raw_packet(device: &dyn HidTransport, timeout_ms: i32, mode: ProtocolMode) -> Result<Option<HidPacket>> {
     let mut buffer = [0u8; PACKET_SIZE];
-    match device.read_timeout(&mut buffer, timeout_ms) {
-        Ok(n) if n > 0 => {
-            if mode == ProtocolMode::Vial {
-                if buffer[0] != VIAL_PREFIX {
-                    // Not a VIAL-prefixed response — discard (VIA echo)
-                    debug!("Discarding non-VIAL packet (byte0=0x{:02X})", buffer[0]);
-                    return Ok(None);
-                }
-                // Strip prefix: shift left by 1
-                let mut stripped = [0u8; PACKET_SIZE];
-                stripped[..PACKET_SIZE - 1].copy_from_slice(&buffer[1..PACKET_SIZE]);
-                Ok(Some(HidPacket::from_bytes(&stripped)))
-            } else {
-                Ok(Some(HidPacket::from_bytes(&buffer)))
+    match device.read_timeout(&mut buffer, timeout_ms)? {
+        0 => Ok(None), // Timeout
+        _ => {
+            let packet = HidPacket::from_wire(&buffer, mode);
+            if packet.is_none() {
+                // Not a VIAL-prefixed response — discard (VIA echo)
+                debug!("Discarding non-VIAL packet (byte0=0x{:02X})", buffer[0]);
             }
+            Ok(packet)
         }
-        Ok(_) => Ok(None), // Timeout
-        Err(e) => Err(anyhow!("HID read error: {}", e)),
     }
 }
 
+/// Human-readable form of an `Error (0xFF)` packet: payload is `[error_code, offending_cmd]`
+fn describe_error(pkt: &HidPacket) -> String {
+    let code = pkt.payload()[0];
+    let reason = ProtoError::from_byte(code)
+        .map(|e| e.description())
+        .unwrap_or("unknown error");
+    format!("{} (code 0x{:02X}, command 0x{:02X})", reason, code, pkt.payload()[1])
+}
+
 /// Read a complete chunked response for a specific command.
 /// Transparently handles interleaved state reports by dispatching them as events.
+///
+/// Recovers from a lost END flag (a new START restarts reassembly, a stall times
+/// out) and fails on an `Error` reply or a response larger than `MAX_MESSAGE_SIZE`.
 fn read_response(
-    device: &HidDevice,
+    device: &dyn HidTransport,
     expected_cmd: HidCommand,
+    serial: &str,
     event_tx: &DaemonEventSender,
+    metrics: &HidMetrics,
     mode: ProtocolMode,
 ) -> Result<ResponsePacket> {
     let mut payload = Vec::new();
@@ -975,7 +1336,7 @@ fn read_response(
     let mut type_string_buf = Vec::new();
 
     // Read packets until we get a complete response (up to reasonable limit)
-    for _ in 0..20 {
+    for _ in 0..MAX_RESPONSE_READS {
         let pkt = match read_raw_packet(device, 200, mode)? {
             Some(pkt) => pkt,
             None => {
@@ -997,12 +1358,21 @@ fn read_response(
                 | Some(HidCommand::Ping)
         );
         if is_device_initiated {
-            dispatch_incoming_packet(&pkt, event_tx, &mut type_string_buf);
+            dispatch_incoming_packet(&pkt, serial, event_tx, &mut type_string_buf, mode);
             continue;
         }
 
+        // Error reply: [error_code, offending_cmd]. Errors for other commands are stale.
+        if pkt.command() == Some(HidCommand::Error) {
+            if pkt.payload()[1] != expected_cmd.as_byte() {
+                debug!("Ignoring stale error reply: {}", describe_error(&pkt));
+                continue;
+            }
+            return Err(anyhow!("Device rejected {:?}: {}", expected_cmd, describe_error(&pkt)));
+        }
+
         // Check command matches
-        if pkt.command() != Some(expected_cmd) && pkt.command() != Some(HidCommand::Error) {
+        if pkt.command() != Some(expected_cmd) {
             debug!(
                 "Unexpected response command: {:?} (expected {:?})",
                 pkt.command(),
@@ -1012,21 +1382,39 @@ fn read_response(
         }
 
         if pkt.is_start() {
+            if got_start {
+                // Previous response lost its END — start over with this one
+                debug!("Response restarted before END, discarding {} bytes", payload.len());
+                metrics.chunk_retries.inc();
+            }
             got_start = true;
             command_byte = pkt.command_byte();
             payload.clear();
+        } else if !got_start {
+            debug!("Continuation without START for {:?}, discarding", expected_cmd);
+            continue;
         }
 
-        if got_start {
-            payload.extend_from_slice(pkt.payload());
-        }
+        payload.extend_from_slice(pkt.wire_payload(mode));
 
-        if pkt.is_end() && got_start {
+        if !pkt.is_end() {
+            if payload.len() > MAX_MESSAGE_SIZE {
+                flush_input(device, serial, event_tx, mode);
+                return Err(anyhow!(
+                    "Response to {:?} exceeded {} bytes without END",
+                    expected_cmd,
+                    MAX_MESSAGE_SIZE
+                ));
+            }
+        } else {
             // Complete response assembled
             // Trim trailing zeros from the last chunk
             while payload.last() == Some(&0) {
                 payload.pop();
             }
+            if payload.len() > MAX_MESSAGE_SIZE {
+                return Err(anyhow!("Response to {:?} exceeded {} bytes", expected_cmd, MAX_MESSAGE_SIZE));
+            }
 
             let status = if payload.is_empty() { 0 } else { payload[0] };
             let data = if payload.len() > 1 {
@@ -1046,23 +1434,39 @@ fn read_response(
     Err(anyhow!("Response read exceeded maximum packet count"))
 }
 
+/// Discard whatever the device still has queued after an aborted response,
+/// forwarding device-initiated packets so no key presses are lost.
+fn flush_input(device: &dyn HidTransport, serial: &str, event_tx: &DaemonEventSender, mode: ProtocolMode) {
+    let mut type_string_buf = Vec::new();
+    for _ in 0..MAX_RESPONSE_READS {
+        match read_raw_packet(device, 20, mode) {
+            Ok(Some(pkt)) => dispatch_incoming_packet(&pkt, serial, event_tx, &mut type_string_buf, mode),
+            _ => break,
+        }
+    }
+}
+
 /// Dispatch a single incoming packet from the device, emitting appropriate AppEvents.
 ///
 /// Handles: StateReport, KeyEvent, TypeString, Ping (pong). All other commands are ignored
 /// (they are responses to host-initiated commands handled elsewhere).
 ///
-/// `type_string_buf` accumulates chunked TypeString payloads across calls.
+/// `serial` identifies the deck the packet came from. `type_string_buf`
+/// accumulates chunked TypeString payloads across calls.
 fn dispatch_incoming_packet(
     pkt: &HidPacket,
+    serial: &str,
     event_tx: &DaemonEventSender,
     type_string_buf: &mut Vec<u8>,
+    mode: ProtocolMode,
 ) {
     match pkt.command() {
         Some(HidCommand::StateReport) => {
             let state_byte = pkt.payload()[0];
             let ds = DeviceState::from_byte(state_byte);
-            debug!("State report: mode={}, yolo={}", ds.mode, ds.yolo);
+            debug!("State report from {}: mode={}, yolo={}", serial, ds.mode, ds.yolo);
             let _ = event_tx.send(DaemonEvent::DeviceStateChanged {
+                serial: serial.to_string(),
                 mode: ds.mode,
                 yolo: ds.yolo,
             });
@@ -1072,22 +1476,33 @@ fn dispatch_incoming_packet(
             let payload = pkt.payload();
             if payload.len() >= 2 {
                 let keycode = ((payload[0] as u16) << 8) | (payload[1] as u16);
-                debug!("Key event: keycode=0x{:04X}", keycode);
-                let _ = event_tx.send(DaemonEvent::HidKeyEvent { keycode });
+                debug!("Key event from {}: keycode=0x{:04X}", serial, keycode);
+                let _ = event_tx.send(DaemonEvent::HidKeyEvent {
+                    serial: serial.to_string(),
+                    keycode,
+                });
             }
         }
         Some(HidCommand::TypeString) => {
             // Chunked: accumulate payload, dispatch on END packet
             // Payload format: [flags_byte, ...string_data]
             // flags_byte bit 0: send_enter
-            let payload = pkt.payload();
-
             if pkt.is_start() {
+                // A START while buffering means the previous string lost its END
                 type_string_buf.clear();
+            } else if type_string_buf.is_empty() {
+                debug!("TypeString continuation without START, discarding");
+                return;
             }
 
             // Append raw payload (first byte of first chunk has flags)
-            type_string_buf.extend_from_slice(payload);
+            type_string_buf.extend_from_slice(pkt.wire_payload(mode));
+
+            if !pkt.is_end() && type_string_buf.len() > MAX_MESSAGE_SIZE {
+                warn!("TypeString exceeded {} bytes without END, discarding", MAX_MESSAGE_SIZE);
+                type_string_buf.clear();
+                return;
+            }
 
             if pkt.is_end() && !type_string_buf.is_empty() {
                 // First byte is flags, rest is UTF-8 string
@@ -1103,6 +1518,7 @@ fn dispatch_incoming_packet(
                 if let Ok(text) = std::str::from_utf8(str_bytes) {
                     debug!("Type string: {:?} (send_enter={})", text, send_enter);
                     let _ = event_tx.send(DaemonEvent::HidTypeString {
+                        serial: serial.to_string(),
                         text: text.to_string(),
                         send_enter,
                     });
@@ -1124,6 +1540,12 @@ fn dispatch_incoming_packet(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hid::virtual_deck::{VirtualBackend, VirtualDeck};
+    use tokio::sync::mpsc;
+
+    fn two_decks() -> [VirtualDeck; 2] {
+        [VirtualDeck::new(ProtocolMode::Standalone), VirtualDeck::new(ProtocolMode::Standalone)]
+    }
 
     #[test]
     fn test_hid_config_default() {
@@ -1133,4 +1555,100 @@ mod tests {
         assert_eq!(config.usage_page, 0xFF60);
         assert_eq!(config.usage_id, 0x61);
     }
+
+    #[test]
+    fn test_select() {
+        let decks: Mutex<BTreeMap<String, Arc<Deck>>> = Mutex::new(
+            ["virtual-2", "virtual-1"]
+                .into_iter()
+                .map(|serial| (serial.to_string(), Arc::new(Deck::new("Deck".into()))))
+                .collect(),
+        );
+        let serials = |serial| select(&decks, serial).map(|d| d.into_iter().map(|(s, _)| s).collect::<Vec<_>>());
+
+        // `None` addresses every deck, ordered by serial, open or not
+        assert_eq!(serials(None).unwrap(), ["virtual-1", "virtual-2"]);
+        assert_eq!(serials(Some("virtual-2")).unwrap(), ["virtual-2"]);
+        assert!(serials(Some("nope")).is_err());
+    }
+
+    #[test]
+    fn test_rescan_reconciles_decks() {
+        let plugged = two_decks();
+        let config = HidConfig::default();
+        let backend: Mutex<Box<dyn HidBackend>> = Mutex::new(Box::new(VirtualBackend::with_decks(plugged.to_vec())));
+        let decks = Mutex::new(BTreeMap::new());
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let event_tx = DaemonEventSender::new(tx);
+        let metrics = HidMetrics::default();
+        let rescan = || rescan(&backend, &config, &decks, &event_tx, &metrics, "test");
+        let serials = || decks.lock().keys().cloned().collect::<Vec<_>>();
+
+        assert!(rescan());
+        assert_eq!(serials(), ["virtual-1", "virtual-2"]);
+        assert!(!rescan());
+
+        // Unplugging an open deck closes it and counts a lost link
+        let second = Arc::clone(&decks.lock()["virtual-2"]);
+        *second.transport.lock() = Some(backend.lock().open(&config, "virtual-2").unwrap());
+        second.connected.store(true, Ordering::Relaxed);
+        plugged[1].set_present(false);
+        assert!(rescan());
+        assert_eq!(serials(), ["virtual-1"]);
+        assert!(!second.is_connected());
+        assert_eq!(metrics.link_losses.get(), 1);
+
+        plugged[1].set_present(true);
+        assert!(rescan());
+        assert_eq!(serials(), ["virtual-1", "virtual-2"]);
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(match event {
+                DaemonEvent::DeviceAvailable { serial, .. } => format!("available {}", serial),
+                DaemonEvent::DeviceUnavailable { serial } => format!("unavailable {}", serial),
+                DaemonEvent::HidDisconnected { serial } => format!("disconnected {}", serial),
+                other => panic!("unexpected {:?}", other),
+            });
+        }
+        assert_eq!(
+            events,
+            [
+                "available virtual-1",
+                "available virtual-2",
+                "disconnected virtual-2",
+                "unavailable virtual-2",
+                "available virtual-2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_soft_keys_per_deck() {
+        let decks = two_decks();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let hid = HidManager::with_backend(
+            HidConfig::default(),
+            DaemonEventSender::new(tx),
+            Box::new(VirtualBackend::with_decks(decks.to_vec())),
+        );
+        hid.open_device(None).wait().unwrap();
+
+        hid.set_soft_key(Some("virtual-2"), 0, SoftKeyType::String, b"ls", false).wait().unwrap();
+        assert_eq!(decks[0].snapshot().soft_keys[0].key_type, SoftKeyType::Default);
+        assert_eq!(decks[1].snapshot().soft_keys[0].key_type, SoftKeyType::String);
+        assert_eq!(hid.get_soft_key(Some("virtual-2"), 0).wait().unwrap().key_type, SoftKeyType::String);
+        assert!(hid.get_soft_key(Some("nope"), 0).wait().is_err());
+
+        // Reads without a serial come from the first open deck
+        assert_eq!(hid.get_soft_key(None, 0).wait().unwrap().key_type, SoftKeyType::Default);
+        hid.close_device(Some("virtual-1")).wait().unwrap();
+        assert_eq!(hid.get_soft_key(None, 0).wait().unwrap().key_type, SoftKeyType::String);
+
+        // Writes without a serial reach every open deck; a closed one is an error when named
+        hid.set_soft_key(None, 1, SoftKeyType::String, b"pwd", false).wait().unwrap();
+        assert_eq!(decks[0].snapshot().soft_keys[1].key_type, SoftKeyType::Default);
+        assert_eq!(decks[1].snapshot().soft_keys[1].data, b"pwd");
+        assert!(hid.set_soft_key(Some("virtual-1"), 1, SoftKeyType::Default, &[], false).wait().is_err());
+    }
 }
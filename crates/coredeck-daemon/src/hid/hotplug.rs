@@ -0,0 +1,15 @@
+//! Native USB hotplug notifications (IOKit on macOS, kernel uevents on Linux)
+
+#[cfg(target_os = "linux")]
+pub use super::hotplug_linux::HotplugWatcher;
+#[cfg(target_os = "macos")]
+pub use super::hotplug_macos::HotplugWatcher;
+
+/// Event type for hotplug notifications
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotplugEvent {
+    /// Device was connected
+    DeviceArrived,
+    /// Device was removed
+    DeviceRemoved,
+}
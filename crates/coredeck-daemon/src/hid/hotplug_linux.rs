@@ -0,0 +1,264 @@
+//! Linux USB hotplug detection via kernel uevents
+//!
+//! Listens on a `NETLINK_KOBJECT_UEVENT` socket for `hidraw` nodes being added
+//! or removed, so no libudev or polling is needed. Every matching node fires
+//! an event (a deck exposes one per HID interface, and several decks may be
+//! plugged in); the manager re-enumerates to find out what changed.
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use super::hotplug::HotplugEvent;
+
+/// Multicast group for raw kernel uevents (udevd re-broadcasts on group 2)
+const KERNEL_UEVENT_GROUP: u32 = 1;
+
+/// How long `poll()` waits before re-checking the stop flag
+const POLL_TIMEOUT_MS: i32 = 1000;
+
+/// Largest uevent the kernel sends (`UEVENT_BUFFER_SIZE`)
+const UEVENT_BUFFER_SIZE: usize = 2048;
+
+/// Hotplug watcher for USB HID devices on Linux
+pub struct HotplugWatcher {
+    /// Whether the watcher should stop
+    stop: Arc<AtomicBool>,
+    /// Handle to the watcher thread
+    thread_handle: Option<thread::JoinHandle<()>>,
+}
+
+impl HotplugWatcher {
+    /// Create a new hotplug watcher for the given USB VID/PID
+    pub fn new(
+        vendor_id: u16,
+        product_id: u16,
+        event_tx: mpsc::UnboundedSender<HotplugEvent>,
+    ) -> Result<Self, String> {
+        // Open the socket up front so failure falls back to polling
+        let socket = open_uevent_socket()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = Arc::clone(&stop);
+
+        let thread_handle = thread::spawn(move || {
+            if let Err(e) = run_watcher(socket, vendor_id, product_id, event_tx, stop_clone) {
+                error!("Hotplug watcher error: {}", e);
+            }
+        });
+
+        Ok(Self {
+            stop,
+            thread_handle: Some(thread_handle),
+        })
+    }
+
+    /// Stop the hotplug watcher (returns within `POLL_TIMEOUT_MS`)
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.thread_handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for HotplugWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Bind a netlink socket to the kernel uevent multicast group
+fn open_uevent_socket() -> Result<OwnedFd, String> {
+    // SAFETY: plain socket/bind syscalls; the fd is owned by `OwnedFd` from here on.
+    unsafe {
+        let fd = libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_KOBJECT_UEVENT,
+        );
+        if fd < 0 {
+            return Err(format!("netlink socket failed: {}", std::io::Error::last_os_error()));
+        }
+        let socket = OwnedFd::from_raw_fd(fd);
+
+        let mut addr: libc::sockaddr_nl = std::mem::zeroed();
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = KERNEL_UEVENT_GROUP;
+        let rc = libc::bind(
+            fd,
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        );
+        if rc < 0 {
+            return Err(format!("netlink bind failed: {}", std::io::Error::last_os_error()));
+        }
+        Ok(socket)
+    }
+}
+
+/// Receive and filter uevents until stopped
+fn run_watcher(
+    socket: OwnedFd,
+    vendor_id: u16,
+    product_id: u16,
+    event_tx: mpsc::UnboundedSender<HotplugEvent>,
+    stop: Arc<AtomicBool>,
+) -> Result<(), String> {
+    info!(
+        "uevent hotplug watcher started for VID:0x{:04X} PID:0x{:04X}",
+        vendor_id, product_id
+    );
+
+    let mut buf = [0u8; UEVENT_BUFFER_SIZE];
+    while !stop.load(Ordering::SeqCst) {
+        let mut pfd = libc::pollfd {
+            fd: socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `pfd` is a valid pollfd for the duration of the call.
+        let ready = unsafe { libc::poll(&mut pfd, 1, POLL_TIMEOUT_MS) };
+        if ready < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(format!("poll failed: {}", err));
+        }
+        if ready == 0 {
+            continue;
+        }
+
+        let mut sender: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        let mut sender_len = std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+        // SAFETY: `buf` and `sender` outlive the call and their lengths are passed along.
+        let len = unsafe {
+            libc::recvfrom(
+                socket.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+                &mut sender as *mut libc::sockaddr_nl as *mut libc::sockaddr,
+                &mut sender_len,
+            )
+        };
+        if len < 0 {
+            let err = std::io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) | Some(libc::EAGAIN) => continue,
+                Some(libc::ENOBUFS) => {
+                    // Events were dropped — any event makes the manager re-enumerate
+                    warn!("uevent buffer overrun, rescanning devices");
+                    let _ = event_tx.send(HotplugEvent::DeviceArrived);
+                    continue;
+                }
+                _ => return Err(format!("recvfrom failed: {}", err)),
+            }
+        }
+
+        // Only trust messages from the kernel itself
+        if sender.nl_pid != 0 {
+            continue;
+        }
+
+        if let Some(event) = parse_uevent(&buf[..len as usize], vendor_id, product_id) {
+            match event {
+                HotplugEvent::DeviceArrived => debug!("hidraw node added"),
+                HotplugEvent::DeviceRemoved => debug!("hidraw node removed"),
+            }
+            let _ = event_tx.send(event);
+        }
+    }
+
+    info!("uevent hotplug watcher stopped");
+    Ok(())
+}
+
+/// Event for a hidraw node of our VID/PID being added or removed
+fn parse_uevent(msg: &[u8], vendor_id: u16, product_id: u16) -> Option<HotplugEvent> {
+    // Kernel format: "ACTION@DEVPATH\0KEY=VALUE\0KEY=VALUE\0..."
+    let mut fields = msg.split(|&b| b == 0).filter_map(|f| std::str::from_utf8(f).ok());
+    fields.next()?.split_once('@')?;
+
+    let (mut action, mut devpath, mut subsystem) = (None, None, None);
+    for field in fields {
+        match field.split_once('=') {
+            Some(("ACTION", v)) => action = Some(v),
+            Some(("DEVPATH", v)) => devpath = Some(v),
+            Some(("SUBSYSTEM", v)) => subsystem = Some(v),
+            _ => {}
+        }
+    }
+    if subsystem != Some("hidraw") {
+        return None;
+    }
+    if hid_ids(devpath?) != Some((vendor_id, product_id)) {
+        return None;
+    }
+
+    match action? {
+        "add" => Some(HotplugEvent::DeviceArrived),
+        "remove" => Some(HotplugEvent::DeviceRemoved),
+        _ => None,
+    }
+}
+
+/// VID/PID from the HID device component of a sysfs path
+/// (`.../0003:FEED:0803.0004/hidraw/hidraw2` → `(0xFEED, 0x0803)`).
+fn hid_ids(devpath: &str) -> Option<(u16, u16)> {
+    devpath.rsplit('/').find_map(|component| {
+        // BUS:VID:PID.INSTANCE, four hex digits each (unlike PCI addresses)
+        let (ids, instance) = component.split_once('.')?;
+        let mut parts = ids.split(':');
+        let (bus, vid, pid) = (parts.next()?, parts.next()?, parts.next()?);
+        let is_hex4 = |s: &str| s.len() == 4 && s.bytes().all(|b| b.is_ascii_hexdigit());
+        if parts.next().is_some() || ![bus, vid, pid, instance].into_iter().all(is_hex4) {
+            return None;
+        }
+        Some((u16::from_str_radix(vid, 16).ok()?, u16::from_str_radix(pid, 16).ok()?))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IFACE0: &str = "/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:FEED:0803.0004/hidraw/hidraw2";
+    const IFACE1: &str = "/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.1/0003:FEED:0803.0005/hidraw/hidraw3";
+
+    fn uevent(action: &str, devpath: &str, subsystem: &str) -> Vec<u8> {
+        format!(
+            "{action}@{devpath}\0ACTION={action}\0DEVPATH={devpath}\0SUBSYSTEM={subsystem}\0SEQNUM=1\0"
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_hid_ids() {
+        assert_eq!(hid_ids(IFACE0), Some((0xFEED, 0x0803)));
+        assert_eq!(hid_ids("/devices/virtual/misc/uhid/0003:046D:C52B.0001/hidraw/hidraw0"), Some((0x046D, 0xC52B)));
+        assert_eq!(hid_ids("/devices/pci0000:00/0000:00:14.0/usb1/1-2"), None);
+    }
+
+    #[test]
+    fn test_every_interface_reported() {
+        let (vid, pid) = (0xFEED, 0x0803);
+        assert_eq!(parse_uevent(&uevent("add", IFACE0, "hidraw"), vid, pid), Some(HotplugEvent::DeviceArrived));
+        assert_eq!(parse_uevent(&uevent("add", IFACE1, "hidraw"), vid, pid), Some(HotplugEvent::DeviceArrived));
+        assert_eq!(parse_uevent(&uevent("remove", IFACE0, "hidraw"), vid, pid), Some(HotplugEvent::DeviceRemoved));
+    }
+
+    #[test]
+    fn test_unrelated_uevents_ignored() {
+        let other = "/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/0003:046D:C52B.0001/hidraw/hidraw0";
+        assert_eq!(parse_uevent(&uevent("add", other, "hidraw"), 0xFEED, 0x0803), None);
+        // Parent HID device, not the hidraw node
+        let hid = "/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:FEED:0803.0004";
+        assert_eq!(parse_uevent(&uevent("add", hid, "hid"), 0xFEED, 0x0803), None);
+        assert_eq!(parse_uevent(&uevent("change", IFACE0, "hidraw"), 0xFEED, 0x0803), None);
+        assert_eq!(parse_uevent(b"libudev\0garbage", 0xFEED, 0x0803), None);
+    }
+}
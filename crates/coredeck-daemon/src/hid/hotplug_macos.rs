@@ -21,14 +21,7 @@ use std::thread;
 use tokio::sync::mpsc;
 use tracing::{debug, error, info};
 
-/// Event type for hotplug notifications
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum HotplugEvent {
-    /// Device was connected
-    DeviceArrived,
-    /// Device was removed
-    DeviceRemoved,
-}
+use super::hotplug::HotplugEvent;
 
 /// Hotplug watcher for USB devices on macOS
 pub struct HotplugWatcher {
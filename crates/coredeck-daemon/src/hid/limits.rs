@@ -0,0 +1,205 @@
+//! Payload validation against the firmware's protocol limits
+//!
+//! The firmware copies text fields into fixed C buffers and drops whatever
+//! doesn't fit its reassembly buffer, cutting mid-character. Text fields are
+//! shortened here on a character boundary instead; tab lists and indices the
+//! deck can't show, and messages still too large, are rejected.
+
+use super::commands;
+use coredeck_protocol::{
+    AlertRequest, DeviceCapabilities, DisplayUpdate, FieldAction, FieldIssue, SoftKeyAction, ValidationError,
+    SOFT_KEY_MAX_STEPS, SOFT_KEY_MAX_TEXT,
+};
+
+/// Fit a display update to `limits`. Returns the truncated fields, or every
+/// issue when something had to be rejected.
+pub fn check_display(limits: &DeviceCapabilities, update: &mut DisplayUpdate) -> Result<Vec<FieldIssue>, ValidationError> {
+    let mut issues = Vec::new();
+    truncate(limits, "session", &mut update.session, &mut issues);
+    truncate(limits, "task", &mut update.task, &mut issues);
+    truncate(limits, "task2", &mut update.task2, &mut issues);
+
+    let max_tabs = usize::from(limits.max_tabs);
+    if update.tabs.len() > max_tabs {
+        issues.push(rejected("tabs", format!("has {} entries, the deck shows at most {}", update.tabs.len(), max_tabs)));
+    } else if update.active >= update.tabs.len().max(1) {
+        issues.push(rejected("active", format!("{} is out of range for {} tabs", update.active, update.tabs.len())));
+    }
+
+    let payload = commands::display_payload(
+        &update.session,
+        Some(update.task.as_str()),
+        Some(update.task2.as_str()),
+        &update.tabs,
+        update.active,
+    );
+    check_payload(limits, &payload, &mut issues);
+    finish(issues)
+}
+
+/// Fit an alert to `limits`. Returns the truncated fields, or every issue
+/// when something had to be rejected.
+pub fn check_alert(limits: &DeviceCapabilities, alert: &mut AlertRequest) -> Result<Vec<FieldIssue>, ValidationError> {
+    let mut issues = Vec::new();
+    truncate(limits, "session", &mut alert.session, &mut issues);
+    truncate(limits, "text", &mut alert.text, &mut issues);
+    if let Some(details) = alert.details.as_mut() {
+        truncate(limits, "details", details, &mut issues);
+    }
+
+    if alert.tab >= usize::from(limits.max_tabs) {
+        issues.push(rejected("tab", format!("{} is out of range for {} tabs", alert.tab, limits.max_tabs)));
+    }
+
+    let payload = commands::alert_payload(alert.tab, &alert.session, &alert.text, alert.details.as_deref());
+    check_payload(limits, &payload, &mut issues);
+    finish(issues)
+}
+
+/// Check a soft key assignment. Text is shortened to what the firmware
+/// stores; indices past the deck's keys and overlong sequences are rejected.
+pub fn check_soft_key(
+    limits: &DeviceCapabilities,
+    index: u8,
+    action: &mut SoftKeyAction,
+) -> Result<Vec<FieldIssue>, ValidationError> {
+    let mut issues = Vec::new();
+    if index >= limits.soft_key_count {
+        issues.push(rejected("index", format!("{} is out of range for {} soft keys", index, limits.soft_key_count)));
+    }
+    match action {
+        SoftKeyAction::String { text, .. } => {
+            let len = text.len();
+            if coredeck_protocol::truncate_utf8(text, SOFT_KEY_MAX_TEXT) {
+                issues.push(FieldIssue {
+                    field: "text".to_string(),
+                    action: FieldAction::Truncated,
+                    reason: format!("shortened from {} to {} bytes", len, text.len()),
+                });
+            }
+        }
+        SoftKeyAction::Sequence { keys } if keys.len() > SOFT_KEY_MAX_STEPS => {
+            issues.push(rejected("keys", format!("has {} steps, a soft key holds at most {}", keys.len(), SOFT_KEY_MAX_STEPS)));
+        }
+        _ => {}
+    }
+    finish(issues)
+}
+
+fn truncate(limits: &DeviceCapabilities, field: &str, value: &mut String, issues: &mut Vec<FieldIssue>) {
+    let len = value.len();
+    if coredeck_protocol::truncate_utf8(value, limits.max_text_len()) {
+        issues.push(FieldIssue {
+            field: field.to_string(),
+            action: FieldAction::Truncated,
+            reason: format!("shortened from {} to {} bytes", len, value.len()),
+        });
+    }
+}
+
+fn check_payload(limits: &DeviceCapabilities, payload: &serde_json::Value, issues: &mut Vec<FieldIssue>) {
+    let len = payload.to_string().len();
+    let max = usize::from(limits.max_payload_bytes);
+    if len > max {
+        issues.push(rejected("payload", format!("is {} bytes of JSON, the deck takes at most {}", len, max)));
+    }
+}
+
+fn rejected(field: &str, reason: String) -> FieldIssue {
+    FieldIssue { field: field.to_string(), action: FieldAction::Rejected, reason }
+}
+
+fn finish(issues: Vec<FieldIssue>) -> Result<Vec<FieldIssue>, ValidationError> {
+    if issues.iter().any(|issue| issue.action == FieldAction::Rejected) {
+        return Err(ValidationError { error: "payload exceeds device limits".into(), fields: issues });
+    }
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(session: &str, tabs: Vec<u8>, active: usize) -> DisplayUpdate {
+        DisplayUpdate { session: session.into(), task: String::new(), task2: String::new(), tabs, active }
+    }
+
+    fn alert(tab: usize, text: &str) -> AlertRequest {
+        AlertRequest { tab, session: "s".into(), text: text.into(), details: None, device: None }
+    }
+
+    #[test]
+    fn test_display_within_limits() {
+        let mut u = update("work", vec![1, 2], 1);
+        assert_eq!(check_display(&DeviceCapabilities::default(), &mut u).unwrap(), vec![]);
+        assert_eq!(u.session, "work");
+    }
+
+    #[test]
+    fn test_display_truncates_on_char_boundary() {
+        let mut u = update(&"é".repeat(100), vec![1], 0);
+        let issues = check_display(&DeviceCapabilities::default(), &mut u).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].field, "session");
+        assert_eq!(issues[0].action, FieldAction::Truncated);
+        assert_eq!(u.session.len(), 126);
+        assert!(u.session.chars().all(|c| c == 'é'));
+    }
+
+    #[test]
+    fn test_display_rejects_tabs_and_active() {
+        let limits = DeviceCapabilities::default();
+        let err = check_display(&limits, &mut update("s", vec![0; 17], 0)).unwrap_err();
+        assert_eq!(err.fields, vec![rejected("tabs", "has 17 entries, the deck shows at most 16".into())]);
+
+        let err = check_display(&limits, &mut update("s", vec![0; 3], 3)).unwrap_err();
+        assert_eq!(err.fields[0].field, "active");
+        assert!(check_display(&limits, &mut update("s", vec![], 1)).is_err());
+        assert!(check_display(&limits, &mut update("s", vec![], 0)).is_ok());
+    }
+
+    #[test]
+    fn test_display_reports_truncation_alongside_rejection() {
+        let mut u = update(&"x".repeat(200), vec![0; 20], 0);
+        let err = check_display(&DeviceCapabilities::default(), &mut u).unwrap_err();
+        let fields: Vec<_> = err.fields.iter().map(|i| (i.field.as_str(), i.action)).collect();
+        assert_eq!(fields, [("session", FieldAction::Truncated), ("tabs", FieldAction::Rejected)]);
+        assert_eq!(err.to_string(), "payload exceeds device limits: tabs has 20 entries, the deck shows at most 16");
+    }
+
+    #[test]
+    fn test_payload_limit() {
+        // Quotes double in JSON, so three full fields overflow 512 bytes
+        let mut u = update(&"\"".repeat(127), vec![1], 0);
+        u.task = u.session.clone();
+        u.task2 = u.session.clone();
+        let err = check_display(&DeviceCapabilities::default(), &mut u).unwrap_err();
+        assert_eq!(err.fields.last().unwrap().field, "payload");
+    }
+
+    #[test]
+    fn test_alert_limits() {
+        let limits = DeviceCapabilities { max_tabs: 4, max_text_bytes: 9, ..Default::default() };
+        let mut a = alert(3, "approve this?");
+        let issues = check_alert(&limits, &mut a).unwrap();
+        assert_eq!(issues[0].field, "text");
+        assert_eq!(a.text, "approve ");
+
+        let err = check_alert(&limits, &mut alert(4, "hi")).unwrap_err();
+        assert_eq!(err.fields[0].field, "tab");
+    }
+
+    #[test]
+    fn test_soft_key_limits() {
+        let limits = DeviceCapabilities::default();
+        let mut text = SoftKeyAction::String { text: "x".repeat(130), send_enter: true };
+        let issues = check_soft_key(&limits, 2, &mut text).unwrap();
+        assert_eq!(issues[0].field, "text");
+        assert_eq!(text, SoftKeyAction::String { text: "x".repeat(SOFT_KEY_MAX_TEXT), send_enter: true });
+
+        let err = check_soft_key(&limits, 3, &mut SoftKeyAction::Default { key: None }).unwrap_err();
+        assert_eq!(err.to_string(), "payload exceeds device limits: index 3 is out of range for 3 soft keys");
+        let mut long = SoftKeyAction::Sequence { keys: vec![Default::default(); 64] };
+        assert_eq!(check_soft_key(&limits, 0, &mut long).unwrap_err().fields[0].field, "keys");
+    }
+}
@@ -0,0 +1,293 @@
+//! HID link health counters
+//!
+//! Totals across every deck since the daemon started, served in Prometheus
+//! text format at `GET /api/metrics` and summarised in `DaemonStatus`.
+//! Everything is atomic so recording never blocks HID traffic.
+
+use super::transport::HidTransport;
+use anyhow::Result;
+use coredeck_protocol::HidLinkStats;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Upper bounds (seconds) of the ping round-trip histogram buckets.
+/// The pong read times out after 100ms, so nothing slower is observed.
+const PING_RTT_BUCKETS: [f64; 7] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1];
+
+/// Monotonic counter
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Fixed-bucket histogram of durations
+#[derive(Default)]
+pub struct Histogram {
+    /// Observations per bucket (not cumulative); the last one is `+Inf`
+    buckets: [AtomicU64; PING_RTT_BUCKETS.len() + 1],
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, value: Duration) {
+        let secs = value.as_secs_f64();
+        let index = PING_RTT_BUCKETS
+            .iter()
+            .position(|&bound| secs <= bound)
+            .unwrap_or(PING_RTT_BUCKETS.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(value.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Mean observation, `None` before the first one
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        (count > 0).then(|| Duration::from_micros(self.sum_micros.load(Ordering::Relaxed) / count))
+    }
+}
+
+/// Link health of every deck since the daemon started
+#[derive(Default)]
+pub struct HidMetrics {
+    /// Reports written to a deck
+    pub packets_sent: Counter,
+    /// Reports read from a deck (responses and device-initiated packets)
+    pub packets_received: Counter,
+    /// Failed report writes
+    pub write_errors: Counter,
+    /// Report writes tried again after a failure
+    pub write_retries: Counter,
+    /// Chunked responses that restarted before their END chunk
+    pub chunk_retries: Counter,
+    /// Pings that could not be written or answered
+    pub ping_failures: Counter,
+    /// Decks that dropped while open (ping failures or unplugged)
+    pub link_losses: Counter,
+    /// Decks reopened after their link was lost
+    pub reconnects: Counter,
+    /// Display updates skipped because the deck already shows them
+    pub display_deduplicated: Counter,
+    /// Queued display updates replaced by a newer one before being sent
+    pub display_coalesced: Counter,
+    /// Commands refused because the write queue was full
+    pub queue_rejections: Counter,
+    /// Time from writing a ping to reading the pong
+    pub ping_rtt: Histogram,
+    /// Serials whose link was lost and not yet reopened
+    lost: Mutex<HashSet<String>>,
+}
+
+impl HidMetrics {
+    /// Record a deck dropping while open
+    pub fn link_lost(&self, serial: &str) {
+        self.link_losses.inc();
+        self.lost.lock().insert(serial.to_string());
+    }
+
+    /// Record a deck being opened; counts a reconnect after a lost link
+    pub fn opened(&self, serial: &str) {
+        if self.lost.lock().remove(serial) {
+            self.reconnects.inc();
+        }
+    }
+
+    /// Summary for `DaemonStatus`
+    pub fn snapshot(&self) -> HidLinkStats {
+        HidLinkStats {
+            packets_sent: self.packets_sent.get(),
+            packets_received: self.packets_received.get(),
+            write_errors: self.write_errors.get(),
+            write_retries: self.write_retries.get(),
+            chunk_retries: self.chunk_retries.get(),
+            ping_failures: self.ping_failures.get(),
+            link_losses: self.link_losses.get(),
+            reconnects: self.reconnects.get(),
+            display_updates_deduplicated: self.display_deduplicated.get(),
+            display_updates_coalesced: self.display_coalesced.get(),
+            queue_rejections: self.queue_rejections.get(),
+            ping_rtt_avg_ms: self.ping_rtt.mean().map(|d| d.as_secs_f64() * 1000.0),
+        }
+    }
+
+    /// Prometheus text exposition format (version 0.0.4)
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("coredeck_hid_packets_sent_total", "Reports written to a deck", &self.packets_sent),
+            ("coredeck_hid_packets_received_total", "Reports read from a deck", &self.packets_received),
+            ("coredeck_hid_write_errors_total", "Failed report writes", &self.write_errors),
+            ("coredeck_hid_write_retries_total", "Report writes tried again after a failure", &self.write_retries),
+            (
+                "coredeck_hid_chunk_retries_total",
+                "Chunked responses restarted before their END chunk",
+                &self.chunk_retries,
+            ),
+            ("coredeck_hid_ping_failures_total", "Pings not written or not answered", &self.ping_failures),
+            ("coredeck_hid_link_losses_total", "Decks dropped while open", &self.link_losses),
+            ("coredeck_hid_reconnects_total", "Decks reopened after a lost link", &self.reconnects),
+            (
+                "coredeck_display_updates_deduplicated_total",
+                "Display updates skipped as identical to the last one sent",
+                &self.display_deduplicated,
+            ),
+            (
+                "coredeck_display_updates_coalesced_total",
+                "Queued display updates replaced by a newer one",
+                &self.display_coalesced,
+            ),
+            (
+                "coredeck_hid_queue_rejections_total",
+                "Commands refused because the write queue was full",
+                &self.queue_rejections,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", counter.get());
+        }
+
+        let name = "coredeck_hid_ping_rtt_seconds";
+        let _ = writeln!(out, "# HELP {name} Ping round-trip time");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (i, bucket) in self.ping_rtt.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = PING_RTT_BUCKETS.get(i).map_or("+Inf".to_string(), |b| b.to_string());
+            let _ = writeln!(out, "{name}_bucket{{le=\"{le}\"}} {cumulative}");
+        }
+        let sum = self.ping_rtt.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {cumulative}");
+        out
+    }
+}
+
+/// Transport wrapper counting reports and write errors
+pub struct MeteredTransport {
+    inner: Box<dyn HidTransport>,
+    metrics: Arc<HidMetrics>,
+}
+
+impl MeteredTransport {
+    pub fn new(inner: Box<dyn HidTransport>, metrics: Arc<HidMetrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+impl HidTransport for MeteredTransport {
+    fn write(&self, report: &[u8]) -> Result<usize> {
+        match self.inner.write(report) {
+            Ok(written) => {
+                self.metrics.packets_sent.inc();
+                Ok(written)
+            }
+            Err(e) => {
+                self.metrics.write_errors.inc();
+                Err(e)
+            }
+        }
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        let read = self.inner.read_timeout(buf, timeout_ms)?;
+        if read > 0 {
+            self.metrics.packets_received.inc();
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    struct Flaky;
+
+    impl HidTransport for Flaky {
+        fn write(&self, report: &[u8]) -> Result<usize> {
+            if report[0] == 0 {
+                Err(anyhow!("unplugged"))
+            } else {
+                Ok(report.len())
+            }
+        }
+
+        fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+            Ok(if timeout_ms > 0 { buf.len() } else { 0 })
+        }
+    }
+
+    #[test]
+    fn test_metered_transport() {
+        let metrics = Arc::new(HidMetrics::default());
+        let transport = MeteredTransport::new(Box::new(Flaky), Arc::clone(&metrics));
+        let mut buf = [0u8; 32];
+
+        transport.write(&[1; 32]).unwrap();
+        assert!(transport.write(&[0; 32]).is_err());
+        transport.read_timeout(&mut buf, 10).unwrap();
+        transport.read_timeout(&mut buf, 0).unwrap(); // Timeout is not a packet
+
+        assert_eq!(metrics.packets_sent.get(), 1);
+        assert_eq!(metrics.write_errors.get(), 1);
+        assert_eq!(metrics.write_retries.get(), 0); // Retries are up to the writer thread
+        assert_eq!(metrics.packets_received.get(), 1);
+    }
+
+    #[test]
+    fn test_reconnects_follow_lost_links() {
+        let metrics = HidMetrics::default();
+        metrics.opened("a");
+        assert_eq!(metrics.reconnects.get(), 0);
+
+        metrics.link_lost("a");
+        metrics.opened("b");
+        metrics.opened("a");
+        metrics.opened("a");
+        assert_eq!(metrics.link_losses.get(), 1);
+        assert_eq!(metrics.reconnects.get(), 1);
+    }
+
+    #[test]
+    fn test_prometheus_histogram() {
+        let metrics = HidMetrics::default();
+        metrics.ping_rtt.observe(Duration::from_micros(800));
+        metrics.ping_rtt.observe(Duration::from_millis(4));
+        metrics.ping_rtt.observe(Duration::from_millis(300));
+        metrics.display_deduplicated.inc();
+
+        let text = metrics.render_prometheus();
+        assert!(text.contains("# TYPE coredeck_hid_ping_rtt_seconds histogram\n"));
+        assert!(text.contains("coredeck_hid_ping_rtt_seconds_bucket{le=\"0.001\"} 1\n"));
+        assert!(text.contains("coredeck_hid_ping_rtt_seconds_bucket{le=\"0.0025\"} 1\n"));
+        assert!(text.contains("coredeck_hid_ping_rtt_seconds_bucket{le=\"0.005\"} 2\n"));
+        assert!(text.contains("coredeck_hid_ping_rtt_seconds_bucket{le=\"0.1\"} 2\n"));
+        assert!(text.contains("coredeck_hid_ping_rtt_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("coredeck_hid_ping_rtt_seconds_count 3\n"));
+        assert!(text.contains("coredeck_display_updates_deduplicated_total 1\n"));
+
+        let stats = metrics.snapshot();
+        assert_eq!(stats.display_updates_deduplicated, 1);
+        assert_eq!(stats.ping_rtt_avg_ms.map(|ms| ms.round()), Some(102.0));
+    }
+}
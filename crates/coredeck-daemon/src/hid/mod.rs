@@ -2,9 +2,24 @@
 
 mod commands;
 mod device;
+mod writer;
+pub mod limits;
+pub mod metrics;
 pub mod protocol;
+pub mod transport;
+pub mod virtual_deck;
 
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+mod hotplug;
 #[cfg(target_os = "macos")]
 mod hotplug_macos;
+#[cfg(target_os = "linux")]
+mod hotplug_linux;
+
+#[cfg(test)]
+mod conformance;
 
 pub use device::HidManager;
+pub use metrics::HidMetrics;
+pub use writer::{HidCompletion, QueueFull};
+pub use virtual_deck::{VirtualBackend, VirtualDeck};
@@ -21,6 +21,9 @@ pub const MAX_PAYLOAD_SIZE: usize = PACKET_SIZE - HEADER_SIZE;
 /// VIAL prefix byte prepended to every packet when VIAL mode is active
 pub const VIAL_PREFIX: u8 = 0x80;
 
+/// Largest reassembled message either side accepts (firmware `PROTO_REASSEMBLY_SIZE`)
+pub const MAX_MESSAGE_SIZE: usize = 512;
+
 /// Protocol mode: standalone custom HID vs VIAL-wrapped
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -78,6 +81,8 @@ pub enum HidCommand {
     GetVersion = 0x09,
     /// Signal host is disconnecting — firmware goes idle immediately
     Disconnect = 0x0A,
+    /// Query firmware limits (response: JSON `DeviceCapabilities`)
+    GetCapabilities = 0x0B,
     /// Device state report (unsolicited from device)
     StateReport = 0x10,
     /// Type a string into the active terminal (unsolicited from device)
@@ -107,6 +112,7 @@ impl HidCommand {
             0x08 => Some(HidCommand::Alert),
             0x09 => Some(HidCommand::GetVersion),
             0x0A => Some(HidCommand::Disconnect),
+            0x0B => Some(HidCommand::GetCapabilities),
             0x10 => Some(HidCommand::StateReport),
             0x11 => Some(HidCommand::TypeString),
             0x12 => Some(HidCommand::KeyEvent),
@@ -175,6 +181,14 @@ impl HidPacket {
         &self.data[HEADER_SIZE..]
     }
 
+    /// Payload bytes actually carried on the wire in `mode`.
+    ///
+    /// In VIAL mode the last payload byte is dropped by the prefix, so only the
+    /// first 29 bytes belong to the message; the 30th is padding.
+    pub fn wire_payload(&self, mode: ProtocolMode) -> &[u8] {
+        &self.payload()[..mode.max_payload_size()]
+    }
+
     /// Get mutable payload slice
     pub fn payload_mut(&mut self) -> &mut [u8] {
         &mut self.data[HEADER_SIZE..]
@@ -190,7 +204,8 @@ impl HidPacket {
         }
     }
 
-    /// Get raw packet data for sending
+    /// Raw packet data (the wire form is `to_wire`)
+    #[cfg(test)]
     pub fn as_bytes(&self) -> &[u8; PACKET_SIZE] {
         &self.data
     }
@@ -202,6 +217,38 @@ impl HidPacket {
         packet.data[..len].copy_from_slice(&bytes[..len]);
         packet
     }
+
+    /// Encode the packet as the 32 bytes that go on the wire.
+    ///
+    /// In VIAL mode the wire bytes become `[0x80, flags, cmd, payload×29]` — the VIAL prefix
+    /// is prepended and the last payload byte is dropped to stay within 32 bytes.
+    pub fn to_wire(&self, mode: ProtocolMode) -> [u8; PACKET_SIZE] {
+        match mode {
+            ProtocolMode::Vial => {
+                let mut buf = [0u8; PACKET_SIZE];
+                buf[0] = VIAL_PREFIX;
+                buf[1..PACKET_SIZE].copy_from_slice(&self.data[..PACKET_SIZE - 1]);
+                buf
+            }
+            ProtocolMode::Standalone => self.data,
+        }
+    }
+
+    /// Decode a packet from wire bytes.
+    ///
+    /// In VIAL mode returns `None` if `bytes[0] != 0x80` (a VIA echo); otherwise the
+    /// prefix is stripped by shifting bytes left by 1.
+    pub fn from_wire(bytes: &[u8], mode: ProtocolMode) -> Option<Self> {
+        match mode {
+            ProtocolMode::Vial => {
+                if bytes.first() != Some(&VIAL_PREFIX) {
+                    return None;
+                }
+                Some(Self::from_bytes(&bytes[1..]))
+            }
+            ProtocolMode::Standalone => Some(Self::from_bytes(bytes)),
+        }
+    }
 }
 
 /// Build chunked packets for a command with a payload.
@@ -404,6 +451,25 @@ mod tests {
         assert_eq!(packets.len(), 3);
     }
 
+    #[test]
+    fn test_wire_roundtrip_vial() {
+        let mut packet = HidPacket::with_command(FLAG_START | FLAG_END, HidCommand::GetVersion);
+        packet.set_payload(b"abc");
+        let wire = packet.to_wire(ProtocolMode::Vial);
+        assert_eq!(wire[0], VIAL_PREFIX);
+        assert_eq!(wire[1], FLAG_START | FLAG_END);
+        assert_eq!(wire[2], HidCommand::GetVersion.as_byte());
+
+        let decoded = HidPacket::from_wire(&wire, ProtocolMode::Vial).unwrap();
+        assert_eq!(decoded.command(), Some(HidCommand::GetVersion));
+        assert_eq!(&decoded.payload()[..3], b"abc");
+
+        // Non-prefixed reports are VIA echoes in VIAL mode
+        let plain = packet.to_wire(ProtocolMode::Standalone);
+        assert!(HidPacket::from_wire(&plain, ProtocolMode::Vial).is_none());
+        assert!(HidPacket::from_wire(&plain, ProtocolMode::Standalone).is_some());
+    }
+
     #[test]
     fn test_display_update_json() {
         let update = DisplayUpdate {
@@ -0,0 +1,181 @@
+//! HID transport abstraction
+//!
+//! `HidManager` never touches hidapi directly — it discovers and opens the deck
+//! through a `HidBackend` and exchanges 32-byte reports through the resulting
+//! `HidTransport`. The real backend wraps hidapi; the virtual backend
+//! (`--virtual-device`) emulates the firmware in software.
+
+use crate::HidConfig;
+use anyhow::{anyhow, Context, Result};
+use hidapi::{HidApi, HidDevice};
+
+/// An open HID interface that exchanges raw reports with the device.
+pub trait HidTransport: Send {
+    /// Write one 32-byte output report (without a report ID byte).
+    fn write(&self, report: &[u8]) -> Result<usize>;
+
+    /// Read one input report into `buf`, waiting at most `timeout_ms`.
+    /// Returns `Ok(0)` on timeout.
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize>;
+}
+
+/// A matching deck found by enumeration (not opened)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceListing {
+    /// Identifies the deck across enumerations (see `DeviceSummary::serial`)
+    pub serial: String,
+    /// Display name
+    pub name: String,
+}
+
+/// Device discovery for one kind of transport.
+pub trait HidBackend: Send {
+    /// Re-scan for devices (called before every presence check)
+    fn refresh(&mut self) -> Result<()>;
+
+    /// Every matching deck, without opening any. Serials are unique.
+    fn list(&self, config: &HidConfig) -> Vec<DeviceListing>;
+
+    /// Open the deck with the given serial.
+    fn open(&self, config: &HidConfig, serial: &str) -> Result<Box<dyn HidTransport>>;
+
+    /// Whether OS hotplug notifications apply to this backend.
+    /// When false, the manager falls back to polling `list()`.
+    fn supports_hotplug(&self) -> bool {
+        true
+    }
+}
+
+// ── hidapi backend ─────────────────────────────────────────────────
+
+/// Backend for physical devices via hidapi
+pub struct HidApiBackend {
+    api: HidApi,
+}
+
+impl HidApiBackend {
+    pub fn new() -> Result<Self> {
+        let api = HidApi::new().context("Failed to initialize HID API")?;
+
+        // Don't seize the device exclusively on macOS — we only need the
+        // vendor-specific raw-HID interface (0xFF60) and must not prevent
+        // the system keyboard driver from receiving events on the standard
+        // keyboard interface of the same composite USB device.
+        #[cfg(target_os = "macos")]
+        {
+            api.set_open_exclusive(false);
+        }
+
+        Ok(Self { api })
+    }
+
+    /// Matching raw-HID interfaces, one per deck, with the serial each is addressed by
+    fn matching(&self, config: &HidConfig) -> Vec<(String, &hidapi::DeviceInfo)> {
+        let infos: Vec<&hidapi::DeviceInfo> = self
+            .api
+            .device_list()
+            .filter(|d| {
+                d.vendor_id() == config.vendor_id
+                    && d.product_id() == config.product_id
+                    && d.usage_page() == config.usage_page
+                    && d.usage() == config.usage_id
+            })
+            .collect();
+        let serials: Vec<Option<&str>> = infos
+            .iter()
+            .map(|d| d.serial_number().map(str::trim).filter(|s| !s.is_empty()))
+            .collect();
+
+        infos
+            .iter()
+            .zip(&serials)
+            .map(|(info, serial)| {
+                // Fall back to the OS path when the serial is missing or not unique
+                let id = match serial {
+                    Some(s) if serials.iter().filter(|other| **other == Some(*s)).count() == 1 => {
+                        s.to_string()
+                    }
+                    _ => info.path().to_string_lossy().into_owned(),
+                };
+                (id, *info)
+            })
+            .collect()
+    }
+}
+
+impl HidBackend for HidApiBackend {
+    fn refresh(&mut self) -> Result<()> {
+        self.api.refresh_devices().context("Failed to refresh device list")
+    }
+
+    fn list(&self, config: &HidConfig) -> Vec<DeviceListing> {
+        self.matching(config)
+            .into_iter()
+            .map(|(serial, info)| DeviceListing {
+                serial,
+                name: device_display_name(info),
+            })
+            .collect()
+    }
+
+    fn open(&self, config: &HidConfig, serial: &str) -> Result<Box<dyn HidTransport>> {
+        let matching = self.matching(config);
+        let device_info = matching
+            .iter()
+            .find(|(id, _)| id == serial)
+            .map(|(_, info)| *info)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Core Deck {} not found (VID: 0x{:04X}, PID: 0x{:04X}, Usage: 0x{:04X}/0x{:02X})",
+                    serial,
+                    config.vendor_id,
+                    config.product_id,
+                    config.usage_page,
+                    config.usage_id
+                )
+            })?;
+
+        let device = device_info
+            .open_device(&self.api)
+            .context("Failed to open HID device")?;
+
+        device
+            .set_blocking_mode(false)
+            .context("Failed to set non-blocking mode")?;
+
+        Ok(Box::new(device))
+    }
+}
+
+/// "Manufacturer Product" as reported by the USB descriptor
+fn device_display_name(info: &hidapi::DeviceInfo) -> String {
+    format!(
+        "{} {}",
+        info.manufacturer_string().unwrap_or("").trim(),
+        info.product_string().unwrap_or("Core Deck").trim()
+    )
+    .trim()
+    .to_string()
+}
+
+impl HidTransport for HidDevice {
+    fn write(&self, report: &[u8]) -> Result<usize> {
+        // hidapi expects a leading report ID byte on macOS and Windows
+        #[cfg(any(target_os = "macos", target_os = "windows"))]
+        let data = {
+            let mut data = Vec::with_capacity(report.len() + 1);
+            data.push(0x00); // Report ID
+            data.extend_from_slice(report);
+            data
+        };
+
+        #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+        let data = report.to_vec();
+
+        HidDevice::write(self, &data).context("Failed to write to HID device")
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        HidDevice::read_timeout(self, buf, timeout_ms).map_err(|e| anyhow!("HID read error: {}", e))
+    }
+}
@@ -0,0 +1,658 @@
+//! Software "virtual deck" backend
+//!
+//! Emulates the Core Deck firmware side of the chunked HID protocol so the
+//! daemon, the app and the REST API can run without the physical macropad
+//! (`coredeck-daemon --virtual-device`). The deck reassembles host messages,
+//! stores display/alert/soft-key state, answers queries, and can inject the
+//! device-initiated packets (key events, type strings, state reports).
+
+use super::protocol::{
+    build_chunked_packets, DeviceMode, DeviceState, HidCommand, HidPacket, ProtoError,
+    ProtocolMode, SoftKeyConfig, SoftKeyType, MAX_MESSAGE_SIZE, PACKET_SIZE,
+};
+use super::transport::{DeviceListing, HidBackend, HidTransport};
+use crate::HidConfig;
+use anyhow::{anyhow, Result};
+use coredeck_protocol::DeviceCapabilities;
+use parking_lot::{Condvar, Mutex};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+
+/// Device name reported by the virtual backend
+pub const VIRTUAL_DEVICE_NAME: &str = "Virtual Core Deck";
+
+/// Firmware version reported by the virtual deck
+pub const VIRTUAL_FIRMWARE_VERSION: &str = "virtual-1.0.0";
+
+/// Brightness after power-on
+const DEFAULT_BRIGHTNESS: u8 = 255;
+
+/// Handle to an emulated deck. Cheap to clone; all clones share state.
+#[derive(Clone)]
+pub struct VirtualDeck {
+    inner: Arc<DeckShared>,
+}
+
+struct DeckShared {
+    state: Mutex<DeckState>,
+    /// Signalled whenever a report is queued for the host
+    outbox_ready: Condvar,
+}
+
+struct DeckState {
+    /// Wire format the emulated firmware speaks
+    protocol: ProtocolMode,
+    /// Whether the deck is "plugged in"
+    present: bool,
+    /// Command ID of the message being reassembled (None = idle)
+    rx_command: Option<u8>,
+    /// Reassembly buffer for the current host message
+    rx_buf: Vec<u8>,
+    /// Raw wire reports waiting to be read by the host
+    outbox: VecDeque<[u8; PACKET_SIZE]>,
+    display: Option<serde_json::Value>,
+    alerts: BTreeMap<usize, serde_json::Value>,
+    soft_keys: [SoftKeyConfig; 3],
+    brightness: u8,
+    device_state: DeviceState,
+    /// Host sent Disconnect and has not talked since
+    idle: bool,
+}
+
+/// Snapshot of everything the virtual deck is showing (for tests and `GET /api/virtual`)
+#[derive(Debug, Clone, Serialize)]
+pub struct VirtualDeckSnapshot {
+    pub present: bool,
+    pub idle: bool,
+    pub display: Option<serde_json::Value>,
+    pub alerts: BTreeMap<usize, serde_json::Value>,
+    pub soft_keys: Vec<SoftKeyConfig>,
+    pub brightness: u8,
+    pub mode: DeviceMode,
+    pub yolo: bool,
+}
+
+impl VirtualDeck {
+    /// Create a plugged-in deck speaking the given wire format
+    pub fn new(protocol: ProtocolMode) -> Self {
+        Self {
+            inner: Arc::new(DeckShared {
+                state: Mutex::new(DeckState {
+                    protocol,
+                    present: true,
+                    rx_command: None,
+                    rx_buf: Vec::new(),
+                    outbox: VecDeque::new(),
+                    display: None,
+                    alerts: BTreeMap::new(),
+                    soft_keys: default_soft_keys(),
+                    brightness: DEFAULT_BRIGHTNESS,
+                    device_state: DeviceState::default(),
+                    idle: true,
+                }),
+                outbox_ready: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Simulate plugging in / unplugging the deck
+    pub fn set_present(&self, present: bool) {
+        let mut state = self.inner.state.lock();
+        state.present = present;
+        if !present {
+            state.outbox.clear();
+            state.rx_command = None;
+            state.rx_buf.clear();
+        }
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.inner.state.lock().present
+    }
+
+    /// Inject a single key event, as if a key routed to the host was pressed
+    pub fn inject_key_event(&self, keycode: u16) {
+        let payload = keycode.to_be_bytes();
+        self.queue_message(HidCommand::KeyEvent, &payload);
+    }
+
+    /// Inject a type-string event, as if a String soft key was pressed
+    pub fn inject_type_string(&self, text: &str, send_enter: bool) {
+        let mut payload = vec![if send_enter { 0x01 } else { 0x00 }];
+        payload.extend_from_slice(text.as_bytes());
+        self.queue_message(HidCommand::TypeString, &payload);
+    }
+
+    /// Change the mode/YOLO state and report it, as if the mode button or switch was used
+    pub fn inject_state(&self, device_state: DeviceState) {
+        self.inner.state.lock().device_state = device_state;
+        self.queue_message(HidCommand::StateReport, &[device_state.to_byte()]);
+    }
+
+    /// Queue an arbitrary raw wire report for the host (for protocol tests)
+    pub fn inject_raw_report(&self, report: [u8; PACKET_SIZE]) {
+        let mut state = self.inner.state.lock();
+        state.outbox.push_back(report);
+        self.inner.outbox_ready.notify_all();
+    }
+
+    pub fn snapshot(&self) -> VirtualDeckSnapshot {
+        let state = self.inner.state.lock();
+        VirtualDeckSnapshot {
+            present: state.present,
+            idle: state.idle,
+            display: state.display.clone(),
+            alerts: state.alerts.clone(),
+            soft_keys: state.soft_keys.to_vec(),
+            brightness: state.brightness,
+            mode: state.device_state.mode,
+            yolo: state.device_state.yolo,
+        }
+    }
+
+    /// Chunk a device-initiated message and queue it for the host
+    fn queue_message(&self, command: HidCommand, payload: &[u8]) {
+        let mut state = self.inner.state.lock();
+        if !state.present {
+            return;
+        }
+        state.queue(command, payload);
+        self.inner.outbox_ready.notify_all();
+    }
+
+    /// Handle one wire report written by the host
+    fn receive_report(&self, report: &[u8]) -> Result<()> {
+        let mut state = self.inner.state.lock();
+        if !state.present {
+            return Err(anyhow!("virtual device unplugged"));
+        }
+        let protocol = state.protocol;
+        let packet = match HidPacket::from_wire(report, protocol) {
+            Some(p) => p,
+            None => {
+                // Not addressed to this firmware (e.g. VIAL probe to a standalone deck)
+                return Ok(());
+            }
+        };
+        state.receive_packet(&packet);
+        self.inner.outbox_ready.notify_all();
+        Ok(())
+    }
+
+    /// Pop the next wire report for the host, waiting up to `timeout`
+    fn next_report(&self, timeout: Duration) -> Result<Option<[u8; PACKET_SIZE]>> {
+        let mut state = self.inner.state.lock();
+        if !state.present {
+            return Err(anyhow!("virtual device unplugged"));
+        }
+        if state.outbox.is_empty() && !timeout.is_zero() {
+            self.inner.outbox_ready.wait_for(&mut state, timeout);
+        }
+        Ok(state.outbox.pop_front())
+    }
+}
+
+impl DeckState {
+    fn queue(&mut self, command: HidCommand, payload: &[u8]) {
+        for packet in build_chunked_packets(command, payload, self.protocol) {
+            self.outbox.push_back(packet.to_wire(self.protocol));
+        }
+    }
+
+    /// Reply with `[status, data...]`, the firmware's `send_response` format
+    fn respond(&mut self, command: HidCommand, status: u8, data: &[u8]) {
+        let mut payload = Vec::with_capacity(data.len() + 1);
+        payload.push(status);
+        payload.extend_from_slice(data);
+        self.queue(command, &payload);
+    }
+
+    fn respond_error(&mut self, error: ProtoError, command_byte: u8) {
+        self.queue(HidCommand::Error, &[error as u8, command_byte]);
+    }
+
+    /// Reassemble chunks; dispatch when the END flag arrives
+    fn receive_packet(&mut self, packet: &HidPacket) {
+        if packet.is_start() {
+            self.rx_command = Some(packet.command_byte());
+            self.rx_buf.clear();
+        } else if self.rx_command.is_none() {
+            self.respond_error(ProtoError::BadSequence, packet.command_byte());
+            return;
+        }
+
+        self.rx_buf.extend_from_slice(packet.wire_payload(self.protocol));
+        if !packet.is_end() && self.rx_buf.len() > MAX_MESSAGE_SIZE {
+            self.respond_error(ProtoError::Overflow, packet.command_byte());
+            self.rx_command = None;
+            self.rx_buf.clear();
+            return;
+        }
+
+        if packet.is_end() {
+            let command_byte = self.rx_command.take().unwrap_or(packet.command_byte());
+            let mut payload = std::mem::take(&mut self.rx_buf);
+            while payload.last() == Some(&0) {
+                payload.pop();
+            }
+            if payload.len() > MAX_MESSAGE_SIZE {
+                self.respond_error(ProtoError::Overflow, command_byte);
+                return;
+            }
+            self.idle = false;
+            self.dispatch(command_byte, &payload);
+        }
+    }
+
+    fn dispatch(&mut self, command_byte: u8, payload: &[u8]) {
+        let command = match HidCommand::from_byte(command_byte) {
+            Some(c) => c,
+            None => {
+                self.respond_error(ProtoError::UnknownCommand, command_byte);
+                return;
+            }
+        };
+
+        debug!("Virtual deck received {:?} ({} bytes)", command, payload.len());
+
+        match command {
+            HidCommand::UpdateDisplay => {
+                match serde_json::from_slice::<serde_json::Value>(payload) {
+                    Ok(json) => {
+                        self.display = Some(json);
+                        self.respond(command, 0x00, &[]);
+                    }
+                    Err(_) => self.respond(command, 0x01, &[]),
+                }
+            }
+            HidCommand::Ping => {
+                self.queue(HidCommand::Ping, &[]);
+            }
+            HidCommand::SetBrightness => {
+                self.brightness = byte_at(payload, 0);
+                self.respond(command, 0x00, &[]);
+            }
+            HidCommand::SetSoftKey => {
+                let index = byte_at(payload, 0);
+                if (index as usize) < self.soft_keys.len() {
+                    let key_type = SoftKeyType::from_byte(byte_at(payload, 1)).unwrap_or(SoftKeyType::Default);
+                    let data = payload.get(3..).unwrap_or(&[]).to_vec();
+                    self.soft_keys[index as usize] = SoftKeyConfig { index, key_type, data };
+                    self.respond(command, 0x00, &[]);
+                } else {
+                    self.respond(command, 0x01, &[]);
+                }
+            }
+            HidCommand::GetSoftKey => {
+                match self.soft_keys.get(byte_at(payload, 0) as usize) {
+                    Some(config) => {
+                        let mut data = vec![config.index, config.key_type as u8];
+                        data.extend_from_slice(&config.data);
+                        self.respond(command, 0x00, &data);
+                    }
+                    None => self.respond(command, 0x01, &[]),
+                }
+            }
+            HidCommand::ResetSoftKeys => {
+                self.soft_keys = default_soft_keys();
+                // Effective assignments: [type, kc_hi, kc_lo] x 3
+                let data: Vec<u8> = self
+                    .soft_keys
+                    .iter()
+                    .flat_map(|k| [k.key_type as u8, 0, 0])
+                    .collect();
+                self.respond(command, 0x00, &data);
+            }
+            HidCommand::SetMode => {
+                self.device_state.mode = DeviceMode::from_byte(byte_at(payload, 0));
+                self.respond(command, 0x00, &[]);
+                // Firmware echoes the new state as confirmation
+                let state_byte = self.device_state.to_byte();
+                self.queue(HidCommand::StateReport, &[state_byte]);
+            }
+            HidCommand::Alert => {
+                match serde_json::from_slice::<serde_json::Value>(payload) {
+                    Ok(json) => {
+                        let tab = json["tab"].as_u64().unwrap_or(0) as usize;
+                        if json.get("text").is_some() {
+                            self.alerts.insert(tab, json);
+                        } else {
+                            self.alerts.remove(&tab);
+                        }
+                        self.respond(command, 0x00, &[]);
+                    }
+                    Err(_) => self.respond(command, 0x01, &[]),
+                }
+            }
+            HidCommand::GetVersion => {
+                self.respond(command, 0x00, VIRTUAL_FIRMWARE_VERSION.as_bytes());
+            }
+            HidCommand::GetCapabilities => {
+                let caps = serde_json::to_vec(&DeviceCapabilities::default()).unwrap_or_default();
+                self.respond(command, 0x00, &caps);
+            }
+            HidCommand::Disconnect => {
+                self.idle = true;
+            }
+            HidCommand::StateReport
+            | HidCommand::TypeString
+            | HidCommand::KeyEvent
+            | HidCommand::Error => {
+                // Device-to-host only
+                self.respond_error(ProtoError::UnknownCommand, command_byte);
+            }
+        }
+    }
+}
+
+/// Argument byte `index` of a message. Trailing zero bytes are trimmed during
+/// reassembly, so anything past the end reads as zero (like the firmware's
+/// zero-filled buffer).
+fn byte_at(payload: &[u8], index: usize) -> u8 {
+    payload.get(index).copied().unwrap_or(0)
+}
+
+fn default_soft_keys() -> [SoftKeyConfig; 3] {
+    [0u8, 1, 2].map(|index| SoftKeyConfig {
+        index,
+        key_type: SoftKeyType::Default,
+        data: vec![],
+    })
+}
+
+// ── Transport + backend ────────────────────────────────────────────
+
+/// Transport connected to a `VirtualDeck`
+pub struct VirtualTransport {
+    deck: VirtualDeck,
+}
+
+impl HidTransport for VirtualTransport {
+    fn write(&self, report: &[u8]) -> Result<usize> {
+        self.deck.receive_report(report)?;
+        Ok(report.len())
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        let timeout = Duration::from_millis(timeout_ms.max(0) as u64);
+        match self.deck.next_report(timeout)? {
+            Some(report) => {
+                let len = buf.len().min(PACKET_SIZE);
+                buf[..len].copy_from_slice(&report[..len]);
+                Ok(len)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+/// Backend that always "finds" the virtual decks that are plugged in
+pub struct VirtualBackend {
+    /// Decks by serial
+    decks: Vec<(String, VirtualDeck)>,
+}
+
+impl VirtualBackend {
+    pub fn new(deck: VirtualDeck) -> Self {
+        Self::with_decks(vec![deck])
+    }
+
+    /// Several decks, with serials `virtual-1`, `virtual-2`, ...
+    pub fn with_decks(decks: Vec<VirtualDeck>) -> Self {
+        let decks = decks
+            .into_iter()
+            .enumerate()
+            .map(|(i, deck)| (format!("virtual-{}", i + 1), deck))
+            .collect();
+        Self { decks }
+    }
+}
+
+impl HidBackend for VirtualBackend {
+    fn refresh(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn list(&self, _config: &HidConfig) -> Vec<DeviceListing> {
+        self.decks
+            .iter()
+            .filter(|(_, deck)| deck.is_present())
+            .map(|(serial, _)| DeviceListing {
+                serial: serial.clone(),
+                name: VIRTUAL_DEVICE_NAME.to_string(),
+            })
+            .collect()
+    }
+
+    fn open(&self, _config: &HidConfig, serial: &str) -> Result<Box<dyn HidTransport>> {
+        let (_, deck) = self
+            .decks
+            .iter()
+            .find(|(s, _)| s == serial)
+            .ok_or_else(|| anyhow!("No virtual deck {}", serial))?;
+        if !deck.is_present() {
+            return Err(anyhow!("Virtual deck unplugged"));
+        }
+        Ok(Box::new(VirtualTransport { deck: deck.clone() }))
+    }
+
+    fn supports_hotplug(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::commands;
+    use crate::hid::protocol::FLAG_END;
+    use crate::state::{DaemonEvent, DaemonEventSender};
+    use crate::hid::HidManager;
+    use tokio::sync::mpsc;
+
+    fn transport(deck: &VirtualDeck) -> VirtualTransport {
+        VirtualTransport { deck: deck.clone() }
+    }
+
+    fn send(t: &VirtualTransport, packets: &[HidPacket], mode: ProtocolMode) {
+        for p in packets {
+            t.write(&p.to_wire(mode)).unwrap();
+        }
+    }
+
+    fn recv(t: &VirtualTransport, mode: ProtocolMode) -> Option<HidPacket> {
+        let mut buf = [0u8; PACKET_SIZE];
+        match t.read_timeout(&mut buf, 10).unwrap() {
+            0 => None,
+            _ => HidPacket::from_wire(&buf, mode),
+        }
+    }
+
+    #[test]
+    fn test_display_update_reassembled() {
+        let deck = VirtualDeck::new(ProtocolMode::Standalone);
+        let t = transport(&deck);
+        let packets = commands::build_display_update(
+            "my-session", Some("Reading a rather long list of files"), None, &[0, 1, 2], 1,
+            ProtocolMode::Standalone,
+        );
+        assert!(packets.len() > 1);
+        send(&t, &packets, ProtocolMode::Standalone);
+
+        let display = deck.snapshot().display.unwrap();
+        assert_eq!(display["session"], "my-session");
+        assert_eq!(display["active"], 1);
+
+        let ack = recv(&t, ProtocolMode::Standalone).unwrap();
+        assert_eq!(ack.command(), Some(HidCommand::UpdateDisplay));
+        assert_eq!(ack.payload()[0], 0x00);
+    }
+
+    #[test]
+    fn test_vial_mode_ignores_unprefixed_reports() {
+        let deck = VirtualDeck::new(ProtocolMode::Vial);
+        let t = transport(&deck);
+        send(&t, &commands::build_get_version(ProtocolMode::Standalone), ProtocolMode::Standalone);
+        assert!(recv(&t, ProtocolMode::Vial).is_none());
+
+        send(&t, &commands::build_get_version(ProtocolMode::Vial), ProtocolMode::Vial);
+        let resp = recv(&t, ProtocolMode::Vial).unwrap();
+        assert_eq!(resp.command(), Some(HidCommand::GetVersion));
+        assert!(resp.is_end());
+    }
+
+    #[test]
+    fn test_continuation_without_start_is_bad_sequence() {
+        let deck = VirtualDeck::new(ProtocolMode::Standalone);
+        let t = transport(&deck);
+        let packet = HidPacket::with_command(FLAG_END, HidCommand::UpdateDisplay);
+        send(&t, &[packet], ProtocolMode::Standalone);
+        let err = recv(&t, ProtocolMode::Standalone).unwrap();
+        assert_eq!(err.command(), Some(HidCommand::Error));
+        assert_eq!(err.payload()[0], ProtoError::BadSequence as u8);
+    }
+
+    #[test]
+    fn test_injected_key_event() {
+        let deck = VirtualDeck::new(ProtocolMode::Standalone);
+        let t = transport(&deck);
+        deck.inject_key_event(0x7E00);
+        let pkt = recv(&t, ProtocolMode::Standalone).unwrap();
+        assert_eq!(pkt.command(), Some(HidCommand::KeyEvent));
+        assert_eq!(&pkt.payload()[..2], &[0x7E, 0x00]);
+    }
+
+    #[test]
+    fn test_unplugged_transport_errors() {
+        let deck = VirtualDeck::new(ProtocolMode::Standalone);
+        let t = transport(&deck);
+        deck.set_present(false);
+        assert!(t.write(&[0u8; PACKET_SIZE]).is_err());
+        assert!(VirtualBackend::new(deck).list(&HidConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn test_manager_end_to_end() {
+        for protocol in [ProtocolMode::Standalone, ProtocolMode::Vial] {
+            let deck = VirtualDeck::new(protocol);
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let hid = HidManager::with_backend(
+                HidConfig::default(),
+                DaemonEventSender::new(tx),
+                Box::new(VirtualBackend::new(deck.clone())),
+            );
+            assert!(hid.is_device_available());
+
+            hid.open_device(None).wait().unwrap();
+            match rx.try_recv().unwrap() {
+                DaemonEvent::HidConnected { serial, device_name, firmware_version, capabilities } => {
+                    assert_eq!(serial, "virtual-1");
+                    assert_eq!(device_name, VIRTUAL_DEVICE_NAME);
+                    assert_eq!(firmware_version, VIRTUAL_FIRMWARE_VERSION);
+                    assert_eq!(capabilities, Some(DeviceCapabilities::default()));
+                }
+                other => panic!("unexpected event {:?}", other),
+            }
+
+            hid.send_display_update(None, "proj", Some("Testing"), None, &[1, 2], 1).wait().unwrap();
+            assert_eq!(deck.snapshot().display.unwrap()["task"], "Testing");
+            hid.send_display_update(None, "proj", Some("Testing"), None, &[1, 2], 1).wait().unwrap();
+            let metrics = hid.metrics().snapshot();
+            assert_eq!(metrics.display_updates_deduplicated, 1);
+            assert!(metrics.packets_sent > 0 && metrics.packets_received > 0);
+
+            hid.set_soft_key(None, 1, SoftKeyType::String, b"\x01hello", false).wait().unwrap();
+            let key = hid.get_soft_key(None, 1).wait().unwrap();
+            assert_eq!(key.key_type, SoftKeyType::String);
+            assert_eq!(key.data, b"\x01hello");
+
+            hid.send_alert(None, 2, "proj", "Approve?", None).wait().unwrap();
+            assert!(deck.snapshot().alerts.contains_key(&2));
+            hid.clear_alert(None, 2).wait().unwrap();
+            assert!(deck.snapshot().alerts.is_empty());
+
+            deck.inject_key_event(0x0029);
+            let deadline = std::time::Instant::now() + Duration::from_secs(2);
+            let mut got_key = false;
+            while std::time::Instant::now() < deadline && !got_key {
+                if let Ok(DaemonEvent::HidKeyEvent { keycode, .. }) = rx.try_recv() {
+                    assert_eq!(keycode, 0x0029);
+                    got_key = true;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert!(got_key, "key event not forwarded ({:?})", protocol);
+
+            hid.close_device(None).wait().unwrap();
+            assert!(deck.snapshot().idle);
+        }
+    }
+
+    #[test]
+    fn test_manager_multiple_decks() {
+        let decks = [VirtualDeck::new(ProtocolMode::Standalone), VirtualDeck::new(ProtocolMode::Vial)];
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let hid = HidManager::with_backend(
+            HidConfig::default(),
+            DaemonEventSender::new(tx),
+            Box::new(VirtualBackend::with_decks(decks.to_vec())),
+        );
+        let serials: Vec<String> = hid.devices().into_iter().map(|d| d.serial).collect();
+        assert_eq!(serials, ["virtual-1", "virtual-2"]);
+
+        hid.open_device(None).wait().unwrap();
+        assert_eq!(hid.connected_serials(), serials);
+
+        // Routed to one deck
+        hid.send_alert(Some("virtual-2"), 0, "proj", "Approve?", None).wait().unwrap();
+        assert!(decks[0].snapshot().alerts.is_empty());
+        assert!(decks[1].snapshot().alerts.contains_key(&0));
+        assert!(hid.send_alert(Some("nope"), 0, "proj", "x", None).wait().is_err());
+
+        // Mirrored to both
+        hid.send_display_update(None, "proj", Some("Mirrored"), None, &[1], 0).wait().unwrap();
+        for deck in &decks {
+            assert_eq!(deck.snapshot().display.unwrap()["task"], "Mirrored");
+        }
+
+        // Key events name the deck they came from
+        decks[1].inject_key_event(0x0029);
+        let deadline = std::time::Instant::now() + Duration::from_secs(2);
+        let mut from = None;
+        while std::time::Instant::now() < deadline && from.is_none() {
+            if let Ok(DaemonEvent::HidKeyEvent { serial, .. }) = rx.try_recv() {
+                from = Some(serial);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(from.as_deref(), Some("virtual-2"));
+
+        hid.close_device(Some("virtual-1")).wait().unwrap();
+        assert!(decks[0].snapshot().idle);
+        assert!(!decks[1].snapshot().idle);
+        assert_eq!(hid.connected_serials(), ["virtual-2"]);
+    }
+
+    #[test]
+    fn test_transient_open_runs_on_writer() {
+        let decks = [VirtualDeck::new(ProtocolMode::Standalone), VirtualDeck::new(ProtocolMode::Standalone)];
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let hid = HidManager::with_backend(
+            HidConfig::default(),
+            DaemonEventSender::new(tx),
+            Box::new(VirtualBackend::with_decks(decks.to_vec())),
+        );
+        hid.open_device(Some("virtual-1")).wait().unwrap();
+
+        // Only the deck that wasn't open yet is reported, and only it is closed again
+        let opened = hid.open_transient(None).wait().unwrap();
+        assert_eq!(opened, ["virtual-2"]);
+        hid.set_brightness(None, 40, false).wait().unwrap();
+        hid.close_transient(opened).wait().unwrap();
+        assert_eq!(hid.connected_serials(), ["virtual-1"]);
+        assert!(decks[1].snapshot().idle);
+    }
+}
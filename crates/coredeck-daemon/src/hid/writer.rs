@@ -0,0 +1,309 @@
+//! HID write queue
+//!
+//! Commands for the decks are queued and carried out in order by one writer
+//! thread, so async handlers never wait on USB I/O themselves. Each command
+//! resolves a `HidCompletion` once the writer is done with it.
+//!
+//! The queue is bounded: a command that finds it full fails at once instead of
+//! piling up behind a stalled device. A display update still waiting in the
+//! queue is replaced by a newer one for the same deck, so a slow deck only
+//! ever gets the latest content.
+//!
+//! Report writes made by queued commands are tried again when they fail, since
+//! USB writes fail transiently while a device is busy.
+
+use super::device::DeckIo;
+use super::metrics::HidMetrics;
+use super::transport::HidTransport;
+use anyhow::{anyhow, Result};
+use coredeck_protocol::DisplayUpdate;
+use parking_lot::{Condvar, Mutex};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Commands that may wait for the writer before new ones are refused
+pub const QUEUE_DEPTH: usize = 32;
+
+/// Tries per report write; the pause before each retry grows by `WRITE_RETRY_DELAY`
+const WRITE_ATTEMPTS: u32 = 3;
+const WRITE_RETRY_DELAY: Duration = Duration::from_millis(5);
+
+/// Outcome of a queued HID command, resolved by the writer thread
+pub struct HidCompletion<T> {
+    rx: oneshot::Receiver<Result<T>>,
+}
+
+impl<T> HidCompletion<T> {
+    /// A completion that is already resolved (the command was never queued)
+    fn ready(result: Result<T>) -> Self {
+        let (tx, rx) = oneshot::channel();
+        let _ = tx.send(result);
+        Self { rx }
+    }
+
+    /// Block until the command is done (outside async code)
+    #[cfg(test)]
+    pub fn wait(self) -> Result<T> {
+        self.rx.blocking_recv().unwrap_or_else(|_| Err(stopped()))
+    }
+}
+
+impl<T> Future for HidCompletion<T> {
+    type Output = Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T>> {
+        Pin::new(&mut self.rx)
+            .poll(cx)
+            .map(|result| result.unwrap_or_else(|_| Err(stopped())))
+    }
+}
+
+fn stopped() -> anyhow::Error {
+    anyhow!("HID writer stopped")
+}
+
+/// A command was refused because `QUEUE_DEPTH` commands were already waiting
+#[derive(Debug)]
+pub struct QueueFull;
+
+impl std::fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HID write queue full ({} commands waiting)", QUEUE_DEPTH)
+    }
+}
+
+impl std::error::Error for QueueFull {}
+
+/// One unit of work for the writer thread
+pub enum Job {
+    /// Display content for a deck (`None` = every open deck). Everyone waiting
+    /// on a replaced update is told the outcome of the one that replaced it.
+    Display {
+        serial: Option<String>,
+        update: DisplayUpdate,
+        done: Vec<oneshot::Sender<Result<()>>>,
+    },
+    /// Any other command; reports its own outcome
+    Command(Box<dyn FnOnce(&DeckIo) + Send>),
+}
+
+impl Job {
+    pub fn run(self, io: &DeckIo) {
+        match self {
+            Job::Display { serial, update, done } => {
+                let result = io.send_display_update(serial.as_deref(), &update).map_err(|e| e.to_string());
+                for tx in done {
+                    let _ = tx.send(result.clone().map_err(|e| anyhow!(e)));
+                }
+            }
+            Job::Command(command) => command(io),
+        }
+    }
+}
+
+/// Write one report for a queued command, trying again while the device is busy
+pub fn write_report(device: &dyn HidTransport, report: &[u8], metrics: &HidMetrics) -> Result<usize> {
+    let mut attempt = 1;
+    loop {
+        match device.write(report) {
+            Ok(written) => return Ok(written),
+            Err(e) if attempt == WRITE_ATTEMPTS => return Err(e),
+            Err(_) => {}
+        }
+        metrics.write_retries.inc();
+        std::thread::sleep(WRITE_RETRY_DELAY * attempt);
+        attempt += 1;
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: VecDeque<Job>,
+    closed: bool,
+}
+
+/// Bounded FIFO between the async handlers and the writer thread
+pub struct HidQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+    metrics: Arc<HidMetrics>,
+}
+
+impl HidQueue {
+    pub fn new(metrics: Arc<HidMetrics>) -> Self {
+        Self { state: Mutex::new(QueueState::default()), ready: Condvar::new(), metrics }
+    }
+
+    /// Queue a command. Fails at once when the queue is full or closed.
+    pub fn push<T: Send + 'static>(
+        &self,
+        command: impl FnOnce(&DeckIo) -> Result<T> + Send + 'static,
+    ) -> HidCompletion<T> {
+        let (tx, rx) = oneshot::channel();
+        let job = Job::Command(Box::new(move |io| {
+            let _ = tx.send(command(io));
+        }));
+        match self.enqueue(job) {
+            Ok(()) => HidCompletion { rx },
+            Err(e) => HidCompletion::ready(Err(e)),
+        }
+    }
+
+    /// Queue display content, replacing a queued update for the same deck in
+    /// place so it stays in order with the commands around it
+    pub fn push_display(&self, serial: Option<String>, update: DisplayUpdate) -> HidCompletion<()> {
+        let (tx, rx) = oneshot::channel();
+        let mut state = self.state.lock();
+        let queued = state.jobs.iter_mut().find_map(|job| match job {
+            Job::Display { serial: s, update: content, done } if *s == serial => Some((content, done)),
+            _ => None,
+        });
+        if let Some((content, done)) = queued {
+            self.metrics.display_coalesced.inc();
+            *content = update;
+            done.push(tx);
+            return HidCompletion { rx };
+        }
+        match self.enqueue_locked(&mut state, Job::Display { serial, update, done: vec![tx] }) {
+            Ok(()) => HidCompletion { rx },
+            Err(e) => HidCompletion::ready(Err(e)),
+        }
+    }
+
+    fn enqueue(&self, job: Job) -> Result<()> {
+        self.enqueue_locked(&mut self.state.lock(), job)
+    }
+
+    fn enqueue_locked(&self, state: &mut QueueState, job: Job) -> Result<()> {
+        if state.closed {
+            return Err(stopped());
+        }
+        if state.jobs.len() >= QUEUE_DEPTH {
+            self.metrics.queue_rejections.inc();
+            return Err(QueueFull.into());
+        }
+        state.jobs.push_back(job);
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    /// Next job for the writer, waiting for one. `None` once closed.
+    pub fn pop(&self) -> Option<Job> {
+        let mut state = self.state.lock();
+        loop {
+            if state.closed {
+                return None;
+            }
+            if let Some(job) = state.jobs.pop_front() {
+                return Some(job);
+            }
+            self.ready.wait(&mut state);
+        }
+    }
+
+    /// Stop the writer; queued commands fail with "HID writer stopped"
+    pub fn close(&self) {
+        let mut state = self.state.lock();
+        state.closed = true;
+        state.jobs.clear();
+        self.ready.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::metrics::MeteredTransport;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails the first `n` writes
+    struct Busy(AtomicU32);
+
+    impl HidTransport for Busy {
+        fn write(&self, report: &[u8]) -> Result<usize> {
+            match self.0.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)) {
+                Ok(_) => Err(anyhow!("busy")),
+                Err(_) => Ok(report.len()),
+            }
+        }
+
+        fn read_timeout(&self, _buf: &mut [u8], _timeout_ms: i32) -> Result<usize> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn test_write_retried_after_transient_error() {
+        let metrics = Arc::new(HidMetrics::default());
+        let device = MeteredTransport::new(Box::new(Busy(AtomicU32::new(1))), Arc::clone(&metrics));
+
+        assert_eq!(write_report(&device, &[1; 32], &metrics).unwrap(), 32);
+        assert_eq!(metrics.write_errors.get(), 1);
+        assert_eq!(metrics.write_retries.get(), 1);
+        assert_eq!(metrics.packets_sent.get(), 1);
+    }
+
+    #[test]
+    fn test_write_gives_up_after_attempts() {
+        let metrics = Arc::new(HidMetrics::default());
+        let device = MeteredTransport::new(Box::new(Busy(AtomicU32::new(u32::MAX))), Arc::clone(&metrics));
+
+        assert!(write_report(&device, &[1; 32], &metrics).is_err());
+        assert_eq!(metrics.write_errors.get(), u64::from(WRITE_ATTEMPTS));
+        assert_eq!(metrics.write_retries.get(), u64::from(WRITE_ATTEMPTS - 1));
+        assert_eq!(metrics.packets_sent.get(), 0);
+    }
+
+    fn update(session: &str) -> DisplayUpdate {
+        DisplayUpdate { session: session.into(), task: String::new(), task2: String::new(), tabs: vec![], active: 0 }
+    }
+
+    #[test]
+    fn test_display_updates_coalesce_per_deck() {
+        let metrics = Arc::new(HidMetrics::default());
+        let queue = HidQueue::new(Arc::clone(&metrics));
+        let _first = queue.push_display(Some("a".into()), update("one"));
+        let _close = queue.push(|_| Ok(()));
+        let _other = queue.push_display(Some("b".into()), update("other"));
+        let _second = queue.push_display(Some("a".into()), update("two"));
+
+        // The newer content takes the queued update's place, ahead of the command
+        assert_eq!(metrics.display_coalesced.get(), 1);
+        let jobs: Vec<_> = std::iter::from_fn(|| queue.state.lock().jobs.pop_front())
+            .map(|job| match job {
+                Job::Display { serial, update, done } => (serial.unwrap(), update.session, done.len()),
+                Job::Command(_) => ("-".into(), "command".into(), 1),
+            })
+            .collect();
+        assert_eq!(
+            jobs,
+            [
+                ("a".into(), "two".into(), 2),
+                ("-".into(), "command".into(), 1),
+                ("b".into(), "other".into(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_full_queue_refuses_commands() {
+        let metrics = Arc::new(HidMetrics::default());
+        let queue = HidQueue::new(Arc::clone(&metrics));
+        let queued: Vec<_> = (0..QUEUE_DEPTH).map(|_| queue.push(|_| Ok(()))).collect();
+
+        let err = queue.push(|_| Ok(())).wait().unwrap_err();
+        assert!(err.is::<QueueFull>(), "{}", err);
+        assert_eq!(metrics.queue_rejections.get(), 1);
+
+        queue.close();
+        assert!(queue.pop().is_none());
+        for done in queued {
+            assert_eq!(done.wait().unwrap_err().to_string(), "HID writer stopped");
+        }
+    }
+}
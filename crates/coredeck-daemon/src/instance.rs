@@ -0,0 +1,222 @@
+//! Single-instance lock — `daemon.pid` next to the daemon's Unix socket
+//!
+//! The running daemon holds a write lock on the file and writes its PID into
+//! it. The kernel drops the lock when the process ends, however it ends, so a
+//! file left behind never blocks the next start; `stop` and `status` ask the
+//! kernel which process holds the lock instead of trusting the file.
+
+use anyhow::{bail, Context, Result};
+use directories::ProjectDirs;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long `stop` waits for the daemon to exit
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Lock file location: `$XDG_RUNTIME_DIR/coredeck/daemon.pid`, or the local
+/// data directory where there is no runtime directory (macOS)
+pub fn default_path() -> Option<PathBuf> {
+    ProjectDirs::from("com", "coredeck", "CoreDeck").map(|dirs| {
+        dirs.runtime_dir()
+            .unwrap_or_else(|| dirs.data_local_dir())
+            .join("daemon.pid")
+    })
+}
+
+/// Held by the running daemon; releasing it lets the next one start
+pub struct InstanceLock {
+    file: File,
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // The lock goes with the file handle; empty the file so no stale PID is left
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// Take the lock, or fail with the PID of the daemon that holds it
+pub fn acquire(path: &Path) -> Result<InstanceLock> {
+    if let Some(parent) = path.parent() {
+        // Shared with the Unix socket, so keep it private like bind_unix_socket does
+        let mut dir = std::fs::DirBuilder::new();
+        dir.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut dir, 0o700);
+        dir.create(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    if !sys::try_lock(&file).with_context(|| format!("Failed to lock {}", path.display()))? {
+        match sys::holder(&file) {
+            Some(pid) => bail!("coredeck-daemon is already running (pid {})", pid),
+            None => bail!("coredeck-daemon is already running ({} is locked)", path.display()),
+        }
+    }
+    file.set_len(0)?;
+    writeln!(file, "{}", std::process::id())?;
+    Ok(InstanceLock { file })
+}
+
+/// PID of the daemon holding the lock at `path`, if one is running
+pub fn running(path: &Path) -> Option<u32> {
+    File::open(path).ok().and_then(|file| sys::holder(&file))
+}
+
+/// Ask the running daemon to shut down and wait until it has.
+/// Returns its PID, or `None` if no daemon was running.
+pub fn stop(path: &Path) -> Result<Option<u32>> {
+    let Some(pid) = running(path) else {
+        return Ok(None);
+    };
+    sys::terminate(pid).with_context(|| format!("Failed to signal pid {}", pid))?;
+
+    let started = Instant::now();
+    while running(path) == Some(pid) {
+        if started.elapsed() > STOP_TIMEOUT {
+            bail!("coredeck-daemon (pid {}) did not exit within {:?}", pid, STOP_TIMEOUT);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(Some(pid))
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    /// Whole-file write lock request
+    fn write_lock() -> libc::flock {
+        // SAFETY: `flock` is a plain C struct; all zeroes is a valid value
+        let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+        lock.l_type = libc::F_WRLCK as libc::c_short;
+        lock.l_whence = libc::SEEK_SET as libc::c_short;
+        lock
+    }
+
+    /// Take the lock without waiting; `false` if another process holds it
+    pub fn try_lock(file: &File) -> std::io::Result<bool> {
+        let lock = write_lock();
+        // SAFETY: F_SETLK reads a valid `flock` struct; the fd is open for writing
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &lock) } == 0 {
+            return Ok(true);
+        }
+        let err = std::io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EACCES) | Some(libc::EAGAIN) => Ok(false),
+            _ => Err(err),
+        }
+    }
+
+    /// PID of another process holding the lock
+    pub fn holder(file: &File) -> Option<u32> {
+        let mut lock = write_lock();
+        // SAFETY: F_GETLK fills in the `flock` struct it is given
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) } != 0 {
+            return None;
+        }
+        (lock.l_type != libc::F_UNLCK as libc::c_short).then_some(lock.l_pid as u32)
+    }
+
+    pub fn terminate(pid: u32) -> std::io::Result<()> {
+        // SAFETY: plain kill(2)
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    }
+}
+
+/// Without advisory locks the file only records the PID; any daemon may start
+#[cfg(not(unix))]
+mod sys {
+    use std::fs::File;
+
+    pub fn try_lock(_file: &File) -> std::io::Result<bool> {
+        Ok(true)
+    }
+
+    pub fn holder(_file: &File) -> Option<u32> {
+        None
+    }
+
+    pub fn terminate(_pid: u32) -> std::io::Result<()> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "stop is only supported on Unix"))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Command, Stdio};
+
+    /// Tells the `hold_lock` child which lock file to take
+    const HOLD_LOCK_ENV: &str = "COREDECK_TEST_HOLD_LOCK";
+
+    #[test]
+    fn test_lock_file() {
+        let path = std::env::temp_dir().join(format!("coredeck-instance-{}.pid", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(running(&path), None);
+        assert_eq!(stop(&path).unwrap(), None);
+
+        let lock = acquire(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), format!("{}\n", std::process::id()));
+        // fcntl locks never conflict within one process, so this only sees other daemons
+        assert_eq!(running(&path), None);
+
+        drop(lock);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        drop(acquire(&path).unwrap());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_lock_held_by_other_process() {
+        let path = std::env::temp_dir().join(format!("coredeck-instance-child-{}.pid", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // Re-run this test binary as a second daemon holding the lock
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args(["instance::tests::hold_lock", "--exact", "--ignored"])
+            .env(HOLD_LOCK_ENV, &path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        while running(&path).is_none() {
+            assert!(started.elapsed() < STOP_TIMEOUT, "child never took the lock");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(running(&path), Some(child.id()));
+        let err = acquire(&path).err().unwrap();
+        assert_eq!(err.to_string(), format!("coredeck-daemon is already running (pid {})", child.id()));
+
+        assert_eq!(stop(&path).unwrap(), Some(child.id()));
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGTERM));
+        assert_eq!(running(&path), None);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    #[ignore = "run as a child by test_lock_held_by_other_process"]
+    fn hold_lock() {
+        let Some(path) = std::env::var_os(HOLD_LOCK_ENV) else { return };
+        let _lock = acquire(Path::new(&path)).unwrap();
+        loop {
+            std::thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
@@ -1,73 +1,126 @@
 //! CoreDeck Daemon - Background process that owns the HID device
 //!
-//! Provides WebSocket (exclusive) and HTTP REST (shared) APIs for
+//! Provides WebSocket (one controller, many observers) and HTTP REST (shared) APIs for
 //! controlling the CoreDeck macropad.
 
+mod actions;
+mod alerts;
+mod auth;
+mod config;
+mod display;
 mod hid;
+mod instance;
+mod profile;
 mod rpc;
 mod state;
+#[cfg(target_os = "linux")]
+mod systemd;
 mod tray;
+mod webhook;
 mod ws;
 
-use coredeck_protocol::{AppControlAction, DEFAULT_DAEMON_ADDR};
+use coredeck_protocol::AppControlAction;
 use clap::{Parser, Subcommand};
-use hid::HidManager;
+use config::DaemonConfig;
+use hid::protocol::ProtocolMode;
+use hid::{HidManager, HidMetrics, VirtualBackend, VirtualDeck};
 use state::{DaemonEvent, DaemonEventSender, DeviceStatus, TrayUpdate};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex, Notify, RwLock};
-use tracing::{error, info};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, watch, Mutex, Notify, RwLock, Semaphore};
+use tracing::{error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
-/// HID device configuration (matches the app's HidConfig)
-#[derive(Debug, Clone)]
-pub struct HidConfig {
-    pub vendor_id: u16,
-    pub product_id: u16,
-    pub usage_page: u16,
-    pub usage_id: u16,
-    pub ping_interval_ms: u64,
-    pub reconnect_interval_ms: u64,
-}
+pub use config::HidConfig;
 
-impl Default for HidConfig {
-    fn default() -> Self {
-        Self {
-            vendor_id: 0xFEED,
-            product_id: 0x0803,
-            usage_page: 0xFF60,
-            usage_id: 0x61,
-            ping_interval_ms: 5000,
-            reconnect_interval_ms: 2000,
-        }
-    }
-}
+/// Events buffered per `GET /api/events` subscriber before it starts lagging
+const EVENT_STREAM_CAPACITY: usize = 64;
+
+/// How long shutdown waits for WS clients to close before closing the decks
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
 
 /// Shared state across the daemon (must be Send + Sync for axum)
 pub struct DaemonState {
     /// HID device manager
     pub hid: Mutex<HidManager>,
-    /// Current device status
-    pub device_status: RwLock<DeviceStatus>,
-    /// Connected WS client (the lock)
-    pub ws_client: Mutex<Option<ws::WsClientHandle>>,
+    /// HID link health counters (readable without waiting on `hid`)
+    pub hid_metrics: Arc<HidMetrics>,
+    /// Status of every plugged-in deck, by serial
+    pub devices: RwLock<BTreeMap<String, DeviceStatus>>,
+    /// Display layers per source (decides what the screen shows)
+    pub display: Mutex<display::DisplayCompositor>,
+    /// History of alerts raised and cleared
+    pub alerts: Mutex<alerts::AlertLog>,
+    /// Connected WS clients (the controller holds the lock)
+    pub ws_clients: Mutex<ws::WsClients>,
     /// Notified when WS lock changes
     pub notify_lock_change: Notify,
+    /// Every daemon event, for `GET /api/events` subscribers
+    pub events_tx: broadcast::Sender<DaemonEvent>,
     /// Channel to send tray updates to the main thread (tray is !Send, lives on main thread)
     pub tray_tx: std::sync::mpsc::Sender<TrayUpdate>,
+    /// Software deck backing the HID manager (`--virtual-device` only)
+    pub virtual_deck: Option<VirtualDeck>,
+    /// Current configuration (swapped on reload)
+    pub config: parking_lot::RwLock<DaemonConfig>,
+    /// Config file being watched for changes
+    pub config_path: Option<PathBuf>,
+    /// API tokens accepted on HTTP/WS requests
+    pub tokens: parking_lot::RwLock<auth::TokenStore>,
+    /// Set to stop the daemon; WS connections close when it flips
+    pub shutdown: watch::Sender<bool>,
+    /// Slots for headless actions running at once
+    pub action_slots: Arc<Semaphore>,
 }
 
 impl DaemonState {
     pub fn send_tray_update(&self, update: TrayUpdate) {
         let _ = self.tray_tx.send(update);
     }
+
+    /// Refresh the tray from the current device statuses
+    async fn update_tray_devices(&self) {
+        let update = TrayUpdate::for_devices(&*self.devices.read().await);
+        self.send_tray_update(update);
+    }
 }
 
 #[derive(Parser)]
 #[command(name = "coredeck-daemon", about = "CoreDeck background daemon")]
 struct Cli {
-    /// Listen address
-    #[arg(long, default_value = DEFAULT_DAEMON_ADDR)]
-    listen: String,
+    /// Listen address (overrides the config file)
+    #[arg(long)]
+    listen: Option<String>,
+
+    /// Unix socket path (overrides the config file)
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+
+    /// Config file (default: daemon.toml in the CoreDeck config directory)
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Use a software virtual deck instead of the USB device (no hardware required)
+    #[arg(long)]
+    virtual_device: bool,
+
+    /// Make the virtual deck speak the VIAL-prefixed protocol
+    #[arg(long, requires = "virtual_device")]
+    virtual_vial: bool,
+
+    /// Run without the tray icon (no display server required)
+    #[arg(long)]
+    headless: bool,
+
+    /// Persist the alert history to this JSON file
+    #[arg(long, value_name = "PATH")]
+    alert_log: Option<PathBuf>,
+
+    /// PID/lock file (default: daemon.pid next to the Unix socket)
+    #[arg(long, value_name = "PATH")]
+    pid_file: Option<PathBuf>,
 
     #[command(subcommand)]
     command: Option<Commands>,
@@ -75,35 +128,181 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Install launchd plist for auto-start
+    /// Install for auto-start (launchd on macOS, systemd --user on Linux)
     Install,
-    /// Uninstall launchd plist
+    /// Uninstall the auto-start service
     Uninstall,
+    /// Report whether the daemon is running and the auto-start service's state
+    Status,
+    /// Stop the running daemon, letting it disconnect clients and decks first
+    Stop,
+    /// Copy a deck's soft keys, brightness and mode to or from a profile file
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// Write the deck's profile as TOML to FILE (default: stdout)
+    Export {
+        file: Option<PathBuf>,
+        /// Serial of the deck to read (default: the first connected one)
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// Apply a profile file through the running daemon
+    Import {
+        file: PathBuf,
+        /// Serial of the deck to change (default: every deck)
+        #[arg(long)]
+        device: Option<String>,
+        /// Don't persist soft keys and brightness to the deck's EEPROM
+        #[arg(long)]
+        no_save: bool,
+    },
 }
 
 fn main() {
+    // Take the socket-activation variables while the process is single-threaded
+    #[cfg(target_os = "linux")]
+    let listen_fds = systemd::take_listen_fds();
+
     // Initialize logging
     tracing_subscriber::registry()
         .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
-        .with(tracing_subscriber::fmt::layer())
+        // No colour codes in the journal when running as a systemd service
+        .with(tracing_subscriber::fmt::layer().with_ansi(std::env::var_os("JOURNAL_STREAM").is_none()))
         .init();
 
     let cli = Cli::parse();
 
+    let pid_path = cli.pid_file.clone().or_else(instance::default_path);
+
     // Handle install/uninstall subcommands
     match cli.command {
         Some(Commands::Install) => {
-            install_launchd(&cli.listen);
+            #[cfg(target_os = "linux")]
+            systemd::install(cli.listen.as_deref(), cli.config.as_deref(), cli.socket.as_deref());
+            #[cfg(not(target_os = "linux"))]
+            install_launchd(cli.listen.as_deref(), cli.config.as_deref());
             return;
         }
         Some(Commands::Uninstall) => {
+            #[cfg(target_os = "linux")]
+            systemd::uninstall();
+            #[cfg(not(target_os = "linux"))]
             uninstall_launchd();
             return;
         }
+        Some(Commands::Status) => {
+            let running = pid_path.as_deref().and_then(instance::running);
+            match running {
+                Some(pid) => println!("Daemon: running (pid {})", pid),
+                None => println!("Daemon: not running"),
+            }
+            #[cfg(target_os = "linux")]
+            systemd::status();
+            #[cfg(not(target_os = "linux"))]
+            launchd_status();
+            if running.is_none() {
+                std::process::exit(3);
+            }
+            return;
+        }
+        Some(Commands::Stop) => {
+            let Some(path) = pid_path else {
+                eprintln!("No runtime directory for the PID file; pass --pid-file");
+                std::process::exit(1);
+            };
+            match instance::stop(&path) {
+                Ok(Some(pid)) => println!("Stopped coredeck-daemon (pid {})", pid),
+                Ok(None) => println!("coredeck-daemon is not running"),
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Commands::Profile(ref command)) => {
+            let (_, config) = load_config(cli.config.clone());
+            let client = profile::client(&config, cli.listen.clone(), cli.socket.clone());
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to create tokio runtime");
+            let result = rt.block_on(async {
+                match command {
+                    ProfileCommand::Export { file, device } => {
+                        profile::export(&client, &config, device.as_deref(), file.as_deref()).await
+                    }
+                    ProfileCommand::Import { file, device, no_save } => {
+                        profile::import(&client, device.as_deref(), file, !no_save).await
+                    }
+                }
+            });
+            if let Err(e) = result {
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
         None => {}
     }
 
-    info!("Starting CoreDeck daemon on {}", cli.listen);
+    // Claim the socket-activation fds before anything else can open an fd
+    #[cfg(target_os = "linux")]
+    let activated_socket = systemd::activated_unix_listener(listen_fds);
+
+    let (config_path, config) = load_config(cli.config.clone());
+    if let Some(path) = &config_path {
+        info!("Config: {}", path.display());
+    }
+
+    // One daemon per user: a second one would fight over the decks
+    let instance_lock = match pid_path.as_deref().map(instance::acquire) {
+        Some(Ok(lock)) => Some(lock),
+        Some(Err(e)) => {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+        None => {
+            warn!("No runtime directory for the PID file; not checking for another daemon");
+            None
+        }
+    };
+
+    let tokens = if config.auth.require_token {
+        let Some(path) = config.auth.tokens_path() else {
+            error!("No config directory for the API token file; set [auth] tokens_file");
+            std::process::exit(1);
+        };
+        let store = auth::ensure_token_file(&path).and_then(|_| auth::TokenStore::load(&path));
+        match store {
+            Ok(store) => {
+                info!("API tokens: {} ({} loaded)", path.display(), store.len());
+                store
+            }
+            Err(e) => {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        warn!("API authentication disabled ([auth] require_token = false)");
+        auth::TokenStore::default()
+    };
+
+    let listeners = Listeners {
+        tcp: cli
+            .listen
+            .clone()
+            .or_else(|| config.server.tcp.then(|| config.server.listen.clone())),
+        unix_socket: cli.socket.clone().or_else(|| config.server.socket_path()),
+        #[cfg(target_os = "linux")]
+        activated_socket,
+    };
+    info!("Starting CoreDeck daemon");
 
     // macOS: set activation policy to Accessory (no dock icon, just tray)
     #[cfg(target_os = "macos")]
@@ -114,8 +313,19 @@ fn main() {
     let event_sender = DaemonEventSender::new(event_tx);
 
     // Initialize HID manager
-    let hid_config = HidConfig::default();
-    let hid_manager = match HidManager::new(hid_config, event_sender) {
+    let hid_config = config.hid.clone();
+    let mut virtual_deck = None;
+    let hid_result = if cli.virtual_device {
+        let protocol = if cli.virtual_vial { ProtocolMode::Vial } else { ProtocolMode::Standalone };
+        info!("Using virtual deck ({:?} protocol)", protocol);
+        let deck = VirtualDeck::new(protocol);
+        virtual_deck = Some(deck.clone());
+        let backend = Box::new(VirtualBackend::new(deck));
+        Ok(HidManager::with_backend(hid_config, event_sender, backend))
+    } else {
+        HidManager::new(hid_config, event_sender)
+    };
+    let hid_manager = match hid_result {
         Ok(hid) => {
             info!("HID manager initialized");
             hid
@@ -127,11 +337,15 @@ fn main() {
     };
 
     // Create tray (must happen on main thread on macOS)
-    let (tray_manager, tray_action_rx) = match tray::DaemonTrayManager::new() {
-        Ok((tray, rx)) => (Some(tray), Some(rx)),
-        Err(e) => {
-            error!("Failed to create tray: {}", e);
-            (None, None)
+    let (tray_manager, tray_action_rx) = if cli.headless {
+        (None, None)
+    } else {
+        match tray::DaemonTrayManager::new() {
+            Ok((tray, rx)) => (Some(tray), Some(rx)),
+            Err(e) => {
+                error!("Failed to create tray: {}", e);
+                (None, None)
+            }
         }
     };
 
@@ -139,138 +353,398 @@ fn main() {
     let (tray_update_tx, tray_update_rx) = std::sync::mpsc::channel::<TrayUpdate>();
 
     // Initialize device status from HID manager's enumeration
-    let initial_status = DeviceStatus {
-        available: hid_manager.is_device_available(),
-        device_name: hid_manager.cached_device_name(),
-        ..DeviceStatus::default()
-    };
+    let initial_devices: BTreeMap<String, DeviceStatus> = hid_manager
+        .devices()
+        .into_iter()
+        .map(|listing| {
+            let status = DeviceStatus {
+                device_name: listing.name,
+                ..DeviceStatus::default()
+            };
+            (listing.serial, status)
+        })
+        .collect();
 
     // Build shared state (Send + Sync — no tray handle here)
     let state = Arc::new(DaemonState {
+        hid_metrics: hid_manager.metrics(),
         hid: Mutex::new(hid_manager),
-        device_status: RwLock::new(initial_status),
-        ws_client: Mutex::new(None),
+        devices: RwLock::new(initial_devices),
+        display: Mutex::new(display::DisplayCompositor::default()),
+        alerts: Mutex::new(alerts::AlertLog::new(cli.alert_log.clone())),
+        ws_clients: Mutex::new(ws::WsClients::default()),
         notify_lock_change: Notify::new(),
+        events_tx: broadcast::channel(EVENT_STREAM_CAPACITY).0,
         tray_tx: tray_update_tx,
+        virtual_deck,
+        config: parking_lot::RwLock::new(config),
+        config_path,
+        tokens: parking_lot::RwLock::new(tokens),
+        shutdown: watch::channel(false).0,
+        action_slots: Arc::new(Semaphore::new(actions::MAX_RUNNING_ACTIONS)),
     });
 
     // Run the tokio runtime + axum server on a spawned thread.
     // The winit event loop must run on the main thread (required for tray on macOS).
     let state_clone = Arc::clone(&state);
-    let listen_addr = cli.listen.clone();
-    std::thread::spawn(move || {
+    let runtime_thread = std::thread::spawn(move || {
         let rt = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()
             .expect("Failed to create tokio runtime");
 
         rt.block_on(async move {
-            run_async(state_clone, event_rx, listen_addr).await;
+            run_async(state_clone, event_rx, listeners).await;
         });
+
+        // Clients and decks have been told; the tray's event loop on the main
+        // thread never returns by itself, so end the process from here
+        drop(instance_lock);
+        info!("Daemon exiting");
+        std::process::exit(0);
     });
 
     // Handle tray events on main thread (via winit event loop)
-    run_main_event_loop(state, tray_manager, tray_action_rx, tray_update_rx);
+    if !cli.headless {
+        run_main_event_loop(state, tray_manager, tray_action_rx, tray_update_rx);
+    }
+
+    // Headless (or no event loop available): the async runtime is the whole daemon
+    let _ = runtime_thread.join();
 }
 
-/// Run the async daemon (axum server + event processing)
-async fn run_async(
-    state: Arc<DaemonState>,
-    mut event_rx: mpsc::UnboundedReceiver<DaemonEvent>,
-    listen_addr: String,
-) {
-    // Build axum router
-    let app = axum::Router::new()
+/// The config file to use (`--config` or the default location) and its
+/// contents. Exits when the file exists but can't be loaded.
+fn load_config(path: Option<PathBuf>) -> (Option<PathBuf>, DaemonConfig) {
+    let path = path.or_else(DaemonConfig::default_path);
+    let config = match path.as_deref().map(DaemonConfig::load) {
+        Some(Ok(config)) => config,
+        Some(Err(e)) => {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+        None => {
+            warn!("No config directory available, using defaults");
+            DaemonConfig::default()
+        }
+    };
+    (path, config)
+}
+
+/// Where the HTTP/WS server listens
+struct Listeners {
+    /// TCP address
+    tcp: Option<String>,
+    /// Unix socket path (ignored on Windows)
+    unix_socket: Option<PathBuf>,
+    /// Unix socket passed in by systemd; replaces binding `unix_socket`
+    #[cfg(target_os = "linux")]
+    activated_socket: Option<std::os::unix::net::UnixListener>,
+}
+
+/// Bind the daemon's Unix socket, replacing a stale socket file but never a live one.
+///
+/// The socket is only accessible to the owning user; that is its access control.
+#[cfg(unix)]
+fn bind_unix_socket(path: &Path) -> anyhow::Result<tokio::net::UnixListener> {
+    use anyhow::Context;
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let parent = path.parent().unwrap_or(Path::new("."));
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(parent)
+        .with_context(|| format!("Failed to create {}", parent.display()))?;
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            anyhow::bail!("another daemon is listening on {}", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+
+    // Bind inside a fresh 0700 directory and restrict the socket before moving
+    // it into place: at the usual umask another user could otherwise connect
+    // between bind and chmod and be trusted as a local peer
+    let staging = parent.join(format!(".coredeck-bind-{}", std::process::id()));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .with_context(|| format!("Failed to create {}", staging.display()))?;
+    let staged = staging.join("socket");
+    let bound = tokio::net::UnixListener::bind(&staged)
+        .with_context(|| format!("Failed to bind {}", path.display()))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("Failed to restrict {}", path.display()))?;
+            std::fs::rename(&staged, path).with_context(|| format!("Failed to move socket to {}", path.display()))?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&staging);
+    bound
+}
+
+/// Serve `app` on the Unix socket, whose peers need no token
+#[cfg(unix)]
+async fn serve_unix(listener: tokio::net::UnixListener, app: axum::Router) {
+    let app = app.layer(axum::Extension(auth::LocalPeer));
+    if let Err(e) = axum::serve(listener, app).await {
+        error!("Unix socket server error: {}", e);
+    }
+}
+
+/// HTTP/WS routes with CORS and token checks
+fn router(state: &Arc<DaemonState>) -> axum::Router {
+    let mut app = axum::Router::new()
         .route("/ws", axum::routing::get(ws::ws_handler))
         .route("/api/status", axum::routing::get(rpc::get_status))
+        .route("/api/events", axum::routing::get(rpc::get_events))
         .route("/api/display", axum::routing::post(rpc::post_display))
+        .route("/api/display/clear", axum::routing::post(rpc::post_display_clear))
         .route("/api/alert", axum::routing::post(rpc::post_alert))
         .route("/api/alert/clear", axum::routing::post(rpc::post_alert_clear))
+        .route("/api/alerts", axum::routing::get(rpc::get_alerts))
         .route("/api/brightness", axum::routing::post(rpc::post_brightness))
         .route("/api/mode", axum::routing::post(rpc::post_mode))
         .route("/api/version", axum::routing::get(rpc::get_version))
+        .route("/api/softkeys", axum::routing::get(rpc::get_softkeys))
+        .route("/api/softkeys/{index}", axum::routing::put(rpc::put_softkey))
+        .route("/api/softkeys/reset", axum::routing::post(rpc::post_softkeys_reset))
+        .route("/api/metrics", axum::routing::get(rpc::get_metrics));
+
+    // Test hooks for driving the software deck (--virtual-device only)
+    if state.virtual_deck.is_some() {
+        app = app
+            .route("/api/virtual", axum::routing::get(rpc::get_virtual))
+            .route("/api/virtual/key", axum::routing::post(rpc::post_virtual_key))
+            .route("/api/virtual/type", axum::routing::post(rpc::post_virtual_type))
+            .route("/api/virtual/state", axum::routing::post(rpc::post_virtual_state))
+            .route("/api/virtual/present", axum::routing::post(rpc::post_virtual_present));
+    }
+
+    // Allowed origins are read from the live config, so reloads apply immediately
+    let state_for_cors = Arc::clone(state);
+    let allow_origin = tower_http::cors::AllowOrigin::predicate(move |origin, _| {
+        origin
+            .to_str()
+            .is_ok_and(|origin| state_for_cors.config.read().allows_origin(origin))
+    });
+
+    // Token and origin checks run inside CORS, so preflight requests are answered without a token
+    app.route_layer(axum::middleware::from_fn_with_state(Arc::clone(state), auth::authorize))
         .layer(
             tower_http::cors::CorsLayer::new()
-                .allow_origin(tower_http::cors::Any)
+                .allow_origin(allow_origin)
                 .allow_methods(tower_http::cors::Any)
                 .allow_headers(tower_http::cors::Any),
         )
-        .with_state(Arc::clone(&state));
+        .with_state(Arc::clone(state))
+}
 
-    // Start HTTP/WS server
-    let listener = match tokio::net::TcpListener::bind(&listen_addr).await {
-        Ok(l) => {
-            info!("Listening on {}", listen_addr);
-            l
-        }
-        Err(e) => {
-            error!("Failed to bind to {}: {}", listen_addr, e);
-            std::process::exit(1);
-        }
+/// Run the async daemon (axum server + event processing)
+async fn run_async(
+    state: Arc<DaemonState>,
+    mut event_rx: mpsc::UnboundedReceiver<DaemonEvent>,
+    listeners: Listeners,
+) {
+    let app = router(&state);
+
+    // Start HTTP/WS servers. Either listener failing is fine as long as one is up.
+    let mut servers = Vec::new();
+
+    // A socket from systemd stays systemd's: it is neither bound nor removed here
+    #[cfg(target_os = "linux")]
+    let activated = listeners.activated_socket.and_then(|listener| {
+        listener
+            .set_nonblocking(true)
+            .and_then(|_| tokio::net::UnixListener::from_std(listener))
+            .map_err(|e| error!("Socket-activated listener unusable: {}", e))
+            .ok()
+    });
+    #[cfg(all(unix, not(target_os = "linux")))]
+    let activated: Option<tokio::net::UnixListener> = None;
+
+    #[cfg(unix)]
+    let (unix_listener, bound_socket) = match (activated, listeners.unix_socket) {
+        (Some(listener), _) => (Some(listener), None),
+        (None, Some(path)) => match bind_unix_socket(&path) {
+            Ok(listener) => {
+                info!("Listening on {}", path.display());
+                (Some(listener), Some(path))
+            }
+            Err(e) => {
+                error!("{:#}", e);
+                (None, None)
+            }
+        },
+        (None, None) => (None, None),
     };
+    #[cfg(unix)]
+    if let Some(listener) = unix_listener {
+        servers.push(tokio::spawn(serve_unix(listener, app.clone())));
+    }
+    #[cfg(not(unix))]
+    let bound_socket: Option<PathBuf> = listeners.unix_socket.and_then(|path| {
+        warn!("Unix sockets are not supported here, ignoring {}", path.display());
+        None
+    });
 
-    let server = tokio::spawn(async move {
-        if let Err(e) = axum::serve(listener, app).await {
-            error!("Server error: {}", e);
+    if let Some(addr) = listeners.tcp {
+        match tokio::net::TcpListener::bind(&addr).await {
+            Ok(listener) => {
+                info!("Listening on {}", addr);
+                servers.push(tokio::spawn(async move {
+                    if let Err(e) = axum::serve(listener, app).await {
+                        error!("Server error: {}", e);
+                    }
+                }));
+            }
+            Err(e) => error!("Failed to bind to {}: {}", addr, e),
+        }
+    }
+
+    if servers.is_empty() {
+        error!("No listener could be started");
+        std::process::exit(1);
+    }
+
+    // Sweep expired display leases
+    let state_for_display = Arc::clone(&state);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(display::LEASE_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            display::expire_leases(&state_for_display).await;
         }
     });
 
+    // Reload the config file on SIGHUP or change
+    tokio::spawn(config::watch(Arc::clone(&state)));
+
+    // Take the decks for headless actions until an app connects
+    actions::update_headless(&state).await;
+
     // Process HID events and forward to WS client
     let state_for_events = Arc::clone(&state);
     let event_handler = tokio::spawn(async move {
         while let Some(event) = event_rx.recv().await {
             // Update shared device status and notify tray
             match &event {
-                DaemonEvent::HidConnected { device_name, firmware_version } => {
-                    let mut status = state_for_events.device_status.write().await;
-                    status.available = true;
+                DaemonEvent::HidConnected { serial, device_name, firmware_version, capabilities } => {
+                    let mut devices = state_for_events.devices.write().await;
+                    let status = devices.entry(serial.clone()).or_default();
                     status.connected = true;
-                    status.device_name = Some(device_name.clone());
+                    status.device_name = device_name.clone();
                     status.firmware_version = Some(firmware_version.clone());
+                    status.capabilities = capabilities.clone();
+                    drop(devices);
+                    state_for_events.update_tray_devices().await;
 
-                    state_for_events.send_tray_update(TrayUpdate::DeviceConnected(device_name.clone()));
+                    let brightness = state_for_events.config.read().display.default_brightness;
+                    if let Some(level) = brightness {
+                        let done = state_for_events.hid.lock().await.set_brightness(Some(serial), level, false);
+                        if let Err(e) = done.await {
+                            warn!("Failed to apply default brightness: {}", e);
+                        }
+                    }
                 }
-                DaemonEvent::HidDisconnected => {
-                    let mut status = state_for_events.device_status.write().await;
-                    status.connected = false;
-                    status.device_name = None;
-                    status.firmware_version = None;
-
-                    state_for_events.send_tray_update(TrayUpdate::DeviceDisconnected);
+                DaemonEvent::HidDisconnected { serial } => {
+                    if let Some(status) = state_for_events.devices.write().await.get_mut(serial) {
+                        status.connected = false;
+                        status.firmware_version = None;
+                        status.capabilities = None;
+                    }
+                    state_for_events.update_tray_devices().await;
+                }
+                DaemonEvent::DeviceAvailable { serial, device_name } => {
+                    state_for_events.devices.write().await.insert(
+                        serial.clone(),
+                        DeviceStatus {
+                            device_name: device_name.clone(),
+                            ..DeviceStatus::default()
+                        },
+                    );
+                    state_for_events.update_tray_devices().await;
                 }
-                DaemonEvent::DeviceAvailable { device_name } => {
-                    let mut status = state_for_events.device_status.write().await;
-                    status.available = true;
-                    status.device_name = Some(device_name.clone());
-                    state_for_events.send_tray_update(TrayUpdate::DeviceAvailable(device_name.clone()));
+                DaemonEvent::DeviceUnavailable { serial } => {
+                    state_for_events.devices.write().await.remove(serial);
+                    state_for_events.update_tray_devices().await;
                 }
-                DaemonEvent::DeviceUnavailable => {
-                    let mut status = state_for_events.device_status.write().await;
-                    status.available = false;
-                    status.device_name = None;
-                    status.firmware_version = None;
-                    state_for_events.send_tray_update(TrayUpdate::DeviceUnavailable);
+                DaemonEvent::DeviceStateChanged { serial, mode, yolo } => {
+                    let before = state_for_events.devices.write().await.get_mut(serial).map(|status| {
+                        let before = (status.mode, status.yolo);
+                        status.mode = *mode;
+                        status.yolo = *yolo;
+                        before
+                    });
+                    actions::dispatch(&state_for_events, &event, before).await;
                 }
-                DaemonEvent::DeviceStateChanged { mode, yolo } => {
-                    let mut status = state_for_events.device_status.write().await;
-                    status.mode = *mode;
-                    status.yolo = *yolo;
+                DaemonEvent::HidKeyEvent { serial, keycode } => {
+                    alerts::acknowledge_on_key(&state_for_events, serial, *keycode).await;
+                    actions::dispatch(&state_for_events, &event, None).await;
                 }
-                _ => {}
+                DaemonEvent::HidTypeString { .. } => {}
             }
 
-            // Forward to WS client
+            // Forward to WS clients, webhooks and event stream subscribers
             ws::forward_event_to_ws(&state_for_events, &event).await;
+            webhook::notify(&state_for_events, &event);
+            let _ = state_for_events.events_tx.send(event);
         }
     });
 
-    // Wait for shutdown
+    // Wait for shutdown. A server that fails has logged why; the others keep
+    // serving, so only a signal or a shutdown request ends the daemon.
+    let mut quit = state.shutdown.subscribe();
     tokio::select! {
-        _ = server => {}
         _ = event_handler => {}
         _ = tokio::signal::ctrl_c() => {
             info!("Received Ctrl+C, shutting down...");
         }
+        _ = terminated() => {
+            info!("Received SIGTERM, shutting down...");
+        }
+        _ = quit.wait_for(|quit| *quit) => {}
+    }
+    shut_down(&state).await;
+
+    if let Some(path) = bound_socket {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Resolves on SIGTERM (sent by `coredeck-daemon stop`, systemd and launchd)
+async fn terminated() {
+    #[cfg(unix)]
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        Ok(mut signal) => {
+            signal.recv().await;
+        }
+        Err(e) => {
+            warn!("Failed to listen for SIGTERM: {}", e);
+            std::future::pending::<()>().await;
+        }
+    }
+    #[cfg(not(unix))]
+    std::future::pending::<()>().await;
+}
+
+/// Say goodbye before exiting: WS clients get a close frame and every open
+/// deck the `Disconnect` packet, so the firmware goes back to standalone mode
+async fn shut_down(state: &Arc<DaemonState>) {
+    state.shutdown.send_replace(true);
+
+    // Let clients close so a controller's release runs as usual
+    let deadline = Instant::now() + SHUTDOWN_GRACE;
+    while !state.ws_clients.lock().await.is_empty() && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let closed = state.hid.lock().await.close_device(None);
+    if let Err(e) = closed.await {
+        warn!("Failed to close HID devices: {}", e);
     }
 }
 
@@ -303,7 +777,7 @@ fn run_main_event_loop(
             _event: winit::event::WindowEvent,
         ) {}
 
-        fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
             // Process tray updates from async code (non-blocking)
             while let Ok(update) = self.tray_update_rx.try_recv() {
                 if let Some(ref mut tray) = self.tray_manager {
@@ -311,10 +785,6 @@ fn run_main_event_loop(
                         TrayUpdate::DeviceConnected(name) => {
                             tray.set_device_status(tray::DevicePresence::Active, Some(&name));
                         }
-                        TrayUpdate::DeviceDisconnected => {
-                            // Device interface closed but may still be physically available
-                            tray.set_device_status(tray::DevicePresence::Available, None);
-                        }
                         TrayUpdate::DeviceAvailable(name) => {
                             tray.set_device_status(tray::DevicePresence::Available, Some(&name));
                         }
@@ -349,8 +819,9 @@ fn run_main_event_loop(
                             });
                         }
                         tray::DaemonTrayAction::Quit => {
+                            // The runtime thread shuts down and ends the process
                             info!("Quit requested from tray");
-                            event_loop.exit();
+                            self.state.shutdown.send_replace(true);
                         }
                     }
                 }
@@ -358,7 +829,14 @@ fn run_main_event_loop(
         }
     }
 
-    let event_loop = EventLoop::new().expect("Failed to create event loop");
+    let event_loop = match EventLoop::new() {
+        Ok(event_loop) => event_loop,
+        Err(e) => {
+            // No display server — keep serving without a tray
+            error!("Failed to create event loop: {} (continuing headless)", e);
+            return;
+        }
+    };
     let mut app = TrayApp {
         state,
         tray_manager,
@@ -366,9 +844,6 @@ fn run_main_event_loop(
         tray_update_rx,
     };
     let _ = event_loop.run_app(&mut app);
-
-    info!("Daemon exiting");
-    std::process::exit(0);
 }
 
 #[cfg(target_os = "macos")]
@@ -389,7 +864,8 @@ fn setup_macos_accessory() {}
 
 // ── launchd install/uninstall ──────────────────────────────────────
 
-fn install_launchd(listen: &str) {
+#[cfg(not(target_os = "linux"))]
+fn install_launchd(listen: Option<&str>, config: Option<&Path>) {
     #[cfg(target_os = "macos")]
     {
         let home = std::env::var("HOME").expect("HOME not set");
@@ -401,6 +877,16 @@ fn install_launchd(listen: &str) {
             .to_string_lossy()
             .to_string();
 
+        // Only pin what was given on the command line; the rest comes from the config file
+        let mut args = String::new();
+        if let Some(listen) = listen {
+            args.push_str(&format!("\n        <string>--listen</string>\n        <string>{listen}</string>"));
+        }
+        if let Some(config) = config {
+            let config = config.to_string_lossy();
+            args.push_str(&format!("\n        <string>--config</string>\n        <string>{config}</string>"));
+        }
+
         let plist = format!(
             r#"<?xml version="1.0" encoding="UTF-8"?>
 <!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
@@ -410,9 +896,7 @@ fn install_launchd(listen: &str) {
     <string>com.coredeck.daemon</string>
     <key>ProgramArguments</key>
     <array>
-        <string>{exe}</string>
-        <string>--listen</string>
-        <string>{listen}</string>
+        <string>{exe}</string>{args}
     </array>
     <key>RunAtLoad</key>
     <true/>
@@ -444,11 +928,12 @@ fn install_launchd(listen: &str) {
 
     #[cfg(not(target_os = "macos"))]
     {
-        let _ = listen;
-        eprintln!("launchd is only available on macOS");
+        let _ = (listen, config);
+        eprintln!("Auto-start is only supported on macOS (launchd) and Linux (systemd)");
     }
 }
 
+#[cfg(not(target_os = "linux"))]
 fn uninstall_launchd() {
     #[cfg(target_os = "macos")]
     {
@@ -468,6 +953,127 @@ fn uninstall_launchd() {
 
     #[cfg(not(target_os = "macos"))]
     {
-        eprintln!("launchd is only available on macOS");
+        eprintln!("Auto-start is only supported on macOS (launchd) and Linux (systemd)");
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn launchd_status() {
+    #[cfg(target_os = "macos")]
+    {
+        let home = std::env::var("HOME").expect("HOME not set");
+        let plist_path = format!("{}/Library/LaunchAgents/com.coredeck.daemon.plist", home);
+
+        if !Path::new(&plist_path).exists() {
+            println!("Not installed ({} missing)", plist_path);
+            return;
+        }
+        println!("Installed: {}", plist_path);
+
+        let running = std::process::Command::new("launchctl")
+            .args(["list", "com.coredeck.daemon"])
+            .output()
+            .is_ok_and(|out| out.status.success());
+        println!("com.coredeck.daemon: {}", if running { "loaded" } else { "not loaded" });
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        eprintln!("Auto-start is only supported on macOS (launchd) and Linux (systemd)");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file from the workspace checkout, read at test time so the crate
+    /// still packages on its own. `None` (and the caller skips) when absent.
+    pub(crate) fn workspace_file(path: &str) -> Option<String> {
+        let full = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../..").join(path);
+        let text = std::fs::read_to_string(&full).ok();
+        if text.is_none() {
+            eprintln!("skipping: {} not found", full.display());
+        }
+        text
+    }
+
+    /// Daemon state over a virtual deck (as with `--virtual-device`), with
+    /// token auth on and no tokens issued
+    pub(crate) fn test_state() -> Arc<DaemonState> {
+        let (event_tx, _) = mpsc::unbounded_channel();
+        let deck = VirtualDeck::new(ProtocolMode::Standalone);
+        let hid = HidManager::with_backend(
+            HidConfig::default(),
+            DaemonEventSender::new(event_tx),
+            Box::new(VirtualBackend::new(deck.clone())),
+        );
+        Arc::new(DaemonState {
+            hid_metrics: hid.metrics(),
+            hid: Mutex::new(hid),
+            devices: RwLock::default(),
+            display: Mutex::default(),
+            alerts: Mutex::new(alerts::AlertLog::new(None)),
+            ws_clients: Mutex::default(),
+            notify_lock_change: Notify::new(),
+            events_tx: broadcast::channel(EVENT_STREAM_CAPACITY).0,
+            tray_tx: std::sync::mpsc::channel().0,
+            virtual_deck: Some(deck),
+            config: parking_lot::RwLock::new(DaemonConfig::default()),
+            config_path: None,
+            tokens: parking_lot::RwLock::default(),
+            shutdown: watch::channel(false).0,
+            action_slots: Arc::new(Semaphore::new(actions::MAX_RUNNING_ACTIONS)),
+        })
+    }
+
+    /// Status line of a bodyless GET over `stream`
+    async fn get<S>(mut stream: S, path: &str) -> String
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_private_and_trusted() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("coredeck-socket-{}", std::process::id()));
+        let path = dir.join("daemon.sock");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // A socket file left by a crashed daemon is replaced
+        std::fs::create_dir_all(&dir).unwrap();
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let listener = bind_unix_socket(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        // The private directory it was bound in is gone
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        // ...but a live one never is
+        let err = bind_unix_socket(&path).unwrap_err();
+        assert!(err.to_string().contains("another daemon"), "{}", err);
+
+        let state = test_state();
+        tokio::spawn(serve_unix(listener, router(&state)));
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        assert_eq!(get(stream, "/api/alerts").await, "HTTP/1.1 200 OK");
+
+        // The same request over TCP needs a token
+        let tcp = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(tcp, router(&state)).await });
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        assert_eq!(get(stream, "/api/alerts").await, "HTTP/1.1 401 Unauthorized");
+
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
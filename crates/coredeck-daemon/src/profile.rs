@@ -0,0 +1,112 @@
+//! Device profiles — `coredeck-daemon profile export`/`import`
+//!
+//! The subcommands are clients of the running daemon: they go through its REST
+//! API, so they never compete with it for the HID device. The Unix socket is
+//! preferred (its permissions stand in for a token); over TCP the app's token
+//! from the token file is sent.
+
+use anyhow::{Context, Result};
+use coredeck_client::{Endpoint, RestClient};
+use coredeck_protocol::{
+    BrightnessRequest, DeviceProfile, SetModeRequest, SetSoftKeyRequest, TokenFile, APP_TOKEN_NAME,
+};
+use std::path::{Path, PathBuf};
+
+use crate::config::DaemonConfig;
+
+/// Client for the daemon as configured; `listen` and `socket` override the config
+pub fn client(config: &DaemonConfig, listen: Option<String>, socket: Option<PathBuf>) -> RestClient {
+    let token = config
+        .auth
+        .require_token
+        .then(|| config.auth.tokens_path())
+        .flatten()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| toml::from_str::<TokenFile>(&content).ok())
+        .and_then(|file| file.find(APP_TOKEN_NAME).map(|t| t.token.clone()));
+    RestClient::new(Endpoint {
+        socket: socket.or_else(|| config.server.socket_path()),
+        addr: listen.or_else(|| config.server.tcp.then(|| config.server.listen.clone())),
+        token,
+    })
+}
+
+/// Read the deck's soft keys and mode. Brightness can't be read back from the
+/// firmware, so the `[display] default_brightness` setting stands in for it.
+pub async fn export(client: &RestClient, config: &DaemonConfig, device: Option<&str>, out: Option<&Path>) -> Result<()> {
+    let keys = client.soft_keys(device).await?;
+    let status = client.status().await?;
+    let mode = match device {
+        Some(serial) => status.devices.iter().find(|d| d.serial == serial).map(|d| d.mode),
+        None => Some(status.device_mode),
+    };
+
+    let profile = DeviceProfile {
+        brightness: config.display.default_brightness,
+        mode,
+        soft_keys: keys.into_iter().map(|key| key.action).collect(),
+    };
+    let content = format!(
+        "# CoreDeck device profile — apply with `coredeck-daemon profile import`\n\n{}",
+        toml::to_string_pretty(&profile).context("Failed to serialize profile")?
+    );
+    match out {
+        Some(path) => {
+            std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+            println!("Profile written to {}", path.display());
+        }
+        None => print!("{}", content),
+    }
+    Ok(())
+}
+
+/// Apply a profile file: soft keys first, then brightness and mode
+pub async fn import(client: &RestClient, device: Option<&str>, path: &Path, save: bool) -> Result<()> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let profile: DeviceProfile =
+        toml::from_str(&content).with_context(|| format!("Failed to parse profile {}", path.display()))?;
+    let device = device.map(str::to_string);
+
+    for (index, action) in (0u8..).zip(profile.soft_keys) {
+        let req = SetSoftKeyRequest { action, save, device: device.clone() };
+        for issue in client.set_soft_key(index, &req).await? {
+            eprintln!("Soft key {}: {} {}", index, issue.field, issue.reason);
+        }
+        println!("Soft key {} set", index);
+    }
+    if let Some(level) = profile.brightness {
+        client.set_brightness(&BrightnessRequest { level, save, device: device.clone() }).await?;
+        println!("Brightness set to {}", level);
+    }
+    if let Some(mode) = profile.mode {
+        client.set_mode(&SetModeRequest { mode, device }).await?;
+        println!("Mode set to {:?}", mode);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coredeck_protocol::{DeviceMode, KeyStroke, SoftKeyAction};
+
+    #[test]
+    fn test_profile_toml_roundtrip() {
+        let enter = KeyStroke { usage: 0x28, ..Default::default() };
+        let profile = DeviceProfile {
+            brightness: Some(180),
+            mode: Some(DeviceMode::Plan),
+            soft_keys: vec![
+                SoftKeyAction::Keycode { key: KeyStroke { usage: 0x06, ctrl: true, ..Default::default() } },
+                SoftKeyAction::String { text: "git status".into(), send_enter: true },
+                SoftKeyAction::Sequence { keys: vec![enter, enter] },
+            ],
+        };
+        let text = toml::to_string_pretty(&profile).unwrap();
+        assert_eq!(toml::from_str::<DeviceProfile>(&text).unwrap(), profile);
+
+        let sparse: DeviceProfile = toml::from_str("[[soft_keys]]\ntype = \"default\"\n").unwrap();
+        assert_eq!(sparse.soft_keys, vec![SoftKeyAction::Default { key: None }]);
+        assert_eq!((sparse.brightness, sparse.mode), (None, None));
+    }
+}
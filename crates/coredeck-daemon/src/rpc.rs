@@ -1,101 +1,272 @@
 //! HTTP REST endpoints for third-party access
 //!
-//! Read-only endpoints always work. Mutating endpoints return 409 when a WS
-//! client holds the lock. When no WS client is connected, mutating endpoints
+//! Read-only endpoints always work, reading through the controller's open
+//! device when one is connected. Mutating endpoints return 409 when a WS
+//! controller holds the lock. When no WS client is connected, mutating endpoints
 //! transiently open the HID device for the duration of the request, then close
-//! it so the keyboard works normally.
+//! it so the keyboard works normally. Decks held open for headless actions
+//! stay open.
 
 use coredeck_protocol::{
-    AlertRequest, ApiError, BrightnessRequest, ClearAlertRequest, DaemonStatus,
-    DisplayUpdateRequest, SetModeRequest,
+    AlertRecord, AlertRequest, AlertSource, ApiError, BrightnessRequest, ClearAlertRequest, ClearDisplayRequest,
+    DaemonStatus, DeviceQuery, DeviceState, DeviceSummary, DisplayUpdateRequest, FieldAdjustments, FieldIssue,
+    SetModeRequest, SetSoftKeyRequest, SoftKeyAssignment,
 };
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     Json,
 };
+use futures_util::stream::{self, Stream};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::MutexGuard;
+use tracing::warn;
 
 use crate::DaemonState;
-use crate::hid::HidManager;
-
-/// Transiently open the HID device if it's not already open.
-/// Returns `true` if we opened it (caller must close after use).
-fn ensure_device_open(hid: &HidManager) -> Result<bool, String> {
-    if hid.is_connected() {
-        return Ok(false); // Already open (WS client holds it)
-    }
+use crate::alerts;
+use crate::display::{self, REST_SOURCE};
+use crate::hid::{limits, HidCompletion, HidManager, QueueFull, VirtualDeck};
+
+/// Transiently open the addressed decks (`None` = every deck) that aren't open yet.
+/// The writer thread opens them, so no USB I/O runs here. The caller keeps the
+/// HID lock until it has queued its commands and the close, so no other
+/// request's close can slip in between.
+/// Returns the serials we opened (caller must close them after use).
+async fn ensure_device_open(hid: &HidManager, device: Option<&str>) -> Result<Vec<String>, String> {
     if !hid.is_device_available() {
         return Err("Device not available".into());
     }
-    hid.open_device().map_err(|e| e.to_string())?;
-    Ok(true) // We opened it transiently
+    hid.open_transient(device).await.map_err(|e| e.to_string())
+}
+
+/// Queue closing the decks `ensure_device_open` opened, behind the commands
+/// already queued. The writer closes them whether or not anyone waits.
+fn close_transient(hid: &HidManager, opened: Vec<String>) -> Option<HidCompletion<()>> {
+    (!opened.is_empty()).then(|| hid.close_transient(opened))
+}
+
+/// Wait for queued HID commands after releasing the HID lock. Decks opened
+/// transiently are closed by a job queued behind the commands.
+async fn complete<T>(
+    hid: MutexGuard<'_, HidManager>,
+    transient: Vec<String>,
+    done: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    let closed = close_transient(&hid, transient);
+    drop(hid);
+    let result = done.await;
+    if let Some(closed) = closed {
+        if let Err(e) = closed.await {
+            warn!("Failed to close transiently opened decks: {}", e);
+        }
+    }
+    result
+}
+
+/// 503 when the HID write queue is full, 500 for any other HID failure
+fn hid_error(e: anyhow::Error) -> Response {
+    let status = if e.is::<QueueFull>() { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::INTERNAL_SERVER_ERROR };
+    (status, Json(ApiError { error: e.to_string() })).into_response()
+}
+
+/// 200, listing the text fields validation shortened (if any)
+fn ok_adjusted(adjusted: Vec<FieldIssue>) -> Response {
+    if adjusted.is_empty() {
+        StatusCode::OK.into_response()
+    } else {
+        Json(FieldAdjustments { adjusted }).into_response()
+    }
 }
 
 /// GET /api/status — always available
 pub async fn get_status(State(state): State<Arc<DaemonState>>) -> impl IntoResponse {
-    let status = state.device_status.read().await;
-    let ws_locked = state.ws_client.lock().await.is_some();
+    let devices: Vec<DeviceSummary> = state
+        .devices
+        .read()
+        .await
+        .iter()
+        .map(|(serial, status)| DeviceSummary {
+            serial: serial.clone(),
+            name: status.device_name.clone(),
+            connected: status.connected,
+            firmware_version: status.firmware_version.clone(),
+            capabilities: status.capabilities.clone(),
+            mode: status.mode,
+            yolo: status.yolo,
+        })
+        .collect();
+    let (ws_locked, ws_observers) = {
+        let clients = state.ws_clients.lock().await;
+        (clients.has_controller(), clients.observer_count())
+    };
 
+    // Single-deck view for older clients: the first connected deck, else the first one
+    let primary = devices.iter().find(|d| d.connected).or(devices.first());
     Json(DaemonStatus {
-        device_available: status.available,
-        device_connected: status.connected,
-        device_name: status.device_name.clone(),
-        firmware_version: status.firmware_version.clone(),
-        device_mode: status.mode,
-        device_yolo: status.yolo,
+        device_available: !devices.is_empty(),
+        device_connected: devices.iter().any(|d| d.connected),
+        device_name: primary.map(|d| d.name.clone()),
+        firmware_version: primary.and_then(|d| d.firmware_version.clone()),
+        device_mode: primary.map(|d| d.mode).unwrap_or_default(),
+        device_yolo: primary.is_some_and(|d| d.yolo),
         ws_locked,
+        ws_observers,
+        devices,
+        hid_link: state.hid_metrics.snapshot(),
     })
 }
 
-/// POST /api/display
+/// GET /api/metrics — HID link health in Prometheus text format. Never takes the lock.
+pub async fn get_metrics(State(state): State<Arc<DaemonState>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.hid_metrics.render_prometheus(),
+    )
+}
+
+/// GET /api/events — Server-Sent Events stream of every `DaemonEvent`.
+///
+/// Each SSE event is named after the JSON `type` field. Never takes the lock.
+pub async fn get_events(
+    State(state): State<Arc<DaemonState>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let rx = state.events_tx.subscribe();
+
+    let events = stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    let sse = match serde_json::to_value(&event) {
+                        Ok(json) => {
+                            let name = json["type"].as_str().unwrap_or("event").to_string();
+                            Event::default().event(name).data(json.to_string())
+                        }
+                        Err(e) => {
+                            warn!("Failed to serialize event {:?}: {}", event, e);
+                            continue;
+                        }
+                    };
+                    return Some((Ok(sse), rx));
+                }
+                // Slow subscriber: tell it how much it missed and carry on
+                Err(RecvError::Lagged(skipped)) => {
+                    let data = serde_json::json!({ "skipped": skipped }).to_string();
+                    let sse = Event::default().event("lagged").data(data);
+                    return Some((Ok(sse), rx));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// POST /api/display — composited with other sources, so it never conflicts with the WS lock
 pub async fn post_display(
     State(state): State<Arc<DaemonState>>,
     Json(req): Json<DisplayUpdateRequest>,
 ) -> impl IntoResponse {
-    if state.ws_client.lock().await.is_some() {
-        return (StatusCode::CONFLICT, Json(ApiError { error: "device locked by WebSocket client".into() })).into_response();
-    }
-
     let hid = state.hid.lock().await;
-    let transient = match ensure_device_open(&hid) {
+    let transient = match ensure_device_open(&hid, req.device.as_deref()).await {
         Ok(t) => t,
         Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, Json(ApiError { error: e })).into_response(),
     };
 
-    let result = hid.send_display_update(&req.session, Some(req.task.as_str()).filter(|s| !s.is_empty()), Some(req.task2.as_str()).filter(|s| !s.is_empty()), &req.tabs, req.active);
+    let mut update = req.display_update();
+    let adjusted = match limits::check_display(&hid.limits(req.device.as_deref()), &mut update) {
+        Ok(adjusted) => adjusted,
+        Err(e) => {
+            close_transient(&hid, transient);
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(e)).into_response();
+        }
+    };
 
-    if transient { hid.close_device(); }
+    let pushed = {
+        let mut display = state.display.lock().await;
+        display.set(
+            req.source.as_deref().unwrap_or(REST_SOURCE),
+            req.device.as_deref(),
+            update,
+            req.priority,
+            req.ttl_secs.map(Duration::from_secs),
+            Instant::now(),
+        );
+        display::push_current(&hid, &display)
+    };
+    let result = complete(hid, transient, pushed).await;
 
     match result {
+        Ok(()) => ok_adjusted(adjusted),
+        Err(e) => hid_error(e),
+    }
+}
+
+/// POST /api/display/clear — drop a display layer; the next one down is shown, or a blank screen
+pub async fn post_display_clear(
+    State(state): State<Arc<DaemonState>>,
+    Json(req): Json<ClearDisplayRequest>,
+) -> impl IntoResponse {
+    let hid = state.hid.lock().await;
+    let mut display = state.display.lock().await;
+    if !display.clear(req.source.as_deref().unwrap_or(REST_SOURCE), req.device.as_deref()) {
+        return (StatusCode::NOT_FOUND, Json(ApiError { error: "no such display layer".into() })).into_response();
+    }
+
+    // Only redraw while a controller keeps the device open
+    if !hid.is_connected() {
+        return StatusCode::OK.into_response();
+    }
+    let pushed = display::push_current(&hid, &display);
+    drop((display, hid));
+    match pushed.await {
         Ok(()) => StatusCode::OK.into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiError { error: e.to_string() })).into_response(),
+        Err(e) => hid_error(e),
     }
 }
 
 /// POST /api/alert
 pub async fn post_alert(
     State(state): State<Arc<DaemonState>>,
-    Json(req): Json<AlertRequest>,
+    Json(mut req): Json<AlertRequest>,
 ) -> impl IntoResponse {
-    if state.ws_client.lock().await.is_some() {
+    if state.ws_clients.lock().await.has_controller() {
         return (StatusCode::CONFLICT, Json(ApiError { error: "device locked by WebSocket client".into() })).into_response();
     }
 
     let hid = state.hid.lock().await;
-    let transient = match ensure_device_open(&hid) {
+    let transient = match ensure_device_open(&hid, req.device.as_deref()).await {
         Ok(t) => t,
         Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, Json(ApiError { error: e })).into_response(),
     };
 
-    let result = hid.send_alert(req.tab, &req.session, &req.text, req.details.as_deref());
+    let adjusted = match limits::check_alert(&hid.limits(req.device.as_deref()), &mut req) {
+        Ok(adjusted) => adjusted,
+        Err(e) => {
+            close_transient(&hid, transient);
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(e)).into_response();
+        }
+    };
+
+    let sent = hid.send_alert(req.device.as_deref(), req.tab, &req.session, &req.text, req.details.as_deref());
+    let result = complete(hid, transient, sent).await;
 
-    if transient { hid.close_device(); }
+    if result.is_ok() {
+        alerts::raised(&state, &req, AlertSource::Rest).await;
+    }
 
     match result {
-        Ok(()) => StatusCode::OK.into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiError { error: e.to_string() })).into_response(),
+        Ok(()) => ok_adjusted(adjusted),
+        Err(e) => hid_error(e),
     }
 }
 
@@ -104,48 +275,55 @@ pub async fn post_alert_clear(
     State(state): State<Arc<DaemonState>>,
     Json(req): Json<ClearAlertRequest>,
 ) -> impl IntoResponse {
-    if state.ws_client.lock().await.is_some() {
+    if state.ws_clients.lock().await.has_controller() {
         return (StatusCode::CONFLICT, Json(ApiError { error: "device locked by WebSocket client".into() })).into_response();
     }
 
     let hid = state.hid.lock().await;
-    let transient = match ensure_device_open(&hid) {
+    let transient = match ensure_device_open(&hid, req.device.as_deref()).await {
         Ok(t) => t,
         Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, Json(ApiError { error: e })).into_response(),
     };
 
-    let result = hid.clear_alert(req.tab);
+    let sent = hid.clear_alert(req.device.as_deref(), req.tab);
+    let result = complete(hid, transient, sent).await;
 
-    if transient { hid.close_device(); }
+    if result.is_ok() {
+        alerts::cleared(&state, req.tab, req.device.as_deref(), AlertSource::Rest).await;
+    }
 
     match result {
         Ok(()) => StatusCode::OK.into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiError { error: e.to_string() })).into_response(),
+        Err(e) => hid_error(e),
     }
 }
 
+/// GET /api/alerts — alert history, newest first
+pub async fn get_alerts(State(state): State<Arc<DaemonState>>) -> Json<Vec<AlertRecord>> {
+    Json(state.alerts.lock().await.records().cloned().collect())
+}
+
 /// POST /api/brightness
 pub async fn post_brightness(
     State(state): State<Arc<DaemonState>>,
     Json(req): Json<BrightnessRequest>,
 ) -> impl IntoResponse {
-    if state.ws_client.lock().await.is_some() {
+    if state.ws_clients.lock().await.has_controller() {
         return (StatusCode::CONFLICT, Json(ApiError { error: "device locked by WebSocket client".into() })).into_response();
     }
 
     let hid = state.hid.lock().await;
-    let transient = match ensure_device_open(&hid) {
+    let transient = match ensure_device_open(&hid, req.device.as_deref()).await {
         Ok(t) => t,
         Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, Json(ApiError { error: e })).into_response(),
     };
 
-    let result = hid.set_brightness(req.level, req.save);
-
-    if transient { hid.close_device(); }
+    let sent = hid.set_brightness(req.device.as_deref(), req.level, req.save);
+    let result = complete(hid, transient, sent).await;
 
     match result {
         Ok(()) => StatusCode::OK.into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiError { error: e.to_string() })).into_response(),
+        Err(e) => hid_error(e),
     }
 }
 
@@ -154,41 +332,401 @@ pub async fn post_mode(
     State(state): State<Arc<DaemonState>>,
     Json(req): Json<SetModeRequest>,
 ) -> impl IntoResponse {
-    if state.ws_client.lock().await.is_some() {
+    if state.ws_clients.lock().await.has_controller() {
         return (StatusCode::CONFLICT, Json(ApiError { error: "device locked by WebSocket client".into() })).into_response();
     }
 
     let hid = state.hid.lock().await;
-    let transient = match ensure_device_open(&hid) {
+    let transient = match ensure_device_open(&hid, req.device.as_deref()).await {
         Ok(t) => t,
         Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, Json(ApiError { error: e })).into_response(),
     };
 
-    let result = hid.set_mode(req.mode);
-
-    if transient { hid.close_device(); }
+    let sent = hid.set_mode(req.device.as_deref(), req.mode);
+    let result = complete(hid, transient, sent).await;
 
     match result {
         Ok(()) => StatusCode::OK.into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiError { error: e.to_string() })).into_response(),
+        Err(e) => hid_error(e),
+    }
+}
+
+/// GET /api/version — reads through the controller's connection if one holds the lock.
+/// `?device=<serial>` picks the deck (default: the first connected one).
+pub async fn get_version(
+    State(state): State<Arc<DaemonState>>,
+    Query(query): Query<DeviceQuery>,
+) -> impl IntoResponse {
+    let hid = state.hid.lock().await;
+    // Without a serial, reading from an already-open deck is enough
+    let transient = if query.device.is_none() && hid.is_connected() {
+        Vec::new()
+    } else {
+        match ensure_device_open(&hid, query.device.as_deref()).await {
+            Ok(t) => t,
+            Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, Json(ApiError { error: e })).into_response(),
+        }
+    };
+
+    let queried = hid.query_version(query.device.as_deref());
+    let version = complete(hid, transient, queried).await.unwrap_or_else(|_| "unknown".to_string());
+
+    Json(serde_json::json!({ "version": version })).into_response()
+}
+
+/// GET /api/softkeys — reads through the controller's connection if one holds the lock.
+/// `?device=<serial>` picks the deck (default: the first connected one).
+pub async fn get_softkeys(
+    State(state): State<Arc<DaemonState>>,
+    Query(query): Query<DeviceQuery>,
+) -> impl IntoResponse {
+    let hid = state.hid.lock().await;
+    let transient = if query.device.is_none() && hid.is_connected() {
+        Vec::new()
+    } else {
+        match ensure_device_open(&hid, query.device.as_deref()).await {
+            Ok(t) => t,
+            Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, Json(ApiError { error: e })).into_response(),
+        }
+    };
+
+    let count = hid.limits(query.device.as_deref()).soft_key_count;
+    let reads: Vec<_> = (0..count).map(|index| hid.get_soft_key(query.device.as_deref(), index)).collect();
+    let read_all = async move {
+        let mut keys = Vec::with_capacity(reads.len());
+        for read in reads {
+            keys.push(SoftKeyAssignment::from(&read.await?));
+        }
+        Ok(keys)
+    };
+
+    match complete(hid, transient, read_all).await {
+        Ok(keys) => Json(keys).into_response(),
+        Err(e) => hid_error(e),
     }
 }
 
-/// GET /api/version
-pub async fn get_version(State(state): State<Arc<DaemonState>>) -> impl IntoResponse {
-    if state.ws_client.lock().await.is_some() {
+/// PUT /api/softkeys/{index}
+pub async fn put_softkey(
+    State(state): State<Arc<DaemonState>>,
+    Path(index): Path<u8>,
+    Json(mut req): Json<SetSoftKeyRequest>,
+) -> impl IntoResponse {
+    if state.ws_clients.lock().await.has_controller() {
         return (StatusCode::CONFLICT, Json(ApiError { error: "device locked by WebSocket client".into() })).into_response();
     }
 
     let hid = state.hid.lock().await;
-    let transient = match ensure_device_open(&hid) {
+    let transient = match ensure_device_open(&hid, req.device.as_deref()).await {
         Ok(t) => t,
         Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, Json(ApiError { error: e })).into_response(),
     };
 
-    let version = hid.query_version();
+    let adjusted = match limits::check_soft_key(&hid.limits(req.device.as_deref()), index, &mut req.action) {
+        Ok(adjusted) => adjusted,
+        Err(e) => {
+            close_transient(&hid, transient);
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(e)).into_response();
+        }
+    };
 
-    if transient { hid.close_device(); }
+    let (key_type, data) = req.action.to_wire();
+    let sent = hid.set_soft_key(req.device.as_deref(), index, key_type, &data, req.save);
+    let result = complete(hid, transient, sent).await;
 
-    Json(serde_json::json!({ "version": version })).into_response()
+    match result {
+        Ok(()) => ok_adjusted(adjusted),
+        Err(e) => hid_error(e),
+    }
+}
+
+/// POST /api/softkeys/reset — returns the firmware defaults now in effect.
+/// `?device=<serial>` picks the deck (default: every deck).
+pub async fn post_softkeys_reset(
+    State(state): State<Arc<DaemonState>>,
+    Query(query): Query<DeviceQuery>,
+) -> impl IntoResponse {
+    if state.ws_clients.lock().await.has_controller() {
+        return (StatusCode::CONFLICT, Json(ApiError { error: "device locked by WebSocket client".into() })).into_response();
+    }
+
+    let hid = state.hid.lock().await;
+    let transient = match ensure_device_open(&hid, query.device.as_deref()).await {
+        Ok(t) => t,
+        Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, Json(ApiError { error: e })).into_response(),
+    };
+
+    let sent = hid.reset_soft_keys(query.device.as_deref());
+    let result = complete(hid, transient, sent).await;
+
+    match result {
+        Ok(keys) => Json(keys.iter().map(SoftKeyAssignment::from).collect::<Vec<_>>()).into_response(),
+        Err(e) => hid_error(e),
+    }
+}
+
+// ── Virtual deck (--virtual-device only) ───────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct VirtualKeyRequest {
+    pub keycode: u16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VirtualTypeRequest {
+    pub text: String,
+    #[serde(default)]
+    pub send_enter: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VirtualPresentRequest {
+    pub present: bool,
+}
+
+fn virtual_deck(state: &DaemonState) -> Result<&VirtualDeck, (StatusCode, Json<ApiError>)> {
+    state.virtual_deck.as_ref().ok_or_else(|| {
+        (StatusCode::NOT_FOUND, Json(ApiError { error: "virtual device not enabled".into() }))
+    })
+}
+
+/// GET /api/virtual — what the virtual deck is currently showing
+pub async fn get_virtual(State(state): State<Arc<DaemonState>>) -> impl IntoResponse {
+    match virtual_deck(&state) {
+        Ok(deck) => Json(deck.snapshot()).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// POST /api/virtual/key — simulate a key press on the deck
+pub async fn post_virtual_key(
+    State(state): State<Arc<DaemonState>>,
+    Json(req): Json<VirtualKeyRequest>,
+) -> impl IntoResponse {
+    match virtual_deck(&state) {
+        Ok(deck) => {
+            deck.inject_key_event(req.keycode);
+            StatusCode::OK.into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// POST /api/virtual/type — simulate a type-string request from the deck
+pub async fn post_virtual_type(
+    State(state): State<Arc<DaemonState>>,
+    Json(req): Json<VirtualTypeRequest>,
+) -> impl IntoResponse {
+    match virtual_deck(&state) {
+        Ok(deck) => {
+            deck.inject_type_string(&req.text, req.send_enter);
+            StatusCode::OK.into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// POST /api/virtual/state — simulate a mode/YOLO change on the deck
+pub async fn post_virtual_state(
+    State(state): State<Arc<DaemonState>>,
+    Json(req): Json<DeviceState>,
+) -> impl IntoResponse {
+    match virtual_deck(&state) {
+        Ok(deck) => {
+            deck.inject_state(req);
+            StatusCode::OK.into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// POST /api/virtual/present — simulate plugging/unplugging the deck
+pub async fn post_virtual_present(
+    State(state): State<Arc<DaemonState>>,
+    Json(req): Json<VirtualPresentRequest>,
+) -> impl IntoResponse {
+    match virtual_deck(&state) {
+        Ok(deck) => {
+            deck.set_present(req.present);
+            StatusCode::OK.into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::LocalPeer;
+    use crate::state::DaemonEvent;
+    use axum::body::Body;
+    use axum::http::Request;
+    use coredeck_protocol::WsClientRole;
+    use futures_util::StreamExt;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    /// Send a request as a Unix socket client would (no token needed)
+    async fn request(state: &Arc<DaemonState>, method: &str, uri: &str, body: Option<Value>) -> Response {
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap();
+        crate::router(state).layer(axum::Extension(LocalPeer)).oneshot(req).await.unwrap()
+    }
+
+    /// Status and JSON body (`Null` when empty)
+    async fn call(state: &Arc<DaemonState>, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let response = request(state, method, uri, body).await;
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json = if bytes.is_empty() { Value::Null } else { serde_json::from_slice(&bytes).unwrap() };
+        (status, json)
+    }
+
+    /// Name and data of the next SSE event from a streaming body
+    async fn next_sse(body: &mut (impl Stream<Item = Result<axum::body::Bytes, axum::Error>> + Unpin)) -> (String, Value) {
+        let mut text = String::new();
+        while !text.ends_with("\n\n") {
+            let chunk = tokio::time::timeout(Duration::from_secs(2), body.next()).await.unwrap().unwrap().unwrap();
+            text.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+        let lines: Vec<&str> = text.trim_end().lines().collect();
+        let [event, data] = lines[..] else { panic!("unexpected SSE framing: {:?}", text) };
+        let name = event.strip_prefix("event: ").expect("event line");
+        let data = data.strip_prefix("data: ").expect("data line");
+        (name.to_string(), serde_json::from_str(data).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_events_stream_framing() {
+        let state = crate::tests::test_state();
+        let response = request(&state, "GET", "/api/events", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+        let mut body = response.into_body().into_data_stream();
+
+        state.events_tx.send(DaemonEvent::HidKeyEvent { serial: "A1".into(), keycode: 0x29 }).unwrap();
+        let (name, data) = next_sse(&mut body).await;
+        assert_eq!(name, "hid_key_event");
+        assert_eq!(data, json!({ "type": "hid_key_event", "serial": "A1", "keycode": 0x29 }));
+    }
+
+    #[tokio::test]
+    async fn test_events_stream_reports_lag() {
+        let state = crate::tests::test_state();
+        let response = request(&state, "GET", "/api/events", None).await;
+        let mut body = response.into_body().into_data_stream();
+
+        // Overflow the subscriber's buffer before it reads anything
+        for keycode in 0..crate::EVENT_STREAM_CAPACITY as u16 + 5 {
+            state.events_tx.send(DaemonEvent::HidKeyEvent { serial: "A1".into(), keycode }).unwrap();
+        }
+        assert_eq!(next_sse(&mut body).await, ("lagged".to_string(), json!({ "skipped": 5 })));
+        // ...and carries on with the oldest event still buffered
+        assert_eq!(next_sse(&mut body).await.1["keycode"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_softkeys_round_trip() {
+        let state = crate::tests::test_state();
+        let (status, keys) = call(&state, "GET", "/api/softkeys", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(keys, json!([{ "index": 0, "type": "default" }, { "index": 1, "type": "default" }, { "index": 2, "type": "default" }]));
+
+        let action = json!({ "type": "string", "text": "ls -la", "send_enter": true });
+        let (status, _) = call(&state, "PUT", "/api/softkeys/1", Some(action)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, keys) = call(&state, "GET", "/api/softkeys", None).await;
+        assert_eq!(keys[1], json!({ "index": 1, "type": "string", "text": "ls -la", "send_enter": true }));
+
+        let (status, keys) = call(&state, "POST", "/api/softkeys/reset", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(keys.as_array().unwrap().len(), 3);
+        assert!(keys.as_array().unwrap().iter().all(|key| key["type"] == "default"), "{}", keys);
+        let (_, keys) = call(&state, "GET", "/api/softkeys", None).await;
+        assert_eq!(keys[1]["type"], "default");
+    }
+
+    #[tokio::test]
+    async fn test_softkeys_rejected() {
+        let state = crate::tests::test_state();
+        let action = json!({ "type": "keycode", "key": { "usage": 4 } });
+        let (status, error) = call(&state, "PUT", "/api/softkeys/3", Some(action.clone())).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error["fields"][0]["field"], "index", "{}", error);
+
+        // Writes conflict with a controller; reads go through its connection
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        state.ws_clients.lock().await.register(tx, WsClientRole::Controller);
+        let (status, _) = call(&state, "PUT", "/api/softkeys/0", Some(action)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = call(&state, "POST", "/api/softkeys/reset", None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(call(&state, "GET", "/api/softkeys", None).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_display_layers() {
+        let state = crate::tests::test_state();
+        let update = json!({ "session": "build", "task": "cargo test", "tabs": [2, 1], "active": 0 });
+        let (status, _) = call(&state, "POST", "/api/display", Some(update)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, deck) = call(&state, "GET", "/api/virtual", None).await;
+        assert_eq!(deck["display"]["session"], "build", "{}", deck);
+        assert_eq!(deck["display"]["tabs"], json!([2, 1]));
+
+        let (status, error) = call(&state, "POST", "/api/display", Some(json!({ "session": "x", "tabs": vec![0u8; 64] }))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error["fields"][0]["field"], "tabs", "{}", error);
+
+        let (status, _) = call(&state, "POST", "/api/display/clear", Some(json!({ "source": "hook" }))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = call(&state, "POST", "/api/display/clear", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(state.display.lock().await.current("virtual-1").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_alert_history() {
+        let state = crate::tests::test_state();
+        let alert = json!({ "tab": 1, "session": "build", "text": "Tests failed", "details": null });
+        let (status, _) = call(&state, "POST", "/api/alert", Some(alert)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, deck) = call(&state, "GET", "/api/virtual", None).await;
+        assert_eq!(deck["alerts"]["1"]["text"], "Tests failed", "{}", deck);
+
+        let (status, _) = call(&state, "POST", "/api/alert/clear", Some(json!({ "tab": 1 }))).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, deck) = call(&state, "GET", "/api/virtual", None).await;
+        assert_eq!(deck["alerts"], json!({}));
+
+        let (status, records) = call(&state, "GET", "/api/alerts", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(records.as_array().unwrap().len(), 1, "{}", records);
+        assert_eq!(records[0]["text"], "Tests failed");
+        assert_eq!(records[0]["raised_by"], "rest");
+        assert_eq!(records[0]["cleared_by"], "rest");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_counts_writes() {
+        let state = crate::tests::test_state();
+        let (status, _) = call(&state, "POST", "/api/display", Some(json!({ "session": "build" }))).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let response = request(&state, "GET", "/api/metrics", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain"));
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        let sent = text
+            .lines()
+            .find_map(|line| line.strip_prefix("coredeck_hid_packets_sent_total "))
+            .expect("packets sent counter");
+        assert!(sent.parse::<u64>().unwrap() > 0, "{}", text);
+    }
 }
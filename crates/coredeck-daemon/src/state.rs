@@ -1,28 +1,37 @@
 //! Daemon shared state
 
-use coredeck_protocol::DeviceMode;
+use coredeck_protocol::{DeviceCapabilities, DeviceMode};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use tokio::sync::mpsc;
 
-/// Events emitted by the HID subsystem to the daemon core
-#[derive(Debug, Clone)]
+/// Events emitted by the HID subsystem to the daemon core.
+///
+/// Every event names the deck it concerns by `serial`.
+/// Serialized as `{"type": "<snake_case variant>", ...fields}` for `GET /api/events`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum DaemonEvent {
     /// HID device connected (interface opened, communicating)
     HidConnected {
+        serial: String,
         device_name: String,
         firmware_version: String,
+        /// `None` when the firmware predates `GetCapabilities`
+        capabilities: Option<DeviceCapabilities>,
     },
     /// HID device disconnected (interface closed or lost)
-    HidDisconnected,
+    HidDisconnected { serial: String },
     /// USB device physically available (enumerated, not opened)
-    DeviceAvailable { device_name: String },
+    DeviceAvailable { serial: String, device_name: String },
     /// USB device physically removed
-    DeviceUnavailable,
+    DeviceUnavailable { serial: String },
     /// Device state changed (mode button / YOLO switch)
-    DeviceStateChanged { mode: DeviceMode, yolo: bool },
+    DeviceStateChanged { serial: String, mode: DeviceMode, yolo: bool },
     /// Single key event from device
-    HidKeyEvent { keycode: u16 },
+    HidKeyEvent { serial: String, keycode: u16 },
     /// Type string from device
-    HidTypeString { text: String, send_enter: bool },
+    HidTypeString { serial: String, text: String, send_enter: bool },
 }
 
 /// Sender for daemon events — wraps a tokio unbounded channel.
@@ -42,15 +51,14 @@ impl DaemonEventSender {
     }
 }
 
-/// Current device status (shared across daemon subsystems)
+/// Status of one plugged-in deck (shared across daemon subsystems)
 #[derive(Debug, Clone, Default)]
 pub struct DeviceStatus {
-    /// Device physically present (USB enumerated)
-    pub available: bool,
     /// HID interface open and communicating
     pub connected: bool,
-    pub device_name: Option<String>,
+    pub device_name: String,
     pub firmware_version: Option<String>,
+    pub capabilities: Option<DeviceCapabilities>,
     pub mode: DeviceMode,
     pub yolo: bool,
 }
@@ -58,14 +66,81 @@ pub struct DeviceStatus {
 /// Tray updates sent from async code to the main thread
 #[derive(Debug, Clone)]
 pub enum TrayUpdate {
-    /// HID interface opened and communicating
+    /// At least one HID interface open and communicating
     DeviceConnected(String),
-    /// HID interface closed or lost
-    DeviceDisconnected,
-    /// USB device physically available (plugged in, not opened)
+    /// Devices physically available (plugged in, none opened)
     DeviceAvailable(String),
-    /// USB device physically removed
+    /// No device plugged in
     DeviceUnavailable,
     AppConnected,
     AppDisconnected,
 }
+
+impl TrayUpdate {
+    /// Tray state for a set of decks: connected if any deck is open,
+    /// available if any is plugged in
+    pub fn for_devices(devices: &BTreeMap<String, DeviceStatus>) -> Self {
+        let label = |n: usize, first: &DeviceStatus| {
+            if n == 1 {
+                first.device_name.clone()
+            } else {
+                format!("{} decks", n)
+            }
+        };
+        let connected: Vec<&DeviceStatus> = devices.values().filter(|d| d.connected).collect();
+        if let Some(first) = connected.first() {
+            return TrayUpdate::DeviceConnected(label(connected.len(), first));
+        }
+        match devices.values().next() {
+            Some(first) => TrayUpdate::DeviceAvailable(label(devices.len(), first)),
+            None => TrayUpdate::DeviceUnavailable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daemon_event_json() {
+        let json = serde_json::to_value(DaemonEvent::HidKeyEvent {
+            serial: "A1".to_string(),
+            keycode: 0x04,
+        })
+        .unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "hid_key_event", "serial": "A1", "keycode": 4 }));
+
+        let json = serde_json::to_value(DaemonEvent::DeviceStateChanged {
+            serial: "A1".to_string(),
+            mode: DeviceMode::Plan,
+            yolo: true,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "type": "device_state_changed", "serial": "A1", "mode": "Plan", "yolo": true })
+        );
+
+        let json = serde_json::to_value(DaemonEvent::HidDisconnected { serial: "A1".to_string() }).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "hid_disconnected", "serial": "A1" }));
+    }
+
+    #[test]
+    fn test_tray_for_devices() {
+        let mut devices = BTreeMap::new();
+        assert!(matches!(TrayUpdate::for_devices(&devices), TrayUpdate::DeviceUnavailable));
+
+        let deck = DeviceStatus {
+            device_name: "Core Deck".to_string(),
+            ..DeviceStatus::default()
+        };
+        devices.insert("A".to_string(), deck.clone());
+        assert!(matches!(TrayUpdate::for_devices(&devices), TrayUpdate::DeviceAvailable(n) if n == "Core Deck"));
+
+        devices.insert("B".to_string(), DeviceStatus { connected: true, ..deck });
+        assert!(matches!(TrayUpdate::for_devices(&devices), TrayUpdate::DeviceConnected(n) if n == "Core Deck"));
+        devices.get_mut("A").unwrap().connected = true;
+        assert!(matches!(TrayUpdate::for_devices(&devices), TrayUpdate::DeviceConnected(n) if n == "2 decks"));
+    }
+}
@@ -0,0 +1,264 @@
+//! systemd `--user` service management (Linux)
+//!
+//! `install` writes a service unit plus a socket unit for the Unix socket, so
+//! the daemon can be started on demand when the app connects. Output goes to
+//! stderr and ends up in the journal (`journalctl --user -u coredeck-daemon`).
+
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+const SERVICE_NAME: &str = "coredeck-daemon.service";
+const SOCKET_NAME: &str = "coredeck-daemon.socket";
+
+/// First file descriptor passed by systemd socket activation (`SD_LISTEN_FDS_START`)
+const LISTEN_FDS_START: RawFd = 3;
+
+/// `~/.config/systemd/user` (or under `$XDG_CONFIG_HOME`)
+fn unit_dir() -> PathBuf {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            let home = std::env::var("HOME").expect("HOME not set");
+            PathBuf::from(home).join(".config")
+        });
+    config_home.join("systemd/user")
+}
+
+/// Escape `%` specifiers, which systemd expands in most unit settings
+fn escape_specifiers(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+/// One word of an `ExecStart=` command line. systemd splits on whitespace,
+/// honours quotes and expands `%` specifiers and `$VAR`, so those are escaped.
+fn exec_word(word: &str) -> String {
+    let escaped = escape_specifiers(word).replace('$', "$$");
+    let bare = !escaped.is_empty()
+        && escaped.chars().all(|c| c.is_ascii_alphanumeric() || "/-_.:=,+@%$".contains(c));
+    if bare {
+        escaped
+    } else {
+        format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+fn service_unit(exe: &str, args: &[String]) -> String {
+    let words: Vec<String> = std::iter::once(exe)
+        .chain(args.iter().map(String::as_str))
+        .map(exec_word)
+        .collect();
+    let exec_start = words.join(" ");
+    format!(
+        "[Unit]
+Description=CoreDeck daemon
+Requires={SOCKET_NAME}
+After={SOCKET_NAME}
+
+[Service]
+ExecStart={exec_start}
+Restart=on-failure
+Environment=RUST_LOG=info
+
+[Install]
+WantedBy=default.target
+Also={SOCKET_NAME}
+"
+    )
+}
+
+/// `socket` is `None` for the default `%t/coredeck/daemon.sock` (`%t` = `$XDG_RUNTIME_DIR`)
+fn socket_unit(socket: Option<&Path>) -> String {
+    let listen = socket
+        .map(|p| escape_specifiers(&p.display().to_string()))
+        .unwrap_or_else(|| format!("%t/{}", coredeck_protocol::DAEMON_SOCKET_NAME));
+    format!(
+        "[Unit]
+Description=CoreDeck daemon socket
+
+[Socket]
+ListenStream={listen}
+SocketMode=0600
+DirectoryMode=0700
+
+[Install]
+WantedBy=sockets.target
+"
+    )
+}
+
+fn systemctl(args: &[&str]) -> bool {
+    match Command::new("systemctl").arg("--user").args(args).status() {
+        Ok(status) => status.success(),
+        Err(e) => {
+            eprintln!("Failed to run systemctl: {}", e);
+            false
+        }
+    }
+}
+
+/// First line of `systemctl --user <query> <unit>` (e.g. `is-active`)
+fn systemctl_query(query: &str, unit: &str) -> String {
+    Command::new("systemctl")
+        .args(["--user", query, unit])
+        .output()
+        .ok()
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .and_then(|s| s.lines().next().map(str::to_string))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Write the service and socket units, then enable and start them
+pub fn install(listen: Option<&str>, config: Option<&Path>, socket: Option<&Path>) {
+    let exe = std::env::current_exe()
+        .expect("Failed to get current exe path")
+        .to_string_lossy()
+        .to_string();
+
+    // Only pin what was given on the command line; the rest comes from the config file
+    let mut args = Vec::new();
+    if let Some(listen) = listen {
+        args.extend(["--listen".to_string(), listen.to_string()]);
+    }
+    if let Some(config) = config {
+        args.extend(["--config".to_string(), config.display().to_string()]);
+    }
+
+    let dir = unit_dir();
+    std::fs::create_dir_all(&dir).expect("Failed to create systemd user unit dir");
+    let service_path = dir.join(SERVICE_NAME);
+    let socket_path = dir.join(SOCKET_NAME);
+    std::fs::write(&service_path, service_unit(&exe, &args)).expect("Failed to write service unit");
+    std::fs::write(&socket_path, socket_unit(socket)).expect("Failed to write socket unit");
+
+    if systemctl(&["daemon-reload"]) && systemctl(&["enable", "--now", SOCKET_NAME, SERVICE_NAME]) {
+        println!("Installed and started: {}", service_path.display());
+        println!("Logs: journalctl --user -u {}", SERVICE_NAME);
+    } else {
+        eprintln!("Units written to {} but systemctl failed", dir.display());
+    }
+}
+
+/// Stop, disable and remove the units
+pub fn uninstall() {
+    let dir = unit_dir();
+    let service_path = dir.join(SERVICE_NAME);
+    let socket_path = dir.join(SOCKET_NAME);
+
+    if !service_path.exists() && !socket_path.exists() {
+        println!("Service not installed: {}", service_path.display());
+        return;
+    }
+
+    systemctl(&["disable", "--now", SERVICE_NAME, SOCKET_NAME]);
+    for path in [&service_path, &socket_path] {
+        if path.exists() {
+            std::fs::remove_file(path).expect("Failed to remove unit file");
+        }
+    }
+    systemctl(&["daemon-reload"]);
+    println!("Uninstalled: {}", service_path.display());
+}
+
+/// Report whether the units are installed, enabled and running
+pub fn status() {
+    let service_path = unit_dir().join(SERVICE_NAME);
+    if !service_path.exists() {
+        println!("Not installed ({} missing)", service_path.display());
+        return;
+    }
+    println!("Installed: {}", service_path.display());
+    for unit in [SERVICE_NAME, SOCKET_NAME] {
+        println!(
+            "{}: {}, {}",
+            unit,
+            systemctl_query("is-enabled", unit),
+            systemctl_query("is-active", unit)
+        );
+    }
+}
+
+/// Number of sockets passed to this process by systemd, per `LISTEN_PID`/`LISTEN_FDS`
+fn listen_fds(pid: u32, listen_pid: Option<&str>, listen_fds: Option<&str>) -> usize {
+    match (listen_pid.and_then(|p| p.parse::<u32>().ok()), listen_fds) {
+        (Some(listen_pid), Some(fds)) if listen_pid == pid => fds.parse().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Read and clear the socket-activation variables, returning how many sockets
+/// systemd passed. Must run before any thread is spawned: changing the
+/// environment of a multi-threaded process is unsound.
+pub fn take_listen_fds() -> usize {
+    let count = listen_fds(
+        std::process::id(),
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+    );
+    // Don't pass the sockets on to anything we spawn
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+    count
+}
+
+/// Take over the Unix socket among the `count` handed to us by socket
+/// activation ([`take_listen_fds`]), if any
+pub fn activated_unix_listener(count: usize) -> Option<UnixListener> {
+    let mut found = None;
+    for fd in LISTEN_FDS_START..LISTEN_FDS_START + count as RawFd {
+        // SAFETY: systemd passes `count` open listening sockets starting at fd 3,
+        // and nothing else in the process has claimed them.
+        let listener = unsafe { UnixListener::from_raw_fd(fd) };
+        if found.is_none() && listener.local_addr().is_ok() {
+            info!("Using socket-activated listener (fd {})", fd);
+            found = Some(listener);
+        } else {
+            warn!("Ignoring socket-activated fd {} (not a Unix socket)", fd);
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_fds() {
+        assert_eq!(listen_fds(42, Some("42"), Some("1")), 1);
+        assert_eq!(listen_fds(42, Some("41"), Some("1")), 0);
+        assert_eq!(listen_fds(42, None, Some("1")), 0);
+        assert_eq!(listen_fds(42, Some("42"), Some("x")), 0);
+    }
+
+    #[test]
+    fn test_units() {
+        let service = service_unit("/usr/bin/coredeck-daemon", &["--listen".into(), "127.0.0.1:9000".into()]);
+        assert!(service.contains("ExecStart=/usr/bin/coredeck-daemon --listen 127.0.0.1:9000\n"));
+        assert!(service.contains("Also=coredeck-daemon.socket"));
+
+        assert!(socket_unit(None).contains("ListenStream=%t/coredeck/daemon.sock\n"));
+        let custom = socket_unit(Some(Path::new("/run/deck.sock")));
+        assert!(custom.contains("ListenStream=/run/deck.sock\n"));
+        assert!(socket_unit(Some(Path::new("/run/100%.sock"))).contains("ListenStream=/run/100%%.sock\n"));
+    }
+
+    #[test]
+    fn test_exec_start_quoting() {
+        let args = ["--config".to_string(), "/home/me/My Deck/100% $HOME \"x\".toml".to_string()];
+        let service = service_unit("/opt/Core Deck/coredeck-daemon", &args);
+        assert!(
+            service.contains(
+                "ExecStart=\"/opt/Core Deck/coredeck-daemon\" --config \"/home/me/My Deck/100%% $$HOME \\\"x\\\".toml\"\n"
+            ),
+            "{}",
+            service
+        );
+        assert_eq!(exec_word(""), "\"\"");
+        assert_eq!(exec_word("a\\b"), "\"a\\\\b\"");
+    }
+}
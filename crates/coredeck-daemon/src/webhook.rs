@@ -0,0 +1,344 @@
+//! Outbound webhooks — daemon events and alert changes POSTed as JSON
+//!
+//! Each `[[webhooks]]` entry in `daemon.toml` selects event types by the
+//! `type` field of the body: the `GET /api/events` names plus `alert_raised`
+//! and `alert_cleared`. Failed deliveries are retried with exponential
+//! backoff; with a `secret`, the body is signed in `X-CoreDeck-Signature`.
+//!
+//! URLs may be `http://` (local services such as home automation or scripts
+//! behind a small HTTP server) or `https://` (hosted chat tools, which only
+//! accept HTTPS). TLS uses rustls with the Mozilla root certificates, so no
+//! system OpenSSL is needed.
+
+use anyhow::{anyhow, bail, Context, Result};
+use coredeck_protocol::AlertRecord;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::DaemonState;
+use crate::config::WebhookConfig;
+
+/// How long one delivery may take, connect to response
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait before the first retry; doubled for each one after
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between retries
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Event types a webhook can select
+pub const WEBHOOK_EVENTS: &[&str] = &[
+    "hid_connected",
+    "hid_disconnected",
+    "device_available",
+    "device_unavailable",
+    "device_state_changed",
+    "hid_key_event",
+    "hid_type_string",
+    "alert_raised",
+    "alert_cleared",
+];
+
+/// Alert history changes, serialized like `DaemonEvent`s
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertEvent {
+    AlertRaised { alert: AlertRecord },
+    /// Also sent for alerts replaced by a new one on the same tab
+    AlertCleared { alert: AlertRecord },
+}
+
+/// Send `event` to every webhook that selected its type
+pub fn notify(state: &DaemonState, event: &impl Serialize) {
+    let body = match serde_json::to_value(event) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Failed to serialize webhook event: {}", e);
+            return;
+        }
+    };
+    let kind = body["type"].as_str().unwrap_or_default().to_string();
+    let webhooks: Vec<WebhookConfig> =
+        state.config.read().webhooks.iter().filter(|w| w.wants(&kind)).cloned().collect();
+    if webhooks.is_empty() {
+        return;
+    }
+
+    let body = body.to_string();
+    for webhook in webhooks {
+        let delivery = match Delivery::new(&webhook, &kind, body.clone()) {
+            Ok(delivery) => delivery,
+            Err(e) => {
+                warn!("Skipping webhook {}: {:#}", webhook.url, e);
+                continue;
+            }
+        };
+        let kind = kind.clone();
+        tokio::spawn(async move {
+            if let Err(e) = delivery.send().await {
+                warn!("Webhook {} dropped {} event: {:#}", webhook.url, kind, e);
+            }
+        });
+    }
+}
+
+/// One event for one webhook, with its retry budget
+struct Delivery {
+    url: WebhookUrl,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Delivery {
+    fn new(webhook: &WebhookConfig, kind: &str, body: String) -> Result<Self> {
+        let mut headers = vec![("X-CoreDeck-Event", kind.to_string())];
+        if let Some(secret) = &webhook.secret {
+            headers.push(("X-CoreDeck-Signature", signature(secret, &body)));
+        }
+        Ok(Self {
+            url: WebhookUrl::parse(&webhook.url)?,
+            headers,
+            body,
+            retries: webhook.retries,
+            backoff: RETRY_BACKOFF,
+        })
+    }
+
+    /// Deliver until the webhook accepts the event, refuses it with a client
+    /// error, or the retries run out.
+    async fn send(mut self) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            let error = match post_json(&self.url, &self.body, &self.headers).await {
+                Ok(status) if (200..300).contains(&status) => return Ok(()),
+                // Retrying won't change the answer, except for timeouts and rate limits
+                Ok(status) if (400..500).contains(&status) && status != 408 && status != 429 => {
+                    bail!("rejected with status {}", status)
+                }
+                Ok(status) => anyhow!("answered {}", status),
+                Err(e) => e,
+            };
+            if attempt == self.retries {
+                return Err(error.context(format!("giving up after {} attempts", attempt + 1)));
+            }
+            attempt += 1;
+            debug!("Webhook {} failed ({:#}), retry {} in {:?}", self.url, error, attempt, self.backoff);
+            tokio::time::sleep(self.backoff).await;
+            self.backoff = (self.backoff * 2).min(MAX_RETRY_BACKOFF);
+        }
+    }
+}
+
+/// `sha256=<hex>` HMAC of the body, for `X-CoreDeck-Signature`
+fn signature(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    let hex: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+/// An `http://` or `https://` URL with a host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookUrl(reqwest::Url);
+
+impl WebhookUrl {
+    pub fn parse(url: &str) -> Result<Self> {
+        let parsed = reqwest::Url::parse(url).with_context(|| format!("invalid webhook URL '{}'", url))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            bail!("webhook URL must start with http:// or https:// (got '{}')", url);
+        }
+        if parsed.host_str().map_or(true, str::is_empty) {
+            bail!("webhook URL has no host: '{}'", url);
+        }
+        Ok(Self(parsed))
+    }
+}
+
+impl std::fmt::Display for WebhookUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// HTTP client shared by every delivery, so connections and TLS sessions are reused
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("coredeck-daemon/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("TLS backend failed to initialize")
+    })
+}
+
+/// POST a JSON body; returns the response status. Any status is a successful
+/// exchange; it is up to the caller to treat non-2xx as failure.
+pub async fn post_json(url: &WebhookUrl, body: &str, headers: &[(&str, String)]) -> Result<u16> {
+    let mut request = client()
+        .post(url.0.clone())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string());
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    match request.send().await {
+        Ok(response) => Ok(response.status().as_u16()),
+        Err(e) if e.is_timeout() => bail!("no response from {} within {:?}", url, REQUEST_TIMEOUT),
+        Err(e) => Err(anyhow::Error::new(e).context(format!("Failed to reach {}", url))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let url = WebhookUrl::parse("http://localhost:8123/api/webhook/deck").unwrap();
+        assert_eq!(url.to_string(), "http://localhost:8123/api/webhook/deck");
+        assert_eq!(WebhookUrl::parse("http://[::1]:9000/hook").unwrap().to_string(), "http://[::1]:9000/hook");
+        assert_eq!(WebhookUrl::parse("https://example.com").unwrap().to_string(), "https://example.com/");
+
+        assert!(WebhookUrl::parse("ftp://example.com/hook").is_err());
+        assert!(WebhookUrl::parse("http://").is_err());
+        assert!(WebhookUrl::parse("example.com/hook").is_err());
+    }
+
+    /// Answer one request per status in `statuses`; yields the requests received
+    async fn serve(statuses: &[u16]) -> (WebhookUrl, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = WebhookUrl::parse(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+        let statuses = statuses.to_vec();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !String::from_utf8_lossy(&request).ends_with('}') {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let response = format!("HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+                requests.push(String::from_utf8(request).unwrap());
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn delivery(url: WebhookUrl, retries: u32) -> Delivery {
+        Delivery {
+            url,
+            headers: vec![("X-CoreDeck-Event", "alert_raised".to_string())],
+            body: "{\"a\":1}".to_string(),
+            retries,
+            backoff: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_post_json() {
+        let (url, server) = serve(&[204]).await;
+        let status = post_json(&url, "{\"a\":1}", &[("X-Test", "yes".to_string())]).await.unwrap();
+        assert_eq!(status, 204);
+        let request = server.await.unwrap().remove(0);
+        assert!(request.starts_with("POST /hook HTTP/1.1\r\n"), "{}", request);
+        assert!(request.contains("content-type: application/json\r\n"), "{}", request);
+        assert!(request.contains("content-length: 7\r\n"), "{}", request);
+        assert!(request.contains("x-test: yes\r\n"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn test_https_uses_tls() {
+        use tokio::io::AsyncReadExt;
+
+        // A plain TCP listener sees the TLS handshake, which then fails
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = WebhookUrl::parse(&format!("https://{}/hook", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.read_u8().await.unwrap()
+        });
+        let err = post_json(&url, "{}", &[]).await.unwrap_err();
+        assert!(err.to_string().starts_with("Failed to reach https://"), "{:#}", err);
+        assert_eq!(server.await.unwrap(), 0x16); // TLS handshake record
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_accepted() {
+        let (url, server) = serve(&[503, 429, 200]).await;
+        delivery(url, 3).send().await.unwrap();
+        assert_eq!(server.await.unwrap().len(), 3);
+
+        let (url, server) = serve(&[500, 500]).await;
+        let err = delivery(url, 1).send().await.unwrap_err();
+        assert_eq!(format!("{:#}", err), "giving up after 2 attempts: answered 500");
+        assert_eq!(server.await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_client_error_not_retried() {
+        let (url, server) = serve(&[404]).await;
+        let err = delivery(url, 3).send().await.unwrap_err();
+        assert_eq!(err.to_string(), "rejected with status 404");
+        assert_eq!(server.await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature("key", "The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn test_event_names() {
+        use crate::state::DaemonEvent;
+        use coredeck_protocol::{AlertSource, DeviceMode};
+
+        let serial = || "A1".to_string();
+        let alert = AlertRecord {
+            id: 1,
+            tab: 0,
+            session: "proj".to_string(),
+            text: "Approve?".to_string(),
+            details: None,
+            raised_at: 100,
+            raised_by: AlertSource::App,
+            cleared_at: None,
+            cleared_by: None,
+            device: None,
+        };
+        let events = [
+            serde_json::to_value(DaemonEvent::HidConnected {
+                serial: serial(),
+                device_name: "Deck".to_string(),
+                firmware_version: "1.0".to_string(),
+                capabilities: None,
+            }),
+            serde_json::to_value(DaemonEvent::HidDisconnected { serial: serial() }),
+            serde_json::to_value(DaemonEvent::DeviceAvailable { serial: serial(), device_name: "Deck".to_string() }),
+            serde_json::to_value(DaemonEvent::DeviceUnavailable { serial: serial() }),
+            serde_json::to_value(DaemonEvent::DeviceStateChanged { serial: serial(), mode: DeviceMode::Plan, yolo: true }),
+            serde_json::to_value(DaemonEvent::HidKeyEvent { serial: serial(), keycode: 4 }),
+            serde_json::to_value(DaemonEvent::HidTypeString { serial: serial(), text: "ls".to_string(), send_enter: true }),
+            serde_json::to_value(AlertEvent::AlertRaised { alert: alert.clone() }),
+            serde_json::to_value(AlertEvent::AlertCleared { alert }),
+        ];
+        let names: Vec<String> = events.iter().map(|e| e.as_ref().unwrap()["type"].as_str().unwrap().to_string()).collect();
+        assert_eq!(names, WEBHOOK_EVENTS);
+    }
+}
@@ -1,144 +1,394 @@
-//! WebSocket server — exclusive connection lock and bidirectional message relay
+//! WebSocket server — controller/observer arbitration and bidirectional message relay
 //!
-//! Only one WebSocket client (the app) may be connected at a time.
-//! While a WS client holds the lock, HTTP mutating endpoints return 409.
+//! One WebSocket client (normally the app) is the controller: it owns the
+//! display and alerts, and while it is connected HTTP mutating endpoints
+//! return 409. Any number of observers may connect alongside it to receive
+//! device events. Control is handed over with `YieldControl`/`RequestControl`.
 
 use coredeck_protocol::{
-    AppControlAction, DeviceInfo, DeviceMode, SoftKeyType, WsCommandTag,
-    WsEventTag, WsResponseTag, decode_ws_frame, encode_ws_frame,
+    AlertSource, AppControlAction, DaemonHello, DeviceInfo, PROTOCOL_VERSION, WsClientRole, WsCommand,
+    WsCommandResult, WsCommandTag, WsEvent, WsEventTag, WsFormat, WsJsonReply, WsJsonRequest, WsResponseTag,
+    decode_ws_frame, encode_ws_frame,
 };
 use axum::{
-    extract::{State, ws::{Message, WebSocket, WebSocketUpgrade}},
+    Extension,
+    extract::{Query, State, ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code}},
     response::IntoResponse,
 };
+use futures_util::future::{self, BoxFuture, FutureExt};
 use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tracing::{info, warn};
 
 use crate::DaemonState;
+use crate::actions;
+use crate::alerts;
+use crate::auth::Grant;
+use crate::display::{self, APP_SOURCE};
+use crate::hid::limits;
 use crate::state::{DaemonEvent, TrayUpdate};
 
-/// Handle for the connected WS client
+/// Handle for a connected WS client
 pub struct WsClientHandle {
     /// Send frames to the connected client
     pub tx: mpsc::UnboundedSender<Vec<u8>>,
+    /// Current role (changes on handover)
+    pub role: WsClientRole,
+}
+
+/// How the controller slot changed after a registry operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ControlChange {
+    /// Controller slot untouched
+    Unchanged,
+    /// A client took the empty controller slot
+    Acquired,
+    /// Control moved to another client (device stays open)
+    Handover,
+    /// No controller remains
+    Released,
+}
+
+/// Connected WS clients and who holds control
+#[derive(Default)]
+pub struct WsClients {
+    next_id: u64,
+    clients: HashMap<u64, WsClientHandle>,
+    controller: Option<u64>,
+    /// Observers waiting for control, oldest first
+    control_requests: VecDeque<u64>,
+}
+
+impl WsClients {
+    /// Whether a controller is connected (the lock is held)
+    pub fn has_controller(&self) -> bool {
+        self.controller.is_some()
+    }
+
+    /// Whether no client at all is connected
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// Number of connected observers
+    pub fn observer_count(&self) -> usize {
+        self.clients.len() - usize::from(self.controller.is_some())
+    }
+
+    /// Current role of a client, if it is still connected
+    fn role(&self, id: u64) -> Option<WsClientRole> {
+        self.clients.get(&id).map(|c| c.role)
+    }
+
+    /// Send a frame to the controller (if any)
+    fn send_to_controller(&self, frame: Vec<u8>) {
+        if let Some(client) = self.controller.and_then(|id| self.clients.get(&id)) {
+            let _ = client.tx.send(frame);
+        }
+    }
+
+    /// Send a frame to every connected client
+    pub fn broadcast(&self, frame: &[u8]) {
+        for client in self.clients.values() {
+            let _ = client.tx.send(frame.to_vec());
+        }
+    }
+
+    /// Register a new client. A controller request is downgraded to observer
+    /// when the slot is already taken. The client is told its role.
+    pub(crate) fn register(
+        &mut self,
+        tx: mpsc::UnboundedSender<Vec<u8>>,
+        requested: WsClientRole,
+    ) -> (u64, ControlChange) {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.clients.insert(id, WsClientHandle { tx, role: WsClientRole::Observer });
+
+        let change = if requested == WsClientRole::Controller && self.controller.is_none() {
+            self.controller = Some(id);
+            ControlChange::Acquired
+        } else {
+            ControlChange::Unchanged
+        };
+        let role = if change == ControlChange::Acquired {
+            WsClientRole::Controller
+        } else {
+            WsClientRole::Observer
+        };
+        self.set_role(id, role);
+        (id, change)
+    }
+
+    /// Remove a disconnected client, handing control to the next requester
+    /// if it was the controller.
+    fn unregister(&mut self, id: u64) -> ControlChange {
+        self.clients.remove(&id);
+        self.control_requests.retain(|&r| r != id);
+        if self.controller == Some(id) {
+            self.controller = None;
+            self.promote_next()
+        } else {
+            ControlChange::Unchanged
+        }
+    }
+
+    /// Observer asks for control. Granted at once if nobody holds it,
+    /// otherwise queued and the controller is notified.
+    fn request_control(&mut self, id: u64) -> ControlChange {
+        if self.controller == Some(id) || self.control_requests.contains(&id) {
+            return ControlChange::Unchanged;
+        }
+        if self.controller.is_none() {
+            self.controller = Some(id);
+            self.set_role(id, WsClientRole::Controller);
+            return ControlChange::Acquired;
+        }
+        self.control_requests.push_back(id);
+        self.send_to_controller(encode_ws_frame(WsEventTag::ControlRequested as u8, 0, &[]));
+        ControlChange::Unchanged
+    }
+
+    /// Controller gives up control to the oldest requester (if any)
+    fn yield_control(&mut self, id: u64) -> Result<ControlChange, String> {
+        if self.controller != Some(id) {
+            return Err("not the controller".to_string());
+        }
+        self.controller = None;
+        self.set_role(id, WsClientRole::Observer);
+        Ok(self.promote_next())
+    }
+
+    /// Fill the empty controller slot from the request queue
+    fn promote_next(&mut self) -> ControlChange {
+        while let Some(next) = self.control_requests.pop_front() {
+            if self.clients.contains_key(&next) {
+                self.controller = Some(next);
+                self.set_role(next, WsClientRole::Controller);
+                return ControlChange::Handover;
+            }
+        }
+        ControlChange::Released
+    }
+
+    /// Update a client's role and send it a RoleChanged event
+    fn set_role(&mut self, id: u64, role: WsClientRole) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.role = role;
+            let frame = encode_ws_frame(WsEventTag::RoleChanged as u8, 0, &[role as u8]);
+            let _ = client.tx.send(frame);
+        }
+    }
+}
+
+/// Query parameters for the WebSocket upgrade (`/ws?role=observer&format=json`)
+#[derive(Debug, Default, Deserialize)]
+pub struct WsConnectQuery {
+    #[serde(default)]
+    pub role: WsClientRole,
+    #[serde(default)]
+    pub format: WsFormat,
 }
 
 /// WebSocket upgrade handler
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
+    Query(query): Query<WsConnectQuery>,
     State(state): State<Arc<DaemonState>>,
+    Extension(grant): Extension<Grant>,
 ) -> impl IntoResponse {
-    // Check if a client already holds the lock
-    {
-        let guard = state.ws_client.lock().await;
-        if guard.is_some() {
-            return axum::http::StatusCode::CONFLICT.into_response();
+    if query.role == WsClientRole::Controller {
+        // Control opens the device; read-only tokens must connect as observers
+        if !grant.allows(WsCommandTag::RequestControl.required_scope()) {
+            return axum::http::StatusCode::FORBIDDEN.into_response();
         }
     }
 
-    ws.on_upgrade(move |socket| handle_ws_connection(socket, state))
+    ws.on_upgrade(move |socket| handle_ws_connection(socket, state, query, grant))
 }
 
-async fn handle_ws_connection(socket: WebSocket, state: Arc<DaemonState>) {
-    let (mut ws_tx, mut ws_rx) = socket.split();
+/// Side effects of the controller slot changing: the device is open while a
+/// controller is connected (and without one only for headless actions).
+async fn apply_control_change(state: &Arc<DaemonState>, change: ControlChange) {
+    // The previous controller's display content is stale either way
+    if matches!(change, ControlChange::Handover | ControlChange::Released) {
+        state.display.lock().await.clear(APP_SOURCE, None);
+    }
 
-    // Create channel for sending frames to the client
-    let (client_tx, mut client_rx) = mpsc::unbounded_channel::<Vec<u8>>();
+    match change {
+        ControlChange::Unchanged => return,
+        ControlChange::Handover => {
+            info!("WS control handed over");
+        }
+        ControlChange::Acquired => {
+            info!("WS controller connected (lock acquired)");
 
-    // Set the lock
-    {
-        let mut guard = state.ws_client.lock().await;
-        *guard = Some(WsClientHandle { tx: client_tx.clone() });
+            // Open every deck — keys now route through daemon → controller
+            let opened = {
+                let hid = state.hid.lock().await;
+                hid.is_device_available().then(|| hid.open_device(None))
+            };
+            if let Some(opened) = opened {
+                if let Err(e) = opened.await {
+                    warn!("Failed to open HID device: {}", e);
+                }
+            }
+
+            state.send_tray_update(TrayUpdate::AppConnected);
+        }
+        ControlChange::Released => {
+            info!("WS controller gone (lock released)");
+            state.send_tray_update(TrayUpdate::AppDisconnected);
+
+            // Headless actions take over the open decks; otherwise release keys back to system
+            if actions::keep_decks_open(state) {
+                info!("Decks stay open for headless actions");
+            } else {
+                release_decks(state).await;
+            }
+        }
     }
     state.notify_lock_change.notify_waiters();
-    info!("WS client connected (lock acquired)");
+}
 
-    // Open HID device — keys now route through daemon → app
-    {
+/// Close every deck and tell the remaining observers (close_device() is silent)
+pub async fn release_decks(state: &DaemonState) {
+    let (was_connected, closed) = {
         let hid = state.hid.lock().await;
-        if hid.is_device_available() && !hid.is_connected() {
-            if let Err(e) = hid.open_device() {
-                warn!("Failed to open HID device: {}", e);
-            }
-        }
+        (hid.connected_serials(), hid.close_device(None))
+    };
+    if let Err(e) = closed.await {
+        warn!("Failed to close HID devices: {}", e);
     }
 
-    // Notify tray via channel
-    state.send_tray_update(TrayUpdate::AppConnected);
+    let clients = state.ws_clients.lock().await;
+    for serial in was_connected {
+        clients.broadcast(&encode_ws_frame(WsEventTag::DeviceDisconnected as u8, 0, serial.as_bytes()));
+    }
+}
+
+async fn handle_ws_connection(
+    socket: WebSocket,
+    state: Arc<DaemonState>,
+    query: WsConnectQuery,
+    grant: Grant,
+) {
+    let (mut ws_tx, mut ws_rx) = socket.split();
+
+    // Create channels for sending binary frames and JSON replies to the client
+    let (client_tx, mut client_rx) = mpsc::unbounded_channel::<Vec<u8>>();
+    let (text_tx, mut text_rx) = mpsc::unbounded_channel::<String>();
+
+    let (client_id, change) = state.ws_clients.lock().await.register(client_tx.clone(), query.role);
+    info!("WS client {} connected", client_id);
+    apply_control_change(&state, change).await;
 
     // Send current device status directly from HidManager (avoids race with event handler)
-    {
-        let hid = state.hid.lock().await;
-        if hid.is_connected() {
-            let name = hid.cached_device_name().unwrap_or_default();
-            let fw = hid.query_version();
-            let info = DeviceInfo {
-                name,
-                firmware: fw,
-            };
-            let payload = serde_json::to_vec(&info).unwrap_or_default();
-            let frame = encode_ws_frame(WsEventTag::DeviceConnected as u8, 0, &payload);
-            let _ = client_tx.send(frame);
-
-            // Also send current state
-            let status = state.device_status.read().await;
-            let state_byte = coredeck_protocol::DeviceState {
-                mode: status.mode,
-                yolo: status.yolo,
-            }
-            .to_byte();
-            let frame = encode_ws_frame(WsEventTag::StateChanged as u8, 0, &[state_byte]);
-            let _ = client_tx.send(frame);
-        }
+    for info in connected_devices(&state).await {
+        let serial = info.serial.clone();
+        let _ = client_tx.send(WsEvent::DeviceConnected(info).encode());
+
+        // Also send current state
+        let devices = state.devices.read().await;
+        let status = devices.get(&serial).cloned().unwrap_or_default();
+        let event = WsEvent::StateChanged {
+            mode: status.mode,
+            yolo: status.yolo,
+            serial: Some(serial),
+        };
+        let _ = client_tx.send(event.encode());
     }
 
     // Spawn writer task
+    let format = query.format;
+    let mut shutdown = state.shutdown.subscribe();
     let writer = tokio::spawn(async move {
-        while let Some(frame) = client_rx.recv().await {
-            if ws_tx.send(Message::Binary(frame.into())).await.is_err() {
+        let closing = async move {
+            let _ = shutdown.wait_for(|stop| *stop).await;
+        };
+        tokio::pin!(closing);
+        loop {
+            let msg = tokio::select! {
+                _ = &mut closing => {
+                    let frame = CloseFrame { code: close_code::AWAY, reason: "daemon shutting down".into() };
+                    let _ = ws_tx.send(Message::Close(Some(frame))).await;
+                    break;
+                }
+                Some(frame) = client_rx.recv() => match format {
+                    WsFormat::Json => json_event(&frame)
+                        .map(|text| Message::Text(text.into()))
+                        .unwrap_or_else(|| Message::Binary(frame.into())),
+                    WsFormat::Binary => Message::Binary(frame.into()),
+                },
+                Some(text) = text_rx.recv() => Message::Text(text.into()),
+                else => break,
+            };
+            if ws_tx.send(msg).await.is_err() {
                 break;
             }
         }
     });
 
-    // Read loop — process incoming commands from the app
+    // Read loop — process incoming commands from the client
     while let Some(msg) = ws_rx.next().await {
         match msg {
             Ok(Message::Binary(data)) => {
-                handle_ws_command(&data, &state, &client_tx).await;
+                handle_ws_command(&data, &state, client_id, &grant, &client_tx).await;
+            }
+            Ok(Message::Text(text)) => {
+                handle_ws_text(&text, &state, client_id, &grant, &text_tx).await;
             }
             Ok(Message::Close(_)) => break,
             Err(e) => {
                 warn!("WS read error: {}", e);
                 break;
             }
-            _ => {} // Ignore text/ping/pong
+            _ => {} // Ignore ping/pong
         }
     }
 
-    // Connection closed — release lock
+    // Connection closed — unregister and hand over control if we held it
     writer.abort();
-    {
-        let mut guard = state.ws_client.lock().await;
-        *guard = None;
-    }
-    state.notify_lock_change.notify_waiters();
-    state.send_tray_update(TrayUpdate::AppDisconnected);
-    info!("WS client disconnected (lock released)");
+    let change = state.ws_clients.lock().await.unregister(client_id);
+    info!("WS client {} disconnected", client_id);
+    apply_control_change(&state, change).await;
+}
 
-    // Close HID device — release keys back to system
-    {
+/// Name and firmware of every open deck
+async fn connected_devices(state: &DaemonState) -> Vec<DeviceInfo> {
+    let queried: Vec<_> = {
         let hid = state.hid.lock().await;
-        hid.close_device();
+        let connected = hid.connected_serials();
+        hid.devices()
+            .into_iter()
+            .filter(|d| connected.contains(&d.serial))
+            .map(|listing| (hid.query_version(Some(&listing.serial)), hid.capabilities(&listing.serial), listing))
+            .collect()
+    };
+
+    let mut devices = Vec::new();
+    for (version, capabilities, listing) in queried {
+        devices.push(DeviceInfo {
+            firmware: version.await.unwrap_or_else(|_| "unknown".to_string()),
+            capabilities,
+            name: listing.name,
+            serial: listing.serial,
+        });
     }
+    devices
 }
 
-/// Process a single WS binary command from the app
+/// Process a single WS binary command from a client
 async fn handle_ws_command(
     data: &[u8],
     state: &Arc<DaemonState>,
+    client_id: u64,
+    grant: &Grant,
     reply_tx: &mpsc::UnboundedSender<Vec<u8>>,
 ) {
     let (tag, seq, payload) = match decode_ws_frame(data) {
@@ -146,212 +396,415 @@ async fn handle_ws_command(
         None => return,
     };
 
-    let cmd = match WsCommandTag::from_byte(tag) {
-        Some(c) => c,
-        None => {
-            let err = encode_ws_frame(
-                WsResponseTag::CommandError as u8,
-                seq,
-                b"unknown command",
-            );
-            let _ = reply_tx.send(err);
-            return;
-        }
+    let result = match WsCommandTag::from_byte(tag) {
+        Some(tag) => match WsCommand::decode(tag, payload) {
+            Ok(cmd) => run_command(cmd, state, client_id, grant).await,
+            Err(e) => Err(e),
+        },
+        None => Err("unknown command".to_string()),
     };
 
-    let hid = state.hid.lock().await;
+    let frame = match result {
+        Ok(result) => result.encode(seq),
+        Err(e) => encode_ws_frame(WsResponseTag::CommandError as u8, seq, e.as_bytes()),
+    };
+    let _ = reply_tx.send(frame);
+}
 
-    let result: Result<Option<Vec<u8>>, String> = match cmd {
-        WsCommandTag::UpdateDisplay => {
-            match serde_json::from_slice::<coredeck_protocol::DisplayUpdate>(payload) {
-                Ok(update) => {
-                    hid.send_display_update(
-                        &update.session,
-                        Some(update.task.as_str()).filter(|s| !s.is_empty()),
-                        Some(update.task2.as_str()).filter(|s| !s.is_empty()),
-                        &update.tabs,
-                        update.active,
-                    )
-                        .map(|_| None)
-                        .map_err(|e| e.to_string())
-                }
-                Err(e) => Err(format!("invalid JSON: {}", e)),
+/// Process a JSON command from a WS text frame
+async fn handle_ws_text(
+    text: &str,
+    state: &Arc<DaemonState>,
+    client_id: u64,
+    grant: &Grant,
+    reply_tx: &mpsc::UnboundedSender<String>,
+) {
+    // Pick out the id first so even a malformed command gets a matching reply
+    let value = serde_json::from_str::<serde_json::Value>(text).map_err(|e| format!("invalid JSON: {}", e));
+    let id = value.as_ref().ok().and_then(|v| v.get("id")).and_then(serde_json::Value::as_u64);
+
+    let request = value.and_then(|v| {
+        serde_json::from_value::<WsJsonRequest>(v).map_err(|e| format!("invalid command: {}", e))
+    });
+    let result = match request {
+        Ok(req) => run_command(req.command, state, client_id, grant).await,
+        Err(e) => Err(e),
+    };
+
+    let reply = WsJsonReply::new(id, result);
+    let _ = reply_tx.send(serde_json::to_string(&reply).unwrap_or_default());
+}
+
+/// Check a client may send a command, then carry it out
+async fn run_command(
+    cmd: WsCommand,
+    state: &Arc<DaemonState>,
+    client_id: u64,
+    grant: &Grant,
+) -> Result<WsCommandResult, String> {
+    let tag = cmd.tag();
+    let scope = tag.required_scope();
+    if !grant.allows(scope) {
+        return Err(format!("token lacks the {} scope", scope.as_str()));
+    }
+
+    // Handover commands only touch the client registry
+    if matches!(tag, WsCommandTag::YieldControl | WsCommandTag::RequestControl) {
+        let change = {
+            let mut clients = state.ws_clients.lock().await;
+            if tag == WsCommandTag::YieldControl {
+                clients.yield_control(client_id)?
+            } else {
+                clients.request_control(client_id)
             }
+        };
+        apply_control_change(state, change).await;
+        return Ok(WsCommandResult::Ack);
+    }
+
+    if tag.requires_control() {
+        let role = state.ws_clients.lock().await.role(client_id);
+        if role != Some(WsClientRole::Controller) {
+            return Err("observers cannot send this command".to_string());
         }
-        WsCommandTag::Ping => {
-            // Just ack — HID ping is handled internally by daemon
-            Ok(None)
+    }
+
+    // Commands are queued under the HID lock and awaited after releasing it
+    let hid = state.hid.lock().await;
+
+    let done: BoxFuture<'static, anyhow::Result<WsCommandResult>> = match cmd {
+        WsCommand::UpdateDisplay(mut update) => {
+            limits::check_display(&hid.limits(None), &mut update).map_err(|e| e.to_string())?;
+            let mut display = state.display.lock().await;
+            display.set(APP_SOURCE, None, update, 0, None, Instant::now());
+            ack(display::push_current(&hid, &display))
         }
-        WsCommandTag::SetBrightness => {
-            if payload.len() >= 2 {
-                let level = payload[0];
-                let save = payload[1] != 0;
-                hid.set_brightness(level, save).map(|_| None).map_err(|e| e.to_string())
-            } else {
-                Err("invalid payload".to_string())
-            }
+        WsCommand::UpdateDisplayLayer(req) => {
+            let mut update = req.display_update();
+            limits::check_display(&hid.limits(req.device.as_deref()), &mut update).map_err(|e| e.to_string())?;
+            let mut display = state.display.lock().await;
+            display.set(
+                req.source.as_deref().unwrap_or(APP_SOURCE),
+                req.device.as_deref(),
+                update,
+                req.priority,
+                req.ttl_secs.map(Duration::from_secs),
+                Instant::now(),
+            );
+            ack(display::push_current(&hid, &display))
         }
-        WsCommandTag::SetSoftKey => {
-            if payload.len() >= 3 {
-                let index = payload[0];
-                let key_type = SoftKeyType::from_byte(payload[1]).unwrap_or(SoftKeyType::Default);
-                let save = payload[2] != 0;
-                let data = &payload[3..];
-                hid.set_soft_key(index, key_type, data, save).map(|_| None).map_err(|e| e.to_string())
-            } else {
-                Err("invalid payload".to_string())
-            }
+        WsCommand::ClearDisplayLayer(req) => {
+            let mut display = state.display.lock().await;
+            display.clear(req.source.as_deref().unwrap_or(APP_SOURCE), req.device.as_deref());
+            ack(display::push_current(&hid, &display))
         }
-        WsCommandTag::GetSoftKey => {
-            if !payload.is_empty() {
-                let index = payload[0];
-                match hid.get_soft_key(index) {
-                    Ok(config) => {
-                        let mut resp = vec![config.index, config.key_type as u8];
-                        resp.extend_from_slice(&config.data);
-                        Ok(Some(encode_ws_frame(WsResponseTag::SoftKeyResponse as u8, seq, &resp)))
-                    }
-                    Err(e) => Err(e.to_string()),
-                }
-            } else {
-                Err("missing index".to_string())
-            }
+        WsCommand::Ping => {
+            // Just ack — HID ping is handled internally by daemon
+            future::ready(Ok(WsCommandResult::Ack)).boxed()
         }
-        WsCommandTag::ResetSoftKeys => {
-            match hid.reset_soft_keys() {
-                Ok(configs) => {
-                    // Return all 3 configs serialized
-                    let mut resp = Vec::new();
-                    for config in &configs {
-                        resp.push(config.index);
-                        resp.push(config.key_type as u8);
-                        let data_len = config.data.len() as u8;
-                        resp.push(data_len);
-                        resp.extend_from_slice(&config.data);
-                    }
-                    Ok(Some(encode_ws_frame(WsResponseTag::SoftKeyResponse as u8, seq, &resp)))
-                }
-                Err(e) => Err(e.to_string()),
-            }
+        WsCommand::SetBrightness(req) => ack(hid.set_brightness(req.device.as_deref(), req.level, req.save)),
+        WsCommand::SetSoftKey { index, key_type, data, save, device } => {
+            ack(hid.set_soft_key(device.as_deref(), index, key_type, &data, save))
         }
-        WsCommandTag::SetMode => {
-            if !payload.is_empty() {
-                let mode = DeviceMode::from_byte(payload[0]);
-                hid.set_mode(mode).map(|_| None).map_err(|e| e.to_string())
-            } else {
-                Err("missing mode".to_string())
+        WsCommand::GetSoftKey { index, device } => hid
+            .get_soft_key(device.as_deref(), index)
+            .map(|config| config.map(WsCommandResult::SoftKey))
+            .boxed(),
+        WsCommand::ResetSoftKeys(query) => hid
+            .reset_soft_keys(query.and_then(|q| q.device).as_deref())
+            .map(|configs| configs.map(|configs| WsCommandResult::SoftKeys(configs.to_vec())))
+            .boxed(),
+        WsCommand::SetMode(req) => ack(hid.set_mode(req.device.as_deref(), req.mode)),
+        WsCommand::Alert(mut req) => {
+            limits::check_alert(&hid.limits(req.device.as_deref()), &mut req).map_err(|e| e.to_string())?;
+            let sent = hid.send_alert(req.device.as_deref(), req.tab, &req.session, &req.text, req.details.as_deref());
+            let state = Arc::clone(state);
+            async move {
+                sent.await?;
+                alerts::raised(&state, &req, AlertSource::App).await;
+                Ok(WsCommandResult::Ack)
             }
+            .boxed()
         }
-        WsCommandTag::Alert => {
-            match serde_json::from_slice::<coredeck_protocol::AlertRequest>(payload) {
-                Ok(req) => {
-                    hid.send_alert(req.tab, &req.session, &req.text, req.details.as_deref())
-                        .map(|_| None)
-                        .map_err(|e| e.to_string())
-                }
-                Err(e) => Err(format!("invalid JSON: {}", e)),
+        WsCommand::GetVersion => hid
+            .query_version(None)
+            .map(|version| Ok(WsCommandResult::Version(version.unwrap_or_else(|_| "unknown".to_string()))))
+            .boxed(),
+        WsCommand::ClearAlert(req) => {
+            let cleared = hid.clear_alert(req.device.as_deref(), req.tab);
+            let state = Arc::clone(state);
+            async move {
+                cleared.await?;
+                alerts::cleared(&state, req.tab, req.device.as_deref(), AlertSource::App).await;
+                Ok(WsCommandResult::Ack)
             }
+            .boxed()
         }
-        WsCommandTag::GetVersion => {
-            let version = hid.query_version();
-            Ok(Some(encode_ws_frame(
-                WsResponseTag::VersionResponse as u8,
-                seq,
-                version.as_bytes(),
-            )))
-        }
-        WsCommandTag::ClearAlert => {
-            if !payload.is_empty() {
-                let tab = payload[0] as usize;
-                hid.clear_alert(tab).map(|_| None).map_err(|e| e.to_string())
-            } else {
-                match serde_json::from_slice::<coredeck_protocol::ClearAlertRequest>(payload) {
-                    Ok(req) => hid.clear_alert(req.tab).map(|_| None).map_err(|e| e.to_string()),
-                    Err(e) => Err(format!("invalid payload: {}", e)),
-                }
+        WsCommand::Hello(hello) => {
+            info!(
+                "WS client {} is {} (protocol v{}, daemon v{})",
+                client_id,
+                if hello.client.is_empty() { "unnamed" } else { &hello.client },
+                hello.protocol_version,
+                PROTOCOL_VERSION
+            );
+            let state = Arc::clone(state);
+            async move {
+                Ok(WsCommandResult::Hello(DaemonHello {
+                    protocol_version: PROTOCOL_VERSION,
+                    daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+                    commands: WsCommandTag::ALL.iter().map(|&tag| tag as u8).collect(),
+                    devices: connected_devices(&state).await,
+                }))
             }
+            .boxed()
         }
+        WsCommand::YieldControl | WsCommand::RequestControl => unreachable!("handled above"),
     };
+    drop(hid);
 
-    match result {
-        Ok(Some(response_frame)) => {
-            let _ = reply_tx.send(response_frame);
-        }
-        Ok(None) => {
-            // Send ACK
-            let ack = encode_ws_frame(WsResponseTag::CommandAck as u8, seq, &[]);
-            let _ = reply_tx.send(ack);
-        }
-        Err(e) => {
-            let err = encode_ws_frame(WsResponseTag::CommandError as u8, seq, e.as_bytes());
-            let _ = reply_tx.send(err);
-        }
-    }
+    done.await.map_err(|e| e.to_string())
+}
+
+/// Acknowledge a queued command once the HID writer is done with it
+fn ack(
+    done: impl Future<Output = anyhow::Result<()>> + Send + 'static,
+) -> BoxFuture<'static, anyhow::Result<WsCommandResult>> {
+    done.map(|result| result.map(|()| WsCommandResult::Ack)).boxed()
 }
 
-/// Forward a daemon event to the connected WS client (if any).
+/// Forward a daemon event to every connected WS client.
 ///
-/// Also handles auto-opening the device when it becomes available while an app is connected.
+/// Also handles auto-opening the device when it becomes available while a controller
+/// is connected or headless actions are configured.
 pub async fn forward_event_to_ws(state: &Arc<DaemonState>, event: &DaemonEvent) {
-    // Auto-open device when it becomes available and an app is connected
-    if let DaemonEvent::DeviceAvailable { .. } = event {
-        let ws_connected = state.ws_client.lock().await.is_some();
-        if ws_connected {
-            let hid = state.hid.lock().await;
-            if hid.is_device_available() && !hid.is_connected() {
-                info!("Device became available while app connected — auto-opening");
-                if let Err(e) = hid.open_device() {
-                    warn!("Failed to auto-open HID device: {}", e);
-                }
+    // Auto-open a deck when it becomes available and something listens to its keys
+    if let DaemonEvent::DeviceAvailable { serial, .. } = event {
+        let has_controller = state.ws_clients.lock().await.has_controller();
+        if has_controller || actions::keep_decks_open(state) {
+            info!("Device {} became available — auto-opening", serial);
+            let opened = state.hid.lock().await.open_device(Some(serial));
+            if let Err(e) = opened.await {
+                warn!("Failed to auto-open HID device {}: {}", serial, e);
             }
         }
     }
 
-    let guard = state.ws_client.lock().await;
-    let client = match guard.as_ref() {
-        Some(c) => c,
-        None => return,
-    };
-
-    let frame = match event {
-        DaemonEvent::HidConnected { device_name, firmware_version } => {
-            let info = DeviceInfo {
+    let event = match event {
+        DaemonEvent::HidConnected { serial, device_name, firmware_version, capabilities } => {
+            WsEvent::DeviceConnected(DeviceInfo {
                 name: device_name.clone(),
                 firmware: firmware_version.clone(),
-            };
-            let payload = serde_json::to_vec(&info).unwrap_or_default();
-            encode_ws_frame(WsEventTag::DeviceConnected as u8, 0, &payload)
-        }
-        DaemonEvent::HidDisconnected => {
-            encode_ws_frame(WsEventTag::DeviceDisconnected as u8, 0, &[])
-        }
-        DaemonEvent::DeviceStateChanged { mode, yolo } => {
-            let state_byte = coredeck_protocol::DeviceState { mode: *mode, yolo: *yolo }.to_byte();
-            encode_ws_frame(WsEventTag::StateChanged as u8, 0, &[state_byte])
-        }
-        DaemonEvent::HidKeyEvent { keycode } => {
-            let hi = (*keycode >> 8) as u8;
-            let lo = (*keycode & 0xFF) as u8;
-            encode_ws_frame(WsEventTag::KeyEvent as u8, 0, &[hi, lo])
-        }
-        DaemonEvent::HidTypeString { text, send_enter } => {
-            let mut payload = vec![if *send_enter { 1u8 } else { 0u8 }];
-            payload.extend_from_slice(text.as_bytes());
-            encode_ws_frame(WsEventTag::TypeString as u8, 0, &payload)
+                serial: serial.clone(),
+                capabilities: capabilities.clone(),
+            })
         }
+        DaemonEvent::HidDisconnected { serial } => WsEvent::DeviceDisconnected { serial: Some(serial.clone()) },
+        DaemonEvent::DeviceStateChanged { serial, mode, yolo } => WsEvent::StateChanged {
+            mode: *mode,
+            yolo: *yolo,
+            serial: Some(serial.clone()),
+        },
+        DaemonEvent::HidKeyEvent { serial, keycode } => WsEvent::KeyEvent {
+            keycode: *keycode,
+            serial: Some(serial.clone()),
+        },
+        // Typed text goes to the focused app regardless of which deck sent it
+        DaemonEvent::HidTypeString { text, send_enter, .. } => WsEvent::TypeString {
+            text: text.clone(),
+            send_enter: *send_enter,
+        },
         // DeviceAvailable/DeviceUnavailable are handled above (auto-open) and
         // in the event handler (tray updates). No WS frame needed.
-        DaemonEvent::DeviceAvailable { .. } | DaemonEvent::DeviceUnavailable => return,
+        DaemonEvent::DeviceAvailable { .. } | DaemonEvent::DeviceUnavailable { .. } => return,
     };
 
-    let _ = client.tx.send(frame);
+    state.ws_clients.lock().await.broadcast(&event.encode());
+}
+
+/// JSON text of a binary event frame, for clients connected with `format=json`.
+/// `None` for command responses, which stay binary.
+fn json_event(frame: &[u8]) -> Option<String> {
+    let (tag, seq, payload) = decode_ws_frame(frame)?;
+    if seq != 0 {
+        return None;
+    }
+    let event = WsEvent::decode(WsEventTag::from_byte(tag)?, payload)?;
+    serde_json::to_string(&event).ok()
 }
 
-/// Send an AppControl message to the WS client
+/// Send an AppControl message to the WS controller
 pub async fn send_app_control(state: &Arc<DaemonState>, action: AppControlAction) {
-    let guard = state.ws_client.lock().await;
-    if let Some(client) = guard.as_ref() {
-        let frame = encode_ws_frame(WsEventTag::AppControl as u8, 0, &[action as u8]);
-        let _ = client.tx.send(frame);
+    let frame = encode_ws_frame(WsEventTag::AppControl as u8, 0, &[action as u8]);
+    state.ws_clients.lock().await.send_to_controller(frame);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> (mpsc::UnboundedSender<Vec<u8>>, mpsc::UnboundedReceiver<Vec<u8>>) {
+        mpsc::unbounded_channel()
+    }
+
+    /// Drain a client's queue and return the (tag, payload) pairs it received
+    fn tags(rx: &mut mpsc::UnboundedReceiver<Vec<u8>>) -> Vec<(u8, Vec<u8>)> {
+        let mut out = Vec::new();
+        while let Ok(frame) = rx.try_recv() {
+            let (tag, _, payload) = decode_ws_frame(&frame).unwrap();
+            out.push((tag, payload.to_vec()));
+        }
+        out
+    }
+
+    fn role_frame(role: WsClientRole) -> (u8, Vec<u8>) {
+        (WsEventTag::RoleChanged as u8, vec![role as u8])
+    }
+
+    #[test]
+    fn test_second_controller_downgraded() {
+        let mut clients = WsClients::default();
+        let (tx_a, mut rx_a) = client();
+        let (tx_b, mut rx_b) = client();
+
+        let (a, change) = clients.register(tx_a, WsClientRole::Controller);
+        assert_eq!(change, ControlChange::Acquired);
+        let (b, change) = clients.register(tx_b, WsClientRole::Controller);
+        assert_eq!(change, ControlChange::Unchanged);
+
+        assert_eq!(clients.role(a), Some(WsClientRole::Controller));
+        assert_eq!(clients.role(b), Some(WsClientRole::Observer));
+        assert_eq!(clients.observer_count(), 1);
+        assert_eq!(tags(&mut rx_a), vec![role_frame(WsClientRole::Controller)]);
+        assert_eq!(tags(&mut rx_b), vec![role_frame(WsClientRole::Observer)]);
+    }
+
+    #[tokio::test]
+    async fn test_simultaneous_controllers_get_one_slot() {
+        let clients = Arc::new(tokio::sync::Mutex::new(WsClients::default()));
+        let connects: Vec<_> = (0..8)
+            .map(|_| {
+                let clients = Arc::clone(&clients);
+                tokio::spawn(async move {
+                    let (tx, rx) = client();
+                    let (_, change) = clients.lock().await.register(tx, WsClientRole::Controller);
+                    (change, rx)
+                })
+            })
+            .collect();
+
+        let mut controllers = 0;
+        for connect in connects {
+            let (change, mut rx) = connect.await.unwrap();
+            let role = if change == ControlChange::Acquired { WsClientRole::Controller } else { WsClientRole::Observer };
+            controllers += usize::from(role == WsClientRole::Controller);
+            assert_eq!(tags(&mut rx), vec![role_frame(role)]);
+        }
+        assert_eq!(controllers, 1);
+        assert_eq!(clients.lock().await.observer_count(), 7);
+    }
+
+    #[test]
+    fn test_observers_do_not_take_control() {
+        let mut clients = WsClients::default();
+        let (tx, _rx) = client();
+        let (_, change) = clients.register(tx, WsClientRole::Observer);
+        assert_eq!(change, ControlChange::Unchanged);
+        assert!(!clients.has_controller());
+    }
+
+    #[test]
+    fn test_request_control_when_free() {
+        let mut clients = WsClients::default();
+        let (tx, mut rx) = client();
+        let (id, _) = clients.register(tx, WsClientRole::Observer);
+        tags(&mut rx);
+
+        assert_eq!(clients.request_control(id), ControlChange::Acquired);
+        assert_eq!(clients.role(id), Some(WsClientRole::Controller));
+        assert_eq!(tags(&mut rx), vec![role_frame(WsClientRole::Controller)]);
+    }
+
+    #[test]
+    fn test_yield_hands_over_to_requester() {
+        let mut clients = WsClients::default();
+        let (tx_a, mut rx_a) = client();
+        let (tx_b, mut rx_b) = client();
+        let (a, _) = clients.register(tx_a, WsClientRole::Controller);
+        let (b, _) = clients.register(tx_b, WsClientRole::Observer);
+        tags(&mut rx_a);
+        tags(&mut rx_b);
+
+        assert_eq!(clients.request_control(b), ControlChange::Unchanged);
+        assert_eq!(tags(&mut rx_a), vec![(WsEventTag::ControlRequested as u8, vec![])]);
+
+        // Only the controller may yield
+        assert!(clients.yield_control(b).is_err());
+
+        assert_eq!(clients.yield_control(a), Ok(ControlChange::Handover));
+        assert_eq!(clients.role(a), Some(WsClientRole::Observer));
+        assert_eq!(clients.role(b), Some(WsClientRole::Controller));
+        assert_eq!(tags(&mut rx_a), vec![role_frame(WsClientRole::Observer)]);
+        assert_eq!(tags(&mut rx_b), vec![role_frame(WsClientRole::Controller)]);
+    }
+
+    #[test]
+    fn test_yield_without_requester_releases() {
+        let mut clients = WsClients::default();
+        let (tx, _rx) = client();
+        let (a, _) = clients.register(tx, WsClientRole::Controller);
+        assert_eq!(clients.yield_control(a), Ok(ControlChange::Released));
+        assert!(!clients.has_controller());
+        assert_eq!(clients.observer_count(), 1);
+    }
+
+    #[test]
+    fn test_controller_disconnect_skips_departed_requesters() {
+        let mut clients = WsClients::default();
+        let (tx_a, _rx_a) = client();
+        let (tx_b, _rx_b) = client();
+        let (tx_c, _rx_c) = client();
+        let (a, _) = clients.register(tx_a, WsClientRole::Controller);
+        let (b, _) = clients.register(tx_b, WsClientRole::Observer);
+        let (c, _) = clients.register(tx_c, WsClientRole::Observer);
+        clients.request_control(b);
+        clients.request_control(c);
+
+        assert_eq!(clients.unregister(b), ControlChange::Unchanged);
+        assert_eq!(clients.unregister(a), ControlChange::Handover);
+        assert_eq!(clients.role(c), Some(WsClientRole::Controller));
+        assert_eq!(clients.unregister(c), ControlChange::Released);
+    }
+
+    #[test]
+    fn test_broadcast_reaches_everyone() {
+        let mut clients = WsClients::default();
+        let (tx_a, mut rx_a) = client();
+        let (tx_b, mut rx_b) = client();
+        clients.register(tx_a, WsClientRole::Controller);
+        clients.register(tx_b, WsClientRole::Observer);
+        tags(&mut rx_a);
+        tags(&mut rx_b);
+
+        clients.broadcast(&encode_ws_frame(WsEventTag::KeyEvent as u8, 0, &[0x00, 0x04]));
+        clients.send_to_controller(encode_ws_frame(WsEventTag::AppControl as u8, 0, &[1]));
+
+        assert_eq!(tags(&mut rx_a).len(), 2);
+        assert_eq!(tags(&mut rx_b), vec![(WsEventTag::KeyEvent as u8, vec![0x00, 0x04])]);
+    }
+
+    #[test]
+    fn test_json_event_translation() {
+        let frame = encode_ws_frame(WsEventTag::RoleChanged as u8, 0, &[WsClientRole::Observer as u8]);
+        assert_eq!(json_event(&frame).unwrap(), r#"{"event":"role_changed","data":{"role":"observer"}}"#);
+
+        let frame = encode_ws_frame(WsEventTag::KeyEvent as u8, 0, b"\x00\x29CD-1");
+        assert_eq!(
+            json_event(&frame).unwrap(),
+            r#"{"event":"key_event","data":{"keycode":41,"serial":"CD-1"}}"#
+        );
+
+        // Responses to binary commands stay binary
+        assert_eq!(json_event(&WsCommandResult::Ack.encode(5)), None);
     }
 }
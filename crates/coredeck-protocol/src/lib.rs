@@ -69,8 +69,9 @@ impl DeviceState {
     }
 }
 
-/// Display update data structure matching firmware JSON format
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Display update data structure matching firmware JSON format.
+/// The default is a blank screen.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DisplayUpdate {
     /// Session name
     pub session: String,
@@ -120,6 +121,249 @@ pub struct SoftKeyConfig {
     pub data: Vec<u8>,
 }
 
+/// Longest text a `String` soft key types, in bytes
+pub const SOFT_KEY_MAX_TEXT: usize = 126;
+/// Most steps in a `Sequence` soft key
+pub const SOFT_KEY_MAX_STEPS: usize = 63;
+
+// QMK modifier bits (left modifiers) in the upper byte of a keycode
+const MOD_LCTL: u16 = 0x0100;
+const MOD_LSFT: u16 = 0x0200;
+const MOD_LALT: u16 = 0x0400;
+const MOD_LGUI: u16 = 0x0800;
+
+/// A key with optional modifiers; `usage` is the USB HID usage code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct KeyStroke {
+    pub usage: u8,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub shift: bool,
+    #[serde(default)]
+    pub alt: bool,
+    #[serde(default)]
+    pub gui: bool,
+}
+
+impl KeyStroke {
+    /// Decode a 16-bit QMK keycode
+    pub fn from_keycode(keycode: u16) -> Self {
+        Self {
+            usage: (keycode & 0xFF) as u8,
+            ctrl: keycode & MOD_LCTL != 0,
+            shift: keycode & MOD_LSFT != 0,
+            alt: keycode & MOD_LALT != 0,
+            gui: keycode & MOD_LGUI != 0,
+        }
+    }
+
+    /// Encode as a 16-bit QMK keycode
+    pub fn to_keycode(self) -> u16 {
+        let mut keycode = u16::from(self.usage);
+        if self.ctrl { keycode |= MOD_LCTL; }
+        if self.shift { keycode |= MOD_LSFT; }
+        if self.alt { keycode |= MOD_LALT; }
+        if self.gui { keycode |= MOD_LGUI; }
+        keycode
+    }
+}
+
+/// JSON form of a soft key assignment (same model as the settings UI)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SoftKeyAction {
+    /// Firmware default; `key` is the resolved key when the deck reports it
+    Default {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<KeyStroke>,
+    },
+    /// Single key with optional modifiers
+    Keycode { key: KeyStroke },
+    /// Typed text, optionally followed by Enter
+    String {
+        text: String,
+        #[serde(default)]
+        send_enter: bool,
+    },
+    /// Keys tapped one after another
+    Sequence { keys: Vec<KeyStroke> },
+}
+
+impl SoftKeyAction {
+    /// Decode the wire bytes of a soft key
+    pub fn from_config(config: &SoftKeyConfig) -> Self {
+        let data = &config.data;
+        let keycode = |i: usize| KeyStroke::from_keycode(u16::from_be_bytes([data[i], data[i + 1]]));
+        match config.key_type {
+            // Only ResetSoftKeys responses carry the resolved default
+            SoftKeyType::Default => Self::Default { key: (data.len() >= 2).then(|| keycode(0)) },
+            SoftKeyType::Keycode if data.len() >= 2 => Self::Keycode { key: keycode(0) },
+            SoftKeyType::Keycode => Self::Default { key: None },
+            // [flags, text..., NUL]; flags bit 0 = send Enter
+            SoftKeyType::String => {
+                let Some((&flags, text)) = data.split_first() else {
+                    return Self::String { text: String::new(), send_enter: false };
+                };
+                let end = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+                Self::String {
+                    text: String::from_utf8_lossy(&text[..end]).into_owned(),
+                    send_enter: flags & 0x01 != 0,
+                }
+            }
+            // [count, keycode pairs...]
+            SoftKeyType::Sequence => {
+                let count = data.first().map_or(0, |&n| usize::from(n));
+                let keys = (0..count).map(|i| 1 + i * 2).take_while(|&at| at + 1 < data.len()).map(keycode).collect();
+                Self::Sequence { keys }
+            }
+        }
+    }
+
+    /// Encode for the wire, cutting text and sequences the firmware can't hold
+    pub fn to_wire(&self) -> (SoftKeyType, Vec<u8>) {
+        match self {
+            Self::Default { .. } => (SoftKeyType::Default, vec![]),
+            Self::Keycode { key } => (SoftKeyType::Keycode, key.to_keycode().to_be_bytes().to_vec()),
+            Self::String { text, send_enter } => {
+                let mut text = text.clone();
+                truncate_utf8(&mut text, SOFT_KEY_MAX_TEXT);
+                let mut data = vec![u8::from(*send_enter)];
+                data.extend_from_slice(text.as_bytes());
+                data.push(0);
+                (SoftKeyType::String, data)
+            }
+            Self::Sequence { keys } => {
+                let keys = &keys[..keys.len().min(SOFT_KEY_MAX_STEPS)];
+                let mut data = vec![keys.len() as u8];
+                for key in keys {
+                    data.extend_from_slice(&key.to_keycode().to_be_bytes());
+                }
+                (SoftKeyType::Sequence, data)
+            }
+        }
+    }
+}
+
+/// A soft key and its assignment, as returned by the REST API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SoftKeyAssignment {
+    pub index: u8,
+    #[serde(flatten)]
+    pub action: SoftKeyAction,
+}
+
+impl From<&SoftKeyConfig> for SoftKeyAssignment {
+    fn from(config: &SoftKeyConfig) -> Self {
+        Self { index: config.index, action: SoftKeyAction::from_config(config) }
+    }
+}
+
+/// Portable deck setup, stored as TOML (`coredeck-daemon profile`, and
+/// Import/Export in the app's soft key settings)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    /// Backlight level (0-255); unset leaves the deck's brightness alone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    /// Mode set on import; unset leaves the mode alone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<DeviceMode>,
+    /// Soft key assignments, in key order
+    #[serde(default)]
+    pub soft_keys: Vec<SoftKeyAction>,
+}
+
+/// Alert field the firmware can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertField {
+    Session,
+    Text,
+    Details,
+}
+
+/// Limits and features reported by a deck's firmware (`GetCapabilities`).
+///
+/// Fields the firmware leaves out keep their defaults, which are the limits of
+/// firmware that predates the query (see `docs/Protocol-Limits.md`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceCapabilities {
+    /// Display resolution in pixels
+    pub display_width: u16,
+    pub display_height: u16,
+    /// Most entries in a display update's `tabs` (also the alert tab range)
+    pub max_tabs: u16,
+    /// Buffer size of each text field, including the NUL terminator
+    pub max_text_bytes: u16,
+    /// Largest reassembled JSON message
+    pub max_payload_bytes: u16,
+    pub soft_key_count: u8,
+    /// Alert fields the firmware draws
+    pub alert_fields: Vec<AlertField>,
+}
+
+impl Default for DeviceCapabilities {
+    fn default() -> Self {
+        Self {
+            display_width: 284,
+            display_height: 76,
+            max_tabs: 16,
+            max_text_bytes: 128,
+            max_payload_bytes: 512,
+            soft_key_count: 3,
+            alert_fields: vec![AlertField::Session, AlertField::Text, AlertField::Details],
+        }
+    }
+}
+
+impl DeviceCapabilities {
+    /// Longest text field the firmware shows in full, in bytes
+    pub fn max_text_len(&self) -> usize {
+        usize::from(self.max_text_bytes.saturating_sub(1))
+    }
+
+    /// Fit a display update to these limits: shorten text fields, drop
+    /// tabs past `max_tabs` and keep `active` in range.
+    pub fn clamp_display(&self, update: &mut DisplayUpdate) {
+        let max = self.max_text_len();
+        for field in [&mut update.session, &mut update.task, &mut update.task2] {
+            truncate_utf8(field, max);
+        }
+        update.tabs.truncate(usize::from(self.max_tabs));
+        update.active = update.active.min(update.tabs.len().saturating_sub(1));
+    }
+
+    /// Fit an alert to these limits: shorten text fields and drop `details`
+    /// when the firmware cannot show them.
+    pub fn clamp_alert(&self, alert: &mut AlertRequest) {
+        let max = self.max_text_len();
+        truncate_utf8(&mut alert.session, max);
+        truncate_utf8(&mut alert.text, max);
+        if !self.alert_fields.contains(&AlertField::Details) {
+            alert.details = None;
+        }
+        if let Some(details) = alert.details.as_mut() {
+            truncate_utf8(details, max);
+        }
+    }
+}
+
+/// Shorten `s` to at most `max` bytes without splitting a UTF-8 sequence.
+/// Returns whether anything was cut.
+pub fn truncate_utf8(s: &mut String, max: usize) -> bool {
+    if s.len() <= max {
+        return false;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s.truncate(end);
+    true
+}
+
 // ── WebSocket binary protocol ──────────────────────────────────────
 //
 // Every binary WS frame: [tag: u8][seq_lo: u8][seq_hi: u8][payload...]
@@ -127,6 +371,11 @@ pub struct SoftKeyConfig {
 // Daemon→App events use seq=0. App→Daemon commands use seq>0.
 // Daemon→App responses echo the seq from the original command.
 
+/// Version of the WebSocket protocol, exchanged with `WsCommandTag::Hello`.
+/// Bumped whenever commands, events or payloads change. Daemons that predate
+/// the handshake answer `Hello` with "unknown command" and count as version 0.
+pub const PROTOCOL_VERSION: u32 = 1;
+
 /// WebSocket message tags: App → Daemon (commands)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -141,9 +390,33 @@ pub enum WsCommandTag {
     Alert = 0x08,
     GetVersion = 0x09,
     ClearAlert = 0x0A,
+    YieldControl = 0x0B,
+    RequestControl = 0x0C,
+    UpdateDisplayLayer = 0x0D,
+    ClearDisplayLayer = 0x0E,
+    Hello = 0x0F,
 }
 
 impl WsCommandTag {
+    /// Every command this version of the protocol defines
+    pub const ALL: [Self; 15] = [
+        Self::UpdateDisplay,
+        Self::Ping,
+        Self::SetBrightness,
+        Self::SetSoftKey,
+        Self::GetSoftKey,
+        Self::ResetSoftKeys,
+        Self::SetMode,
+        Self::Alert,
+        Self::GetVersion,
+        Self::ClearAlert,
+        Self::YieldControl,
+        Self::RequestControl,
+        Self::UpdateDisplayLayer,
+        Self::ClearDisplayLayer,
+        Self::Hello,
+    ];
+
     pub fn from_byte(byte: u8) -> Option<Self> {
         match byte {
             0x01 => Some(Self::UpdateDisplay),
@@ -156,9 +429,40 @@ impl WsCommandTag {
             0x08 => Some(Self::Alert),
             0x09 => Some(Self::GetVersion),
             0x0A => Some(Self::ClearAlert),
+            0x0B => Some(Self::YieldControl),
+            0x0C => Some(Self::RequestControl),
+            0x0D => Some(Self::UpdateDisplayLayer),
+            0x0E => Some(Self::ClearDisplayLayer),
+            0x0F => Some(Self::Hello),
             _ => None,
         }
     }
+
+    /// Whether only the controller may send this command.
+    /// Observers may still query the device and negotiate control.
+    pub fn requires_control(self) -> bool {
+        !matches!(
+            self,
+            Self::Ping
+                | Self::GetSoftKey
+                | Self::GetVersion
+                | Self::YieldControl
+                | Self::RequestControl
+                | Self::Hello
+        )
+    }
+
+    /// Token scope needed to send this command.
+    /// Taking control opens the device, so it needs the display scope.
+    pub fn required_scope(self) -> AuthScope {
+        match self {
+            Self::Ping | Self::GetSoftKey | Self::GetVersion | Self::YieldControl | Self::Hello => {
+                AuthScope::Read
+            }
+            Self::SetSoftKey | Self::ResetSoftKeys => AuthScope::Softkey,
+            _ => AuthScope::Display,
+        }
+    }
 }
 
 /// WebSocket message tags: Daemon → App (events, seq=0)
@@ -171,6 +475,9 @@ pub enum WsEventTag {
     KeyEvent = 0x83,
     TypeString = 0x84,
     AppControl = 0x89,
+    RoleChanged = 0x8A,
+    ControlRequested = 0x8B,
+    AlertAcknowledged = 0x8C,
 }
 
 impl WsEventTag {
@@ -182,6 +489,9 @@ impl WsEventTag {
             0x83 => Some(Self::KeyEvent),
             0x84 => Some(Self::TypeString),
             0x89 => Some(Self::AppControl),
+            0x8A => Some(Self::RoleChanged),
+            0x8B => Some(Self::ControlRequested),
+            0x8C => Some(Self::AlertAcknowledged),
             _ => None,
         }
     }
@@ -195,6 +505,7 @@ pub enum WsResponseTag {
     VersionResponse = 0x86,
     CommandAck = 0x87,
     CommandError = 0x88,
+    HelloResponse = 0x8D,
 }
 
 impl WsResponseTag {
@@ -204,13 +515,15 @@ impl WsResponseTag {
             0x86 => Some(Self::VersionResponse),
             0x87 => Some(Self::CommandAck),
             0x88 => Some(Self::CommandError),
+            0x8D => Some(Self::HelloResponse),
             _ => None,
         }
     }
 }
 
 /// AppControl actions sent from daemon tray to app via WS
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[repr(u8)]
 pub enum AppControlAction {
     ShowWindow = 0x01,
@@ -227,11 +540,109 @@ impl AppControlAction {
     }
 }
 
+/// Role of a WebSocket client.
+///
+/// One controller owns the display and alerts; any number of observers
+/// receive device events. Selected with `/ws?role=observer`; connections
+/// without a role ask for control, as the app always has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum WsClientRole {
+    Observer = 0,
+    #[default]
+    Controller = 1,
+}
+
+impl WsClientRole {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Observer),
+            1 => Some(Self::Controller),
+            _ => None,
+        }
+    }
+}
+
+/// Encoding of the events a WebSocket client receives.
+///
+/// Selected with `/ws?format=json`. Commands may be sent either way on any
+/// connection; JSON commands are always answered in JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WsFormat {
+    #[default]
+    Binary,
+    Json,
+}
+
 /// Device info sent in DeviceConnected event
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceInfo {
     pub name: String,
     pub firmware: String,
+    /// Serial number of the deck (see [`DeviceSummary::serial`])
+    #[serde(default)]
+    pub serial: String,
+    /// Limits reported by the firmware (`None`: firmware predates the query)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<DeviceCapabilities>,
+}
+
+/// Sent by a client in `WsCommand::Hello` right after connecting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientHello {
+    /// Highest `PROTOCOL_VERSION` the client speaks
+    pub protocol_version: u32,
+    /// Client name, for the daemon's log
+    #[serde(default)]
+    pub client: String,
+}
+
+/// The daemon's answer to `Hello`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonHello {
+    /// `PROTOCOL_VERSION` the daemon was built with
+    pub protocol_version: u32,
+    /// Daemon package version
+    pub daemon_version: String,
+    /// `WsCommandTag`s the daemon accepts
+    pub commands: Vec<u8>,
+    /// Decks currently open, with their firmware
+    #[serde(default)]
+    pub devices: Vec<DeviceInfo>,
+}
+
+impl DaemonHello {
+    /// What a daemon that predates the handshake supports
+    pub fn legacy() -> Self {
+        Self {
+            protocol_version: 0,
+            daemon_version: String::new(),
+            commands: (WsCommandTag::UpdateDisplay as u8..=WsCommandTag::ClearDisplayLayer as u8).collect(),
+            devices: Vec::new(),
+        }
+    }
+
+    pub fn supports(&self, tag: WsCommandTag) -> bool {
+        self.commands.contains(&(tag as u8))
+    }
+}
+
+/// Append the originating deck's serial to a fixed-size event payload
+/// (`KeyEvent`, `StateChanged`, `DeviceDisconnected`).
+pub fn tag_with_serial(mut payload: Vec<u8>, serial: &str) -> Vec<u8> {
+    payload.extend_from_slice(serial.as_bytes());
+    payload
+}
+
+/// Serial appended to an event payload after its first `fixed_len` bytes.
+/// `None` for events from daemons that predate multi-device support.
+pub fn event_serial(payload: &[u8], fixed_len: usize) -> Option<&str> {
+    payload
+        .get(fixed_len..)
+        .filter(|s| !s.is_empty())
+        .and_then(|s| std::str::from_utf8(s).ok())
 }
 
 // ── Frame encoding/decoding helpers ────────────────────────────────
@@ -256,12 +667,431 @@ pub fn decode_ws_frame(data: &[u8]) -> Option<(u8, u16, &[u8])> {
     Some((tag, seq, &data[3..]))
 }
 
+// ── WebSocket JSON protocol ────────────────────────────────────────
+//
+// Text frames carry the same commands and events as JSON, for clients that
+// would rather not implement the binary codec:
+//   → {"id": 1, "method": "set_mode", "params": {"mode": "Accept"}}
+//   ← {"id": 1, "result": "ack"}
+//   ← {"event": "key_event", "data": {"keycode": 41, "serial": "CD-0001"}}
+
+/// A WebSocket command, one variant per `WsCommandTag`.
+/// The JSON form is `{"method": ..., "params": ...}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum WsCommand {
+    UpdateDisplay(DisplayUpdate),
+    Ping,
+    SetBrightness(BrightnessRequest),
+    SetSoftKey {
+        index: u8,
+        key_type: SoftKeyType,
+        #[serde(default)]
+        data: Vec<u8>,
+        #[serde(default)]
+        save: bool,
+        /// Serial of the deck to change (default: every deck)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        device: Option<String>,
+    },
+    GetSoftKey {
+        index: u8,
+        /// Serial of the deck to read from (default: the first connected deck)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        device: Option<String>,
+    },
+    /// `params` may be omitted to reset every deck
+    ResetSoftKeys(Option<DeviceQuery>),
+    SetMode(SetModeRequest),
+    Alert(AlertRequest),
+    GetVersion,
+    ClearAlert(ClearAlertRequest),
+    YieldControl,
+    RequestControl,
+    UpdateDisplayLayer(DisplayUpdateRequest),
+    ClearDisplayLayer(ClearDisplayRequest),
+    Hello(ClientHello),
+}
+
+impl WsCommand {
+    pub fn tag(&self) -> WsCommandTag {
+        match self {
+            Self::UpdateDisplay(_) => WsCommandTag::UpdateDisplay,
+            Self::Ping => WsCommandTag::Ping,
+            Self::SetBrightness(_) => WsCommandTag::SetBrightness,
+            Self::SetSoftKey { .. } => WsCommandTag::SetSoftKey,
+            Self::GetSoftKey { .. } => WsCommandTag::GetSoftKey,
+            Self::ResetSoftKeys(_) => WsCommandTag::ResetSoftKeys,
+            Self::SetMode(_) => WsCommandTag::SetMode,
+            Self::Alert(_) => WsCommandTag::Alert,
+            Self::GetVersion => WsCommandTag::GetVersion,
+            Self::ClearAlert(_) => WsCommandTag::ClearAlert,
+            Self::YieldControl => WsCommandTag::YieldControl,
+            Self::RequestControl => WsCommandTag::RequestControl,
+            Self::UpdateDisplayLayer(_) => WsCommandTag::UpdateDisplayLayer,
+            Self::ClearDisplayLayer(_) => WsCommandTag::ClearDisplayLayer,
+            Self::Hello(_) => WsCommandTag::Hello,
+        }
+    }
+
+    /// Parse the payload of a binary command frame
+    pub fn decode(tag: WsCommandTag, payload: &[u8]) -> Result<Self, String> {
+        fn json<T: serde::de::DeserializeOwned>(payload: &[u8]) -> Result<T, String> {
+            serde_json::from_slice(payload).map_err(|e| format!("invalid JSON: {}", e))
+        }
+        /// Trailing deck serial, `None` when absent
+        fn serial_field(bytes: &[u8]) -> Result<Option<String>, String> {
+            match std::str::from_utf8(bytes) {
+                Ok("") => Ok(None),
+                Ok(serial) => Ok(Some(serial.to_string())),
+                Err(_) => Err("invalid serial".to_string()),
+            }
+        }
+
+        match tag {
+            WsCommandTag::UpdateDisplay => json(payload).map(Self::UpdateDisplay),
+            WsCommandTag::Ping => Ok(Self::Ping),
+            WsCommandTag::SetBrightness => match payload {
+                [level, save, ..] => Ok(Self::SetBrightness(BrightnessRequest {
+                    level: *level,
+                    save: *save != 0,
+                    device: None,
+                })),
+                _ => Err("invalid payload".to_string()),
+            },
+            WsCommandTag::SetSoftKey => match payload {
+                [index, key_type, save, data @ ..] => Ok(Self::SetSoftKey {
+                    index: *index,
+                    key_type: SoftKeyType::from_byte(*key_type).unwrap_or(SoftKeyType::Default),
+                    data: data.to_vec(),
+                    save: *save != 0,
+                    device: None,
+                }),
+                _ => Err("invalid payload".to_string()),
+            },
+            WsCommandTag::GetSoftKey => match payload {
+                [index, serial @ ..] => Ok(Self::GetSoftKey { index: *index, device: serial_field(serial)? }),
+                [] => Err("missing index".to_string()),
+            },
+            WsCommandTag::ResetSoftKeys => {
+                Ok(Self::ResetSoftKeys(serial_field(payload)?.map(|device| DeviceQuery { device: Some(device) })))
+            }
+            WsCommandTag::SetMode => match payload.first() {
+                Some(&mode) => Ok(Self::SetMode(SetModeRequest {
+                    mode: DeviceMode::from_byte(mode),
+                    device: None,
+                })),
+                None => Err("missing mode".to_string()),
+            },
+            WsCommandTag::Alert => json(payload).map(Self::Alert),
+            WsCommandTag::GetVersion => Ok(Self::GetVersion),
+            WsCommandTag::ClearAlert => match payload {
+                [tab] => Ok(Self::ClearAlert(ClearAlertRequest {
+                    tab: *tab as usize,
+                    device: None,
+                })),
+                _ => serde_json::from_slice(payload)
+                    .map(Self::ClearAlert)
+                    .map_err(|e| format!("invalid payload: {}", e)),
+            },
+            WsCommandTag::YieldControl => Ok(Self::YieldControl),
+            WsCommandTag::RequestControl => Ok(Self::RequestControl),
+            WsCommandTag::UpdateDisplayLayer => json(payload).map(Self::UpdateDisplayLayer),
+            WsCommandTag::ClearDisplayLayer if payload.is_empty() => {
+                Ok(Self::ClearDisplayLayer(ClearDisplayRequest::default()))
+            }
+            WsCommandTag::ClearDisplayLayer => json(payload).map(Self::ClearDisplayLayer),
+            WsCommandTag::Hello => json(payload).map(Self::Hello),
+        }
+    }
+
+    /// Binary command frame with sequence number `seq`, the inverse of `decode`.
+    /// Binary `SetBrightness`, `SetMode` and `SetSoftKey` have no room for a deck
+    /// serial and apply to every deck; send those as JSON to address one.
+    pub fn encode(&self, seq: u16) -> Vec<u8> {
+        fn json<T: Serialize>(value: &T) -> Vec<u8> {
+            serde_json::to_vec(value).unwrap_or_default()
+        }
+
+        let payload = match self {
+            Self::UpdateDisplay(update) => json(update),
+            Self::SetBrightness(req) => vec![req.level, u8::from(req.save)],
+            Self::SetSoftKey { index, key_type, data, save, .. } => {
+                [&[*index, *key_type as u8, u8::from(*save)][..], data].concat()
+            }
+            Self::GetSoftKey { index, device } => tag_with_serial(vec![*index], device.as_deref().unwrap_or_default()),
+            Self::ResetSoftKeys(query) => {
+                tag_with_serial(Vec::new(), query.as_ref().and_then(|q| q.device.as_deref()).unwrap_or_default())
+            }
+            Self::SetMode(req) => vec![req.mode as u8],
+            Self::Alert(req) => json(req),
+            // Daemons that predate multi-device support only take the tab byte
+            Self::ClearAlert(req) => match u8::try_from(req.tab) {
+                Ok(tab) if req.device.is_none() => vec![tab],
+                _ => json(req),
+            },
+            Self::UpdateDisplayLayer(req) => json(req),
+            Self::ClearDisplayLayer(req) => json(req),
+            Self::Hello(hello) => json(hello),
+            Self::Ping | Self::GetVersion | Self::YieldControl | Self::RequestControl => {
+                Vec::new()
+            }
+        };
+        encode_ws_frame(self.tag() as u8, seq, &payload)
+    }
+}
+
+/// Successful outcome of a WebSocket command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WsCommandResult {
+    /// Done, nothing to return (`CommandAck`)
+    Ack,
+    /// `GetSoftKey`
+    SoftKey(SoftKeyConfig),
+    /// `ResetSoftKeys`: the assignment of every key after the reset
+    SoftKeys(Vec<SoftKeyConfig>),
+    /// `GetVersion`: firmware version string
+    Version(String),
+    /// `Hello`: the daemon's protocol version and capabilities
+    Hello(DaemonHello),
+}
+
+impl WsCommandResult {
+    /// Binary response frame echoing `seq`
+    pub fn encode(&self, seq: u16) -> Vec<u8> {
+        match self {
+            Self::Ack => encode_ws_frame(WsResponseTag::CommandAck as u8, seq, &[]),
+            Self::SoftKey(config) => {
+                let mut payload = vec![config.index, config.key_type as u8];
+                payload.extend_from_slice(&config.data);
+                encode_ws_frame(WsResponseTag::SoftKeyResponse as u8, seq, &payload)
+            }
+            Self::SoftKeys(configs) => {
+                // [index, type, data_len, data...] per key
+                let mut payload = Vec::new();
+                for config in configs {
+                    payload.extend_from_slice(&[config.index, config.key_type as u8, config.data.len() as u8]);
+                    payload.extend_from_slice(&config.data);
+                }
+                encode_ws_frame(WsResponseTag::SoftKeyResponse as u8, seq, &payload)
+            }
+            Self::Version(version) => {
+                encode_ws_frame(WsResponseTag::VersionResponse as u8, seq, version.as_bytes())
+            }
+            Self::Hello(hello) => {
+                let payload = serde_json::to_vec(hello).unwrap_or_default();
+                encode_ws_frame(WsResponseTag::HelloResponse as u8, seq, &payload)
+            }
+        }
+    }
+
+    /// Parse the binary response to a `command`. `CommandError` responses
+    /// yield the daemon's error message.
+    pub fn decode(command: WsCommandTag, tag: u8, payload: &[u8]) -> Result<Self, String> {
+        let invalid = || format!("invalid {:?} response", command);
+        match WsResponseTag::from_byte(tag) {
+            Some(WsResponseTag::CommandError) => Err(String::from_utf8_lossy(payload).into_owned()),
+            Some(WsResponseTag::CommandAck) => Ok(Self::Ack),
+            // [index, type, data...]
+            Some(WsResponseTag::SoftKeyResponse) if command == WsCommandTag::GetSoftKey => match payload {
+                [index, key_type, data @ ..] => Ok(Self::SoftKey(SoftKeyConfig {
+                    index: *index,
+                    key_type: SoftKeyType::from_byte(*key_type).unwrap_or(SoftKeyType::Default),
+                    data: data.to_vec(),
+                })),
+                _ => Err(invalid()),
+            },
+            // [index, type, data_len, data...] per key
+            Some(WsResponseTag::SoftKeyResponse) => {
+                let mut configs = Vec::new();
+                let mut rest = payload;
+                while let [index, key_type, len, tail @ ..] = rest {
+                    let data = tail.get(..*len as usize).ok_or_else(invalid)?;
+                    configs.push(SoftKeyConfig {
+                        index: *index,
+                        key_type: SoftKeyType::from_byte(*key_type).unwrap_or(SoftKeyType::Default),
+                        data: data.to_vec(),
+                    });
+                    rest = &tail[data.len()..];
+                }
+                Ok(Self::SoftKeys(configs))
+            }
+            Some(WsResponseTag::VersionResponse) => Ok(Self::Version(String::from_utf8_lossy(payload).into_owned())),
+            Some(WsResponseTag::HelloResponse) => serde_json::from_slice(payload).map(Self::Hello).map_err(|_| invalid()),
+            None => Err(format!("unknown response tag 0x{:02X}", tag)),
+        }
+    }
+}
+
+/// A JSON command in a text frame. `id` is echoed in the reply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsJsonRequest {
+    #[serde(default)]
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub command: WsCommand,
+}
+
+/// Reply to a `WsJsonRequest`: `result` on success, `error` otherwise
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsJsonReply {
+    pub id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<WsCommandResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl WsJsonReply {
+    pub fn new(id: Option<u64>, result: Result<WsCommandResult, String>) -> Self {
+        match result {
+            Ok(result) => Self { id, result: Some(result), error: None },
+            Err(error) => Self { id, result: None, error: Some(error) },
+        }
+    }
+}
+
+/// A WebSocket event, one variant per `WsEventTag`.
+/// The JSON form is `{"event": ..., "data": ...}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum WsEvent {
+    DeviceConnected(DeviceInfo),
+    DeviceDisconnected {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        serial: Option<String>,
+    },
+    StateChanged {
+        mode: DeviceMode,
+        yolo: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        serial: Option<String>,
+    },
+    KeyEvent {
+        keycode: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        serial: Option<String>,
+    },
+    TypeString {
+        text: String,
+        send_enter: bool,
+    },
+    AppControl {
+        action: AppControlAction,
+    },
+    RoleChanged {
+        role: WsClientRole,
+    },
+    ControlRequested,
+    AlertAcknowledged(AlertRecord),
+}
+
+impl WsEvent {
+    pub fn tag(&self) -> WsEventTag {
+        match self {
+            Self::DeviceConnected(_) => WsEventTag::DeviceConnected,
+            Self::DeviceDisconnected { .. } => WsEventTag::DeviceDisconnected,
+            Self::StateChanged { .. } => WsEventTag::StateChanged,
+            Self::KeyEvent { .. } => WsEventTag::KeyEvent,
+            Self::TypeString { .. } => WsEventTag::TypeString,
+            Self::AppControl { .. } => WsEventTag::AppControl,
+            Self::RoleChanged { .. } => WsEventTag::RoleChanged,
+            Self::ControlRequested => WsEventTag::ControlRequested,
+            Self::AlertAcknowledged(_) => WsEventTag::AlertAcknowledged,
+        }
+    }
+
+    /// Binary event frame (seq 0)
+    pub fn encode(&self) -> Vec<u8> {
+        let tagged = |fixed: Vec<u8>, serial: &Option<String>| tag_with_serial(fixed, serial.as_deref().unwrap_or(""));
+        let payload = match self {
+            Self::DeviceConnected(info) => serde_json::to_vec(info).unwrap_or_default(),
+            Self::DeviceDisconnected { serial } => tagged(Vec::new(), serial),
+            Self::StateChanged { mode, yolo, serial } => {
+                let state = DeviceState { mode: *mode, yolo: *yolo };
+                tagged(vec![state.to_byte()], serial)
+            }
+            Self::KeyEvent { keycode, serial } => tagged(keycode.to_be_bytes().to_vec(), serial),
+            Self::TypeString { text, send_enter } => [&[u8::from(*send_enter)][..], text.as_bytes()].concat(),
+            Self::AppControl { action } => vec![*action as u8],
+            Self::RoleChanged { role } => vec![*role as u8],
+            Self::ControlRequested => Vec::new(),
+            Self::AlertAcknowledged(record) => serde_json::to_vec(record).unwrap_or_default(),
+        };
+        encode_ws_frame(self.tag() as u8, 0, &payload)
+    }
+
+    /// Parse the payload of a binary event frame
+    pub fn decode(tag: WsEventTag, payload: &[u8]) -> Option<Self> {
+        let serial = |fixed_len| event_serial(payload, fixed_len).map(str::to_string);
+        Some(match tag {
+            WsEventTag::DeviceConnected => Self::DeviceConnected(serde_json::from_slice(payload).ok()?),
+            WsEventTag::DeviceDisconnected => Self::DeviceDisconnected { serial: serial(0) },
+            WsEventTag::StateChanged => {
+                let state = DeviceState::from_byte(*payload.first()?);
+                Self::StateChanged { mode: state.mode, yolo: state.yolo, serial: serial(1) }
+            }
+            WsEventTag::KeyEvent => match payload {
+                [hi, lo, ..] => Self::KeyEvent { keycode: u16::from_be_bytes([*hi, *lo]), serial: serial(2) },
+                _ => return None,
+            },
+            WsEventTag::TypeString => {
+                let (flags, text) = payload.split_first()?;
+                Self::TypeString {
+                    text: String::from_utf8_lossy(text).into_owned(),
+                    send_enter: flags & 0x01 != 0,
+                }
+            }
+            WsEventTag::AppControl => Self::AppControl {
+                action: AppControlAction::from_byte(*payload.first()?)?,
+            },
+            WsEventTag::RoleChanged => Self::RoleChanged {
+                role: WsClientRole::from_byte(*payload.first()?)?,
+            },
+            WsEventTag::ControlRequested => Self::ControlRequested,
+            WsEventTag::AlertAcknowledged => Self::AlertAcknowledged(serde_json::from_slice(payload).ok()?),
+        })
+    }
+}
+
 // ── HTTP REST types ────────────────────────────────────────────────
 
 /// Default daemon listen address
 pub const DEFAULT_DAEMON_ADDR: &str = "127.0.0.1:19384";
 
+/// Default daemon Unix socket, relative to `$XDG_RUNTIME_DIR`
+pub const DAEMON_SOCKET_NAME: &str = "coredeck/daemon.sock";
+
+/// Default daemon Unix socket path. `None` where `$XDG_RUNTIME_DIR` is unset (e.g. macOS).
+pub fn default_socket_path() -> Option<std::path::PathBuf> {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty())?;
+    Some(std::path::PathBuf::from(dir).join(DAEMON_SOCKET_NAME))
+}
+
+/// One deck in GET /api/status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceSummary {
+    /// USB serial number, or the OS device path when the deck has none
+    /// (or shares it with another deck). Used to address the deck.
+    pub serial: String,
+    pub name: String,
+    /// HID interface open and communicating
+    pub connected: bool,
+    /// Firmware version (if connected)
+    pub firmware_version: Option<String>,
+    /// Limits reported by the firmware (if connected and supported)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<DeviceCapabilities>,
+    pub mode: DeviceMode,
+    pub yolo: bool,
+}
+
 /// Response for GET /api/status
+///
+/// The `device_*` fields summarise `devices` for single-deck clients: present
+/// or connected if any deck is, the rest taken from the first connected deck
+/// (or the first deck when none is connected).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DaemonStatus {
     /// Whether the USB device is physically present (enumerated)
@@ -279,9 +1109,46 @@ pub struct DaemonStatus {
     pub device_yolo: bool,
     /// Whether a WebSocket client (app) is connected (has the lock)
     pub ws_locked: bool,
+    /// Number of WebSocket clients connected as observers
+    #[serde(default)]
+    pub ws_observers: usize,
+    /// Every deck plugged in, ordered by serial
+    #[serde(default)]
+    pub devices: Vec<DeviceSummary>,
+    /// HID link health across all decks (details at GET /api/metrics)
+    #[serde(default)]
+    pub hid_link: HidLinkStats,
 }
 
-/// Request body for POST /api/display
+/// HID link health counters since the daemon started
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HidLinkStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub write_errors: u64,
+    /// Report writes tried again after a failure
+    #[serde(default)]
+    pub write_retries: u64,
+    /// Chunked responses that restarted before their END chunk
+    pub chunk_retries: u64,
+    pub ping_failures: u64,
+    /// Decks that dropped while open (ping failures or unplugged)
+    pub link_losses: u64,
+    /// Decks reopened after their link was lost
+    pub reconnects: u64,
+    /// Display updates skipped as identical to the last one sent
+    pub display_updates_deduplicated: u64,
+    /// Queued display updates replaced by a newer one before being sent
+    #[serde(default)]
+    pub display_updates_coalesced: u64,
+    /// Commands refused because the HID write queue was full
+    #[serde(default)]
+    pub queue_rejections: u64,
+    /// Mean ping round trip in milliseconds (`None` before the first pong)
+    pub ping_rtt_avg_ms: Option<f64>,
+}
+
+/// Request body for POST /api/display (also the UpdateDisplayLayer WS payload)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DisplayUpdateRequest {
     pub session: String,
@@ -293,6 +1160,42 @@ pub struct DisplayUpdateRequest {
     pub tabs: Vec<u8>,
     #[serde(default)]
     pub active: usize,
+    /// Display layer this content belongs to (default: "rest" over HTTP, "app" over WS)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Higher priority layers are shown first; ties go to the most recent update
+    #[serde(default)]
+    pub priority: u8,
+    /// Lease in seconds — the layer is dropped afterwards and the display reverts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+    /// Serial of the deck to show this on (default: every deck)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+impl DisplayUpdateRequest {
+    /// The display content, without the layer metadata
+    pub fn display_update(&self) -> DisplayUpdate {
+        DisplayUpdate {
+            session: self.session.clone(),
+            task: self.task.clone(),
+            task2: self.task2.clone(),
+            tabs: self.tabs.clone(),
+            active: self.active,
+        }
+    }
+}
+
+/// Request body for POST /api/display/clear (also the ClearDisplayLayer WS payload)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClearDisplayRequest {
+    /// Display layer to remove (default: "rest" over HTTP, "app" over WS)
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Only remove the layer's content for this deck (default: on every deck)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
 }
 
 /// Request body for POST /api/alert
@@ -302,12 +1205,51 @@ pub struct AlertRequest {
     pub session: String,
     pub text: String,
     pub details: Option<String>,
+    /// Serial of the deck to show this on (default: every deck)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+/// Who raised or cleared an alert
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSource {
+    /// The WebSocket controller (the app)
+    App,
+    /// An HTTP REST caller
+    Rest,
+    /// A key pressed on the device while the alert's tab was active
+    Key,
+    /// A newer alert on the same tab took its place
+    Replaced,
+}
+
+/// One entry in the alert history (GET /api/alerts, AlertAcknowledged event)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRecord {
+    pub id: u64,
+    pub tab: usize,
+    pub session: String,
+    pub text: String,
+    pub details: Option<String>,
+    /// Unix time in milliseconds
+    pub raised_at: u64,
+    pub raised_by: AlertSource,
+    /// Unix time in milliseconds, `None` while the alert is still shown
+    pub cleared_at: Option<u64>,
+    pub cleared_by: Option<AlertSource>,
+    /// Deck the alert was shown on (`None` = every deck)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
 }
 
 /// Request body for POST /api/alert/clear
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClearAlertRequest {
     pub tab: usize,
+    /// Serial of the deck to clear it on (default: every deck)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
 }
 
 /// Request body for POST /api/brightness
@@ -316,12 +1258,39 @@ pub struct BrightnessRequest {
     pub level: u8,
     #[serde(default)]
     pub save: bool,
+    /// Serial of the deck to change (default: every deck)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
 }
 
 /// Request body for POST /api/mode
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SetModeRequest {
     pub mode: DeviceMode,
+    /// Serial of the deck to change (default: every deck)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+/// Request body for PUT /api/softkeys/{index}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetSoftKeyRequest {
+    #[serde(flatten)]
+    pub action: SoftKeyAction,
+    /// Keep the assignment across power cycles
+    #[serde(default)]
+    pub save: bool,
+    /// Serial of the deck to change (default: every deck)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+/// Query string selecting one deck for read endpoints (`?device=<serial>`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceQuery {
+    /// Serial of the deck to read from (default: the first connected deck)
+    #[serde(default)]
+    pub device: Option<String>,
 }
 
 /// Generic API error response
@@ -330,6 +1299,107 @@ pub struct ApiError {
     pub error: String,
 }
 
+/// What the daemon did with a field that broke a protocol limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldAction {
+    /// Shortened to fit, on a character boundary
+    Truncated,
+    /// Cannot be fitted; the request was refused
+    Rejected,
+}
+
+/// One field of a display update or alert that broke a protocol limit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldIssue {
+    /// Request field name (`payload` for the whole JSON message)
+    pub field: String,
+    pub action: FieldAction,
+    pub reason: String,
+}
+
+/// 422 response body: at least one field was rejected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationError {
+    pub error: String,
+    /// Every field that was truncated or rejected
+    pub fields: Vec<FieldIssue>,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.error)?;
+        for (i, issue) in self.fields.iter().filter(|i| i.action == FieldAction::Rejected).enumerate() {
+            let sep = if i == 0 { ": " } else { "; " };
+            write!(f, "{}{} {}", sep, issue.field, issue.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// 200 response body when the request went through with shortened fields
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldAdjustments {
+    pub adjusted: Vec<FieldIssue>,
+}
+
+// ── Authentication ─────────────────────────────────────────────────
+
+/// Token file read by the daemon (and the app), in the CoreDeck config directory
+pub const TOKENS_FILE_NAME: &str = "tokens.toml";
+
+/// Name of the token the daemon generates for the app on first start
+pub const APP_TOKEN_NAME: &str = "app";
+
+/// Query parameter carrying the token (for WebSocket clients that can't set headers)
+pub const TOKEN_QUERY_PARAM: &str = "token";
+
+/// What an API token may do. Every valid token may read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthScope {
+    /// Status, events, alert history, version and observer connections
+    Read,
+    /// Display, alerts, brightness, mode and WS control
+    Display,
+    /// Soft key writes
+    Softkey,
+}
+
+impl AuthScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Display => "display",
+            Self::Softkey => "softkey",
+        }
+    }
+}
+
+/// One entry in `tokens.toml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// Label shown in the daemon log
+    pub name: String,
+    pub token: String,
+    #[serde(default)]
+    pub scopes: Vec<AuthScope>,
+}
+
+/// Contents of `tokens.toml` (`[[token]]` tables)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenFile {
+    #[serde(default, rename = "token")]
+    pub tokens: Vec<ApiToken>,
+}
+
+impl TokenFile {
+    /// Look up a token by name
+    pub fn find(&self, name: &str) -> Option<&ApiToken> {
+        self.tokens.iter().find(|t| t.name == name)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -369,6 +1439,172 @@ mod tests {
         assert!(data.is_empty());
     }
 
+    #[test]
+    fn test_event_serial() {
+        let payload = tag_with_serial(vec![0x00, 0x04], "CD-0001");
+        assert_eq!(event_serial(&payload, 2), Some("CD-0001"));
+        assert_eq!(event_serial(&[0x00, 0x04], 2), None);
+        assert_eq!(event_serial(&[0x05], 2), None);
+    }
+
+    #[test]
+    fn test_json_command() {
+        let req: WsJsonRequest =
+            serde_json::from_str(r#"{"id":7,"method":"set_mode","params":{"mode":"Plan"}}"#).unwrap();
+        assert_eq!(req.id, Some(7));
+        assert!(matches!(req.command, WsCommand::SetMode(SetModeRequest { mode: DeviceMode::Plan, .. })));
+        assert_eq!(req.command.tag(), WsCommandTag::SetMode);
+
+        // Commands without parameters need no `params`
+        let req: WsJsonRequest = serde_json::from_str(r#"{"method":"get_version"}"#).unwrap();
+        assert_eq!(req.id, None);
+        assert!(matches!(req.command, WsCommand::GetVersion));
+        let req: WsJsonRequest = serde_json::from_str(r#"{"method":"reset_soft_keys"}"#).unwrap();
+        assert!(matches!(req.command, WsCommand::ResetSoftKeys(None)));
+        let req: WsJsonRequest =
+            serde_json::from_str(r#"{"method":"get_soft_key","params":{"index":1,"device":"CD-0002"}}"#).unwrap();
+        assert!(matches!(req.command, WsCommand::GetSoftKey { index: 1, device: Some(ref d) } if d == "CD-0002"));
+
+        assert!(serde_json::from_str::<WsJsonRequest>(r#"{"id":1,"method":"reboot"}"#).is_err());
+    }
+
+    #[test]
+    fn test_binary_command_decode() {
+        let cmd = WsCommand::decode(WsCommandTag::SetSoftKey, &[1, 2, 1, b'l', b's']).unwrap();
+        let WsCommand::SetSoftKey { index, key_type, data, save, device: None } = cmd else {
+            panic!("decoded {:?}", cmd);
+        };
+        assert_eq!((index, key_type, save), (1, SoftKeyType::String, true));
+        assert_eq!(data, b"ls");
+
+        assert!(matches!(
+            WsCommand::decode(WsCommandTag::ClearAlert, &[3]),
+            Ok(WsCommand::ClearAlert(ClearAlertRequest { tab: 3, device: None }))
+        ));
+        assert!(matches!(
+            WsCommand::decode(WsCommandTag::ClearDisplayLayer, &[]),
+            Ok(WsCommand::ClearDisplayLayer(ClearDisplayRequest { source: None, device: None }))
+        ));
+        assert_eq!(
+            WsCommand::decode(WsCommandTag::SetBrightness, &[200]).unwrap_err(),
+            "invalid payload"
+        );
+    }
+
+    #[test]
+    fn test_binary_command_encode() {
+        let cmd =
+            WsCommand::SetSoftKey { index: 2, key_type: SoftKeyType::String, data: b"ls".to_vec(), save: true, device: None };
+        assert_eq!(cmd.encode(7), vec![0x04, 7, 0, 2, 2, 1, b'l', b's']);
+        let clear = WsCommand::ClearAlert(ClearAlertRequest { tab: 3, device: None });
+        assert_eq!(clear.encode(1), vec![0x0A, 1, 0, 3]);
+
+        let commands = [
+            WsCommand::SetBrightness(BrightnessRequest { level: 200, save: true, device: None }),
+            WsCommand::SetMode(SetModeRequest { mode: DeviceMode::Plan, device: None }),
+            WsCommand::ClearAlert(ClearAlertRequest { tab: 1, device: Some("CD-0001".into()) }),
+            WsCommand::ClearDisplayLayer(ClearDisplayRequest::default()),
+            WsCommand::GetVersion,
+            WsCommand::GetSoftKey { index: 1, device: None },
+            WsCommand::GetSoftKey { index: 2, device: Some("CD-0002".into()) },
+            WsCommand::ResetSoftKeys(None),
+            WsCommand::ResetSoftKeys(Some(DeviceQuery { device: Some("CD-0002".into()) })),
+        ];
+        for cmd in commands {
+            let frame = cmd.encode(5);
+            let (tag, seq, payload) = decode_ws_frame(&frame).unwrap();
+            let decoded = WsCommand::decode(WsCommandTag::from_byte(tag).unwrap(), payload).unwrap();
+            assert_eq!(seq, 5);
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&cmd).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_command_result_decode() {
+        let keys = vec![
+            SoftKeyConfig { index: 0, key_type: SoftKeyType::Keycode, data: vec![0x00, 0x29] },
+            SoftKeyConfig { index: 1, key_type: SoftKeyType::Default, data: vec![] },
+        ];
+        let frame = WsCommandResult::SoftKeys(keys).encode(2);
+        let decoded = WsCommandResult::decode(WsCommandTag::ResetSoftKeys, frame[0], &frame[3..]).unwrap();
+        let WsCommandResult::SoftKeys(decoded) = decoded else {
+            panic!("decoded {:?}", decoded);
+        };
+        assert_eq!(decoded.len(), 2);
+        assert_eq!((decoded[0].key_type, &decoded[0].data[..]), (SoftKeyType::Keycode, &[0x00, 0x29][..]));
+
+        let key = WsCommandResult::decode(WsCommandTag::GetSoftKey, 0x85, &[2, 2, b'l', b's']).unwrap();
+        assert!(matches!(key, WsCommandResult::SoftKey(SoftKeyConfig { index: 2, key_type: SoftKeyType::String, .. })));
+        assert!(matches!(
+            WsCommandResult::decode(WsCommandTag::GetVersion, 0x86, b"1.2.0"),
+            Ok(WsCommandResult::Version(v)) if v == "1.2.0"
+        ));
+        assert_eq!(
+            WsCommandResult::decode(WsCommandTag::SetMode, 0x88, b"not the controller").unwrap_err(),
+            "not the controller"
+        );
+        assert!(WsCommandResult::decode(WsCommandTag::ResetSoftKeys, 0x85, &[0, 1, 4, 0]).is_err());
+    }
+
+    #[test]
+    fn test_command_result_encoding() {
+        let keys = WsCommandResult::SoftKeys(vec![
+            SoftKeyConfig { index: 0, key_type: SoftKeyType::Keycode, data: vec![0x00, 0x29] },
+            SoftKeyConfig { index: 1, key_type: SoftKeyType::Default, data: vec![] },
+        ]);
+        assert_eq!(keys.encode(9), vec![0x85, 9, 0, 0, 1, 2, 0x00, 0x29, 1, 0, 0]);
+        assert_eq!(WsCommandResult::Ack.encode(9), vec![0x87, 9, 0]);
+
+        let reply = WsJsonReply::new(Some(3), Ok(WsCommandResult::Version("1.0.0".to_string())));
+        assert_eq!(serde_json::to_string(&reply).unwrap(), r#"{"id":3,"result":{"version":"1.0.0"}}"#);
+        let reply = WsJsonReply::new(None, Err("Device not connected".to_string()));
+        assert_eq!(serde_json::to_string(&reply).unwrap(), r#"{"id":null,"error":"Device not connected"}"#);
+    }
+
+    #[test]
+    fn test_hello() {
+        let cmd = WsCommand::decode(WsCommandTag::Hello, br#"{"protocol_version":1}"#).unwrap();
+        assert!(matches!(cmd, WsCommand::Hello(ClientHello { protocol_version: 1, .. })));
+
+        let hello = DaemonHello {
+            protocol_version: PROTOCOL_VERSION,
+            daemon_version: "0.3.0".to_string(),
+            commands: WsCommandTag::ALL.iter().map(|&t| t as u8).collect(),
+            devices: Vec::new(),
+        };
+        let frame = WsCommandResult::Hello(hello).encode(4);
+        let (tag, seq, payload) = decode_ws_frame(&frame).unwrap();
+        assert_eq!((tag, seq), (WsResponseTag::HelloResponse as u8, 4));
+        let decoded: DaemonHello = serde_json::from_slice(payload).unwrap();
+        assert!(decoded.supports(WsCommandTag::Hello));
+
+        let legacy = DaemonHello::legacy();
+        assert!(legacy.supports(WsCommandTag::ClearDisplayLayer));
+        assert!(!legacy.supports(WsCommandTag::Hello));
+    }
+
+    #[test]
+    fn test_event_round_trip() {
+        let events = [
+            WsEvent::KeyEvent { keycode: 0x7E01, serial: Some("CD-0001".to_string()) },
+            WsEvent::StateChanged { mode: DeviceMode::Accept, yolo: true, serial: None },
+            WsEvent::TypeString { text: "ls".to_string(), send_enter: true },
+            WsEvent::RoleChanged { role: WsClientRole::Observer },
+            WsEvent::DeviceDisconnected { serial: Some("CD-0002".to_string()) },
+        ];
+        for event in events {
+            let frame = event.encode();
+            let (tag, seq, payload) = decode_ws_frame(&frame).unwrap();
+            assert_eq!(seq, 0);
+            let decoded = WsEvent::decode(WsEventTag::from_byte(tag).unwrap(), payload).unwrap();
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&event).unwrap());
+        }
+
+        let json = serde_json::to_value(WsEvent::KeyEvent { keycode: 4, serial: None }).unwrap();
+        assert_eq!(json, serde_json::json!({"event": "key_event", "data": {"keycode": 4}}));
+        assert!(WsEvent::decode(WsEventTag::KeyEvent, &[0x00]).is_none());
+    }
+
     #[test]
     fn test_ws_frame_too_short() {
         assert!(decode_ws_frame(&[0x01]).is_none());
@@ -383,6 +1619,49 @@ mod tests {
         assert_eq!(SoftKeyType::from_byte(4), None);
     }
 
+    #[test]
+    fn test_soft_key_action_wire_roundtrip() {
+        let ctrl_c = KeyStroke { usage: 0x06, ctrl: true, ..Default::default() };
+        assert_eq!(ctrl_c.to_keycode(), 0x0106);
+        for action in [
+            SoftKeyAction::Keycode { key: ctrl_c },
+            SoftKeyAction::String { text: "git status".into(), send_enter: true },
+            SoftKeyAction::Sequence { keys: vec![ctrl_c, KeyStroke { usage: 0x19, ..Default::default() }] },
+            SoftKeyAction::Default { key: None },
+        ] {
+            let (key_type, data) = action.to_wire();
+            assert_eq!(SoftKeyAction::from_config(&SoftKeyConfig { index: 0, key_type, data }), action);
+        }
+
+        let (_, data) = SoftKeyAction::String { text: "é".repeat(100), send_enter: false }.to_wire();
+        assert_eq!(data.len(), 1 + SOFT_KEY_MAX_TEXT + 1);
+        let (_, data) = SoftKeyAction::Sequence { keys: vec![ctrl_c; 70] }.to_wire();
+        assert_eq!(data[0] as usize, SOFT_KEY_MAX_STEPS);
+    }
+
+    #[test]
+    fn test_soft_key_json() {
+        let req: SetSoftKeyRequest =
+            serde_json::from_str(r#"{"type": "keycode", "key": {"usage": 41, "shift": true}, "save": true}"#).unwrap();
+        let key = KeyStroke { usage: 41, shift: true, ..Default::default() };
+        assert_eq!(req.action, SoftKeyAction::Keycode { key });
+        assert!(req.save && req.device.is_none());
+
+        let req: SetSoftKeyRequest = serde_json::from_str(r#"{"type": "string", "text": "ls"}"#).unwrap();
+        assert_eq!(req.action, SoftKeyAction::String { text: "ls".into(), send_enter: false });
+
+        let reset = SoftKeyConfig { index: 2, key_type: SoftKeyType::Default, data: vec![0x00, 0x29] };
+        let json = serde_json::to_value(SoftKeyAssignment::from(&reset)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "index": 2,
+                "type": "default",
+                "key": {"usage": 41, "ctrl": false, "shift": false, "alt": false, "gui": false}
+            })
+        );
+    }
+
     #[test]
     fn test_display_update_json() {
         let update = DisplayUpdate {
@@ -406,24 +1685,149 @@ mod tests {
             device_mode: DeviceMode::Default,
             device_yolo: false,
             ws_locked: false,
+            ws_observers: 2,
+            devices: vec![DeviceSummary {
+                serial: "CD-0001".to_string(),
+                name: "Core Deck".to_string(),
+                connected: true,
+                firmware_version: Some("1.0.0".to_string()),
+                capabilities: Some(DeviceCapabilities::default()),
+                mode: DeviceMode::Default,
+                yolo: false,
+            }],
+            hid_link: HidLinkStats { reconnects: 1, ..HidLinkStats::default() },
         };
         let json = serde_json::to_string(&status).unwrap();
         let parsed: DaemonStatus = serde_json::from_str(&json).unwrap();
         assert!(parsed.device_connected);
         assert_eq!(parsed.device_name.as_deref(), Some("Core Deck"));
+        assert_eq!(parsed.ws_observers, 2);
+        assert_eq!(parsed.devices[0].serial, "CD-0001");
+        assert_eq!(parsed.devices[0].capabilities, Some(DeviceCapabilities::default()));
+        assert_eq!(parsed.hid_link.reconnects, 1);
+    }
+
+    #[test]
+    fn test_capabilities_defaults() {
+        // Firmware reports only what differs from the original limits
+        let caps: DeviceCapabilities = serde_json::from_str(r#"{"max_tabs":8,"alert_fields":["text"]}"#).unwrap();
+        assert_eq!(caps.max_tabs, 8);
+        assert_eq!(caps.max_text_bytes, 128);
+        assert_eq!((caps.display_width, caps.display_height), (284, 76));
+
+        let mut update = DisplayUpdate {
+            session: "é".repeat(100),
+            task: "build".to_string(),
+            task2: String::new(),
+            tabs: vec![1; 12],
+            active: 11,
+        };
+        caps.clamp_display(&mut update);
+        assert_eq!(update.session.len(), 126); // 127 would split a 2-byte char
+        assert_eq!((update.tabs.len(), update.active), (8, 7));
+
+        let mut alert = AlertRequest {
+            tab: 0,
+            session: "proj".to_string(),
+            text: "Approve?".to_string(),
+            details: Some("src/main.rs".to_string()),
+            device: None,
+        };
+        caps.clamp_alert(&mut alert);
+        assert_eq!(alert.details, None);
+        assert_eq!(alert.text, "Approve?");
+    }
+
+    #[test]
+    fn test_daemon_status_without_observers() {
+        let json = r#"{"device_connected":false,"device_name":null,"firmware_version":null,"device_mode":"Default","device_yolo":false,"ws_locked":true}"#;
+        let parsed: DaemonStatus = serde_json::from_str(json).unwrap();
+        assert!(parsed.ws_locked);
+        assert_eq!(parsed.ws_observers, 0);
+        assert!(parsed.devices.is_empty());
+        assert_eq!(parsed.hid_link, HidLinkStats::default());
+    }
+
+    #[test]
+    fn test_display_request_defaults() {
+        let req: DisplayUpdateRequest = serde_json::from_str(r#"{"session":"ci"}"#).unwrap();
+        assert_eq!(req.source, None);
+        assert_eq!(req.priority, 0);
+        assert_eq!(req.ttl_secs, None);
+        assert_eq!(req.device, None);
+
+        let req: DisplayUpdateRequest = serde_json::from_str(
+            r#"{"session":"ci","task":"Build failed","source":"ci","priority":5,"ttl_secs":30}"#,
+        )
+        .unwrap();
+        assert_eq!(req.source.as_deref(), Some("ci"));
+        assert_eq!(req.ttl_secs, Some(30));
+        assert_eq!(req.display_update().task, "Build failed");
+    }
+
+    #[test]
+    fn test_alert_record_json() {
+        let record = AlertRecord {
+            id: 7,
+            tab: 1,
+            session: "proj".to_string(),
+            text: "Allow edit?".to_string(),
+            details: None,
+            raised_at: 1_000,
+            raised_by: AlertSource::App,
+            cleared_at: Some(2_000),
+            cleared_by: Some(AlertSource::Key),
+            device: None,
+        };
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["raised_by"], "app");
+        assert_eq!(json["cleared_by"], "key");
+        let parsed: AlertRecord = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.cleared_by, Some(AlertSource::Key));
     }
 
     #[test]
     fn test_command_tags() {
         assert_eq!(WsCommandTag::from_byte(0x01), Some(WsCommandTag::UpdateDisplay));
         assert_eq!(WsCommandTag::from_byte(0x0A), Some(WsCommandTag::ClearAlert));
+        assert_eq!(WsCommandTag::from_byte(0x0C), Some(WsCommandTag::RequestControl));
         assert_eq!(WsCommandTag::from_byte(0xFF), None);
+        for tag in WsCommandTag::ALL {
+            assert_eq!(WsCommandTag::from_byte(tag as u8), Some(tag));
+        }
+    }
+
+    #[test]
+    fn test_command_requires_control() {
+        assert!(WsCommandTag::UpdateDisplay.requires_control());
+        assert!(WsCommandTag::Alert.requires_control());
+        assert!(!WsCommandTag::GetVersion.requires_control());
+        assert!(!WsCommandTag::RequestControl.requires_control());
+    }
+
+    #[test]
+    fn test_command_required_scope() {
+        assert_eq!(WsCommandTag::GetVersion.required_scope(), AuthScope::Read);
+        assert_eq!(WsCommandTag::RequestControl.required_scope(), AuthScope::Display);
+        assert_eq!(WsCommandTag::SetSoftKey.required_scope(), AuthScope::Softkey);
+        let scopes: Vec<AuthScope> = serde_json::from_str(r#"["read","softkey"]"#).unwrap();
+        assert_eq!(scopes, vec![AuthScope::Read, AuthScope::Softkey]);
+    }
+
+    #[test]
+    fn test_client_role() {
+        assert_eq!(WsClientRole::from_byte(WsClientRole::Observer as u8), Some(WsClientRole::Observer));
+        assert_eq!(WsClientRole::from_byte(2), None);
+        assert_eq!(WsClientRole::default(), WsClientRole::Controller);
+        let role: WsClientRole = serde_json::from_str("\"observer\"").unwrap();
+        assert_eq!(role, WsClientRole::Observer);
     }
 
     #[test]
     fn test_event_tags() {
         assert_eq!(WsEventTag::from_byte(0x80), Some(WsEventTag::DeviceConnected));
         assert_eq!(WsEventTag::from_byte(0x89), Some(WsEventTag::AppControl));
+        assert_eq!(WsEventTag::from_byte(0x8B), Some(WsEventTag::ControlRequested));
         assert_eq!(WsEventTag::from_byte(0x00), None);
     }
 
@@ -431,6 +1835,7 @@ mod tests {
     fn test_response_tags() {
         assert_eq!(WsResponseTag::from_byte(0x85), Some(WsResponseTag::SoftKeyResponse));
         assert_eq!(WsResponseTag::from_byte(0x88), Some(WsResponseTag::CommandError));
+        assert_eq!(WsResponseTag::from_byte(0x8D), Some(WsResponseTag::HelloResponse));
         assert_eq!(WsResponseTag::from_byte(0x00), None);
     }
 }
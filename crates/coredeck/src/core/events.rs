@@ -49,6 +49,12 @@ pub enum AppEvent {
     /// Daemon WebSocket disconnected (covers both daemon down and device unplug)
     DaemonDisconnected,
 
+    /// Daemon answered the protocol handshake (version 0 for daemons that predate it)
+    DaemonHello {
+        protocol_version: u32,
+        daemon_version: String,
+    },
+
     /// HID device connected (received from daemon)
     HidConnected {
         device_name: String,
@@ -3,12 +3,13 @@
 //! Bridges between the HID protocol's wire format and the settings UI.
 
 use anyhow::{Context, Result};
+use coredeck_protocol::{DeviceProfile, SoftKeyAction};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use super::keycodes::{compose_keycode, decompose_keycode, KeyModifiers, QmkKeycode};
-use super::protocol::{SoftKeyConfig, SoftKeyType};
+use super::protocol::{DeviceMode, SoftKeyConfig, SoftKeyType};
 
 /// A single key with optional modifiers
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -143,6 +144,18 @@ impl SoftKeyEditState {
         }
     }
 
+    /// Portable form used by device profiles and the daemon's REST API
+    pub fn to_action(&self) -> SoftKeyAction {
+        let (key_type, data) = self.to_wire_data();
+        SoftKeyAction::from_config(&SoftKeyConfig { index: 0, key_type, data })
+    }
+
+    /// Decode the portable form (keys without a UI name fall back to Default)
+    pub fn from_action(action: &SoftKeyAction) -> Self {
+        let (key_type, data) = action.to_wire();
+        Self::from_config(&SoftKeyConfig { index: 0, key_type, data })
+    }
+
     /// Human-readable summary for display
     pub fn summary(&self) -> String {
         match self {
@@ -489,6 +502,51 @@ impl PresetManager {
     }
 }
 
+// --- Device profiles (shared with `coredeck-daemon profile`) ---
+
+/// Contents of a device profile file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedProfile {
+    pub keys: [SoftKeyEditState; 3],
+    pub brightness: Option<u8>,
+    pub mode: Option<DeviceMode>,
+}
+
+/// Where the settings modal offers to import/export a profile
+pub fn default_profile_path() -> PathBuf {
+    dirs::home_dir().unwrap_or_default().join("coredeck-profile.toml")
+}
+
+/// Write soft keys (and the device mode, if known) as a device profile
+pub fn export_profile(path: &Path, keys: &[SoftKeyEditState; 3], mode: Option<DeviceMode>) -> Result<()> {
+    let profile = DeviceProfile {
+        brightness: None,
+        mode,
+        soft_keys: keys.iter().map(SoftKeyEditState::to_action).collect(),
+    };
+    let content = toml::to_string_pretty(&profile).context("Failed to serialize profile")?;
+    std::fs::write(path, content).with_context(|| format!("Failed to write profile: {:?}", path))?;
+    Ok(())
+}
+
+/// Read a device profile. Keys missing from the file are left at Default.
+pub fn import_profile(path: &Path) -> Result<ImportedProfile> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read profile: {:?}", path))?;
+    let profile: DeviceProfile =
+        toml::from_str(&content).with_context(|| format!("Failed to parse profile: {:?}", path))?;
+    let key = |i: usize| {
+        profile
+            .soft_keys
+            .get(i)
+            .map_or(SoftKeyEditState::Default(None), SoftKeyEditState::from_action)
+    };
+    Ok(ImportedProfile {
+        keys: [key(0), key(1), key(2)],
+        brightness: profile.brightness,
+        mode: profile.mode,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -736,4 +794,28 @@ mod tests {
         assert!(mgr.all().is_empty());
         assert!(!mgr.remove("Nonexistent"));
     }
+
+    #[test]
+    fn test_profile_roundtrip() {
+        let keys = presets().remove(0).keys;
+        let path = std::env::temp_dir().join(format!("coredeck-profile-test-{}.toml", std::process::id()));
+        export_profile(&path, &keys, Some(DeviceMode::Accept)).unwrap();
+        let imported = import_profile(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(imported.keys, keys);
+        assert_eq!(imported.mode, Some(DeviceMode::Accept));
+        assert_eq!(imported.brightness, None);
+    }
+
+    #[test]
+    fn test_action_roundtrip() {
+        let state = SoftKeyEditState::Keycode(KeycodeEntry::with_mods(
+            QmkKeycode::C,
+            KeyModifiers { ctrl: true, ..Default::default() },
+        ));
+        let action = state.to_action();
+        assert!(matches!(action, SoftKeyAction::Keycode { key } if key.usage == 0x06 && key.ctrl));
+        assert_eq!(SoftKeyEditState::from_action(&action), state);
+    }
 }
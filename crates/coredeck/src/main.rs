@@ -874,6 +874,24 @@ impl App {
                     }
                 }
             }
+            TerminalAction::ApplyDeviceSettings { brightness, mode } => {
+                if let Some(ref client) = self.daemon_client {
+                    if let Some(level) = brightness {
+                        if let Err(e) = client.set_brightness(level, true) {
+                            error!("Failed to set brightness: {}", e);
+                        }
+                    }
+                    if let Some(mode) = mode {
+                        // Same bookkeeping as HidSetMode, so the echoed StateReport
+                        // isn't taken for a button press
+                        self.terminal_window.last_device_reported_mode = Some(mode);
+                        self.terminal_window.mode_set_from_app_at = Some(std::time::Instant::now());
+                        if let Err(e) = client.set_mode(mode) {
+                            error!("Failed to set mode: {}", e);
+                        }
+                    }
+                }
+            }
             TerminalAction::HidDisplayUpdate { session, task, task2, tabs, active } => {
                 if let Some(ref client) = self.daemon_client {
                     if let Err(e) = client.send_display_update(&session, task.as_deref(), task2.as_deref(), &tabs, active) {
@@ -953,6 +971,7 @@ impl App {
                     state.firmware_version = None;
                 }
                 self.terminal_window.daemon_connected = false;
+                self.terminal_window.daemon_outdated = false;
                 self.terminal_window.hid_connected = false;
                 self.terminal_window.device_name = None;
                 self.terminal_window.firmware_version = None;
@@ -963,6 +982,11 @@ impl App {
                     session.hid_alert_details = None;
                 }
             }
+            AppEvent::DaemonHello { protocol_version, daemon_version } => {
+                debug!("Daemon {} speaks protocol v{}", daemon_version, protocol_version);
+                self.terminal_window.daemon_outdated =
+                    protocol_version < coredeck_protocol::PROTOCOL_VERSION;
+            }
             AppEvent::HidConnected { device_name, firmware_version } => {
                 info!("HID device connected: {} (fw {})", device_name, firmware_version);
                 {
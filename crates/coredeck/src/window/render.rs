@@ -999,6 +999,7 @@ pub fn render_device_popup(
     ctx: &egui::Context,
     device_popup_open: &mut bool,
     daemon_connected: bool,
+    daemon_outdated: bool,
     hid_connected: bool,
     device_name: &Option<String>,
     firmware_version: &Option<String>,
@@ -1188,6 +1189,21 @@ pub fn render_device_popup(
                             }
                         });
                     }
+
+                    if daemon_connected && daemon_outdated {
+                        ui.add_space(8.0);
+                        ui.separator();
+                        ui.add_space(4.0);
+                        ui.vertical_centered(|ui| {
+                            ui.label(
+                                egui::RichText::new(
+                                    "The daemon is older than this app. Restart coredeck-daemon to update it.",
+                                )
+                                .size(11.0)
+                                .color(egui::Color32::from_rgb(218, 165, 32)),
+                            );
+                        });
+                    }
                 });
         });
 
@@ -1277,6 +1293,9 @@ pub fn handle_settings_modal_result(
         SettingsModalResult::ResetSoftKeys => {
             new_actions.push(TerminalAction::ResetSoftKeys);
         }
+        SettingsModalResult::ApplyDeviceSettings { brightness, mode } => {
+            new_actions.push(TerminalAction::ApplyDeviceSettings { brightness, mode });
+        }
         SettingsModalResult::Cancel => {}
         SettingsModalResult::None => {}
     }
@@ -5,6 +5,7 @@
 
 use crate::core::settings::{Settings, MAX_FONT_SIZE, MIN_FONT_SIZE};
 use crate::hid::keycodes::{self, QmkKeycode};
+use crate::hid::protocol::DeviceMode;
 use crate::hid::soft_keys::{self, is_builtin_preset_name, KeycodeEntry, PresetManager, SoftKeyEditState};
 use super::glyph_cache::BASE_DPI;
 
@@ -34,6 +35,10 @@ enum PresetEditMode {
     Renaming(String, String),
     /// Confirming deletion (name to delete)
     ConfirmDelete(String),
+    /// Exporting a device profile (path being typed)
+    Exporting(String),
+    /// Importing a device profile (path being typed)
+    Importing(String),
 }
 
 /// State for the settings modal
@@ -60,6 +65,10 @@ pub struct SettingsModal {
     preset_manager: PresetManager,
     /// Current preset edit mode
     preset_edit: PresetEditMode,
+    /// Last mode the device reported (written to exported profiles)
+    pub device_mode: Option<DeviceMode>,
+    /// Outcome of the last profile import/export
+    profile_status: Option<String>,
 }
 
 impl SettingsModal {
@@ -77,6 +86,8 @@ impl SettingsModal {
             capturing_key: None,
             preset_manager: PresetManager::load().unwrap_or_default(),
             preset_edit: PresetEditMode::None,
+            device_mode: None,
+            profile_status: None,
         }
     }
 
@@ -93,6 +104,7 @@ impl SettingsModal {
         self.capturing_key = None;
         self.preset_manager = PresetManager::load().unwrap_or_default();
         self.preset_edit = PresetEditMode::None;
+        self.profile_status = None;
     }
 
     /// Close the modal without saving
@@ -147,6 +159,8 @@ pub enum SettingsModalResult {
     ApplySoftKeys([SoftKeyEditState; 3]),
     /// Reset soft keys to firmware defaults
     ResetSoftKeys,
+    /// Apply brightness and mode from an imported profile
+    ApplyDeviceSettings { brightness: Option<u8>, mode: Option<DeviceMode> },
 }
 
 impl PartialEq for SettingsModalResult {
@@ -467,7 +481,7 @@ fn render_soft_keys_content(
 
     // Preset selector area
     ui.add_space(6.0);
-    render_preset_area(ui, modal, &all_presets);
+    let result = render_preset_area(ui, modal, &all_presets);
 
     ui.add_space(6.0);
     ui.separator();
@@ -490,7 +504,7 @@ fn render_soft_keys_content(
         });
     modal.capturing_key = capturing;
 
-    SettingsModalResult::None
+    result
 }
 
 /// Render the sticky bottom buttons based on active tab and state
@@ -813,7 +827,8 @@ fn render_preset_area(
     ui: &mut egui::Ui,
     modal: &mut SettingsModal,
     builtin_presets: &[soft_keys::SoftKeyPreset],
-) {
+) -> SettingsModalResult {
+    let mut result = SettingsModalResult::None;
     let current_keys = modal.soft_keys.as_ref().unwrap();
 
     // Detect current preset match
@@ -883,6 +898,18 @@ fn render_preset_area(
                         modal.preset_edit = PresetEditMode::ConfirmDelete(name.clone());
                     }
                 }
+
+                ui.separator();
+
+                // Device profile file (shared with `coredeck-daemon profile`)
+                if ui.button("Import").clicked() {
+                    let path = soft_keys::default_profile_path().display().to_string();
+                    modal.preset_edit = PresetEditMode::Importing(path);
+                }
+                if ui.button("Export").clicked() {
+                    let path = soft_keys::default_profile_path().display().to_string();
+                    modal.preset_edit = PresetEditMode::Exporting(path);
+                }
             });
         }
 
@@ -996,7 +1023,76 @@ fn render_preset_area(
                 modal.preset_edit = PresetEditMode::None;
             }
         }
+
+        PresetEditMode::Exporting(ref path) | PresetEditMode::Importing(ref path) => {
+            let importing = matches!(modal.preset_edit, PresetEditMode::Importing(_));
+            let mut path = path.clone();
+            let mut done = false;
+            ui.horizontal(|ui| {
+                ui.label(if importing { "Import from:" } else { "Export to:" });
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut path)
+                        .desired_width(300.0)
+                        .hint_text("Profile file..."),
+                );
+
+                if response.gained_focus() || ui.memory(|m| m.has_focus(response.id)) {
+                    // Already focused
+                } else {
+                    response.request_focus();
+                }
+
+                let path_valid = !path.trim().is_empty();
+
+                if ui.add_enabled(path_valid, egui::Button::new("OK")).clicked()
+                    || (response.lost_focus()
+                        && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                        && path_valid)
+                {
+                    let file = std::path::PathBuf::from(path.trim());
+                    if importing {
+                        match soft_keys::import_profile(&file) {
+                            Ok(profile) => {
+                                modal.soft_keys = Some(profile.keys);
+                                if profile.brightness.is_some() || profile.mode.is_some() {
+                                    result = SettingsModalResult::ApplyDeviceSettings {
+                                        brightness: profile.brightness,
+                                        mode: profile.mode,
+                                    };
+                                }
+                                modal.profile_status =
+                                    Some("Profile imported. Apply to Device to write the soft keys.".to_string());
+                            }
+                            Err(e) => modal.profile_status = Some(format!("Import failed: {:#}", e)),
+                        }
+                    } else if let Some(ref keys) = modal.soft_keys {
+                        modal.profile_status = Some(match soft_keys::export_profile(&file, keys, modal.device_mode) {
+                            Ok(()) => format!("Exported to {}", file.display()),
+                            Err(e) => format!("Export failed: {:#}", e),
+                        });
+                    }
+                    done = true;
+                }
+
+                if ui.button("Close").clicked() {
+                    done = true;
+                }
+            });
+            if done {
+                modal.preset_edit = PresetEditMode::None;
+            } else if importing {
+                modal.preset_edit = PresetEditMode::Importing(path);
+            } else {
+                modal.preset_edit = PresetEditMode::Exporting(path);
+            }
+        }
+    }
+
+    if let Some(ref status) = modal.profile_status {
+        ui.label(egui::RichText::new(status).size(12.0).color(egui::Color32::GRAY));
     }
+
+    result
 }
 
 /// What the current keys match
@@ -91,6 +91,8 @@ pub enum TerminalAction {
     ApplySoftKeys([SoftKeyEditState; 3]),
     /// Reset soft keys to firmware defaults
     ResetSoftKeys,
+    /// Set brightness and/or mode from an imported device profile
+    ApplyDeviceSettings { brightness: Option<u8>, mode: Option<DeviceMode> },
     /// Send HID display update with session name, current task, tab states, and active index
     HidDisplayUpdate { session: String, task: Option<String>, task2: Option<String>, tabs: Vec<u8>, active: usize },
     /// Send HID alert overlay for a background session
@@ -127,6 +129,8 @@ pub struct TerminalWindowState {
     pub(super) settings_modal: SettingsModal,
     /// Whether daemon WebSocket is connected
     pub daemon_connected: bool,
+    /// Whether the daemon speaks an older protocol than the app
+    pub daemon_outdated: bool,
     /// HID device connection state
     pub hid_connected: bool,
     /// Connected device name
@@ -233,6 +237,7 @@ impl TerminalWindowState {
             settings: settings.clone(),
             settings_modal: SettingsModal::new(settings),
             daemon_connected: false,
+            daemon_outdated: false,
             hid_connected: false,
             device_name: None,
             firmware_version: None,
@@ -883,6 +888,7 @@ impl TerminalWindowState {
                 ctx,
                 render_params.device_popup_open,
                 self.daemon_connected,
+                self.daemon_outdated,
                 hid_connected,
                 &device_name,
                 &firmware_version,
@@ -890,6 +896,7 @@ impl TerminalWindowState {
             );
 
             // Render settings modal
+            self.settings_modal.device_mode = self.last_device_reported_mode;
             let settings_result = render_settings_modal(ctx, &mut self.settings_modal, hid_connected);
             handle_settings_modal_result(settings_result, &mut new_actions);
 
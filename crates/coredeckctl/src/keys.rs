@@ -0,0 +1,138 @@
+//! Key names on the command line: `ctrl+shift+t`, `enter`, `f5`, `0x68`
+
+use anyhow::{anyhow, bail, Result};
+use coredeck_protocol::KeyStroke;
+
+/// HID usages by name, besides letters, digits and F-keys. The first name
+/// for a usage is the one printed.
+const NAMED_KEYS: &[(&str, u8)] = &[
+    ("enter", 0x28),
+    ("return", 0x28),
+    ("esc", 0x29),
+    ("escape", 0x29),
+    ("backspace", 0x2A),
+    ("tab", 0x2B),
+    ("space", 0x2C),
+    ("minus", 0x2D),
+    ("equal", 0x2E),
+    ("lbracket", 0x2F),
+    ("rbracket", 0x30),
+    ("backslash", 0x31),
+    ("semicolon", 0x33),
+    ("quote", 0x34),
+    ("grave", 0x35),
+    ("comma", 0x36),
+    ("dot", 0x37),
+    ("slash", 0x38),
+    ("capslock", 0x39),
+    ("printscreen", 0x46),
+    ("scrolllock", 0x47),
+    ("pause", 0x48),
+    ("insert", 0x49),
+    ("home", 0x4A),
+    ("pageup", 0x4B),
+    ("pgup", 0x4B),
+    ("delete", 0x4C),
+    ("del", 0x4C),
+    ("end", 0x4D),
+    ("pagedown", 0x4E),
+    ("pgdn", 0x4E),
+    ("right", 0x4F),
+    ("left", 0x50),
+    ("down", 0x51),
+    ("up", 0x52),
+];
+
+fn usage_by_name(name: &str) -> Option<u8> {
+    let bytes = name.as_bytes();
+    match bytes {
+        [c @ b'a'..=b'z'] => return Some(0x04 + (c - b'a')),
+        [b'0'] => return Some(0x27),
+        [c @ b'1'..=b'9'] => return Some(0x1E + (c - b'1')),
+        _ => {}
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        return match n {
+            1..=12 => Some(0x3A + n - 1),
+            13..=24 => Some(0x68 + n - 13),
+            _ => None,
+        };
+    }
+    if let Some(hex) = name.strip_prefix("0x") {
+        return u8::from_str_radix(hex, 16).ok();
+    }
+    NAMED_KEYS.iter().find(|(n, _)| *n == name).map(|&(_, usage)| usage)
+}
+
+fn usage_name(usage: u8) -> String {
+    match usage {
+        0x04..=0x1D => ((b'a' + usage - 0x04) as char).to_string(),
+        0x1E..=0x26 => ((b'1' + usage - 0x1E) as char).to_string(),
+        0x27 => "0".to_string(),
+        0x3A..=0x45 => format!("f{}", usage - 0x3A + 1),
+        0x68..=0x73 => format!("f{}", usage - 0x68 + 13),
+        _ => NAMED_KEYS
+            .iter()
+            .find(|&&(_, u)| u == usage)
+            .map_or_else(|| format!("0x{:02x}", usage), |(name, _)| name.to_string()),
+    }
+}
+
+/// Parse `[modifier+]...key`, case-insensitively
+pub fn parse_key(text: &str) -> Result<KeyStroke> {
+    let lower = text.to_ascii_lowercase();
+    let mut parts: Vec<&str> = lower.split('+').collect();
+    let key = parts.pop().filter(|k| !k.is_empty()).ok_or_else(|| anyhow!("Missing key in '{}'", text))?;
+    let mut stroke = KeyStroke {
+        usage: usage_by_name(key).ok_or_else(|| anyhow!("Unknown key '{}'", key))?,
+        ..Default::default()
+    };
+    for modifier in parts {
+        match modifier {
+            "ctrl" | "control" => stroke.ctrl = true,
+            "shift" => stroke.shift = true,
+            "alt" | "opt" | "option" => stroke.alt = true,
+            "gui" | "cmd" | "super" | "win" | "meta" => stroke.gui = true,
+            _ => bail!("Unknown modifier '{}' in '{}'", modifier, text),
+        }
+    }
+    Ok(stroke)
+}
+
+/// Inverse of `parse_key`
+pub fn format_key(key: &KeyStroke) -> String {
+    let modifiers = [(key.ctrl, "ctrl+"), (key.shift, "shift+"), (key.alt, "alt+"), (key.gui, "gui+")];
+    let mut text: String = modifiers.iter().filter(|(on, _)| *on).map(|(_, name)| *name).collect();
+    text.push_str(&usage_name(key.usage));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        let key = parse_key("Ctrl+Shift+T").unwrap();
+        assert_eq!(key, KeyStroke { usage: 0x17, ctrl: true, shift: true, ..Default::default() });
+        assert_eq!(parse_key("enter").unwrap().usage, 0x28);
+        assert_eq!(parse_key("return").unwrap().usage, 0x28);
+        assert_eq!(parse_key("f13").unwrap().usage, 0x68);
+        assert_eq!(parse_key("0").unwrap().usage, 0x27);
+        assert_eq!(parse_key("cmd+0x64").unwrap(), KeyStroke { usage: 0x64, gui: true, ..Default::default() });
+
+        assert!(parse_key("hyper+a").is_err());
+        assert!(parse_key("ctrl+").is_err());
+        assert!(parse_key("f25").is_err());
+    }
+
+    #[test]
+    fn test_format_key_roundtrip() {
+        for text in ["ctrl+c", "shift+alt+f5", "gui+space", "9", "pgdn", "0x64"] {
+            let key = parse_key(text).unwrap();
+            assert_eq!(parse_key(&format_key(&key)).unwrap(), key, "{}", text);
+        }
+        assert_eq!(format_key(&parse_key("del").unwrap()), "delete");
+        assert_eq!(format_key(&parse_key("alt+ctrl+a").unwrap()), "ctrl+alt+a");
+    }
+}
@@ -0,0 +1,374 @@
+//! coredeckctl — command-line control for the CoreDeck daemon
+//!
+//! Commands go through the daemon's REST API, so those that change the deck
+//! get `409 Conflict` while the app holds it (display updates excepted: they
+//! are shown as their own layer). `watch` connects to the WebSocket as an
+//! observer and runs alongside the app.
+
+mod keys;
+mod print;
+
+use anyhow::{bail, Result};
+use clap::{Args, Parser, Subcommand};
+use coredeck_client::{app_token, Endpoint, RestClient, WsClient};
+use coredeck_protocol::{
+    default_socket_path, AlertRequest, ApiError, BrightnessRequest, ClearAlertRequest, ClearDisplayRequest,
+    DeviceMode, DisplayUpdateRequest, FieldAdjustments, FieldIssue, SetModeRequest, SetSoftKeyRequest,
+    SoftKeyAction, WsClientRole, DEFAULT_DAEMON_ADDR,
+};
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+
+use keys::parse_key;
+
+#[derive(Parser)]
+#[command(name = "coredeckctl", about = "Control a CoreDeck through the running daemon")]
+struct Cli {
+    /// Daemon TCP address, used when the Unix socket is unavailable
+    #[arg(long, default_value = DEFAULT_DAEMON_ADDR)]
+    addr: String,
+
+    /// Daemon Unix socket (default: $XDG_RUNTIME_DIR/coredeck/daemon.sock)
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+
+    /// API token for TCP (default: the app's token from tokens.toml)
+    #[arg(long)]
+    token: Option<String>,
+
+    /// Print results, errors and events as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the daemon's decks and connections
+    Status,
+    /// Print a deck's firmware version
+    Version {
+        /// Serial of the deck (default: the first connected one)
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// Show content on the display, as a layer over or under the app's
+    Display(DisplayArgs),
+    /// Remove a display layer
+    ClearDisplay {
+        /// Layer to remove (default: "rest")
+        #[arg(long)]
+        source: Option<String>,
+        /// Serial of the deck (default: every deck)
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// Show an alert overlay for a tab
+    Alert {
+        tab: usize,
+        text: String,
+        /// Session name shown with the alert
+        #[arg(long, default_value = "")]
+        session: String,
+        #[arg(long)]
+        details: Option<String>,
+        /// Serial of the deck (default: every deck)
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// Clear the alert overlay for a tab
+    Clear {
+        tab: usize,
+        /// Serial of the deck (default: every deck)
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// List the alert history
+    Alerts,
+    /// Set the display brightness (0-255)
+    Brightness {
+        level: u8,
+        /// Keep the level across power cycles
+        #[arg(long)]
+        save: bool,
+        /// Serial of the deck (default: every deck)
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// Set the LED mode: default, accept or plan
+    Mode {
+        #[arg(value_parser = parse_mode)]
+        mode: DeviceMode,
+        /// Serial of the deck (default: every deck)
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// Read, assign or reset soft keys
+    #[command(subcommand)]
+    Softkey(SoftKeyCommand),
+    /// Print device events until the daemon goes away
+    Watch,
+}
+
+#[derive(Args)]
+struct DisplayArgs {
+    session: String,
+    #[arg(long, default_value = "")]
+    task: String,
+    #[arg(long, default_value = "")]
+    task2: String,
+    /// Tab states, comma-separated (0 inactive, 1 started, 2 working)
+    #[arg(long, value_delimiter = ',')]
+    tabs: Vec<u8>,
+    /// Index of the active tab
+    #[arg(long, default_value_t = 0)]
+    active: usize,
+    /// Display layer (default: "rest")
+    #[arg(long)]
+    source: Option<String>,
+    /// Layers with higher priority are shown first
+    #[arg(long, default_value_t = 0)]
+    priority: u8,
+    /// Drop the layer after this many seconds
+    #[arg(long, value_name = "SECS")]
+    ttl: Option<u64>,
+    /// Serial of the deck (default: every deck)
+    #[arg(long)]
+    device: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum SoftKeyCommand {
+    /// Show the soft keys, or one of them
+    Get {
+        index: Option<u8>,
+        /// Serial of the deck (default: the first connected one)
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// Assign a soft key
+    Set {
+        index: u8,
+        #[command(subcommand)]
+        action: ActionArgs,
+        /// Keep the assignment across power cycles
+        #[arg(long, global = true)]
+        save: bool,
+        /// Serial of the deck (default: every deck)
+        #[arg(long, global = true)]
+        device: Option<String>,
+    },
+    /// Reset every soft key to the firmware default
+    Reset {
+        /// Serial of the deck (default: the first connected one)
+        #[arg(long)]
+        device: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ActionArgs {
+    /// The firmware's default key
+    Default,
+    /// One key with modifiers, e.g. `ctrl+c` or `f13`
+    Key { key: String },
+    /// Type text
+    Text {
+        text: String,
+        /// Press Enter after the text
+        #[arg(long)]
+        enter: bool,
+    },
+    /// Tap keys one after another, e.g. `ctrl+a delete`
+    Sequence {
+        #[arg(required = true)]
+        keys: Vec<String>,
+    },
+}
+
+impl ActionArgs {
+    fn into_action(self) -> Result<SoftKeyAction> {
+        Ok(match self {
+            Self::Default => SoftKeyAction::Default { key: None },
+            Self::Key { key } => SoftKeyAction::Keycode { key: parse_key(&key)? },
+            Self::Text { text, enter } => SoftKeyAction::String { text, send_enter: enter },
+            Self::Sequence { keys } => SoftKeyAction::Sequence {
+                keys: keys.iter().map(|key| parse_key(key)).collect::<Result<_>>()?,
+            },
+        })
+    }
+}
+
+fn parse_mode(text: &str) -> Result<DeviceMode, String> {
+    [DeviceMode::Default, DeviceMode::Accept, DeviceMode::Plan]
+        .into_iter()
+        .find(|mode| mode.to_string().eq_ignore_ascii_case(text))
+        .ok_or_else(|| "expected default, accept or plan".to_string())
+}
+
+/// Write a line to stdout. Exits quietly once the reader is gone (`| head`).
+fn emit(line: &str) -> Result<()> {
+    match writeln!(std::io::stdout().lock(), "{}", line) {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => std::process::exit(0),
+        result => Ok(result?),
+    }
+}
+
+/// Prints results as JSON or as text
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    fn print<T: Serialize>(&self, value: &T, human: impl FnOnce(&T) -> String) -> Result<()> {
+        if self.json {
+            emit(&serde_json::to_string_pretty(value)?)
+        } else {
+            emit(&human(value))
+        }
+    }
+
+    /// Report the fields the daemon shortened to fit the deck
+    fn adjusted(&self, adjusted: Vec<FieldIssue>) -> Result<()> {
+        if self.json {
+            emit(&serde_json::to_string_pretty(&FieldAdjustments { adjusted })?)?;
+        } else {
+            for issue in adjusted {
+                eprintln!("{}: {}", issue.field, issue.reason);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn lines<T>(items: &[T], line: impl Fn(&T) -> String, empty: &str) -> String {
+    if items.is_empty() {
+        return empty.to_string();
+    }
+    items.iter().map(line).collect::<Vec<_>>().join("\n")
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let json = cli.json;
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to create tokio runtime");
+    if let Err(e) = rt.block_on(run(cli)) {
+        if json {
+            let error = ApiError { error: format!("{:#}", e) };
+            eprintln!("{}", serde_json::to_string(&error).unwrap_or_default());
+        } else {
+            eprintln!("{:#}", e);
+        }
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let endpoint = Endpoint {
+        socket: cli.socket.or_else(default_socket_path),
+        addr: Some(cli.addr),
+        token: cli.token.or_else(app_token),
+    };
+    let out = Output { json: cli.json };
+    let rest = RestClient::new(endpoint.clone());
+
+    match cli.command {
+        Command::Status => out.print(&rest.status().await?, print::status),
+        Command::Version { device } => {
+            let version = rest.version(device.as_deref()).await?;
+            out.print(&serde_json::json!({ "version": version }), |_| version.clone())
+        }
+        Command::Display(args) => {
+            let req = DisplayUpdateRequest {
+                session: args.session,
+                task: args.task,
+                task2: args.task2,
+                tabs: args.tabs,
+                active: args.active,
+                source: args.source,
+                priority: args.priority,
+                ttl_secs: args.ttl,
+                device: args.device,
+            };
+            out.adjusted(rest.update_display(&req).await?)
+        }
+        Command::ClearDisplay { source, device } => rest.clear_display(&ClearDisplayRequest { source, device }).await,
+        Command::Alert { tab, text, session, details, device } => {
+            let req = AlertRequest { tab, session, text, details, device };
+            out.adjusted(rest.alert(&req).await?)
+        }
+        Command::Clear { tab, device } => rest.clear_alert(&ClearAlertRequest { tab, device }).await,
+        Command::Alerts => out.print(&rest.alerts().await?, |alerts| lines(alerts, print::alert, "No alerts")),
+        Command::Brightness { level, save, device } => {
+            rest.set_brightness(&BrightnessRequest { level, save, device }).await
+        }
+        Command::Mode { mode, device } => rest.set_mode(&SetModeRequest { mode, device }).await,
+        Command::Softkey(SoftKeyCommand::Get { index: None, device }) => {
+            let keys = rest.soft_keys(device.as_deref()).await?;
+            out.print(&keys, |keys| lines(keys, print::soft_key, "No soft keys"))
+        }
+        Command::Softkey(SoftKeyCommand::Get { index: Some(index), device }) => {
+            let keys = rest.soft_keys(device.as_deref()).await?;
+            let Some(key) = keys.iter().find(|key| key.index == index) else {
+                bail!("No soft key {} (the deck has {})", index, keys.len());
+            };
+            out.print(key, print::soft_key)
+        }
+        Command::Softkey(SoftKeyCommand::Set { index, action, save, device }) => {
+            let req = SetSoftKeyRequest { action: action.into_action()?, save, device };
+            out.adjusted(rest.set_soft_key(index, &req).await?)
+        }
+        Command::Softkey(SoftKeyCommand::Reset { device }) => {
+            let keys = rest.reset_soft_keys(device.as_deref()).await?;
+            out.print(&keys, |keys| lines(keys, print::soft_key, "No soft keys"))
+        }
+        Command::Watch => watch(&endpoint, &out).await,
+    }
+}
+
+/// Print events as an observer, one per line, until the connection closes
+async fn watch(endpoint: &Endpoint, out: &Output) -> Result<()> {
+    // Dropping the client would close the connection
+    let (_client, mut events) = WsClient::connect(endpoint, WsClientRole::Observer, "coredeckctl").await?;
+    while let Some(event) = events.recv().await {
+        if out.json {
+            emit(&serde_json::to_string(&event)?)?;
+        } else {
+            emit(&print::event(&event))?;
+        }
+    }
+    bail!("Daemon closed the connection")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["coredeckctl", "softkey", "set", "2", "sequence", "ctrl+a", "del", "--save"]).unwrap();
+        let Command::Softkey(SoftKeyCommand::Set { index, action, save, device }) = cli.command else {
+            panic!("parsed another command");
+        };
+        assert_eq!((index, save, device), (2, true, None));
+        let SoftKeyAction::Sequence { keys } = action.into_action().unwrap() else {
+            panic!("parsed another action");
+        };
+        assert_eq!(keys.len(), 2);
+
+        let cli = Cli::try_parse_from(["coredeckctl", "mode", "Plan", "--json"]).unwrap();
+        assert!(cli.json);
+        assert!(matches!(cli.command, Command::Mode { mode: DeviceMode::Plan, device: None }));
+        assert!(Cli::try_parse_from(["coredeckctl", "mode", "yolo"]).is_err());
+    }
+}
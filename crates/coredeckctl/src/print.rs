@@ -0,0 +1,145 @@
+//! Human-readable output; `--json` prints the protocol types instead
+
+use coredeck_protocol::{AlertRecord, AlertSource, DaemonStatus, SoftKeyAction, SoftKeyAssignment, WsEvent};
+
+use crate::keys::format_key;
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// `[serial]` suffix for events from multi-device daemons
+fn from_deck(serial: &Option<String>) -> String {
+    serial.as_deref().map(|s| format!("  [{}]", s)).unwrap_or_default()
+}
+
+pub fn status(status: &DaemonStatus) -> String {
+    let observers = match status.ws_observers {
+        1 => ", 1 observer".to_string(),
+        n => format!(", {} observers", n),
+    };
+    let controller = if status.ws_locked { "app connected" } else { "none" };
+    let mut lines = vec![format!("Controller: {}{}", controller, observers)];
+
+    if status.devices.is_empty() {
+        lines.push("No decks".to_string());
+    }
+    for deck in &status.devices {
+        let link = if deck.connected { "connected" } else { "available" };
+        let firmware = deck.firmware_version.as_deref().map(|v| format!("  firmware {}", v)).unwrap_or_default();
+        lines.push(format!(
+            "{}  {}  {}{}  mode {}  yolo {}",
+            deck.serial,
+            deck.name,
+            link,
+            firmware,
+            deck.mode,
+            on_off(deck.yolo)
+        ));
+    }
+
+    let link = &status.hid_link;
+    let ping = link.ping_rtt_avg_ms.map(|ms| format!(", ping {:.1} ms", ms)).unwrap_or_default();
+    lines.push(format!(
+        "HID link: {} sent, {} received, {} write errors, {} reconnects{}",
+        link.packets_sent, link.packets_received, link.write_errors, link.reconnects, ping
+    ));
+    lines.join("\n")
+}
+
+pub fn action(action: &SoftKeyAction) -> String {
+    match action {
+        SoftKeyAction::Default { key: Some(key) } => format!("default   ({})", format_key(key)),
+        SoftKeyAction::Default { key: None } => "default".to_string(),
+        SoftKeyAction::Keycode { key } => format!("key       {}", format_key(key)),
+        SoftKeyAction::String { text, send_enter } => {
+            format!("text      {:?}{}", text, if *send_enter { " +enter" } else { "" })
+        }
+        SoftKeyAction::Sequence { keys } => {
+            let keys: Vec<String> = keys.iter().map(format_key).collect();
+            format!("sequence  {}", keys.join(" "))
+        }
+    }
+}
+
+pub fn soft_key(assignment: &SoftKeyAssignment) -> String {
+    format!("{}  {}", assignment.index, action(&assignment.action))
+}
+
+fn source(source: AlertSource) -> &'static str {
+    match source {
+        AlertSource::App => "app",
+        AlertSource::Rest => "rest",
+        AlertSource::Key => "key",
+        AlertSource::Replaced => "replaced",
+    }
+}
+
+pub fn alert(record: &AlertRecord) -> String {
+    let state = match record.cleared_by {
+        Some(by) => format!("cleared by {}", source(by)),
+        None => "shown".to_string(),
+    };
+    format!(
+        "#{}  tab {}  {}: {}  ({}, raised by {})",
+        record.id,
+        record.tab,
+        record.session,
+        record.text,
+        state,
+        source(record.raised_by)
+    )
+}
+
+pub fn event(event: &WsEvent) -> String {
+    match event {
+        WsEvent::DeviceConnected(info) => {
+            format!("connected     {} (firmware {})  [{}]", info.name, info.firmware, info.serial)
+        }
+        WsEvent::DeviceDisconnected { serial } => format!("disconnected{}", from_deck(serial)),
+        WsEvent::StateChanged { mode, yolo, serial } => {
+            format!("state         mode {}, yolo {}{}", mode, on_off(*yolo), from_deck(serial))
+        }
+        WsEvent::KeyEvent { keycode, serial } => {
+            let key = coredeck_protocol::KeyStroke::from_keycode(*keycode);
+            format!("key           {} (0x{:04x}){}", format_key(&key), keycode, from_deck(serial))
+        }
+        WsEvent::TypeString { text, send_enter } => {
+            format!("type          {:?}{}", text, if *send_enter { " +enter" } else { "" })
+        }
+        WsEvent::AppControl { action } => format!("app-control   {:?}", action),
+        WsEvent::RoleChanged { role } => format!("role          {:?}", role).to_lowercase(),
+        WsEvent::ControlRequested => "control-requested".to_string(),
+        WsEvent::AlertAcknowledged(record) => format!("alert-ack     {}", alert(record)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coredeck_protocol::KeyStroke;
+
+    #[test]
+    fn test_soft_key_lines() {
+        let ctrl_c = KeyStroke { usage: 0x06, ctrl: true, ..Default::default() };
+        let keys = [
+            SoftKeyAssignment { index: 0, action: SoftKeyAction::Keycode { key: ctrl_c } },
+            SoftKeyAssignment { index: 1, action: SoftKeyAction::String { text: "ls".into(), send_enter: true } },
+            SoftKeyAssignment { index: 2, action: SoftKeyAction::Sequence { keys: vec![ctrl_c, ctrl_c] } },
+        ];
+        let lines: Vec<String> = keys.iter().map(soft_key).collect();
+        assert_eq!(lines, ["0  key       ctrl+c", "1  text      \"ls\" +enter", "2  sequence  ctrl+c ctrl+c"]);
+    }
+
+    #[test]
+    fn test_event_lines() {
+        let key = WsEvent::KeyEvent { keycode: 0x0106, serial: Some("CD-0001".into()) };
+        assert_eq!(event(&key), "key           ctrl+c (0x0106)  [CD-0001]");
+        let role = WsEvent::RoleChanged { role: coredeck_protocol::WsClientRole::Observer };
+        assert_eq!(event(&role), "role          observer");
+    }
+}